@@ -0,0 +1,222 @@
+//! The coding agents a session can drive (`--agent`): how each is invoked for one
+//! prompt. Their output goes through the same scanning, rendering and reporting.
+
+use std::fmt;
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::RunOptions;
+use crate::launch::SANDBOX_BYPASS_FLAG;
+
+/// A coding agent CLI.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Agent {
+    /// OpenAI's codex, run as `codex exec`.
+    #[default]
+    Codex,
+    /// aider, run as `aider --message <prompt>`.
+    Aider,
+    /// Google's gemini CLI, run as `gemini -p <prompt>`.
+    Gemini,
+}
+
+impl Agent {
+    /// Command name of the agent when no binary is given.
+    pub fn default_bin(self) -> &'static str {
+        match self {
+            Self::Codex => "codex",
+            Self::Aider => "aider",
+            Self::Gemini => "gemini",
+        }
+    }
+
+    /// The flag that lets every run act without asking for approval.
+    pub fn auto_approve_flag(self) -> &'static str {
+        match self {
+            Self::Codex => SANDBOX_BYPASS_FLAG,
+            Self::Aider => "--yes",
+            Self::Gemini => "--yolo",
+        }
+    }
+
+    /// Whether the agent is told the work dir by a flag; the others are started in it.
+    pub fn takes_work_dir_flag(self) -> bool {
+        self == Self::Codex
+    }
+
+    /// Arguments running `prompt` once, non-interactively, in `options.work_dir` with
+    /// `options.model`.
+    pub fn args(self, prompt: &str, options: &RunOptions) -> Vec<String> {
+        let mut args: Vec<String> = Vec::new();
+        match self {
+            Self::Codex => {
+                args.extend(["exec".to_string(), SANDBOX_BYPASS_FLAG.to_string()]);
+                if let Some(dir) = options.work_dir.as_deref() {
+                    args.extend(["-C".to_string(), dir.to_string_lossy().to_string()]);
+                }
+            }
+            Self::Aider => {
+                // Without a terminal to answer on, aider must not stop to ask anything.
+                args.extend(
+                    [self.auto_approve_flag(), "--no-check-update", "--no-pretty"]
+                        .map(String::from),
+                );
+            }
+            Self::Gemini => args.push(self.auto_approve_flag().to_string()),
+        }
+        if let Some(model) = &options.model {
+            args.extend(["--model".to_string(), model.clone()]);
+        }
+        match self {
+            Self::Codex => args.push(prompt.to_string()),
+            Self::Aider => args.extend(["--message".to_string(), prompt.to_string()]),
+            Self::Gemini => args.extend(["--prompt".to_string(), prompt.to_string()]),
+        }
+        args
+    }
+}
+
+impl fmt::Display for Agent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.default_bin())
+    }
+}
+
+impl std::str::FromStr for Agent {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.to_ascii_lowercase().as_str() {
+            "codex" => Ok(Self::Codex),
+            "aider" => Ok(Self::Aider),
+            "gemini" => Ok(Self::Gemini),
+            _ => Err(format!(
+                "unknown agent `{text}`, expected codex, aider or gemini"
+            )),
+        }
+    }
+}
+
+/// Placeholders a [`RunnerTemplate`] fills in.
+const PLACEHOLDERS: [&str; 3] = ["{prompt}", "{work_dir}", "{model}"];
+
+/// Any other agent CLI, given as a command line (`--runner-template`) such as
+/// `mytool run --task {prompt} --dir {work_dir}`. `{prompt}`, `{work_dir}` and `{model}`
+/// are filled in per run, inside a word or as one on their own; a filled-in word stays one
+/// argument however many spaces it holds. Words are split on whitespace, with single and
+/// double quotes grouping them.
+#[derive(Debug, Clone)]
+pub struct RunnerTemplate {
+    words: Vec<String>,
+    /// Exit codes that count as success; `0` unless configured.
+    pub success_codes: Vec<i32>,
+    /// When set, a run only succeeds if one of its output lines matches as well.
+    pub success_pattern: Option<Regex>,
+}
+
+impl RunnerTemplate {
+    /// The command the template runs.
+    pub fn program(&self) -> &str {
+        &self.words[0]
+    }
+
+    /// The arguments running `prompt` once, with the placeholders filled in from
+    /// `options`. `{work_dir}` is `.` without a work dir, as the command is started in it,
+    /// and `{model}` is empty without a model.
+    pub fn args(&self, prompt: &str, options: &RunOptions) -> Vec<String> {
+        let work_dir = options
+            .work_dir
+            .as_deref()
+            .map_or_else(|| ".".to_string(), |dir| dir.to_string_lossy().into_owned());
+        let model = options.model.as_deref().unwrap_or("");
+        self.words[1..]
+            .iter()
+            .map(|word| {
+                // One pass, so a prompt that mentions `{model}` is left as written.
+                let mut filled = String::new();
+                let mut rest = word.as_str();
+                while let Some(start) = rest.find('{') {
+                    filled.push_str(&rest[..start]);
+                    rest = &rest[start..];
+                    let (value, len) = match PLACEHOLDERS.iter().find(|p| rest.starts_with(**p)) {
+                        Some(&"{prompt}") => (prompt, "{prompt}".len()),
+                        Some(&"{work_dir}") => (work_dir.as_str(), "{work_dir}".len()),
+                        Some(&"{model}") => (model, "{model}".len()),
+                        _ => ("{", 1),
+                    };
+                    filled.push_str(value);
+                    rest = &rest[len..];
+                }
+                filled.push_str(rest);
+                filled
+            })
+            .collect()
+    }
+
+    /// Whether a run that exited with `code` (none when killed by a signal) succeeded,
+    /// given whether its output matched the success pattern.
+    pub fn succeeded(&self, code: Option<i32>, pattern_seen: bool) -> bool {
+        code.is_some_and(|code| self.success_codes.contains(&code))
+            && (self.success_pattern.is_none() || pattern_seen)
+    }
+}
+
+impl std::str::FromStr for RunnerTemplate {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let words = split_words(text)?;
+        if words.is_empty() {
+            return Err("the runner template is empty".to_string());
+        }
+        for word in &words {
+            let mut rest = word.as_str();
+            while let Some(start) = rest.find('{') {
+                rest = &rest[start..];
+                match PLACEHOLDERS.iter().find(|p| rest.starts_with(**p)) {
+                    Some(placeholder) => rest = &rest[placeholder.len()..],
+                    None => {
+                        let name = rest.split_once('}').map_or(rest, |(name, _)| name);
+                        return Err(format!(
+                            "unknown placeholder `{name}}}`, expected {{prompt}}, {{work_dir}} or {{model}}"
+                        ));
+                    }
+                }
+            }
+        }
+        if !words.iter().any(|word| word.contains("{prompt}")) {
+            return Err("the runner template has no {prompt} placeholder".to_string());
+        }
+        Ok(Self {
+            words,
+            success_codes: vec![0],
+            success_pattern: None,
+        })
+    }
+}
+
+/// `text` split into words on whitespace; quotes group words and are removed.
+fn split_words(text: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    for c in text.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => word.get_or_insert_default().push(c),
+            None if c == '\'' || c == '"' => {
+                quote = Some(c);
+                word.get_or_insert_default();
+            }
+            None if c.is_whitespace() => words.extend(word.take()),
+            None => word.get_or_insert_default().push(c),
+        }
+    }
+    if let Some(q) = quote {
+        return Err(format!("unclosed {q} in the runner template"));
+    }
+    words.extend(word);
+    Ok(words)
+}
@@ -0,0 +1,258 @@
+//! Answering auxiliary prompts, such as plan reviews, through a model provider's HTTP API
+//! (`--aux-model`) instead of an agent run. These prompts only need text back, so they
+//! skip the agent CLI's start-up and never touch the work dir.
+//!
+//! Keys come from `OPENAI_API_KEY` and `ANTHROPIC_API_KEY`; `OPENAI_BASE_URL` and
+//! `ANTHROPIC_BASE_URL` point requests at a proxy or compatible server. A local Ollama
+//! server needs no key and keeps unattended loops going without network or quota; it is
+//! found at `OLLAMA_HOST`, by default `http://localhost:11434`.
+
+use std::fmt;
+use std::time::Duration;
+
+use serde_json::{Value, json};
+
+/// How long one request may take, answer included.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Longest answer requested from Anthropic, which needs a limit.
+const MAX_TOKENS: u32 = 4096;
+
+/// Anthropic API version sent with every request.
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Most of an error response's body kept in [`ApiError::Status`].
+const MAX_ERROR_BODY: usize = 500;
+
+/// A model provider with a chat API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provider {
+    /// The chat completions API, also spoken by many compatible servers.
+    OpenAi,
+    /// The messages API.
+    Anthropic,
+    /// A local Ollama server's chat API.
+    Ollama,
+}
+
+impl Provider {
+    /// Environment variable holding the API key; `None` when no key is needed.
+    pub fn key_var(self) -> Option<&'static str> {
+        match self {
+            Self::OpenAi => Some("OPENAI_API_KEY"),
+            Self::Anthropic => Some("ANTHROPIC_API_KEY"),
+            Self::Ollama => None,
+        }
+    }
+
+    /// Environment variable overriding [`Provider::default_base_url`].
+    pub fn base_url_var(self) -> &'static str {
+        match self {
+            Self::OpenAi => "OPENAI_BASE_URL",
+            Self::Anthropic => "ANTHROPIC_BASE_URL",
+            Self::Ollama => "OLLAMA_HOST",
+        }
+    }
+
+    pub fn default_base_url(self) -> &'static str {
+        match self {
+            Self::OpenAi => "https://api.openai.com/v1",
+            Self::Anthropic => "https://api.anthropic.com/v1",
+            Self::Ollama => "http://localhost:11434",
+        }
+    }
+
+    fn endpoint(self) -> &'static str {
+        match self {
+            Self::OpenAi => "/chat/completions",
+            Self::Anthropic => "/messages",
+            Self::Ollama => "/api/chat",
+        }
+    }
+}
+
+impl fmt::Display for Provider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::OpenAi => "openai",
+            Self::Anthropic => "anthropic",
+            Self::Ollama => "ollama",
+        })
+    }
+}
+
+/// A provider and one of its models, written `openai:gpt-4o-mini` or `ollama:llama3.1`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiModel {
+    pub provider: Provider,
+    pub model: String,
+}
+
+impl fmt::Display for ApiModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.provider, self.model)
+    }
+}
+
+impl std::str::FromStr for ApiModel {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let Some((provider, model)) = text.split_once(':') else {
+            return Err(format!("expected PROVIDER:MODEL, got `{text}`"));
+        };
+        let provider = match provider.trim().to_ascii_lowercase().as_str() {
+            "openai" => Provider::OpenAi,
+            "anthropic" => Provider::Anthropic,
+            "ollama" => Provider::Ollama,
+            other => {
+                return Err(format!(
+                    "unknown provider `{other}`, expected openai, anthropic or ollama"
+                ));
+            }
+        };
+        let model = model.trim();
+        if model.is_empty() {
+            return Err(format!("no model given in `{text}`"));
+        }
+        Ok(Self {
+            provider,
+            model: model.to_string(),
+        })
+    }
+}
+
+/// Why a prompt got no answer.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum ApiError {
+    #[error("{0} is not set")]
+    MissingKey(&'static str),
+    #[error("request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("the API answered {status}: {body}")]
+    Status {
+        status: reqwest::StatusCode,
+        body: String,
+    },
+    #[error("the API response has no text")]
+    NoText,
+}
+
+/// Sends prompts to one model.
+#[derive(Debug, Clone)]
+pub struct ApiClient {
+    model: ApiModel,
+    base_url: String,
+    key: String,
+    http: reqwest::Client,
+}
+
+impl ApiClient {
+    /// A client for `model` with its key and base URL from the environment.
+    pub fn from_env(model: ApiModel) -> Result<Self, ApiError> {
+        let provider = model.provider;
+        let key = match provider.key_var() {
+            Some(var) => std::env::var(var)
+                .ok()
+                .filter(|key| !key.is_empty())
+                .ok_or(ApiError::MissingKey(var))?,
+            None => String::new(),
+        };
+        let base_url = std::env::var(provider.base_url_var())
+            .ok()
+            .filter(|url| !url.is_empty())
+            .map_or_else(|| provider.default_base_url().to_string(), with_scheme);
+        Ok(Self::new(model, &base_url, &key))
+    }
+
+    pub fn new(model: ApiModel, base_url: &str, key: &str) -> Self {
+        Self {
+            model,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            key: key.to_string(),
+            http: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn model(&self) -> &ApiModel {
+        &self.model
+    }
+
+    /// The model's answer to `prompt`, sent as a single user message.
+    pub async fn complete(&self, prompt: &str) -> Result<String, ApiError> {
+        let provider = self.model.provider;
+        let request = self
+            .http
+            .post(format!("{}{}", self.base_url, provider.endpoint()))
+            .json(&request_body(&self.model, prompt));
+        let request = match provider {
+            Provider::OpenAi => request.bearer_auth(&self.key),
+            Provider::Anthropic => request
+                .header("x-api-key", &self.key)
+                .header("anthropic-version", ANTHROPIC_VERSION),
+            Provider::Ollama => request,
+        };
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let mut body = response.text().await.unwrap_or_default();
+            if let Some((end, _)) = body.char_indices().nth(MAX_ERROR_BODY) {
+                body.truncate(end);
+            }
+            return Err(ApiError::Status { status, body });
+        }
+        let body: Value = response.json().await?;
+        response_text(provider, &body).ok_or(ApiError::NoText)
+    }
+}
+
+/// `url` with `http://` in front when it has no scheme, as `OLLAMA_HOST` is often given
+/// as `host:port`.
+fn with_scheme(url: String) -> String {
+    if url.contains("://") {
+        url
+    } else {
+        format!("http://{url}")
+    }
+}
+
+/// The request asking `model` to answer `prompt`.
+pub fn request_body(model: &ApiModel, prompt: &str) -> Value {
+    let messages = json!([{ "role": "user", "content": prompt }]);
+    match model.provider {
+        Provider::OpenAi => json!({ "model": model.model, "messages": messages }),
+        Provider::Anthropic => json!({
+            "model": model.model,
+            "max_tokens": MAX_TOKENS,
+            "messages": messages,
+        }),
+        Provider::Ollama => json!({
+            "model": model.model,
+            "messages": messages,
+            "stream": false,
+        }),
+    }
+}
+
+/// The answer's text in a successful response from `provider`.
+pub fn response_text(provider: Provider, body: &Value) -> Option<String> {
+    match provider {
+        Provider::OpenAi => body["choices"][0]["message"]["content"]
+            .as_str()
+            .map(str::to_string),
+        Provider::Ollama => body["message"]["content"].as_str().map(str::to_string),
+        Provider::Anthropic => {
+            let text: Vec<&str> = body["content"]
+                .as_array()?
+                .iter()
+                .filter(|block| block["type"] == "text")
+                .filter_map(|block| block["text"].as_str())
+                .collect();
+            (!text.is_empty()).then(|| text.concat())
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! Keeping what the agent generates (`--collect`): after each run, the files in the work
+//! dir matching the collect patterns are copied to a directory of the run's own, so
+//! benchmark results and reports survive later runs overwriting them.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+/// A path pattern relative to the work dir: `*` and `?` match within one path component,
+/// `**` across any number of them, e.g. `target/criterion/**` or `reports/*.json`.
+#[derive(Debug, Clone)]
+pub struct Glob {
+    pattern: String,
+    regex: Regex,
+}
+
+impl Glob {
+    /// Whether `path`, relative to the work dir, matches. A file also matches when one of
+    /// its parent dirs does, so `reports` collects everything under it.
+    pub fn matches(&self, path: &Path) -> bool {
+        path.ancestors()
+            .filter(|p| !p.as_os_str().is_empty())
+            .any(|p| self.regex.is_match(&slash_path(p)))
+    }
+
+    /// The matching files under `root`, relative to it; `.git` and symlinks are left out.
+    pub fn files(&self, root: &Path) -> io::Result<BTreeSet<PathBuf>> {
+        let mut files = BTreeSet::new();
+        walk(root, &self.base(), &mut |rel| {
+            if self.matches(rel) {
+                files.insert(rel.to_path_buf());
+            }
+        })?;
+        Ok(files)
+    }
+
+    /// The path below which every match lies: the components before the first wildcard.
+    fn base(&self) -> PathBuf {
+        self.pattern
+            .split('/')
+            .take_while(|component| !component.contains(['*', '?']))
+            .collect()
+    }
+}
+
+impl PartialEq for Glob {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern == other.pattern
+    }
+}
+
+impl fmt::Display for Glob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.pattern)
+    }
+}
+
+impl std::str::FromStr for Glob {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let pattern = text.trim().replace('\\', "/");
+        let pattern = pattern.trim_start_matches("./").trim_end_matches('/');
+        if pattern.is_empty() {
+            return Err("empty pattern".to_string());
+        }
+        if pattern.starts_with('/') || pattern.split('/').any(|c| c == "..") {
+            return Err(format!(
+                "`{text}` must be relative to the work dir and stay inside it"
+            ));
+        }
+        let mut regex = String::from("^");
+        let mut rest = pattern;
+        while let Some(c) = rest.chars().next() {
+            if let Some(after) = rest.strip_prefix("**/") {
+                regex.push_str("(?:.*/)?");
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix("**") {
+                regex.push_str(".*");
+                rest = after;
+            } else {
+                match c {
+                    '*' => regex.push_str("[^/]*"),
+                    '?' => regex.push_str("[^/]"),
+                    c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+                }
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+        regex.push('$');
+        Ok(Self {
+            pattern: pattern.to_string(),
+            regex: Regex::new(&regex).map_err(|e| e.to_string())?,
+        })
+    }
+}
+
+/// `path` with `/` between its components, as patterns are written.
+fn slash_path(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Copies the files matching [`ArtifactCollector::patterns`] out of the work dir after
+/// each run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArtifactCollector {
+    pub patterns: Vec<Glob>,
+    /// Holds one `run-<n>` dir per run that produced artifacts.
+    pub dir: PathBuf,
+}
+
+impl ArtifactCollector {
+    /// Where the artifacts of run `run_idx` (1-based) go.
+    pub fn run_dir(&self, run_idx: usize) -> PathBuf {
+        self.dir.join(format!("run-{run_idx:04}"))
+    }
+
+    /// Copy the matching files under `work_dir` to [`ArtifactCollector::run_dir`], keeping
+    /// their paths relative to `work_dir`. Returns how many were copied; `.git` and
+    /// symlinks are left alone.
+    pub fn collect(&self, work_dir: &Path, run_idx: usize) -> io::Result<usize> {
+        let mut files = BTreeSet::new();
+        for pattern in &self.patterns {
+            files.append(&mut pattern.files(work_dir)?);
+        }
+        let run_dir = self.run_dir(run_idx);
+        for rel in &files {
+            let target = run_dir.join(rel);
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::copy(work_dir.join(rel), &target)?;
+        }
+        Ok(files.len())
+    }
+}
+
+/// Call `found` with every file at or below `rel` in `root`, relative to `root`.
+fn walk(root: &Path, rel: &Path, found: &mut dyn FnMut(&Path)) -> io::Result<()> {
+    let metadata = match std::fs::symlink_metadata(root.join(rel)) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    if metadata.is_file() {
+        found(rel);
+    } else if metadata.is_dir() {
+        for entry in std::fs::read_dir(root.join(rel))? {
+            let name = entry?.file_name();
+            if name != ".git" {
+                walk(root, &rel.join(name), found)?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,237 @@
+//! Attaching to a running session (`agent-loops attach <id>`), typically one started
+//! with `--detach`: its log is followed as it grows, and it can be paused before its next
+//! run or stopped. Requests reach the session as files in its directory, which it checks
+//! while it runs.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::CancelToken;
+use crate::sessions::{SessionStatus, SessionStore};
+
+/// How often the session checks for requests, and the attached side for new output.
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+const PAUSE_FILE: &str = "pause";
+const STOP_FILE: &str = "stop";
+
+/// Pause and stop requests for one session.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionControl {
+    dir: PathBuf,
+}
+
+impl SessionControl {
+    /// Requests for the session whose record is in `dir`.
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    /// Hold the session before its next run until [`SessionControl::resume`].
+    pub fn pause(&self) -> io::Result<()> {
+        File::create(self.dir.join(PAUSE_FILE)).map(drop)
+    }
+
+    pub fn resume(&self) -> io::Result<()> {
+        match fs::remove_file(self.dir.join(PAUSE_FILE)) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            removed => removed,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.dir.join(PAUSE_FILE).exists()
+    }
+
+    /// Ask the session to stop as Ctrl-C would.
+    pub fn stop(&self) -> io::Result<()> {
+        File::create(self.dir.join(STOP_FILE)).map(drop)
+    }
+
+    pub fn stop_requested(&self) -> bool {
+        self.dir.join(STOP_FILE).exists()
+    }
+
+    /// Wait while the session is paused; `false` when `cancel` fires first.
+    pub async fn wait_while_paused(&self, cancel: &CancelToken) -> bool {
+        if !self.is_paused() {
+            return true;
+        }
+        tracing::info!("Session paused; waiting to be resumed before the next run.");
+        while self.is_paused() {
+            tokio::select! {
+                _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                _ = cancel.cancelled() => return false,
+            }
+        }
+        tracing::info!("Session resumed.");
+        true
+    }
+
+    /// Cancel `cancel` once a stop is requested; returns when either happens.
+    pub async fn watch_for_stop(self, cancel: CancelToken) {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                _ = cancel.cancelled() => return,
+            }
+            if self.stop_requested() {
+                tracing::warn!("Stop requested from an attached terminal; stopping the session.");
+                cancel.cancel();
+                return;
+            }
+        }
+    }
+}
+
+/// What a key pressed while attached asks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttachCommand {
+    /// `p`: pause before the next run, or resume.
+    TogglePause,
+    /// `s`: stop the session.
+    Stop,
+    /// `q`: stop following and leave the session running.
+    Detach,
+}
+
+impl AttachCommand {
+    pub fn for_key(key: char) -> Option<Self> {
+        match key.to_ascii_lowercase() {
+            'p' => Some(Self::TogglePause),
+            's' => Some(Self::Stop),
+            'q' => Some(Self::Detach),
+            _ => None,
+        }
+    }
+}
+
+/// Follow the log of session `id` on stdout: in the pinned view on a terminal, otherwise
+/// as [`follow`] does.
+pub async fn follow_stdout(
+    store: &SessionStore,
+    id: &str,
+    cancel: &CancelToken,
+) -> io::Result<Option<SessionStatus>> {
+    #[cfg(feature = "tui")]
+    if std::io::IsTerminal::is_terminal(&io::stdout()) {
+        let header = vec![
+            format!("[Attached to session {id}] p pauses or resumes, s stops, q detaches"),
+            "-".repeat(40),
+        ];
+        let mut log = SessionLog::new(store, id);
+        return crate::tui::follow_pinned(header, &mut log, &store.control(id), cancel).await;
+    }
+    follow(store, id, &mut io::stdout(), cancel).await
+}
+
+/// Follow the log of session `id` from its start, writing it to `out`, until the session
+/// ends or `cancel` fires. On a terminal, keys pressed meanwhile become
+/// [`AttachCommand`]s. Returns the session's status when it ended, `None` when detached
+/// first.
+pub async fn follow(
+    store: &SessionStore,
+    id: &str,
+    out: &mut impl Write,
+    cancel: &CancelToken,
+) -> io::Result<Option<SessionStatus>> {
+    let control = store.control(id);
+    #[cfg(feature = "tui")]
+    let mut keys = crate::keys::KeyReader::start(false);
+    let mut log = SessionLog::new(store, id);
+    let mut buf = Vec::new();
+    loop {
+        let status = log.poll(&mut buf)?;
+        out.write_all(&buf)?;
+        out.flush()?;
+        if status != SessionStatus::Running {
+            return Ok(Some(status));
+        }
+        #[cfg(feature = "tui")]
+        let key = async {
+            match &mut keys {
+                Some(keys) => keys.next().await,
+                None => std::future::pending().await,
+            }
+        };
+        #[cfg(not(feature = "tui"))]
+        let key = std::future::pending::<Option<crate::keys::Key>>();
+        let command = tokio::select! {
+            _ = tokio::time::sleep(POLL_INTERVAL) => None,
+            _ = cancel.cancelled() => return Ok(None),
+            Some(crate::keys::Key::Char(c)) = key => AttachCommand::for_key(c),
+        };
+        if let Some(command) = command {
+            match run_command(&control, command)? {
+                Some(note) => writeln!(out, "{note}")?,
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+/// Carry out `command` for the session: the note reporting it, or `None` to detach.
+pub(crate) fn run_command(
+    control: &SessionControl,
+    command: AttachCommand,
+) -> io::Result<Option<&'static str>> {
+    Ok(match command {
+        AttachCommand::TogglePause if control.is_paused() => {
+            control.resume()?;
+            Some("[attach] Resumed.")
+        }
+        AttachCommand::TogglePause => {
+            control.pause()?;
+            Some("[attach] Pausing before the next run; p resumes.")
+        }
+        AttachCommand::Stop => {
+            control.stop()?;
+            Some("[attach] Stopping the session.")
+        }
+        AttachCommand::Detach => None,
+    })
+}
+
+/// A session's log, read from its start as it grows.
+pub(crate) struct SessionLog<'a> {
+    store: &'a SessionStore,
+    id: &'a str,
+    log: Option<File>,
+}
+
+impl<'a> SessionLog<'a> {
+    pub(crate) fn new(store: &'a SessionStore, id: &'a str) -> Self {
+        Self {
+            store,
+            id,
+            log: None,
+        }
+    }
+
+    /// The session's status, with what its log gained since the last call in `buf`.
+    pub(crate) fn poll(&mut self, buf: &mut Vec<u8>) -> io::Result<SessionStatus> {
+        // The status first, so output written before the session ended is not missed.
+        let status = self.store.find(self.id)?.current_status();
+        if self.log.is_none() {
+            self.log = open_log(&self.store.log_path(self.id))?;
+        }
+        buf.clear();
+        if let Some(log) = &mut self.log {
+            log.read_to_end(buf)?;
+        }
+        Ok(status)
+    }
+}
+
+/// The log from its start; `None` while it does not exist yet.
+fn open_log(path: &Path) -> io::Result<Option<File>> {
+    match File::open(path) {
+        Ok(file) => Ok(Some(file)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
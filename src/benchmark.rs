@@ -0,0 +1,90 @@
+//! Running one task list against several models (`agent-loops benchmark`), each in its
+//! own worktree, and comparing how they did.
+
+use std::fmt::Write as _;
+use std::time::Duration;
+
+use crate::progress::format_run_time;
+use crate::{RunRecord, TaskSpec};
+
+/// `tasks` with their own model and binary dropped, so the benchmarked model is used.
+pub fn benchmark_tasks(tasks: &[TaskSpec]) -> Vec<TaskSpec> {
+    tasks
+        .iter()
+        .map(|task| TaskSpec {
+            model: None,
+            codex_bin: None,
+            ..task.clone()
+        })
+        .collect()
+}
+
+/// How one model did on the task list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelResult {
+    pub model: String,
+    pub runs: usize,
+    pub successes: usize,
+    /// Mean run duration; `None` without runs.
+    pub mean_duration: Option<Duration>,
+    /// Total spend of the runs that reported one.
+    pub cost_usd: Option<f64>,
+}
+
+impl ModelResult {
+    pub fn new(model: &str, results: &[RunRecord]) -> Self {
+        let total: Duration = results.iter().map(|r| r.duration).sum();
+        let costs: Vec<f64> = results.iter().filter_map(|r| r.outcome.cost_usd).collect();
+        Self {
+            model: model.to_string(),
+            runs: results.len(),
+            successes: results.iter().filter(|r| r.outcome.success).count(),
+            mean_duration: u32::try_from(results.len())
+                .ok()
+                .filter(|&n| n > 0)
+                .map(|n| total / n),
+            cost_usd: (!costs.is_empty()).then(|| costs.iter().sum()),
+        }
+    }
+
+    /// Share of the runs that succeeded, from 0 to 100.
+    pub fn success_rate(&self) -> f64 {
+        if self.runs == 0 {
+            0.0
+        } else {
+            self.successes as f64 * 100.0 / self.runs as f64
+        }
+    }
+}
+
+/// The table printed at the end of a benchmark.
+pub fn format_benchmark(results: &[ModelResult]) -> String {
+    let width = results
+        .iter()
+        .map(|r| r.model.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(5);
+    let mut out = String::from("=== Model benchmark ===\n");
+    let _ = writeln!(
+        out,
+        "  {:<width$}  {:>7} {:>5} {:>9} {:>9}",
+        "model", "ok", "rate", "mean", "cost"
+    );
+    for result in results {
+        let _ = writeln!(
+            out,
+            "  {:<width$}  {:>7} {:>4.0}% {:>9} {:>9}",
+            result.model,
+            format!("{}/{}", result.successes, result.runs),
+            result.success_rate(),
+            result
+                .mean_duration
+                .map_or_else(|| "-".to_string(), format_run_time),
+            result
+                .cost_usd
+                .map_or_else(|| "-".to_string(), |cost| format!("${cost:.2}"))
+        );
+    }
+    out
+}
@@ -0,0 +1,149 @@
+//! Skipping runs that already succeeded (`--cache`): every run gets a key hashing its
+//! work dir, the work dir's git `HEAD` and its rendered prompt, which the session store
+//! keeps with the run's result. A later run with the same key as a successful run of an
+//! earlier session is not started again, but reported as cached. Re-running a session
+//! after a partial failure then only redoes the work that did not finish.
+//!
+//! Only `HEAD` is hashed, not uncommitted changes, so a task counts as done until a new
+//! commit is made. The same prompt run again in a session, in a later loop, is a
+//! different run: the key also counts how often it has succeeded in the session.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::sessions::SessionStore;
+use crate::{AgentLoopsError, RunOptions, RunOutcome, Runner, TaskSpec, git, prompt};
+
+/// The keys of the successful runs of the stored sessions.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RunCache {
+    keys: HashSet<String>,
+}
+
+impl RunCache {
+    /// The runs that succeeded in the sessions in `store`.
+    pub fn load(store: &SessionStore) -> io::Result<Self> {
+        let keys = store
+            .list()?
+            .into_iter()
+            .flat_map(|record| record.runs)
+            .filter(|run| run.success)
+            .filter_map(|run| run.cache_key)
+            .collect();
+        Ok(Self { keys })
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.keys.contains(key)
+    }
+}
+
+/// The cache key of the `occurrence`th run (from 1) of `prompt`, as rendered, in
+/// `work_dir` at commit `head`.
+pub fn cache_key(work_dir: &Path, head: &str, prompt: &str, occurrence: usize) -> String {
+    let work_dir = work_dir.to_string_lossy();
+    let occurrence = occurrence.to_string();
+    // FNV-1a, twice with different offsets: stable across builds, unlike std's hasher.
+    let hash = |offset: u64| {
+        let parts = [
+            work_dir.as_bytes(),
+            head.as_bytes(),
+            prompt.as_bytes(),
+            occurrence.as_bytes(),
+        ];
+        parts.iter().fold(offset, |hash, part| {
+            part.iter().chain(&[0]).fold(hash, |hash, &b| {
+                (hash ^ u64::from(b)).wrapping_mul(0x100_0000_01b3)
+            })
+        })
+    };
+    format!(
+        "{:016x}{:016x}",
+        hash(0xcbf2_9ce4_8422_2325),
+        hash(0x6c62_272e_07bb_0142)
+    )
+}
+
+/// [`Runner`] that skips runs found in a [`RunCache`] and tags the others with their key
+/// for the session store. Without a cache, or outside a git repository, every run goes
+/// to the inner runner.
+pub struct CachedRunner<R> {
+    inner: R,
+    cache: Option<RunCache>,
+    /// Where the runs happen and how their prompts are rendered.
+    options: RunOptions,
+    /// Successful runs so far by the key of their first occurrence.
+    succeeded: Mutex<HashMap<String, usize>>,
+}
+
+impl<R: Runner> CachedRunner<R> {
+    pub fn new(inner: R, cache: Option<RunCache>, options: RunOptions) -> Self {
+        Self {
+            inner,
+            cache,
+            options,
+            succeeded: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The keys of the first and of the next successful run of `task`, and the `HEAD`
+    /// they are for; `None` when they cannot be told.
+    async fn keys(&self, task: &TaskSpec) -> Option<(String, String, String)> {
+        let work_dir = self.options.work_dir.as_deref().unwrap_or(Path::new("."));
+        let head = match git::git(work_dir, &["rev-parse", "HEAD"]).await {
+            Ok(head) => head,
+            Err(e) => {
+                tracing::debug!("Not caching the run: {e}");
+                return None;
+            }
+        };
+        let work_dir = fs::canonicalize(work_dir).unwrap_or_else(|_| work_dir.to_path_buf());
+        let prompt = if self.options.raw_prompts {
+            task.prompt.clone()
+        } else {
+            let wrapped = self.options.prompt_wrap.apply(&task.prompt);
+            prompt::render_prompt(&wrapped, &self.options.prompt_vars, &work_dir).ok()?
+        };
+        let first = cache_key(&work_dir, &head, &prompt, 1);
+        let succeeded = self.succeeded.lock().unwrap().get(&first).copied();
+        let next = cache_key(&work_dir, &head, &prompt, succeeded.unwrap_or(0) + 1);
+        Some((first, next, head))
+    }
+
+    fn count_success(&self, first: String) {
+        *self.succeeded.lock().unwrap().entry(first).or_default() += 1;
+    }
+}
+
+impl<R: Runner> Runner for CachedRunner<R> {
+    async fn run(&self, task: &TaskSpec) -> Result<RunOutcome, AgentLoopsError> {
+        let Some(cache) = &self.cache else {
+            return self.inner.run(task).await;
+        };
+        let Some((first, key, head)) = self.keys(task).await else {
+            return self.inner.run(task).await;
+        };
+        if cache.contains(&key) {
+            tracing::info!(
+                "Skipping the run: this prompt already succeeded at commit {}.",
+                &head[..head.len().min(12)]
+            );
+            self.count_success(first);
+            return Ok(RunOutcome {
+                success: true,
+                cached: true,
+                cache_key: Some(key),
+                ..RunOutcome::default()
+            });
+        }
+        let mut outcome = self.inner.run(task).await?;
+        if outcome.success {
+            self.count_success(first);
+        }
+        outcome.cache_key = Some(key);
+        Ok(outcome)
+    }
+}
@@ -0,0 +1,284 @@
+//! A/B comparison of prompt variants (`agent-loops compare`).
+//!
+//! Every loop runs each variant once, in alternating or shuffled order so no variant
+//! always goes first. A run counts as a success when the agent succeeds and the
+//! `--check` command passes afterwards; the work dir is then put back as it was, so
+//! every trial starts from the same tree.
+
+use std::fmt::Write as _;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use tokio::process::Command;
+
+use crate::git::Checkpoint;
+use crate::progress::format_run_time;
+use crate::stats::fisher_exact;
+use crate::{AgentLoopsError, RunOutcome, RunRecord, Runner, TaskSpec};
+
+/// Significance level the comparison summary reports against.
+const SIGNIFICANCE: f64 = 0.05;
+
+/// One prompt variant under comparison.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variant {
+    /// Shown in the summary; the file name for variants loaded from a file.
+    pub label: String,
+    pub prompt: String,
+}
+
+/// Read a variant's prompt from `path`: the whole file, surrounding whitespace trimmed.
+pub fn load_variant(path: &Path) -> io::Result<Variant> {
+    let prompt = std::fs::read_to_string(path)?.trim().to_string();
+    if prompt.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "empty prompt"));
+    }
+    let label = path.file_name().map_or_else(
+        || path.display().to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    );
+    Ok(Variant { label, prompt })
+}
+
+/// Letter naming the variant at `idx` in the summary: A, B, ...
+pub fn variant_letter(idx: usize) -> char {
+    char::from(b'A' + (idx % 26) as u8)
+}
+
+/// Variant indices in trial order, one round of all variants per loop. Without a
+/// `shuffle_seed` each round starts one variant later than the previous one.
+pub fn trial_order(variants: usize, loops: usize, shuffle_seed: Option<u64>) -> Vec<usize> {
+    let mut state = shuffle_seed.map(|seed| seed | 1);
+    let mut order = Vec::with_capacity(variants * loops);
+    for loop_idx in 0..loops {
+        let mut round: Vec<usize> = (0..variants).map(|v| (v + loop_idx) % variants).collect();
+        if let Some(state) = &mut state {
+            // Fisher-Yates with xorshift64; trial order needs no better randomness.
+            for i in (1..round.len()).rev() {
+                *state ^= *state << 13;
+                *state ^= *state >> 7;
+                *state ^= *state << 17;
+                round.swap(i, (*state % (i as u64 + 1)) as usize);
+            }
+        }
+        order.extend(round);
+    }
+    order
+}
+
+/// Tasks for [`trial_order`]: each variant's prompt, named after the variant.
+pub fn trial_tasks(variants: &[Variant], order: &[usize]) -> Vec<TaskSpec> {
+    order
+        .iter()
+        .map(|&idx| TaskSpec {
+            name: Some(format!("{} {}", variant_letter(idx), variants[idx].label)),
+            ..TaskSpec::new(variants[idx].prompt.clone())
+        })
+        .collect()
+}
+
+/// [`Runner`] that judges each run with a check command and then resets the work dir.
+pub struct CheckedRunner<R> {
+    inner: R,
+    check: Option<String>,
+    work_dir: PathBuf,
+    reset: bool,
+}
+
+impl<R: Runner> CheckedRunner<R> {
+    pub fn new(inner: R, check: Option<String>, work_dir: PathBuf) -> Self {
+        Self {
+            inner,
+            check,
+            work_dir,
+            reset: true,
+        }
+    }
+
+    /// Leave each run's changes in the work dir instead of resetting it.
+    pub fn keep_changes(mut self) -> Self {
+        self.reset = false;
+        self
+    }
+
+    /// Run the check command in the work dir; a check that cannot be started fails.
+    async fn passes_check(&self, check: &str) -> bool {
+        #[cfg(windows)]
+        let mut cmd = {
+            let mut cmd = Command::new("cmd");
+            cmd.args(["/C", check]);
+            cmd
+        };
+        #[cfg(not(windows))]
+        let mut cmd = {
+            let mut cmd = Command::new("sh");
+            cmd.args(["-c", check]);
+            cmd
+        };
+        let output = match cmd
+            .current_dir(&self.work_dir)
+            .stdin(Stdio::null())
+            .output()
+            .await
+        {
+            Ok(output) => output,
+            Err(e) => {
+                tracing::warn!("Cannot run the check `{check}`: {e}");
+                return false;
+            }
+        };
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let tail: Vec<&str> = stderr.lines().rev().take(5).collect();
+            tracing::warn!(
+                "Check `{check}` failed ({}){}",
+                output.status,
+                tail.iter()
+                    .rev()
+                    .map(|line| format!("\n  {line}"))
+                    .collect::<String>()
+            );
+        }
+        output.status.success()
+    }
+}
+
+impl<R: Runner> Runner for CheckedRunner<R> {
+    async fn run(&self, task: &TaskSpec) -> Result<RunOutcome, AgentLoopsError> {
+        let checkpoint = if self.reset {
+            match Checkpoint::create(&self.work_dir).await {
+                Ok(checkpoint) => Some(checkpoint),
+                Err(e) => {
+                    tracing::warn!("Cannot checkpoint the work dir, it will not be reset: {e}");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let mut outcome = self.inner.run(task).await;
+        if let (Ok(outcome), Some(check)) = (&mut outcome, &self.check)
+            && outcome.success
+        {
+            let passed = self.passes_check(check).await;
+            outcome.success = passed;
+            outcome.check_passed = Some(passed);
+        }
+        if let Some(checkpoint) = checkpoint
+            && let Err(e) = checkpoint.restore().await
+        {
+            tracing::error!("Cannot reset the work dir after the run: {e}");
+        }
+        outcome
+    }
+}
+
+/// How one variant did over its trials.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariantResult {
+    pub label: String,
+    pub trials: usize,
+    pub successes: usize,
+    /// Mean run duration; `None` without trials.
+    pub mean_duration: Option<Duration>,
+}
+
+impl VariantResult {
+    /// Share of the trials that succeeded, from 0 to 100.
+    pub fn success_rate(&self) -> f64 {
+        if self.trials == 0 {
+            0.0
+        } else {
+            self.successes as f64 * 100.0 / self.trials as f64
+        }
+    }
+}
+
+/// Per-variant results from the session's `results`, given the [`trial_order`] it ran.
+pub fn variant_results(
+    variants: &[Variant],
+    order: &[usize],
+    results: &[RunRecord],
+) -> Vec<VariantResult> {
+    variants
+        .iter()
+        .enumerate()
+        .map(|(idx, variant)| {
+            let trials: Vec<&RunRecord> = results
+                .iter()
+                .filter(|r| order.get(r.task_idx) == Some(&idx))
+                .collect();
+            let total: Duration = trials.iter().map(|r| r.duration).sum();
+            VariantResult {
+                label: variant.label.clone(),
+                trials: trials.len(),
+                successes: trials.iter().filter(|r| r.outcome.success).count(),
+                mean_duration: u32::try_from(trials.len())
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .map(|n| total / n),
+            }
+        })
+        .collect()
+}
+
+/// The comparison summary: each variant's success rate, then the winner and the
+/// two-sided Fisher's exact test p-value against the runner-up.
+pub fn format_comparison(results: &[VariantResult]) -> String {
+    let width = results
+        .iter()
+        .map(|r| r.label.chars().count())
+        .max()
+        .unwrap_or(0);
+    let mut out = String::from("=== Comparison ===\n");
+    for (idx, result) in results.iter().enumerate() {
+        let _ = writeln!(
+            out,
+            "  {} {:<width$}  {}/{} ({:.0}%)  mean {}",
+            variant_letter(idx),
+            result.label,
+            result.successes,
+            result.trials,
+            result.success_rate(),
+            result
+                .mean_duration
+                .map_or_else(|| "-".to_string(), format_run_time)
+        );
+    }
+    let mut ranked: Vec<usize> = (0..results.len()).collect();
+    ranked.sort_by(|&a, &b| {
+        results[b]
+            .success_rate()
+            .total_cmp(&results[a].success_rate())
+    });
+    let [best, runner_up, ..] = ranked[..] else {
+        return out;
+    };
+    let (a, b) = (&results[best], &results[runner_up]);
+    let p = fisher_exact(a.successes, a.trials, b.successes, b.trials);
+    if a.success_rate() == b.success_rate() {
+        let _ = writeln!(
+            out,
+            "No winner: the best variants tie at {:.0}%.",
+            a.success_rate()
+        );
+    } else {
+        let _ = writeln!(
+            out,
+            "Winner: {} ({}), {:.0}% vs {:.0}% for {}; Fisher's exact p = {p:.3} ({} at {SIGNIFICANCE})",
+            variant_letter(best),
+            a.label,
+            a.success_rate(),
+            b.success_rate(),
+            variant_letter(runner_up),
+            if p < SIGNIFICANCE {
+                "significant"
+            } else {
+                "not significant"
+            }
+        );
+    }
+    out
+}
@@ -0,0 +1,178 @@
+//! The per-repository config file, `agent-loops.toml`, read from the current directory
+//! (or `--config FILE`) and written by `agent-loops init`.
+//!
+//! Top-level keys are the long command-line options without their dashes; `_` may stand
+//! for `-`. Options given on the command line or through their `AGENT_LOOPS_*`
+//! environment variables win over the file. `[[task]]` tables, as in a task file, make the
+//! task list when no prompts are given otherwise.
+//!
+//! ```toml
+//! loops = 3
+//! check = "cargo test"
+//! require-clean = true
+//!
+//! [[task]]
+//! prompt = "Fix all clippy warnings"
+//! ```
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use toml::{Table, Value};
+
+use crate::tasks::{TaskSpec, parse_task_file};
+
+/// File name of the config file looked for in the current directory.
+pub const CONFIG_FILE: &str = "agent-loops.toml";
+
+/// A parsed config file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    /// Option values by key, everything but the tasks.
+    pub options: Table,
+    pub tasks: Vec<TaskSpec>,
+}
+
+/// Parse a config file's contents.
+pub fn parse_config(content: &str) -> io::Result<Config> {
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+    let mut options: Table = toml::from_str(content).map_err(|e| invalid(e.to_string()))?;
+    let tasks = match options.remove("task") {
+        Some(task) => {
+            let mut file = Table::new();
+            file.insert("task".to_string(), task);
+            parse_task_file(&file.to_string())?
+        }
+        None => Vec::new(),
+    };
+    Ok(Config { options, tasks })
+}
+
+pub fn load_config(path: &Path) -> io::Result<Config> {
+    parse_config(&fs::read_to_string(path)?)
+}
+
+/// Command-line arguments for `options`, to go before the real ones. Keys whose option
+/// `given` says is already set, on the command line or in the environment, are left out.
+pub fn config_args(
+    command: &clap::Command,
+    options: &Table,
+    given: impl Fn(&str) -> bool,
+) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    for (key, value) in options {
+        let long = key.replace('_', "-");
+        let arg = command
+            .get_arguments()
+            .find(|arg| {
+                arg.get_long() == Some(long.as_str())
+                    && !arg.is_hide_set()
+                    && arg.get_id() != "config"
+                    && !matches!(
+                        arg.get_action(),
+                        clap::ArgAction::Help | clap::ArgAction::Version
+                    )
+            })
+            .ok_or_else(|| format!("unknown option `{key}`"))?;
+        if given(arg.get_id().as_str()) {
+            continue;
+        }
+        let flag = format!("--{long}");
+        let values = match value {
+            Value::Array(values) => values.clone(),
+            Value::Table(table) => table_values(arg, table),
+            value => vec![value.clone()],
+        };
+        for value in &values {
+            let takes_values = arg.get_action().takes_values();
+            match (value, arg.get_action()) {
+                (Value::Integer(count @ 0..), clap::ArgAction::Count) => {
+                    args.extend(std::iter::repeat_n(flag.clone(), *count as usize));
+                }
+                (_, clap::ArgAction::Count) => {
+                    return Err(format!("`{key}` takes {}", describe(arg)));
+                }
+                (Value::Boolean(true), _) if !takes_values => args.push(flag.clone()),
+                (Value::Boolean(false), _) if !takes_values => {}
+                (Value::String(text), _) if takes_values => args.push(format!("{flag}={text}")),
+                (Value::Integer(_) | Value::Float(_), _) if takes_values => {
+                    args.push(format!("{flag}={value}"))
+                }
+                _ => return Err(format!("`{key}` takes {}", describe(arg))),
+            }
+        }
+    }
+    Ok(args)
+}
+
+/// The values of a table for `arg`, e.g. `[env]`: one `KEY=VALUE` per entry, or all
+/// of them in one value for options taking a comma-separated list, e.g. `[theme]`.
+fn table_values(arg: &clap::Arg, table: &Table) -> Vec<Value> {
+    let pairs = table.iter().map(|(key, value)| match value {
+        Value::String(text) => format!("{key}={text}"),
+        value => format!("{key}={value}"),
+    });
+    let list = arg
+        .get_value_names()
+        .is_some_and(|names| names.iter().any(|name| name.ends_with(",...")));
+    if list {
+        vec![Value::String(pairs.collect::<Vec<_>>().join(","))]
+    } else {
+        pairs.map(Value::String).collect()
+    }
+}
+
+fn describe(arg: &clap::Arg) -> &'static str {
+    match arg.get_action() {
+        clap::ArgAction::Count => "a count",
+        action if action.takes_values() => "a string or number, a list of them, or a table",
+        _ => "true or false",
+    }
+}
+
+/// What `agent-loops init` asks for.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Starter {
+    pub prompts: Vec<String>,
+    pub loops: usize,
+    /// Command judging each run, for `check`.
+    pub check: Option<String>,
+    pub require_clean: bool,
+    /// `session`, `loop` or `task`, for `git-branch-strategy`.
+    pub git_branch_strategy: Option<String>,
+}
+
+impl Starter {
+    /// The config file's contents, with the options it leaves out mentioned in comments.
+    pub fn to_toml(&self) -> String {
+        let quote = |text: &str| Value::String(text.to_string()).to_string();
+        let mut out = String::from(
+            "# agent-loops settings for this repository. Keys are the command-line options\n\
+             # without their dashes; options given on the command line win.\n\n",
+        );
+        out.push_str("# Times to go through the task list.\n");
+        out.push_str(&format!("loops = {}\n\n", self.loops.max(1)));
+        out.push_str(
+            "# Command run after each successful run; the run only counts when it passes.\n",
+        );
+        match &self.check {
+            Some(check) => out.push_str(&format!("check = {}\n\n", quote(check))),
+            None => out.push_str("# check = \"cargo test\"\n\n"),
+        }
+        out.push_str("# Refuse to start when the work tree has uncommitted changes.\n");
+        out.push_str(&format!("require-clean = {}\n\n", self.require_clean));
+        out.push_str("# Work on new agent-loops/<date>/... branches: per session, loop or task.\n");
+        match &self.git_branch_strategy {
+            Some(strategy) => out.push_str(&format!("git-branch-strategy = {}\n", quote(strategy))),
+            None => out.push_str("# git-branch-strategy = \"session\"\n"),
+        }
+        if self.prompts.is_empty() {
+            out.push_str("\n# [[task]]\n# prompt = \"Fix all clippy warnings\"\n");
+        }
+        for prompt in &self.prompts {
+            out.push_str(&format!("\n[[task]]\nprompt = {}\n", quote(prompt)));
+        }
+        out
+    }
+}
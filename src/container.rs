@@ -0,0 +1,110 @@
+//! Running each agent invocation inside a container (`--in-container IMAGE`), so an agent
+//! with every approval bypassed can only change the work dir. The container engine's
+//! client is the child process: its output goes through the usual pipeline, and a killed
+//! run also removes its container.
+
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::RunOptions;
+
+/// Containers started by this process, for unique names.
+static STARTED: AtomicUsize = AtomicUsize::new(0);
+
+/// The image agent runs use and the engine that runs them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Container {
+    pub image: String,
+    /// `docker`, or a command-line compatible engine such as `podman`.
+    pub engine: String,
+    /// Variables passed on from our environment, such as the agent's API key.
+    pub pass_env: Vec<String>,
+}
+
+impl Container {
+    pub fn new(image: &str) -> Self {
+        Self {
+            image: image.to_string(),
+            engine: "docker".to_string(),
+            pass_env: Vec::new(),
+        }
+    }
+
+    /// A name for the next run's container, unique among this session's runs.
+    pub fn next_name() -> String {
+        format!(
+            "agent-loops-{}-{}",
+            std::process::id(),
+            STARTED.fetch_add(1, Ordering::Relaxed) + 1
+        )
+    }
+
+    /// Engine arguments running `program` with `args` in a container called `name`.
+    ///
+    /// The work dir (the current dir without one) is mounted at the same path and the
+    /// command starts in it, so paths in prompts and output mean the same inside. Only
+    /// [`Container::pass_env`], the allow-listed variables and `options.env` reach the
+    /// container, and on unix it runs as the current user so files it creates stay ours.
+    pub fn run_args(
+        &self,
+        name: &str,
+        program: &str,
+        args: &[String],
+        options: &RunOptions,
+    ) -> Vec<String> {
+        let work_dir = options
+            .work_dir
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("."));
+        let work_dir = std::path::absolute(&work_dir).unwrap_or(work_dir);
+        let work_dir = work_dir.to_string_lossy();
+        let mut run = vec![
+            "run".to_string(),
+            "--rm".to_string(),
+            "--init".to_string(),
+            "--name".to_string(),
+            name.to_string(),
+            "--volume".to_string(),
+            format!("{work_dir}:{work_dir}"),
+            "--workdir".to_string(),
+            work_dir.into_owned(),
+        ];
+        #[cfg(unix)]
+        {
+            // SAFETY: getuid and getgid cannot fail.
+            let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+            run.extend(["--user".to_string(), format!("{uid}:{gid}")]);
+        }
+        for name in self
+            .pass_env
+            .iter()
+            .chain(options.env_allowlist.iter().flatten())
+        {
+            // A bare name passes the variable on from the engine client's environment.
+            run.extend(["--env".to_string(), name.clone()]);
+        }
+        for (name, value) in &options.env {
+            run.extend(["--env".to_string(), format!("{name}={value}")]);
+        }
+        run.push(self.image.clone());
+        run.push(program.to_string());
+        run.extend(args.iter().cloned());
+        run
+    }
+
+    /// Remove the container `name` if it is still there, as after a killed run; the
+    /// engine client dying does not stop it.
+    pub(crate) async fn remove(&self, name: &str) {
+        let removed = tokio::process::Command::new(&self.engine)
+            .args(["rm", "--force", name])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await;
+        if let Err(e) = removed {
+            tracing::warn!("Cannot remove container {name}: {e}");
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Keeping a session from filling the disk (`--min-free-space`): before each run the
+//! work dir and the log dir must have a minimum of free space, or the session pauses
+//! until there is room again or stops.
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::CancelToken;
+
+/// How often a paused session checks the free space again.
+const RECHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Bytes available to us on the file system holding `path`.
+pub fn free_space(path: &Path) -> io::Result<u64> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = std::ffi::CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        // SAFETY: `path` is NUL-terminated and `stat` is written before it is read.
+        let stat = unsafe {
+            let mut stat: libc::statvfs = std::mem::zeroed();
+            if libc::statvfs(path.as_ptr(), &mut stat) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            stat
+        };
+        // Both are narrower than u64 on some targets.
+        #[allow(clippy::useless_conversion)]
+        let free = u64::from(stat.f_bavail).saturating_mul(u64::from(stat.f_frsize));
+        Ok(free)
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStrExt;
+        use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+        let wide: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+        let mut available = 0u64;
+        // SAFETY: `wide` is NUL-terminated and the out pointers are valid or null.
+        let ok = unsafe {
+            GetDiskFreeSpaceExW(
+                wide.as_ptr(),
+                &mut available,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            )
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(available)
+    }
+}
+
+/// `1.5 GiB`, `300.0 MiB`, `512 B`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+/// What the session does when a watched dir runs low on space (`--on-low-disk`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LowDiskAction {
+    /// Stop the session before the next run.
+    #[default]
+    Abort,
+    /// Wait, checking again every minute, until there is room.
+    Pause,
+}
+
+impl fmt::Display for LowDiskAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Abort => "abort",
+            Self::Pause => "pause",
+        })
+    }
+}
+
+impl std::str::FromStr for LowDiskAction {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "abort" => Ok(Self::Abort),
+            "pause" => Ok(Self::Pause),
+            _ => Err(format!(
+                "unknown low-disk action `{text}` (expected abort or pause)"
+            )),
+        }
+    }
+}
+
+/// Free space every run needs in the watched dirs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiskGuard {
+    /// Bytes that must stay free.
+    pub min_free: u64,
+    /// Dirs whose file systems are checked, e.g. the work dir and the log dir.
+    pub dirs: Vec<PathBuf>,
+    pub action: LowDiskAction,
+}
+
+impl DiskGuard {
+    /// The first watched dir with less than `min_free` bytes free, with its free space.
+    /// A dir not created yet is checked through its closest existing parent; dirs whose
+    /// free space cannot be read are skipped.
+    pub fn low_dir(&self) -> Option<(&Path, u64)> {
+        self.dirs.iter().find_map(|dir| {
+            let probe = dir.ancestors().find(|p| p.exists()).unwrap_or(dir);
+            match free_space(probe) {
+                Ok(free) if free < self.min_free => Some((dir.as_path(), free)),
+                Ok(_) => None,
+                Err(e) => {
+                    tracing::debug!("Cannot read the free space of {}: {e}", dir.display());
+                    None
+                }
+            }
+        })
+    }
+
+    /// Wait until every watched dir has room, as [`DiskGuard::action`] says. Returns
+    /// `false` when the session should stop instead.
+    pub async fn wait_for_space(&self, cancel: &CancelToken) -> bool {
+        let mut paused = false;
+        while let Some((dir, free)) = self.low_dir() {
+            let message = format!(
+                "Only {} free in {}, below the --min-free-space of {}",
+                format_bytes(free),
+                dir.display(),
+                format_bytes(self.min_free)
+            );
+            if self.action == LowDiskAction::Abort {
+                tracing::error!("{message}; stopping the session.");
+                return false;
+            }
+            if !paused {
+                tracing::warn!("{message}; pausing until space is freed.");
+                paused = true;
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(RECHECK_INTERVAL) => {}
+                _ = cancel.cancelled() => return false,
+            }
+        }
+        if paused {
+            tracing::info!("Enough disk space again; resuming the session.");
+        }
+        true
+    }
+}
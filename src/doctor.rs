@@ -0,0 +1,256 @@
+//! Preflight checks behind `agent-loops doctor`.
+
+use std::fmt;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use crate::launch;
+use crate::version::{self, Version, VersionReq};
+
+/// Outcome of a single check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Pass,
+    /// Usable, but likely to degrade the session.
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CheckStatus::Pass => "PASS",
+            CheckStatus::Warn => "WARN",
+            CheckStatus::Fail => "FAIL",
+        })
+    }
+}
+
+/// One line of the doctor checklist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+impl CheckResult {
+    fn new(name: &'static str, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// What `doctor` inspects; mirrors the options a session would run with.
+#[derive(Debug, Clone)]
+pub struct DoctorOptions {
+    pub codex_bin: String,
+    pub work_dir: Option<PathBuf>,
+    pub prompts_file: Option<PathBuf>,
+    pub version_req: VersionReq,
+}
+
+impl Default for DoctorOptions {
+    fn default() -> Self {
+        Self {
+            codex_bin: "codex".to_string(),
+            work_dir: None,
+            prompts_file: None,
+            version_req: VersionReq::at_least(version::MIN_CODEX_VERSION),
+        }
+    }
+}
+
+/// Run every check in checklist order.
+pub async fn run_doctor(options: &DoctorOptions) -> Vec<CheckResult> {
+    let mut checks = Vec::new();
+
+    match launch::resolve_executable(&options.codex_bin) {
+        Some(path) => {
+            checks.push(CheckResult::new(
+                "agent binary",
+                CheckStatus::Pass,
+                path.display().to_string(),
+            ));
+            checks.push(check_version(&path, &options.version_req).await);
+        }
+        None => {
+            checks.push(CheckResult::new(
+                "agent binary",
+                CheckStatus::Fail,
+                format!(
+                    "`{}` not found.\n{}",
+                    options.codex_bin,
+                    launch::not_found_diagnostic(&options.codex_bin)
+                ),
+            ));
+            checks.push(CheckResult::new(
+                "agent version",
+                CheckStatus::Fail,
+                "skipped: agent binary not found",
+            ));
+        }
+    }
+
+    let work_dir = match &options.work_dir {
+        Some(dir) => dir.clone(),
+        None => std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+    };
+    checks.extend(check_work_dir(&work_dir).await);
+
+    if let Some(path) = &options.prompts_file {
+        checks.push(check_prompts_file(path));
+    }
+
+    checks.push(check_terminal());
+    checks
+}
+
+/// Render the checklist, one `[STATUS] name: detail` entry per check.
+pub fn format_checklist(checks: &[CheckResult]) -> String {
+    let mut text = String::new();
+    for check in checks {
+        let mut lines = check.detail.lines();
+        text.push_str(&format!(
+            "[{}] {}: {}\n",
+            check.status,
+            check.name,
+            lines.next().unwrap_or_default()
+        ));
+        for line in lines {
+            text.push_str(&format!("       {line}\n"));
+        }
+    }
+    text
+}
+
+async fn check_version(program: &Path, req: &VersionReq) -> CheckResult {
+    const NAME: &str = "agent version";
+    let output = match version::query_version_output(program).await {
+        Ok(output) => output,
+        Err(e) => {
+            return CheckResult::new(NAME, CheckStatus::Fail, format!("failed to run: {e}"));
+        }
+    };
+    match Version::find_in(&output) {
+        Some(found) if !req.matches(found) => CheckResult::new(
+            NAME,
+            CheckStatus::Fail,
+            format!("{found} does not satisfy {req}"),
+        ),
+        Some(found) => CheckResult::new(NAME, CheckStatus::Pass, found.to_string()),
+        None => CheckResult::new(
+            NAME,
+            CheckStatus::Warn,
+            format!("could not parse a version from `{output}`"),
+        ),
+    }
+}
+
+async fn check_work_dir(dir: &Path) -> Vec<CheckResult> {
+    if !dir.is_dir() {
+        return vec![CheckResult::new(
+            "work dir",
+            CheckStatus::Fail,
+            format!("{} is not a directory", dir.display()),
+        )];
+    }
+
+    let probe = dir.join(format!(".agent-loops-doctor-{}", std::process::id()));
+    let writable = match std::fs::write(&probe, b"") {
+        Ok(()) => {
+            let _ = std::fs::remove_file(&probe);
+            CheckResult::new("work dir", CheckStatus::Pass, dir.display().to_string())
+        }
+        Err(e) => CheckResult::new(
+            "work dir",
+            CheckStatus::Fail,
+            format!("{} is not writable: {e}", dir.display()),
+        ),
+    };
+
+    let inside_git = tokio::process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["rev-parse", "--is-inside-work-tree"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .await;
+    let git = match inside_git {
+        Ok(output) if output.status.success() => {
+            CheckResult::new("git repository", CheckStatus::Pass, "inside a work tree")
+        }
+        Ok(_) => CheckResult::new(
+            "git repository",
+            CheckStatus::Fail,
+            "not inside a git work tree; codex refuses to run here",
+        ),
+        Err(e) => CheckResult::new(
+            "git repository",
+            CheckStatus::Warn,
+            format!("could not run git: {e}"),
+        ),
+    };
+    vec![writable, git]
+}
+
+fn check_prompts_file(path: &Path) -> CheckResult {
+    const NAME: &str = "prompts file";
+    match crate::load_tasks(path) {
+        Ok(tasks) => {
+            let count = tasks.len();
+            if count == 0 {
+                CheckResult::new(
+                    NAME,
+                    CheckStatus::Fail,
+                    format!("{} has no prompts", path.display()),
+                )
+            } else {
+                CheckResult::new(
+                    NAME,
+                    CheckStatus::Pass,
+                    format!("{count} prompt(s) in {}", path.display()),
+                )
+            }
+        }
+        Err(e) => CheckResult::new(
+            NAME,
+            CheckStatus::Fail,
+            format!("cannot read {}: {e}", path.display()),
+        ),
+    }
+}
+
+fn check_terminal() -> CheckResult {
+    const NAME: &str = "terminal";
+    if !std::io::stdout().is_terminal() {
+        return CheckResult::new(
+            NAME,
+            CheckStatus::Warn,
+            "stdout is not a terminal; output is streamed without the live view",
+        );
+    }
+    let term = std::env::var("TERM").unwrap_or_default();
+    if term == "dumb" {
+        return CheckResult::new(
+            NAME,
+            CheckStatus::Warn,
+            "TERM=dumb; the live view needs cursor positioning",
+        );
+    }
+    CheckResult::new(
+        NAME,
+        CheckStatus::Pass,
+        format!(
+            "{}x{}, TERM={}",
+            crate::terminal_cols(),
+            crate::terminal_rows(),
+            if term.is_empty() { "(unset)" } else { &term }
+        ),
+    )
+}
@@ -0,0 +1,123 @@
+//! Normalizing child output to UTF-8.
+//!
+//! On Windows, tools launched by the agent may write in the console's OEM codepage
+//! rather than UTF-8. Chunks that are not valid UTF-8 are converted from that
+//! codepage before they reach the renderer.
+
+/// Converts a byte stream to UTF-8, keeping multibyte sequences split across chunks intact.
+pub struct OutputDecoder {
+    pending: Vec<u8>,
+    fallback: fn(&[u8]) -> String,
+}
+
+impl OutputDecoder {
+    /// Decoder for child console output: non-UTF-8 text is read in the OEM codepage on
+    /// Windows and lossily elsewhere.
+    pub fn for_console() -> Self {
+        Self::with_fallback(decode_console_bytes)
+    }
+
+    /// Decoder that hands chunks which are not valid UTF-8 to `fallback`.
+    pub fn with_fallback(fallback: fn(&[u8]) -> String) -> Self {
+        Self {
+            pending: Vec::new(),
+            fallback,
+        }
+    }
+
+    /// Accept a chunk and return the UTF-8 bytes that can be emitted now.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<u8> {
+        self.pending.extend_from_slice(chunk);
+        match std::str::from_utf8(&self.pending) {
+            Ok(_) => std::mem::take(&mut self.pending),
+            // Only an incomplete sequence at the very end: hold it for the next chunk.
+            Err(e) if e.error_len().is_none() => {
+                let tail = self.pending.split_off(e.valid_up_to());
+                std::mem::replace(&mut self.pending, tail)
+            }
+            Err(_) => (self.fallback)(&std::mem::take(&mut self.pending)).into_bytes(),
+        }
+    }
+
+    /// Return whatever is still buffered.
+    pub fn finish(&mut self) -> Vec<u8> {
+        let rest = std::mem::take(&mut self.pending);
+        match String::from_utf8(rest) {
+            Ok(text) => text.into_bytes(),
+            Err(e) => (self.fallback)(e.as_bytes()).into_bytes(),
+        }
+    }
+}
+
+#[cfg(not(windows))]
+fn decode_console_bytes(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+#[cfg(windows)]
+fn decode_console_bytes(bytes: &[u8]) -> String {
+    use windows_sys::Win32::Globalization::{GetOEMCP, MultiByteToWideChar};
+
+    let Ok(len) = i32::try_from(bytes.len()) else {
+        return String::from_utf8_lossy(bytes).into_owned();
+    };
+    // SAFETY: the buffers passed are valid for the lengths given.
+    unsafe {
+        let codepage = GetOEMCP();
+        let wide_len =
+            MultiByteToWideChar(codepage, 0, bytes.as_ptr(), len, std::ptr::null_mut(), 0);
+        if wide_len <= 0 {
+            return String::from_utf8_lossy(bytes).into_owned();
+        }
+        let mut wide = vec![0_u16; wide_len as usize];
+        let written = MultiByteToWideChar(
+            codepage,
+            0,
+            bytes.as_ptr(),
+            len,
+            wide.as_mut_ptr(),
+            wide_len,
+        );
+        wide.truncate(written.max(0) as usize);
+        String::from_utf16_lossy(&wide)
+    }
+}
+
+/// Restores the console output codepage when dropped.
+pub struct ConsoleCodepageGuard {
+    #[cfg(windows)]
+    previous: u32,
+}
+
+/// Switch the console to UTF-8 for the session so child tools attached to it emit UTF-8.
+/// Returns `None` when there is nothing to change (non-Windows, or already UTF-8).
+pub fn force_utf8_console() -> Option<ConsoleCodepageGuard> {
+    #[cfg(windows)]
+    {
+        use windows_sys::Win32::System::Console::{GetConsoleOutputCP, SetConsoleOutputCP};
+        const CP_UTF8: u32 = 65001;
+
+        // SAFETY: plain console API calls without pointers.
+        unsafe {
+            let previous = GetConsoleOutputCP();
+            if previous == 0 || previous == CP_UTF8 || SetConsoleOutputCP(CP_UTF8) == 0 {
+                return None;
+            }
+            Some(ConsoleCodepageGuard { previous })
+        }
+    }
+    #[cfg(not(windows))]
+    {
+        None
+    }
+}
+
+impl Drop for ConsoleCodepageGuard {
+    fn drop(&mut self) {
+        #[cfg(windows)]
+        // SAFETY: restoring the codepage captured in `force_utf8_console`.
+        unsafe {
+            windows_sys::Win32::System::Console::SetConsoleOutputCP(self.previous);
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//! Error type for runs and runners.
+
+use std::io;
+use std::time::Duration;
+
+/// Why a run ended without an exit status from the agent.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum AgentLoopsError {
+    /// The agent binary was not found, directly or through the shell fallback.
+    #[error("could not execute `{program}`; {detail}")]
+    BinaryNotFound { program: String, detail: String },
+    /// The agent binary exists but could not be started.
+    #[error("failed to start `{program}`: {source}")]
+    SpawnFailed {
+        program: String,
+        #[source]
+        source: io::Error,
+    },
+    /// The run exceeded its timeout and its process tree was killed.
+    #[error("run exceeded timeout of {}s and was killed", .0.as_secs())]
+    Timeout(Duration),
+    /// Writing the live view, plain output or spool file failed.
+    #[error("failed to render output: {0}")]
+    RenderError(#[source] io::Error),
+    /// Reading from or waiting on the child failed.
+    #[error("{0}")]
+    ChildIo(#[source] io::Error),
+    /// The prompt could not be put together, e.g. a `{{file:...}}` placeholder names a
+    /// missing file.
+    #[error("cannot render the prompt: {0}")]
+    Prompt(String),
+    /// The run was aborted through its [`CancelToken`](crate::CancelToken).
+    #[error("run cancelled")]
+    Cancelled,
+}
+
+impl From<io::Error> for AgentLoopsError {
+    /// `Interrupted` becomes [`AgentLoopsError::Cancelled`]; anything else is child I/O.
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::Interrupted => Self::Cancelled,
+            _ => Self::ChildIo(err),
+        }
+    }
+}
+
+impl From<AgentLoopsError> for io::Error {
+    fn from(err: AgentLoopsError) -> Self {
+        let kind = match &err {
+            AgentLoopsError::BinaryNotFound { .. } => io::ErrorKind::NotFound,
+            AgentLoopsError::SpawnFailed { source, .. } => source.kind(),
+            AgentLoopsError::Timeout(_) => io::ErrorKind::TimedOut,
+            AgentLoopsError::RenderError(source) | AgentLoopsError::ChildIo(source) => {
+                source.kind()
+            }
+            AgentLoopsError::Prompt(_) => io::ErrorKind::InvalidInput,
+            AgentLoopsError::Cancelled => io::ErrorKind::Interrupted,
+        };
+        io::Error::new(kind, err)
+    }
+}
@@ -0,0 +1,119 @@
+//! Hiding forwarded output lines by regex (`--grep`, `--grep-v`).
+//!
+//! Filtering only affects what is displayed: the run's full output log, the scanner and
+//! recorded fixtures still see every line.
+
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+use crate::scan::ansi_escape_pattern;
+
+/// Flush a partial line anyway once it grows past this many bytes.
+const MAX_PENDING_BYTES: usize = 16 * 1024;
+
+/// Which output lines are displayed.
+#[derive(Debug, Clone, Default)]
+pub struct LineFilter {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+}
+
+impl LineFilter {
+    /// A filter that shows every line.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Show only lines matching `pattern` (or another include pattern).
+    pub fn with_include(mut self, pattern: &str) -> Result<Self, regex::Error> {
+        self.include.push(Regex::new(pattern)?);
+        Ok(self)
+    }
+
+    /// Hide lines matching `pattern`, even if an include pattern matches them.
+    pub fn with_exclude(mut self, pattern: &str) -> Result<Self, regex::Error> {
+        self.exclude.push(Regex::new(pattern)?);
+        Ok(self)
+    }
+
+    /// Whether any pattern is configured.
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Whether `line`, as shown on a terminal, is displayed.
+    pub fn shows(&self, line: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|re| re.is_match(line)))
+            && !self.exclude.iter().any(|re| re.is_match(line))
+    }
+}
+
+/// Text a terminal would end up showing for a raw line: escape sequences removed and
+/// only what follows the last carriage return (or move to the first column), as progress
+/// bars and spinners redraw that way.
+pub(crate) fn visible_text(line: &[u8]) -> String {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let rewind = line.iter().rposition(|&b| b == b'\r').map(|idx| idx + 1);
+    let column_rewind = column_rewind_pattern()
+        .find_iter(line)
+        .last()
+        .map(|m| m.end());
+    let line = &line[rewind.max(column_rewind).unwrap_or(0)..];
+    ansi_escape_pattern()
+        .replace_all(&String::from_utf8_lossy(line), "")
+        .into_owned()
+}
+
+/// Cursor to the first column (`CSI G`, `CSI 1G`).
+fn column_rewind_pattern() -> &'static regex::bytes::Regex {
+    static PATTERN: OnceLock<regex::bytes::Regex> = OnceLock::new();
+    PATTERN
+        .get_or_init(|| regex::bytes::Regex::new(r"\x1b\[[01]?G").expect("column pattern is valid"))
+}
+
+/// Line-buffers one output stream so whole lines can be kept or dropped.
+pub(crate) struct StreamFilter<'a> {
+    filter: &'a LineFilter,
+    pending: Vec<u8>,
+}
+
+impl<'a> StreamFilter<'a> {
+    pub(crate) fn new(filter: &'a LineFilter) -> Self {
+        Self {
+            filter,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Accept a chunk and return the shown lines that are complete now.
+    pub(crate) fn push(&mut self, chunk: &[u8]) -> Vec<u8> {
+        if self.filter.is_empty() {
+            return chunk.to_vec();
+        }
+        self.pending.extend_from_slice(chunk);
+        let split = match self.pending.iter().rposition(|&b| b == b'\n') {
+            Some(idx) => idx + 1,
+            None if self.pending.len() > MAX_PENDING_BYTES => self.pending.len(),
+            None => return Vec::new(),
+        };
+        let ready: Vec<u8> = self.pending.drain(..split).collect();
+        self.keep_shown(&ready)
+    }
+
+    /// Return the buffered partial line, if it is shown.
+    pub(crate) fn finish(&mut self) -> Vec<u8> {
+        let rest = std::mem::take(&mut self.pending);
+        self.keep_shown(&rest)
+    }
+
+    fn keep_shown(&self, lines: &[u8]) -> Vec<u8> {
+        lines
+            .split_inclusive(|&b| b == b'\n')
+            .filter(|line| self.filter.shows(&visible_text(line)))
+            .flatten()
+            .copied()
+            .collect()
+    }
+}
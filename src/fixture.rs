@@ -0,0 +1,264 @@
+//! Recorded agent runs (`--record-fixtures`) for replay without a live agent.
+//!
+//! A fixture holds the raw bytes a child wrote to stdout and stderr, when each chunk
+//! arrived and how the child exited. It is a text file, one event per line, with bytes
+//! outside printable ASCII escaped:
+//!
+//! ```text
+//! # agent-loops fixture
+//! command codex exec --dangerously-bypass-approvals-and-sandbox fix\x20the\x20tests
+//! stdout 12 Working...\n
+//! stderr 40 warning:\x20slow\x20disk\n
+//! exit 0
+//! ```
+//!
+//! Fixtures recorded into a directory are numbered in run order, which is the order
+//! [`FixtureRunner`](crate::testing::FixtureRunner) plays them back in.
+
+use std::fmt::Write as _;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const HEADER: &str = "# agent-loops fixture";
+const EXTENSION: &str = "fixture";
+
+/// Which output stream a chunk was written to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FixtureStream {
+    Stdout,
+    Stderr,
+}
+
+impl FixtureStream {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Stdout => "stdout",
+            Self::Stderr => "stderr",
+        }
+    }
+}
+
+/// One chunk of child output and when it arrived, relative to the start of the run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FixtureEvent {
+    pub at: Duration,
+    pub stream: FixtureStream,
+    pub bytes: Vec<u8>,
+}
+
+/// A recorded run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Fixture {
+    /// Program and arguments of the recorded child, for reference.
+    pub command: Vec<String>,
+    pub events: Vec<FixtureEvent>,
+    /// Exit code of the child; `None` when it was killed by a signal.
+    pub exit_code: Option<i32>,
+}
+
+impl Fixture {
+    pub fn parse(content: &str) -> io::Result<Self> {
+        let invalid = |line: usize, message: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("fixture line {line}: {message}"),
+            )
+        };
+        let mut fixture = Fixture::default();
+        let mut exited = false;
+        for (i, line) in content.lines().enumerate() {
+            let number = i + 1;
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (kind, rest) = line.split_once(' ').unwrap_or((line, ""));
+            match kind {
+                "command" => {
+                    fixture.command = rest
+                        .split(' ')
+                        .filter(|word| !word.is_empty())
+                        .map(|word| String::from_utf8_lossy(&unescape(word)).into_owned())
+                        .collect();
+                }
+                "stdout" | "stderr" => {
+                    let (ms, data) = rest.split_once(' ').unwrap_or((rest, ""));
+                    let ms: u64 = ms
+                        .parse()
+                        .map_err(|_| invalid(number, "expected a millisecond offset"))?;
+                    fixture.events.push(FixtureEvent {
+                        at: Duration::from_millis(ms),
+                        stream: if kind == "stdout" {
+                            FixtureStream::Stdout
+                        } else {
+                            FixtureStream::Stderr
+                        },
+                        bytes: unescape(data),
+                    });
+                }
+                "exit" => {
+                    fixture.exit_code = match rest.trim() {
+                        "signal" => None,
+                        code => Some(
+                            code.parse()
+                                .map_err(|_| invalid(number, "expected an exit code"))?,
+                        ),
+                    };
+                    exited = true;
+                }
+                _ => return Err(invalid(number, &format!("unknown event `{kind}`"))),
+            }
+        }
+        if !exited {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "fixture has no exit line",
+            ));
+        }
+        Ok(fixture)
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = format!("{HEADER}\n");
+        if !self.command.is_empty() {
+            let words: Vec<String> = self
+                .command
+                .iter()
+                .map(|word| escape(word.as_bytes()))
+                .collect();
+            let _ = writeln!(out, "command {}", words.join(" "));
+        }
+        for event in &self.events {
+            let _ = writeln!(
+                out,
+                "{} {} {}",
+                event.stream.as_str(),
+                event.at.as_millis(),
+                escape(&event.bytes)
+            );
+        }
+        match self.exit_code {
+            Some(code) => {
+                let _ = writeln!(out, "exit {code}");
+            }
+            None => out.push_str("exit signal\n"),
+        }
+        out
+    }
+}
+
+/// Printable ASCII is kept, except spaces and backslashes; everything else is `\xHH`,
+/// `\n`, `\r`, `\t` or `\\`.
+fn escape(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len());
+    for &byte in bytes {
+        match byte {
+            b'\\' => out.push_str("\\\\"),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            b'!'..=b'~' => out.push(char::from(byte)),
+            _ => {
+                let _ = write!(out, "\\x{byte:02x}");
+            }
+        }
+    }
+    out
+}
+
+fn unescape(text: &str) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'\\' || i + 1 >= bytes.len() {
+            out.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        let (byte, len) = match bytes[i + 1] {
+            b'n' => (b'\n', 2),
+            b'r' => (b'\r', 2),
+            b't' => (b'\t', 2),
+            b'x' => match text
+                .get(i + 2..i + 4)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                Some(byte) => (byte, 4),
+                None => (b'\\', 1),
+            },
+            other => (other, 2),
+        };
+        out.push(byte);
+        i += len;
+    }
+    out
+}
+
+/// Fixtures in `dir`, in run order.
+pub fn load_fixtures(dir: &Path) -> io::Result<Vec<Fixture>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == EXTENSION))
+        .collect();
+    paths.sort();
+    paths
+        .iter()
+        .map(|path| {
+            fs::read_to_string(path)
+                .and_then(|content| Fixture::parse(&content))
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))
+        })
+        .collect()
+}
+
+/// Collects a child's output as it is forwarded and writes it out as the next fixture in
+/// `dir` once the child has exited.
+pub(crate) struct FixtureRecorder {
+    dir: PathBuf,
+    started: Instant,
+    fixture: Fixture,
+}
+
+impl FixtureRecorder {
+    pub(crate) fn new(dir: &Path, command: Vec<String>) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            started: Instant::now(),
+            fixture: Fixture {
+                command,
+                ..Fixture::default()
+            },
+        }
+    }
+
+    pub(crate) fn record(&mut self, stream: FixtureStream, bytes: &[u8]) {
+        self.fixture.events.push(FixtureEvent {
+            at: self.started.elapsed(),
+            stream,
+            bytes: bytes.to_vec(),
+        });
+    }
+
+    /// Write the fixture as `run-NNNN.fixture`, numbered after those already in the
+    /// directory.
+    pub(crate) fn finish(mut self, exit_code: Option<i32>) -> io::Result<PathBuf> {
+        self.fixture.exit_code = exit_code;
+        fs::create_dir_all(&self.dir)?;
+        let content = self.fixture.render();
+        for n in 1.. {
+            let path = self.dir.join(format!("run-{n:04}.{EXTENSION}"));
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    file.write_all(content.as_bytes())?;
+                    return Ok(path);
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e),
+            }
+        }
+        unreachable!("fixture numbers are unbounded")
+    }
+}
@@ -0,0 +1,477 @@
+//! Git operations on the work dir around a session.
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Run `git -C <work_dir> <args>` and return its trimmed stdout.
+/// A non-zero exit is an error carrying git's stderr.
+pub async fn git(work_dir: &Path, args: &[&str]) -> io::Result<String> {
+    git_with_index(work_dir, args, None).await
+}
+
+/// [`git`] with `GIT_INDEX_FILE` pointing at `index` when given.
+async fn git_with_index(
+    work_dir: &Path,
+    args: &[&str],
+    index: Option<&Path>,
+) -> io::Result<String> {
+    let stdout = git_stdout(work_dir, args, index).await?;
+    Ok(String::from_utf8_lossy(&stdout).trim().to_string())
+}
+
+/// Run git and return its stdout untouched.
+async fn git_stdout(work_dir: &Path, args: &[&str], index: Option<&Path>) -> io::Result<Vec<u8>> {
+    let mut cmd = tokio::process::Command::new("git");
+    cmd.arg("-C").arg(work_dir).args(args).stdin(Stdio::null());
+    if let Some(index) = index {
+        cmd.env("GIT_INDEX_FILE", index);
+    }
+    let output = cmd.output().await?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!(
+            "`git {}` failed: {}",
+            args.join(" "),
+            stderr.trim()
+        )));
+    }
+    Ok(output.stdout)
+}
+
+/// Whether `work_dir` has uncommitted changes, untracked files included.
+pub async fn is_dirty(work_dir: &Path) -> io::Result<bool> {
+    Ok(!git(work_dir, &["status", "--porcelain"]).await?.is_empty())
+}
+
+/// Record the full state of the work tree, untracked files included, as a git tree
+/// object and return its id. Neither the index nor the work tree is touched.
+pub async fn snapshot(work_dir: &Path) -> io::Result<String> {
+    let index = index_path(work_dir).await?;
+    let scratch = scratch_index_path();
+    // Starting from a copy of the real index lets git reuse its cached file stats.
+    if std::fs::copy(&index, &scratch).is_err() {
+        let _ = std::fs::remove_file(&scratch);
+    }
+    let tree = async {
+        git_with_index(work_dir, &["add", "--all"], Some(&scratch)).await?;
+        git_with_index(work_dir, &["write-tree"], Some(&scratch)).await
+    }
+    .await;
+    let _ = std::fs::remove_file(&scratch);
+    tree
+}
+
+/// Location of the repository's real index file.
+async fn index_path(work_dir: &Path) -> io::Result<PathBuf> {
+    let index = PathBuf::from(git(work_dir, &["rev-parse", "--git-path", "index"]).await?);
+    Ok(if index.is_relative() {
+        work_dir.join(index)
+    } else {
+        index
+    })
+}
+
+/// A fresh temp path for an index file of our own.
+fn scratch_index_path() -> PathBuf {
+    static NEXT_INDEX: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!(
+        "agent-loops-index-{}-{}",
+        std::process::id(),
+        NEXT_INDEX.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+/// Everything needed to put a repository back the way it was: the work tree
+/// snapshot, the checked-out branch and commit, and a copy of the index.
+#[derive(Debug)]
+pub struct Checkpoint {
+    top_level: PathBuf,
+    /// Work tree [`snapshot`].
+    pub tree: String,
+    head: Option<String>,
+    branch: Option<String>,
+    index_backup: Option<PathBuf>,
+}
+
+impl Checkpoint {
+    pub async fn create(work_dir: &Path) -> io::Result<Self> {
+        let top_level = PathBuf::from(git(work_dir, &["rev-parse", "--show-toplevel"]).await?);
+        let tree = snapshot(&top_level).await?;
+        let head = git(&top_level, &["rev-parse", "--verify", "--quiet", "HEAD"])
+            .await
+            .ok();
+        let branch = git(&top_level, &["symbolic-ref", "--quiet", "HEAD"])
+            .await
+            .ok();
+        let index = index_path(&top_level).await?;
+        let backup = scratch_index_path();
+        let index_backup = std::fs::copy(&index, &backup).ok().map(|_| backup);
+        Ok(Self {
+            top_level,
+            tree,
+            head,
+            branch,
+            index_backup,
+        })
+    }
+
+    /// Put the branch, commit, index and work tree back as they were at the checkpoint.
+    /// Files created since then are deleted; ignored files are left alone.
+    pub async fn restore(&self) -> io::Result<()> {
+        let top = self.top_level.as_path();
+        if let Some(branch) = &self.branch
+            && git(top, &["symbolic-ref", "--quiet", "HEAD"])
+                .await
+                .ok()
+                .as_ref()
+                != Some(branch)
+        {
+            git(top, &["symbolic-ref", "HEAD", branch]).await?;
+        }
+        if let Some(head) = &self.head {
+            git(top, &["reset", "--quiet", "--soft", head]).await?;
+        }
+
+        let current = snapshot(top).await?;
+        let added = git(
+            top,
+            &[
+                "diff",
+                "--name-only",
+                "--no-renames",
+                "--diff-filter=A",
+                "-z",
+                &self.tree,
+                &current,
+            ],
+        )
+        .await?;
+        for path in added.split('\0').filter(|path| !path.is_empty()) {
+            std::fs::remove_file(top.join(path))?;
+        }
+
+        let scratch = scratch_index_path();
+        let checkout = async {
+            git_with_index(top, &["read-tree", &self.tree], Some(&scratch)).await?;
+            git_with_index(top, &["checkout-index", "--all", "--force"], Some(&scratch)).await
+        }
+        .await;
+        let _ = std::fs::remove_file(&scratch);
+        checkout?;
+
+        let index = index_path(top).await?;
+        match &self.index_backup {
+            Some(backup) => std::fs::copy(backup, &index).map(|_| ()),
+            None => match std::fs::remove_file(&index) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            },
+        }
+    }
+}
+
+impl Drop for Checkpoint {
+    fn drop(&mut self) {
+        if let Some(backup) = &self.index_backup {
+            let _ = std::fs::remove_file(backup);
+        }
+    }
+}
+
+/// Size of the difference between two snapshots.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiffStat {
+    pub files_changed: usize,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+impl DiffStat {
+    /// Parse the output of `git diff --numstat`. Binary files count as changed files
+    /// without lines.
+    pub fn parse_numstat(output: &str) -> Self {
+        let mut stat = Self::default();
+        for line in output.lines().filter(|line| !line.trim().is_empty()) {
+            let mut fields = line.split('\t');
+            stat.files_changed += 1;
+            stat.insertions += fields.next().and_then(|n| n.parse().ok()).unwrap_or(0);
+            stat.deletions += fields.next().and_then(|n| n.parse().ok()).unwrap_or(0);
+        }
+        stat
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files_changed == 0
+    }
+}
+
+impl fmt::Display for DiffStat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural = if self.files_changed == 1 { "" } else { "s" };
+        write!(
+            f,
+            "{} file{plural} changed, +{} -{}",
+            self.files_changed, self.insertions, self.deletions
+        )
+    }
+}
+
+/// What changed between two [`snapshot`]s.
+pub async fn diff_stat(work_dir: &Path, from: &str, to: &str) -> io::Result<DiffStat> {
+    let output = git(work_dir, &["diff", "--numstat", "--no-renames", from, to]).await?;
+    Ok(DiffStat::parse_numstat(&output))
+}
+
+/// When [`SessionBranches`] starts a new branch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BranchStrategy {
+    /// One branch for the whole session.
+    Session,
+    /// A branch per loop.
+    Loop,
+    /// A branch per run.
+    Task,
+}
+
+impl std::str::FromStr for BranchStrategy {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "session" => Ok(Self::Session),
+            "loop" => Ok(Self::Loop),
+            "task" => Ok(Self::Task),
+            _ => Err(format!(
+                "unknown branch strategy `{text}` (expected session, loop or task)"
+            )),
+        }
+    }
+}
+
+/// Branches created for a session under `agent-loops/<date>/`, named after the unit of
+/// work they hold, e.g. `agent-loops/2024-06-01/loop-3`. Leaving a branch commits
+/// whatever is left uncommitted on it, so every branch carries its own work.
+#[derive(Debug)]
+pub struct SessionBranches {
+    work_dir: PathBuf,
+    strategy: BranchStrategy,
+    prefix: String,
+    current: Option<String>,
+}
+
+impl SessionBranches {
+    /// Pick an unused `agent-loops/<date>` prefix, dated in UTC so machines in different
+    /// time zones agree; a second session on the same day gets `<date>-2`, and so on.
+    pub async fn start(work_dir: &Path, strategy: BranchStrategy) -> io::Result<Self> {
+        let date = chrono::Utc::now().format("%Y-%m-%d");
+        let mut prefix = format!("agent-loops/{date}");
+        for n in 2.. {
+            let taken = git(
+                work_dir,
+                &["for-each-ref", "--count=1", &format!("refs/heads/{prefix}")],
+            )
+            .await?;
+            if taken.is_empty() {
+                break;
+            }
+            prefix = format!("agent-loops/{date}-{n}");
+        }
+        Ok(Self {
+            work_dir: work_dir.to_path_buf(),
+            strategy,
+            prefix,
+            current: None,
+        })
+    }
+
+    /// Branch that run `task_idx` of loop `loop_idx` (both 0-based) belongs on.
+    pub fn branch_for(&self, loop_idx: usize, task_idx: usize) -> String {
+        let unit = match self.strategy {
+            BranchStrategy::Session => "session".to_string(),
+            BranchStrategy::Loop => format!("loop-{}", loop_idx + 1),
+            BranchStrategy::Task => format!("loop-{}-task-{}", loop_idx + 1, task_idx + 1),
+        };
+        format!("{}/{unit}", self.prefix)
+    }
+
+    /// Make sure the run is on its branch, committing the previous branch's work and
+    /// creating the new branch from the current commit as needed.
+    /// Returns the branch name when a switch happened.
+    pub async fn enter(&mut self, loop_idx: usize, task_idx: usize) -> io::Result<Option<String>> {
+        let branch = self.branch_for(loop_idx, task_idx);
+        if self.current.as_ref() == Some(&branch) {
+            return Ok(None);
+        }
+        self.commit_pending().await?;
+        git(&self.work_dir, &["checkout", "--quiet", "-b", &branch]).await?;
+        self.current = Some(branch.clone());
+        Ok(Some(branch))
+    }
+
+    /// Commit what is left on the current branch; call once the session is over.
+    pub async fn finish(&mut self) -> io::Result<()> {
+        self.commit_pending().await
+    }
+
+    async fn commit_pending(&self) -> io::Result<()> {
+        let Some(branch) = &self.current else {
+            return Ok(());
+        };
+        if !is_dirty(&self.work_dir).await? {
+            return Ok(());
+        }
+        git(&self.work_dir, &["add", "--all"]).await?;
+        git(
+            &self.work_dir,
+            &[
+                "commit",
+                "--quiet",
+                "--no-verify",
+                "-m",
+                &format!("agent-loops: {branch}"),
+            ],
+        )
+        .await
+        .map(|_| ())
+    }
+}
+
+/// A detached worktree of a repository, for runs that must not touch the main checkout.
+#[derive(Debug)]
+pub struct Worktree {
+    repo: PathBuf,
+    pub path: PathBuf,
+}
+
+impl Worktree {
+    /// [`Worktree::add`] at a fresh path under the temp dir named after `purpose`, e.g.
+    /// `agent-loops-benchmark-<pid>-1`.
+    pub async fn add_temp(repo: &Path, purpose: &str) -> io::Result<Self> {
+        static NEXT_WORKTREE: AtomicUsize = AtomicUsize::new(1);
+        let path = std::env::temp_dir().join(format!(
+            "agent-loops-{purpose}-{}-{}",
+            std::process::id(),
+            NEXT_WORKTREE.fetch_add(1, Ordering::Relaxed)
+        ));
+        Self::add(repo, &path).await
+    }
+
+    /// Check out the repository's `HEAD` at `path`; uncommitted changes are not carried over.
+    pub async fn add(repo: &Path, path: &Path) -> io::Result<Self> {
+        let target = path.to_string_lossy();
+        git(
+            repo,
+            &["worktree", "add", "--detach", "--quiet", &target, "HEAD"],
+        )
+        .await?;
+        Ok(Self {
+            repo: repo.to_path_buf(),
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Delete the worktree, along with whatever was done in it.
+    pub async fn remove(self) -> io::Result<()> {
+        let target = self.path.to_string_lossy();
+        git(&self.repo, &["worktree", "remove", "--force", &target])
+            .await
+            .map(|_| ())
+    }
+}
+
+/// Apply the changes between the trees or commits `from` and `to` to the work tree of
+/// `work_dir`, e.g. to bring over work done in a [`Worktree`] of the same repository.
+pub async fn apply_changes(work_dir: &Path, from: &str, to: &str) -> io::Result<()> {
+    let patch = git_stdout(
+        work_dir,
+        &["diff", "--binary", "--no-renames", from, to],
+        None,
+    )
+    .await?;
+    if patch.is_empty() {
+        return Ok(());
+    }
+    let file = scratch_index_path().with_extension("patch");
+    std::fs::write(&file, patch)?;
+    let applied = git(
+        work_dir,
+        &["apply", "--whitespace=nowarn", &file.to_string_lossy()],
+    )
+    .await;
+    let _ = std::fs::remove_file(&file);
+    applied.map(|_| ())
+}
+
+/// What to do when the work dir has uncommitted changes before a session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CleanPolicy {
+    /// Refuse to start, offering to stash the changes when on a terminal.
+    Require,
+    /// Stash the changes and restore them after the session.
+    AutoStash,
+}
+
+/// Changes stashed before a session.
+#[derive(Debug)]
+pub struct Stash {
+    work_dir: PathBuf,
+    /// Whether [`Stash::restore`] should pop it after the session.
+    pub restore_after: bool,
+}
+
+impl Stash {
+    /// Stash everything in `work_dir`, untracked files included.
+    pub async fn push(work_dir: &Path, restore_after: bool) -> io::Result<Self> {
+        git(
+            work_dir,
+            &[
+                "stash",
+                "push",
+                "--include-untracked",
+                "--message",
+                "agent-loops: changes present before the session",
+            ],
+        )
+        .await?;
+        Ok(Self {
+            work_dir: work_dir.to_path_buf(),
+            restore_after,
+        })
+    }
+
+    /// Pop the stash back onto the work dir. On conflict git keeps the stash, so
+    /// nothing is lost.
+    pub async fn restore(self) -> io::Result<()> {
+        git(&self.work_dir, &["stash", "pop", "--index"])
+            .await
+            .map(|_| ())
+    }
+}
+
+/// Make sure `work_dir` has no uncommitted changes before a session starts.
+///
+/// Returns the stash created to get there, if any. Under [`CleanPolicy::Require`] the
+/// user is asked first and the stash is left for them to pop; without a terminal the
+/// call fails.
+pub async fn ensure_clean(work_dir: &Path, policy: CleanPolicy) -> io::Result<Option<Stash>> {
+    if !is_dirty(work_dir).await? {
+        return Ok(None);
+    }
+    match policy {
+        CleanPolicy::AutoStash => Stash::push(work_dir, true).await.map(Some),
+        CleanPolicy::Require => {
+            if crate::confirm("The work dir has uncommitted changes. Stash them and continue?")
+                .await
+            {
+                Stash::push(work_dir, false).await.map(Some)
+            } else {
+                Err(io::Error::other(
+                    "the work dir has uncommitted changes; commit or stash them, or pass --auto-stash",
+                ))
+            }
+        }
+    }
+}
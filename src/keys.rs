@@ -0,0 +1,386 @@
+//! Keys pressed while the pinned view is up. The terminal is switched out of line mode
+//! for as long as they are read, so keys arrive one by one and are not echoed; Ctrl-C
+//! still interrupts. With the mouse taken over, wheel turns arrive as keys too.
+
+/// A key the pinned view reacts to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Enter,
+    Esc,
+    Backspace,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    WheelUp,
+    WheelDown,
+}
+
+/// The keys in `bytes` read from a terminal. Escape sequences for the arrow and paging
+/// keys and SGR mouse wheel reports are recognized; other sequences are dropped, and an
+/// `ESC` ending the input is the Esc key itself.
+pub fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    let text = String::from_utf8_lossy(bytes);
+    let mut keys = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let key = match c {
+            '\x1b' => match chars.peek() {
+                None => Key::Esc,
+                Some('[' | 'O') => {
+                    chars.next();
+                    // Parameters, then the final byte.
+                    let mut params = String::new();
+                    let mut last = None;
+                    for c in chars.by_ref() {
+                        if ('\x40'..='\x7e').contains(&c) {
+                            last = Some(c);
+                            break;
+                        }
+                        params.push(c);
+                    }
+                    // SGR mouse report: `<button;column;row`, then `M` for a press.
+                    if let Some(report) = params.strip_prefix('<') {
+                        match (report.split(';').next(), last) {
+                            (Some("64"), Some('M')) => keys.push(Key::WheelUp),
+                            (Some("65"), Some('M')) => keys.push(Key::WheelDown),
+                            _ => {}
+                        }
+                        continue;
+                    }
+                    match (params.as_str(), last) {
+                        (_, Some('A')) => Key::Up,
+                        (_, Some('B')) => Key::Down,
+                        (_, Some('H')) | ("1" | "7", Some('~')) => Key::Home,
+                        (_, Some('F')) | ("4" | "8", Some('~')) => Key::End,
+                        ("5", Some('~')) => Key::PageUp,
+                        ("6", Some('~')) => Key::PageDown,
+                        _ => continue,
+                    }
+                }
+                Some(_) => Key::Esc,
+            },
+            '\r' | '\n' => Key::Enter,
+            '\x7f' | '\x08' => Key::Backspace,
+            c if c.is_control() => continue,
+            c => Key::Char(c),
+        };
+        keys.push(key);
+    }
+    keys
+}
+
+#[cfg(feature = "tui")]
+pub(crate) use reader::{KeyReader, restore_terminal_mode};
+
+#[cfg(feature = "tui")]
+mod reader {
+    use std::io::{self, IsTerminal};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex, PoisonError};
+    use std::time::Duration;
+
+    use tokio::sync::mpsc;
+
+    use super::Key;
+
+    /// How long the reader thread waits for input before checking whether to stop.
+    const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+    /// The terminal mode while a [`KeyReader`] runs; kept here so an abnormal exit can
+    /// restore it as well.
+    static KEY_MODE: Mutex<Option<platform::KeyMode>> = Mutex::new(None);
+
+    /// Put the terminal back the way it was before keys were read from it, if they are.
+    pub(crate) fn restore_terminal_mode() {
+        drop(
+            KEY_MODE
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .take(),
+        );
+    }
+
+    /// Reads keys on a thread of its own until dropped, then puts the terminal back.
+    pub(crate) struct KeyReader {
+        rx: mpsc::UnboundedReceiver<Key>,
+        stop: Arc<AtomicBool>,
+        thread: Option<std::thread::JoinHandle<()>>,
+    }
+
+    impl KeyReader {
+        /// Start reading keys, and mouse wheel turns with `mouse`; `None` when stdin is
+        /// not a terminal or cannot be switched out of line mode.
+        pub(crate) fn start(mouse: bool) -> Option<Self> {
+            if !io::stdin().is_terminal() {
+                return None;
+            }
+            let mode = match platform::KeyMode::enter(mouse) {
+                Ok(mode) => mode,
+                Err(e) => {
+                    tracing::debug!("Cannot read keys from the terminal: {e}");
+                    return None;
+                }
+            };
+            *KEY_MODE.lock().unwrap_or_else(PoisonError::into_inner) = Some(mode);
+            let (tx, rx) = mpsc::unbounded_channel();
+            let stop = Arc::new(AtomicBool::new(false));
+            let thread = {
+                let stop = Arc::clone(&stop);
+                std::thread::spawn(move || {
+                    while !stop.load(Ordering::Relaxed) {
+                        match platform::read_keys(POLL_INTERVAL) {
+                            Ok(keys) => {
+                                if keys.into_iter().any(|key| tx.send(key).is_err()) {
+                                    break;
+                                }
+                            }
+                            Err(e) => {
+                                tracing::debug!("Stopped reading keys: {e}");
+                                break;
+                            }
+                        }
+                    }
+                })
+            };
+            Some(Self {
+                rx,
+                stop,
+                thread: Some(thread),
+            })
+        }
+
+        /// The next key pressed; `None` once the reader has stopped.
+        pub(crate) async fn next(&mut self) -> Option<Key> {
+            self.rx.recv().await
+        }
+    }
+
+    impl Drop for KeyReader {
+        fn drop(&mut self) {
+            self.stop.store(true, Ordering::Relaxed);
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+            restore_terminal_mode();
+        }
+    }
+
+    #[cfg(unix)]
+    mod platform {
+        use std::io::{self, Write};
+        use std::time::Duration;
+
+        use super::super::{Key, parse_keys};
+
+        /// Asks the terminal for SGR-encoded mouse button reports, the wheel included.
+        const MOUSE_ON: &str = "\x1b[?1000h\x1b[?1006h";
+        const MOUSE_OFF: &str = "\x1b[?1006l\x1b[?1000l";
+
+        /// The terminal without line buffering and echo, and reporting the mouse when
+        /// asked to; restores the previous settings when dropped.
+        pub(super) struct KeyMode {
+            saved: libc::termios,
+            mouse: bool,
+        }
+
+        impl KeyMode {
+            pub(super) fn enter(mouse: bool) -> io::Result<Self> {
+                // SAFETY: `termios` is filled in by `tcgetattr` before it is used.
+                unsafe {
+                    let mut termios: libc::termios = std::mem::zeroed();
+                    if libc::tcgetattr(libc::STDIN_FILENO, &mut termios) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    let saved = termios;
+                    termios.c_lflag &= !(libc::ICANON | libc::ECHO);
+                    termios.c_cc[libc::VMIN] = 1;
+                    termios.c_cc[libc::VTIME] = 0;
+                    if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    if mouse {
+                        write_terminal(MOUSE_ON);
+                    }
+                    Ok(Self { saved, mouse })
+                }
+            }
+        }
+
+        impl Drop for KeyMode {
+            fn drop(&mut self) {
+                if self.mouse {
+                    write_terminal(MOUSE_OFF);
+                }
+                // SAFETY: restores settings read by `tcgetattr`.
+                unsafe {
+                    libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.saved);
+                }
+            }
+        }
+
+        fn write_terminal(sequence: &str) {
+            let mut out = io::stdout();
+            let _ = out.write_all(sequence.as_bytes());
+            let _ = out.flush();
+        }
+
+        /// Keys typed within `timeout`; none when nothing was typed.
+        pub(super) fn read_keys(timeout: Duration) -> io::Result<Vec<Key>> {
+            let mut fd = libc::pollfd {
+                fd: libc::STDIN_FILENO,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let millis = i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX);
+            // SAFETY: `fd` and `buf` are valid for the duration of the calls.
+            unsafe {
+                match libc::poll(&mut fd, 1, millis) {
+                    0 => return Ok(Vec::new()),
+                    n if n < 0 => {
+                        let e = io::Error::last_os_error();
+                        return if e.kind() == io::ErrorKind::Interrupted {
+                            Ok(Vec::new())
+                        } else {
+                            Err(e)
+                        };
+                    }
+                    _ => {}
+                }
+                let mut buf = [0u8; 64];
+                match libc::read(libc::STDIN_FILENO, buf.as_mut_ptr().cast(), buf.len()) {
+                    0 => Err(io::ErrorKind::UnexpectedEof.into()),
+                    n if n < 0 => Err(io::Error::last_os_error()),
+                    n => Ok(parse_keys(&buf[..n as usize])),
+                }
+            }
+        }
+    }
+
+    #[cfg(windows)]
+    mod platform {
+        use std::io;
+        use std::time::Duration;
+
+        use windows_sys::Win32::Foundation::{HANDLE, WAIT_OBJECT_0};
+        use windows_sys::Win32::System::Console::{
+            ENABLE_ECHO_INPUT, ENABLE_EXTENDED_FLAGS, ENABLE_LINE_INPUT, ENABLE_MOUSE_INPUT,
+            ENABLE_QUICK_EDIT_MODE, GetConsoleMode, GetStdHandle, INPUT_RECORD, KEY_EVENT,
+            MOUSE_EVENT, MOUSE_WHEELED, ReadConsoleInputW, STD_INPUT_HANDLE, SetConsoleMode,
+        };
+        use windows_sys::Win32::System::Threading::WaitForSingleObject;
+
+        use super::super::Key;
+
+        fn stdin_handle() -> HANDLE {
+            // SAFETY: no preconditions.
+            unsafe { GetStdHandle(STD_INPUT_HANDLE) }
+        }
+
+        /// The console without line input and echo, and with mouse input (quick edit off)
+        /// when asked for; restores the previous mode when dropped.
+        pub(super) struct KeyMode(u32);
+
+        impl KeyMode {
+            pub(super) fn enter(mouse: bool) -> io::Result<Self> {
+                let handle = stdin_handle();
+                let mut mode = 0;
+                // SAFETY: `mode` is a valid out pointer.
+                unsafe {
+                    if GetConsoleMode(handle, &mut mode) == 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    let mut keys_mode = mode & !(ENABLE_LINE_INPUT | ENABLE_ECHO_INPUT);
+                    if mouse {
+                        keys_mode = (keys_mode & !ENABLE_QUICK_EDIT_MODE)
+                            | ENABLE_MOUSE_INPUT
+                            | ENABLE_EXTENDED_FLAGS;
+                    }
+                    if SetConsoleMode(handle, keys_mode) == 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                Ok(Self(mode))
+            }
+        }
+
+        impl Drop for KeyMode {
+            fn drop(&mut self) {
+                // SAFETY: restores the mode read by `GetConsoleMode`.
+                unsafe {
+                    SetConsoleMode(stdin_handle(), self.0);
+                }
+            }
+        }
+
+        /// Keys pressed within `timeout`; none when nothing was pressed.
+        pub(super) fn read_keys(timeout: Duration) -> io::Result<Vec<Key>> {
+            let handle = stdin_handle();
+            let millis = u32::try_from(timeout.as_millis()).unwrap_or(u32::MAX);
+            let mut records: [INPUT_RECORD; 16] = Default::default();
+            let mut read = 0;
+            // SAFETY: `records` holds `records.len()` entries and `read` is a valid out
+            // pointer; the console has input, so the read does not block.
+            unsafe {
+                if WaitForSingleObject(handle, millis) != WAIT_OBJECT_0 {
+                    return Ok(Vec::new());
+                }
+                if ReadConsoleInputW(
+                    handle,
+                    records.as_mut_ptr(),
+                    records.len() as u32,
+                    &mut read,
+                ) == 0
+                {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            let mut keys = Vec::new();
+            for record in &records[..read as usize] {
+                if u32::from(record.EventType) == MOUSE_EVENT {
+                    // SAFETY: a MOUSE_EVENT record holds a mouse event.
+                    let event = unsafe { record.Event.MouseEvent };
+                    if event.dwEventFlags == MOUSE_WHEELED {
+                        // The high word is the signed wheel delta; positive turns away.
+                        keys.push(if (event.dwButtonState >> 16) as i16 > 0 {
+                            Key::WheelUp
+                        } else {
+                            Key::WheelDown
+                        });
+                    }
+                    continue;
+                }
+                if u32::from(record.EventType) != KEY_EVENT {
+                    continue;
+                }
+                // SAFETY: a KEY_EVENT record holds a key event.
+                let event = unsafe { record.Event.KeyEvent };
+                if event.bKeyDown == 0 {
+                    continue;
+                }
+                // SAFETY: both union fields are plain integers.
+                let unit = unsafe { event.uChar.UnicodeChar };
+                let key = match (event.wVirtualKeyCode, unit) {
+                    (0x21, _) => Key::PageUp,
+                    (0x22, _) => Key::PageDown,
+                    (0x23, _) => Key::End,
+                    (0x24, _) => Key::Home,
+                    (0x26, _) => Key::Up,
+                    (0x28, _) => Key::Down,
+                    (_, 0x0d) => Key::Enter,
+                    (_, 0x1b) => Key::Esc,
+                    (_, 0x08) => Key::Backspace,
+                    (_, unit) => match char::from_u32(u32::from(unit)) {
+                        Some(c) if !c.is_control() => Key::Char(c),
+                        _ => continue,
+                    },
+                };
+                keys.push(key);
+            }
+            Ok(keys)
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! Locating the agent executable and building command lines for it.
+
+use std::ffi::OsStr;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use crate::agent::Agent;
+
+/// Look `name` up the way a shell would: paths are checked as-is, bare names against `PATH`.
+/// On Windows, extensions from `PATHEXT` (e.g. `.exe`, `.cmd`) are tried for bare names.
+pub fn find_executable(name: &str) -> Option<PathBuf> {
+    let path_var = std::env::var_os("PATH").unwrap_or_default();
+    let extensions = executable_extensions();
+    let extensions: Vec<&str> = extensions.iter().map(String::as_str).collect();
+    find_executable_in(name, &path_var, &extensions)
+}
+
+/// Like [`find_executable`], with an explicit search path and extension list.
+/// An empty extension list means names are only matched exactly.
+pub fn find_executable_in(name: &str, path_var: &OsStr, extensions: &[&str]) -> Option<PathBuf> {
+    if name.is_empty() {
+        return None;
+    }
+    let candidate = Path::new(name);
+    if candidate.components().count() > 1 || candidate.is_absolute() {
+        return with_extensions(candidate, extensions)
+            .into_iter()
+            .find(|p| is_executable(p));
+    }
+    std::env::split_paths(path_var)
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .flat_map(|dir| with_extensions(&dir.join(name), extensions))
+        .find(|p| is_executable(p))
+}
+
+/// Directories outside `PATH` where agent CLIs are commonly installed
+/// (user-local bins, npm global prefixes, Homebrew).
+pub fn common_install_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(prefix) = std::env::var_os("NPM_CONFIG_PREFIX").filter(|p| !p.is_empty()) {
+        let prefix = PathBuf::from(prefix);
+        if cfg!(windows) {
+            dirs.push(prefix);
+        } else {
+            dirs.push(prefix.join("bin"));
+        }
+    }
+    if cfg!(windows) {
+        if let Some(appdata) = std::env::var_os("APPDATA") {
+            dirs.push(PathBuf::from(appdata).join("npm"));
+        }
+        if let Some(local) = std::env::var_os("LOCALAPPDATA") {
+            dirs.push(PathBuf::from(local).join("Programs").join("codex"));
+        }
+    } else {
+        if let Some(home) = std::env::var_os("HOME").filter(|h| !h.is_empty()) {
+            let home = PathBuf::from(home);
+            dirs.push(home.join(".local").join("bin"));
+            dirs.push(home.join(".npm-global").join("bin"));
+            dirs.push(home.join(".cargo").join("bin"));
+            dirs.push(home.join("bin"));
+        }
+        dirs.push(PathBuf::from("/usr/local/bin"));
+        dirs.push(PathBuf::from("/opt/homebrew/bin"));
+    }
+    dirs
+}
+
+/// Find `name` on `PATH`, then in [`common_install_dirs`].
+pub fn resolve_executable(name: &str) -> Option<PathBuf> {
+    find_executable(name).or_else(|| {
+        let extra = std::env::join_paths(common_install_dirs()).ok()?;
+        let extensions = executable_extensions();
+        let extensions: Vec<&str> = extensions.iter().map(String::as_str).collect();
+        find_executable_in(name, &extra, &extensions)
+    })
+}
+
+/// Explain a failed lookup of `name`: every directory searched, plus how to point at the binary.
+pub fn not_found_diagnostic(name: &str) -> String {
+    let mut text = String::new();
+    if Path::new(name).components().count() > 1 {
+        text.push_str(&format!("`{name}` does not exist or is not executable.\n"));
+    } else {
+        let path_var = std::env::var_os("PATH").unwrap_or_default();
+        text.push_str("Searched PATH:\n");
+        for dir in std::env::split_paths(&path_var) {
+            text.push_str(&format!("  {}\n", dir.display()));
+        }
+        text.push_str("Searched common install locations:\n");
+        for dir in common_install_dirs() {
+            text.push_str(&format!("  {}\n", dir.display()));
+        }
+    }
+    text.push_str(
+        "Hint: pass --codex-bin <full path> (or set AGENT_LOOPS_CODEX_BIN) to point at the binary.",
+    );
+    text
+}
+
+/// The codex flag that gives every run full access to the machine.
+pub const SANDBOX_BYPASS_FLAG: &str = "--dangerously-bypass-approvals-and-sandbox";
+
+/// What the operator confirms before a session starts: that `runs` runs of `agent` will
+/// have full access to `work_dirs` and the rest of the machine.
+pub fn sandbox_bypass_notice(agent: Agent, work_dirs: &[PathBuf], runs: usize) -> String {
+    let mut notice = String::new();
+    let _ = writeln!(
+        notice,
+        "About to start {runs} {agent} run(s) with {} in:",
+        agent.auto_approve_flag()
+    );
+    for dir in work_dirs {
+        let _ = writeln!(notice, "  {}", dir.display());
+    }
+    notice.push_str("The agent can change any file and run any command there, and outside it.");
+    notice
+}
+
+/// Ask the operator on the terminal whether to go ahead; no terminal means no.
+pub async fn confirm_sandbox_bypass() -> bool {
+    crate::confirm("Start the session?").await
+}
+
+/// [`AgentLoopsError::BinaryNotFound`](crate::AgentLoopsError::BinaryNotFound) for `name`,
+/// carrying `detail` and the lookup diagnostic.
+pub(crate) fn binary_not_found_error(name: &str, detail: &str) -> crate::AgentLoopsError {
+    crate::AgentLoopsError::BinaryNotFound {
+        program: name.to_string(),
+        detail: format!("{detail}\n{}", not_found_diagnostic(name)),
+    }
+}
+
+fn with_extensions(path: &Path, extensions: &[&str]) -> Vec<PathBuf> {
+    let mut candidates = Vec::with_capacity(extensions.len() + 1);
+    if path.extension().is_some() || extensions.is_empty() {
+        candidates.push(path.to_path_buf());
+    }
+    for ext in extensions {
+        let mut with_ext = path.as_os_str().to_os_string();
+        with_ext.push(ext);
+        candidates.push(PathBuf::from(with_ext));
+    }
+    candidates
+}
+
+fn executable_extensions() -> Vec<String> {
+    if cfg!(windows) {
+        std::env::var("PATHEXT")
+            .unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string())
+            .split(';')
+            .filter(|ext| !ext.is_empty())
+            .map(str::to_ascii_lowercase)
+            .collect()
+    } else {
+        Vec::new()
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// How a codex command that is not on `PATH` is retried through a shell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ShellFallback {
+    /// Fail with "not found" instead of retrying.
+    Disabled,
+    /// Retry with a login shell (`-lc`), without reading interactive rc files.
+    NonInteractive,
+    /// Retry with a login shell, adding `-i` for bash/zsh so aliases and functions
+    /// defined in rc files resolve. rc files that wait for input can hang the run.
+    #[default]
+    Interactive,
+}
+
+/// Flags placed before the command string when retrying through `shell`.
+pub fn shell_fallback_flags(shell: &str, mode: ShellFallback) -> Vec<&'static str> {
+    let shell_name = Path::new(shell)
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default();
+    match mode {
+        ShellFallback::Disabled => Vec::new(),
+        ShellFallback::NonInteractive => vec!["-lc"],
+        ShellFallback::Interactive if shell_name == "zsh" || shell_name == "bash" => {
+            vec!["-i", "-lc"]
+        }
+        ShellFallback::Interactive => vec!["-lc"],
+    }
+}
+
+/// Quote one argument so `CommandLineToArgvW` (the MSVC runtime) parses it back unchanged.
+pub fn quote_windows_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '\n', '\u{b}', '"']) {
+        return arg.to_string();
+    }
+    let mut quoted = String::with_capacity(arg.len() + 2);
+    quoted.push('"');
+    let mut backslashes = 0;
+    for ch in arg.chars() {
+        match ch {
+            '\\' => backslashes += 1,
+            '"' => {
+                // Backslashes before a quote must be doubled, plus one to escape the quote.
+                quoted.extend(std::iter::repeat_n('\\', backslashes * 2 + 1));
+                quoted.push('"');
+                backslashes = 0;
+            }
+            _ => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes));
+                quoted.push(ch);
+                backslashes = 0;
+            }
+        }
+    }
+    // Backslashes before the closing quote must be doubled too.
+    quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+/// Caret-escape every `cmd.exe` metacharacter so the text reaches the program literally.
+pub fn escape_cmd_metachars(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if matches!(
+            ch,
+            '(' | ')' | '%' | '!' | '^' | '"' | '<' | '>' | '&' | '|'
+        ) {
+            escaped.push('^');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
+
+/// Build the raw command line for `cmd.exe` that runs `program` with `args` unchanged.
+/// Newlines cannot survive `cmd.exe` parsing and end the command early.
+pub fn cmd_exe_command_line(program: &str, args: &[String]) -> String {
+    let mut line = String::from("/d /c ");
+    line.push_str(&escape_cmd_metachars(&quote_windows_arg(program)));
+    for arg in args {
+        line.push(' ');
+        line.push_str(&escape_cmd_metachars(&quote_windows_arg(arg)));
+    }
+    line
+}
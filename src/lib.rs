@@ -1,32 +1,160 @@
-use std::collections::VecDeque;
-use std::io::{self, IsTerminal, Write};
-use std::path::Path;
+use std::fmt;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
 use std::process::{ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
 use tokio::process::Command;
 use tokio::sync::mpsc;
+use tracing::Instrument;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+pub mod agent;
+#[cfg(feature = "api")]
+pub mod api;
+pub mod artifacts;
+pub mod attach;
+pub mod benchmark;
+pub mod cache;
+pub mod compare;
+pub mod config;
+pub mod container;
+pub mod disk;
+pub mod doctor;
+pub mod encoding;
+mod error;
+mod filter;
+pub mod fixture;
+pub mod git;
+pub mod keys;
+pub mod launch;
+pub mod lock;
+pub mod logging;
+pub mod notify;
+pub mod plan;
+pub mod progress;
+pub mod prompt;
+pub use launch::ShellFallback;
+mod process_tree;
+#[cfg(feature = "tui")]
+mod pty;
+mod redact;
+pub mod remote;
+pub mod replay;
+pub mod scan;
+pub mod schedule;
+pub mod search;
+pub mod service;
+pub mod sessions;
+pub mod shutdown;
+pub mod stats;
+pub mod tasks;
+mod tee;
+#[cfg(feature = "otlp")]
+pub mod telemetry;
+pub mod testing;
+pub mod theme;
+pub mod throttle;
+mod timestamps;
+#[cfg(feature = "tui")]
+mod tui;
+pub mod version;
+pub mod vote;
+pub mod watch;
+pub mod workspace;
+
+use agent::{Agent, RunnerTemplate};
+use artifacts::ArtifactCollector;
+use attach::SessionControl;
+use chrono::Local;
+use container::Container;
+use disk::DiskGuard;
+use encoding::OutputDecoder;
+pub use error::AgentLoopsError;
+pub use filter::LineFilter;
+use filter::StreamFilter;
+use fixture::{Fixture, FixtureRecorder, FixtureStream};
+use git::{BranchStrategy, DiffStat, SessionBranches};
+use process_tree::ProcessTree;
+pub use process_tree::{CancelToken, ResourceLimits};
+use progress::{ProgressEstimator, RunProgress};
+use prompt::{PromptVars, PromptWrap};
+use redact::StreamRedactor;
+pub use redact::{Redactor, is_secret_env_name};
+use remote::Remote;
+use scan::{OutputScan, OutputScanner, Usage};
+use schedule::{Blackout, blackout_end};
+pub use tasks::{TaskSpec, load_prompts_file, load_tasks};
+pub use tee::TeeFile;
+use tee::{StreamTee, println_tee};
+pub use throttle::RunThrottle;
+use timestamps::StreamStamper;
+pub use version::{Version, VersionReq};
 
 /// Maximum display length for a single task description in the summary.
 pub const MAX_DISPLAY_LEN: usize = 60;
 /// Maximum display length for the current-task header.
 pub const MAX_CURRENT_TASK_LEN: usize = 120;
-/// Keep a bounded amount of task output in memory while redrawing.
-const MAX_RENDERED_OUTPUT_LINES: usize = 4000;
 
-/// Truncate a string for display, appending "..." if it exceeds `max_len`.
+static DISPLAY_LEN: AtomicUsize = AtomicUsize::new(MAX_DISPLAY_LEN);
+static CURRENT_TASK_LEN: AtomicUsize = AtomicUsize::new(MAX_CURRENT_TASK_LEN);
+static PLAN_FULL: AtomicBool = AtomicBool::new(false);
+
+/// Use `display` columns instead of [`MAX_DISPLAY_LEN`] and `current_task` instead of
+/// [`MAX_CURRENT_TASK_LEN`] from now on (`--display-len`, `--current-task-len`), and with
+/// `plan_full` show the plan's prompts untruncated (`--plan-full`).
+pub fn set_display_lengths(display: usize, current_task: usize, plan_full: bool) {
+    DISPLAY_LEN.store(display, Ordering::Relaxed);
+    CURRENT_TASK_LEN.store(current_task, Ordering::Relaxed);
+    PLAN_FULL.store(plan_full, Ordering::Relaxed);
+}
+
+/// Display length for a task description in plans and summaries.
+pub fn display_len() -> usize {
+    DISPLAY_LEN.load(Ordering::Relaxed)
+}
+
+/// Display length for the current-task header.
+pub fn current_task_len() -> usize {
+    CURRENT_TASK_LEN.load(Ordering::Relaxed)
+}
+
+/// Terminal column width of `s`, counting wide (CJK, emoji) characters as two columns.
+pub fn display_width(s: &str) -> usize {
+    UnicodeWidthStr::width(s)
+}
+
+/// Longest prefix of whole grapheme clusters that fits in `max_width` columns.
+fn take_width(s: &str, max_width: usize) -> &str {
+    let mut width = 0;
+    let mut end = 0;
+    for (idx, grapheme) in s.grapheme_indices(true) {
+        width += display_width(grapheme);
+        if width > max_width {
+            break;
+        }
+        end = idx + grapheme.len();
+    }
+    &s[..end]
+}
+
+/// Truncate a string for display, appending "..." if it is wider than `max_len` columns.
 pub fn truncate_display(s: &str, max_len: usize) -> String {
-    if s.len() <= max_len {
+    if display_width(s) <= max_len {
         s.to_string()
     } else {
-        format!("{}...", &s[..max_len.saturating_sub(3)])
+        format!("{}...", take_width(s, max_len.saturating_sub(3)))
     }
 }
 
 /// Print the execution plan before running.
 pub fn print_plan(prompts: &[String], loops: usize, work_dir: Option<&str>) {
-    println!("=== Agent Loops Plan ===");
+    let header = &theme::theme().header;
+    println!("{}", header.paint("=== Agent Loops Plan ==="));
     if let Some(dir) = work_dir {
         println!("Work dir: {dir}");
     }
@@ -37,10 +165,15 @@ pub fn print_plan(prompts: &[String], loops: usize, work_dir: Option<&str>) {
     );
     println!("Task list:");
     for (i, prompt) in prompts.iter().enumerate() {
-        println!("  {}. {}", i + 1, truncate_display(prompt, MAX_DISPLAY_LEN));
+        let prompt = if PLAN_FULL.load(Ordering::Relaxed) {
+            prompt.trim_end().replace('\n', "\n     ")
+        } else {
+            truncate_display(prompt, display_len())
+        };
+        println!("  {}. {prompt}", i + 1);
     }
     println!();
-    println!("========================\n");
+    println!("{}\n", header.paint("========================"));
 }
 
 fn task_header_slot() -> &'static Mutex<Option<Vec<String>>> {
@@ -70,18 +203,40 @@ fn current_task_header_or_default(prompt: &str) -> Vec<String> {
             "=== Agent Loops ===".to_string(),
             format!(
                 "Current task: {}",
-                truncate_display(prompt, MAX_CURRENT_TASK_LEN)
+                truncate_display(prompt, current_task_len())
             ),
             "----------------------------------------".to_string(),
         ]
     })
 }
 
+fn run_progress_slot() -> &'static Mutex<Option<RunProgress>> {
+    static RUN_PROGRESS: OnceLock<Mutex<Option<RunProgress>>> = OnceLock::new();
+    RUN_PROGRESS.get_or_init(|| Mutex::new(None))
+}
+
+fn set_current_run_progress(progress: Option<RunProgress>) {
+    let mut guard = match run_progress_slot().lock() {
+        Ok(g) => g,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    *guard = progress;
+}
+
+fn current_run_progress() -> Option<RunProgress> {
+    let guard = match run_progress_slot().lock() {
+        Ok(g) => g,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    *guard
+}
+
 struct CurrentTaskHeaderGuard;
 
 impl CurrentTaskHeaderGuard {
-    fn new(lines: Vec<String>) -> Self {
+    fn new(lines: Vec<String>, progress: RunProgress) -> Self {
         set_current_task_header(Some(lines));
+        set_current_run_progress(Some(progress));
         Self
     }
 }
@@ -89,88 +244,631 @@ impl CurrentTaskHeaderGuard {
 impl Drop for CurrentTaskHeaderGuard {
     fn drop(&mut self) {
         set_current_task_header(None);
+        set_current_run_progress(None);
+    }
+}
+
+/// Settings for launching a single codex run.
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    /// The agent CLI `codex_bin` is, which decides how it is invoked.
+    pub agent: Agent,
+    /// Runs this command line instead of `agent`; `codex_bin` is then its program.
+    pub runner_template: Option<RunnerTemplate>,
+    /// Runs the agent inside a container of this image instead of on the host.
+    pub container: Option<Container>,
+    /// Runs the agent on this machine over SSH instead of locally.
+    pub remote: Option<Remote>,
+    /// Codex executable path or command name.
+    pub codex_bin: String,
+    /// Working directory passed to codex via `-C`.
+    pub work_dir: Option<PathBuf>,
+    /// Model passed to codex via `--model`; codex's configured default when `None`.
+    pub model: Option<String>,
+    /// Masks secrets in child output before it is displayed.
+    pub redactor: Redactor,
+    /// Lines of child output to display; hidden lines still reach the full output log.
+    pub line_filter: LineFilter,
+    /// Directory receiving the complete output of each run shown in the pinned view,
+    /// since the view itself only keeps a bounded tail. The pinned view needs the `tui` feature.
+    pub spool_dir: Option<PathBuf>,
+    /// Wrap long output lines in the pinned view instead of truncating them.
+    pub wrap_lines: bool,
+    /// Take over the mouse in the pinned view so the wheel scrolls its output. Selecting
+    /// text then needs Shift held in most terminals.
+    pub mouse: bool,
+    /// Prefix each displayed and spooled output line with the time since the run started.
+    pub timestamps: bool,
+    /// Receives a copy of every displayed output line as it appears.
+    pub tee: Option<TeeFile>,
+    /// Outside the pinned view, write child output to the spool dir instead of
+    /// forwarding it, so CI logs only get the session's summary lines.
+    pub ci_output: bool,
+    /// Keep the full output log outside the pinned view too, for callers that read
+    /// [`RunOutcome::output_log`].
+    pub capture_output: bool,
+    /// Run codex under a pseudo-terminal while the pinned view is active,
+    /// so it renders its interactive UI and does not block-buffer output.
+    /// On Unix, runs with [`RunOptions::limits`] use pipes instead.
+    pub use_pty: bool,
+    /// Kill the run (and everything it spawned) once it has been running this long.
+    pub timeout: Option<Duration>,
+    /// Show a [`progress::heartbeat_line`] once the run has written nothing for this
+    /// long: in the pinned view's header, or on stderr outside it.
+    pub heartbeat: Option<Duration>,
+    /// Memory and CPU caps for the agent and everything it starts.
+    pub limits: ResourceLimits,
+    /// Kills the running child tree when cancelled.
+    pub cancel: CancelToken,
+    /// Shell used to retry a codex command that is not on `PATH`. Defaults to `$SHELL`.
+    pub shell: Option<PathBuf>,
+    /// Whether and how that shell retry happens (on Windows, the `cmd` retry).
+    pub shell_fallback: ShellFallback,
+    /// Extra environment variables for the agent process, applied in order.
+    pub env: Vec<(String, String)>,
+    /// When set, the agent starts from an empty environment and only these variables are
+    /// passed through from ours (before `env` is applied). Include `PATH`.
+    pub env_allowlist: Option<Vec<String>>,
+    /// Price used to turn reported token counts into dollars when the agent does not
+    /// report a cost itself.
+    pub usd_per_1k_tokens: Option<f64>,
+    /// Kill the run once its reported spend exceeds this many dollars.
+    pub max_cost_per_run: Option<f64>,
+    /// Kill the run once it has written more than this many bytes of output.
+    pub max_output_bytes: Option<u64>,
+    /// Directory receiving a [`Fixture`] of each run's raw output and exit status, for
+    /// playback with [`testing::FixtureRunner`].
+    pub record_dir: Option<PathBuf>,
+    /// Shared text [`CodexRunner`] puts around each task's prompt.
+    pub prompt_wrap: PromptWrap,
+    /// Variables [`CodexRunner`] renders each task's prompt with.
+    pub prompt_vars: PromptVars,
+    /// Send prompts as they are, without `prompt_wrap` or rendering: for auxiliary runs
+    /// whose prompts quote task prompts, templates included.
+    pub raw_prompts: bool,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        Self {
+            agent: Agent::default(),
+            runner_template: None,
+            container: None,
+            remote: None,
+            codex_bin: "codex".to_string(),
+            work_dir: None,
+            model: None,
+            redactor: Redactor::new(),
+            line_filter: LineFilter::new(),
+            spool_dir: None,
+            wrap_lines: false,
+            mouse: false,
+            timestamps: false,
+            tee: None,
+            ci_output: false,
+            capture_output: false,
+            use_pty: true,
+            timeout: None,
+            heartbeat: None,
+            limits: ResourceLimits::default(),
+            cancel: CancelToken::new(),
+            shell: None,
+            shell_fallback: ShellFallback::default(),
+            env: Vec::new(),
+            env_allowlist: None,
+            usd_per_1k_tokens: None,
+            max_cost_per_run: None,
+            max_output_bytes: None,
+            record_dir: None,
+            prompt_wrap: PromptWrap::default(),
+            prompt_vars: PromptVars::new(),
+            raw_prompts: false,
+        }
+    }
+}
+
+impl RunOptions {
+    /// Options for running `task`: its agent, binary, model and allow-list replace ours,
+    /// and its environment is applied on top of ours. A task naming an agent runs it
+    /// rather than the runner template.
+    pub fn for_task(&self, task: &TaskSpec) -> RunOptions {
+        let mut options = self.clone();
+        if let Some(agent) = task.agent
+            && (agent != self.agent || self.runner_template.is_some())
+        {
+            options.agent = agent;
+            options.runner_template = None;
+            options.codex_bin = agent.default_bin().to_string();
+        }
+        if let Some(codex_bin) = &task.codex_bin {
+            options.codex_bin = codex_bin.clone();
+        }
+        if let Some(model) = &task.model {
+            options.model = Some(model.clone());
+        }
+        options
+            .env
+            .extend(task.env.iter().map(|(k, v)| (k.clone(), v.clone())));
+        if let Some(allowlist) = &task.env_allowlist {
+            options.env_allowlist = Some(allowlist.clone());
+        }
+        options
+    }
+}
+
+/// Apply the environment settings of `options` to `cmd`.
+fn apply_env(cmd: &mut Command, options: &RunOptions) {
+    if let Some(allowlist) = &options.env_allowlist {
+        cmd.env_clear();
+        for name in allowlist {
+            if let Some(value) = std::env::var_os(name) {
+                cmd.env(name, value);
+            }
+        }
+    }
+    cmd.envs(options.env.iter().map(|(k, v)| (k, v)));
+}
+
+/// Default location for per-run output spool files.
+pub fn default_spool_dir() -> PathBuf {
+    std::env::temp_dir().join("agent-loops")
+}
+
+/// Delete the run logs and artifact dirs under `dir` last changed more than `max_age`
+/// ago, along with the session and artifact dirs they leave empty. Other files are left
+/// alone. Returns how many were deleted.
+pub fn prune_spool(dir: &Path, max_age: Duration) -> io::Result<usize> {
+    let Some(cutoff) = std::time::SystemTime::now().checked_sub(max_age) else {
+        return Ok(0);
+    };
+    let mut removed = 0;
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let meta = entry.metadata()?;
+        if name.starts_with("run-") {
+            if meta.modified()? < cutoff {
+                if meta.is_dir() {
+                    std::fs::remove_dir_all(&path)?;
+                } else {
+                    std::fs::remove_file(&path)?;
+                }
+                removed += 1;
+            }
+        } else if meta.is_dir() && (name.starts_with("session-") || name.starts_with("artifacts-"))
+        {
+            removed += prune_spool(&path, max_age)?;
+            // Only succeeds once the dir is empty.
+            let _ = std::fs::remove_dir(&path);
+        }
+    }
+    Ok(removed)
+}
+
+/// Result of a single run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunOutcome {
+    /// Whether the run exited successfully.
+    pub success: bool,
+    /// File holding the run's complete output, if it was spooled.
+    pub output_log: Option<PathBuf>,
+    /// The output contained a rate-limit or quota error.
+    pub rate_limited: bool,
+    /// Token and cost figures the agent reported.
+    pub usage: Usage,
+    /// Spend in dollars, when it could be determined.
+    pub cost_usd: Option<f64>,
+    /// The run was killed for exceeding [`RunOptions::max_cost_per_run`].
+    pub over_budget: bool,
+    /// The run was killed for writing more than [`RunOptions::max_output_bytes`].
+    pub output_flood: bool,
+    /// What the run changed in [`OrchestrateOptions::git_work_dir`], when tracked.
+    pub changes: Option<DiffStat>,
+    /// Dir holding the files collected after the run ([`OrchestrateOptions::artifacts`]),
+    /// when there were any.
+    pub artifacts: Option<PathBuf>,
+    /// The agent's closing message, read from the end of its transcript.
+    pub final_message: Option<String>,
+    /// Whether the verification command (`--check`) passed after the run; `None` when
+    /// none ran.
+    pub check_passed: Option<bool>,
+    /// The run was not started because it succeeded in an earlier session (`--cache`).
+    pub cached: bool,
+    /// The key a later session looks the run up by, with `--cache`.
+    pub cache_key: Option<String>,
+    /// The prompt as the agent got it, wrapped and rendered.
+    pub sent_prompt: Option<String>,
+}
+
+impl From<bool> for RunOutcome {
+    fn from(success: bool) -> Self {
+        Self {
+            success,
+            ..Self::default()
+        }
+    }
+}
+
+/// One entry of a session's results. Build one with [`RunRecord::new`]; more fields
+/// may be added.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct RunRecord {
+    pub loop_idx: usize,
+    pub task_idx: usize,
+    pub outcome: RunOutcome,
+    /// Wall-clock time of the run, retries included.
+    pub duration: Duration,
+}
+
+impl RunRecord {
+    /// The result of task `task_idx` in loop `loop_idx`, which took `duration`.
+    pub fn new(loop_idx: usize, task_idx: usize, outcome: RunOutcome, duration: Duration) -> Self {
+        Self {
+            loop_idx,
+            task_idx,
+            outcome,
+            duration,
+        }
+    }
+}
+
+/// How a session's failed runs map to the process exit code (`--exit-policy`).
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ExitPolicy {
+    /// Fail when any run failed.
+    #[default]
+    AnyFailure,
+    /// Fail only when more than this percentage of the runs failed.
+    Threshold(f64),
+    /// Always exit successfully; failures are only reported.
+    AlwaysZero,
+}
+
+impl ExitPolicy {
+    /// Whether a session with these results exits successfully.
+    pub fn passes(&self, results: &[RunRecord]) -> bool {
+        let failed = results.iter().filter(|r| !r.outcome.success).count();
+        match *self {
+            Self::AnyFailure => failed == 0,
+            Self::Threshold(pct) => {
+                failed == 0 || failed as f64 * 100.0 <= pct * results.len() as f64
+            }
+            Self::AlwaysZero => true,
+        }
+    }
+}
+
+impl fmt::Display for ExitPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AnyFailure => f.write_str("any-failure"),
+            Self::Threshold(pct) => write!(f, "threshold:{pct}"),
+            Self::AlwaysZero => f.write_str("always-zero"),
+        }
+    }
+}
+
+impl std::str::FromStr for ExitPolicy {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "any-failure" => Ok(Self::AnyFailure),
+            "always-zero" => Ok(Self::AlwaysZero),
+            _ => {
+                let Some(pct) = text.strip_prefix("threshold:") else {
+                    return Err(format!(
+                        "unknown exit policy `{text}` (expected any-failure, threshold:<pct> or always-zero)"
+                    ));
+                };
+                match pct.trim_end_matches('%').parse::<f64>() {
+                    Ok(pct) if (0.0..=100.0).contains(&pct) => Ok(Self::Threshold(pct)),
+                    _ => Err(format!(
+                        "invalid threshold `{pct}` (expected a percentage from 0 to 100)"
+                    )),
+                }
+            }
+        }
+    }
+}
+
+/// Where a session starts (`--start-at`), for picking up a session that died part way.
+/// Earlier runs are left out; later ones keep their numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StartAt {
+    /// Run number, counted across loops from 1: `37` or `run:37`.
+    Run(usize),
+    /// Task number in the first loop: `task:3`. Tasks before it are left out of the
+    /// first loop, whatever order their priorities run them in.
+    Task(usize),
+    /// First run of this loop: `loop:2`.
+    Loop(usize),
+}
+
+impl StartAt {
+    /// Whether run number `run` (from 1), of task `task_idx` in loop `loop_idx` (both
+    /// from 0), comes before the start.
+    pub fn skips(&self, run: usize, loop_idx: usize, task_idx: usize) -> bool {
+        match *self {
+            Self::Run(first) => run < first,
+            Self::Task(first) => loop_idx == 0 && task_idx + 1 < first,
+            Self::Loop(first) => loop_idx + 1 < first,
+        }
+    }
+}
+
+impl fmt::Display for StartAt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Run(run) => write!(f, "run:{run}"),
+            Self::Task(task) => write!(f, "task:{task}"),
+            Self::Loop(loop_number) => write!(f, "loop:{loop_number}"),
+        }
+    }
+}
+
+impl std::str::FromStr for StartAt {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (kind, number) = text.split_once(':').unwrap_or(("run", text));
+        let number = match number.parse::<usize>() {
+            Ok(number) if number > 0 => number,
+            _ => return Err(format!("invalid start `{text}` (expected a number from 1)")),
+        };
+        match kind {
+            "run" => Ok(Self::Run(number)),
+            "task" => Ok(Self::Task(number)),
+            "loop" => Ok(Self::Loop(number)),
+            _ => Err(format!(
+                "unknown start `{text}` (expected <run>, run:<n>, task:<n> or loop:<n>)"
+            )),
+        }
+    }
+}
+
+fn spool_file_path(dir: &Path) -> PathBuf {
+    static SPOOL_COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let seq = SPOOL_COUNTER.fetch_add(1, Ordering::Relaxed);
+    dir.join(format!("run-{}-{seq}.log", std::process::id()))
+}
+
+/// Header and spool target for the pinned output view.
+#[derive(Clone)]
+#[cfg_attr(not(feature = "tui"), allow(dead_code))]
+struct PinnedView {
+    header_lines: Vec<String>,
+    /// Session progress, shown as a status line when the run is part of a session.
+    progress: Option<RunProgress>,
+    spool_path: Option<PathBuf>,
+}
+
+impl PinnedView {
+    fn for_prompt(prompt: &str, options: &RunOptions) -> Self {
+        Self {
+            header_lines: current_task_header_or_default(prompt),
+            progress: current_run_progress(),
+            spool_path: options.spool_dir.as_deref().map(spool_file_path),
+        }
+    }
+
+    /// Rows taken by the header and status line.
+    #[cfg(feature = "tui")]
+    fn height(&self) -> usize {
+        self.header_lines.len() + 1
     }
 }
 
 /// Run a single codex conversation with the given prompt.
 /// Uses `codex exec --dangerously-bypass-approvals-and-sandbox` for full access.
-/// If `work_dir` is provided, passes `-C <dir>` to codex to set its working directory.
-/// Returns `Ok(true)` on success, `Ok(false)` on non-zero exit.
-pub async fn run_codex(
+/// If `options.work_dir` is provided, passes `-C <dir>` to codex to set its working directory,
+/// and `options.model` is passed as `--model`. Another [`RunOptions::agent`] is invoked its
+/// own way ([`Agent::args`]), and a [`RunOptions::runner_template`] as it says.
+/// The outcome is successful only when codex exits with status zero, or as the template
+/// maps exit codes and output.
+pub async fn run_codex(prompt: &str, options: &RunOptions) -> Result<RunOutcome, AgentLoopsError> {
+    let args = match &options.runner_template {
+        Some(template) => template.args(prompt, options),
+        None => options.agent.args(prompt, options),
+    };
+
+    let pinned = PinnedView::for_prompt(prompt, options);
+    let spool_path = pinned.spool_path.clone();
+    let exit = match &options.container {
+        Some(container) => {
+            let name = Container::next_name();
+            let args = container.run_args(&name, &options.codex_bin, &args, options);
+            let options = &RunOptions {
+                codex_bin: container.engine.clone(),
+                ..options.clone()
+            };
+            let exit = run_codex_platform(options, &args, pinned).await;
+            container.remove(&name).await;
+            exit?
+        }
+        None => match &options.remote {
+            Some(remote) => {
+                let args = remote.run_args(&options.codex_bin, &args, options);
+                let options = &RunOptions {
+                    codex_bin: remote.ssh.clone(),
+                    ..options.clone()
+                };
+                run_codex_platform(options, &args, pinned).await?
+            }
+            None => run_codex_platform(options, &args, pinned).await?,
+        },
+    };
+    let success = exit_succeeded(exit.status.code(), &exit.scan, options);
+    Ok(run_outcome(success, prompt, exit.scan, spool_path, options))
+}
+
+/// Whether a run that exited with `code` succeeded: status zero, or what the runner
+/// template counts as success.
+fn exit_succeeded(code: Option<i32>, scan: &OutputScan, options: &RunOptions) -> bool {
+    match &options.runner_template {
+        Some(template) => template.succeeded(code, scan.success_pattern_seen),
+        None => code == Some(0),
+    }
+}
+
+/// Outcome of a run of `prompt` that exited with or without `success`, given what its
+/// output showed.
+fn run_outcome(
+    success: bool,
     prompt: &str,
-    work_dir: Option<&Path>,
-    codex_bin: &str,
-) -> std::io::Result<bool> {
-    let mut args: Vec<String> = vec![
-        "exec".to_string(),
-        "--dangerously-bypass-approvals-and-sandbox".to_string(),
-    ];
-    if let Some(dir) = work_dir {
-        args.extend(["-C".to_string(), dir.to_string_lossy().to_string()]);
+    scan: OutputScan,
+    spool_path: Option<PathBuf>,
+    options: &RunOptions,
+) -> RunOutcome {
+    let usage = scan.usage;
+    RunOutcome {
+        success: success && !scan.over_budget && !scan.output_flood,
+        output_log: spool_path.filter(|path| path.exists()),
+        rate_limited: scan.rate_limited,
+        usage,
+        cost_usd: usage.cost(options.usd_per_1k_tokens),
+        over_budget: scan.over_budget,
+        output_flood: scan.output_flood,
+        changes: None,
+        artifacts: None,
+        final_message: scan.final_message,
+        check_passed: None,
+        cached: false,
+        cache_key: None,
+        sent_prompt: Some(prompt.to_string()),
     }
-    args.push(prompt.to_string());
+}
 
-    let pinned_header = current_task_header_or_default(prompt);
-    let status = run_codex_platform(codex_bin, &args, pinned_header).await?;
-    Ok(status.success())
+/// Play `fixture` back as the run of `prompt`: its output goes through the same
+/// decoding, redaction, scanning and rendering as a live child's, with the recorded
+/// pauses when `paced` is set.
+pub(crate) async fn replay_fixture(
+    fixture: &Fixture,
+    prompt: &str,
+    options: &RunOptions,
+    paced: bool,
+) -> Result<RunOutcome, AgentLoopsError> {
+    let pinned = PinnedView::for_prompt(prompt, options);
+    let spool_path = pinned.spool_path.clone();
+    let plain_spool = spool_path.clone().filter(|_| keeps_plain_spool(options));
+    let pinned = Some(pinned).filter(|_| cfg!(feature = "tui") && io::stdout().is_terminal());
+    let (tx, rx) = mpsc::unbounded_channel::<(OutputStream, Vec<u8>)>();
+    let feed = async move {
+        let mut previous = Duration::ZERO;
+        for event in &fixture.events {
+            if paced {
+                tokio::time::sleep(event.at.saturating_sub(previous)).await;
+                previous = event.at;
+            }
+            let stream = match event.stream {
+                FixtureStream::Stdout => OutputStream::Stdout,
+                FixtureStream::Stderr => OutputStream::Stderr,
+            };
+            if tx.send((stream, event.bytes.clone())).is_err() {
+                break;
+            }
+        }
+    };
+    let run = async {
+        let (scan, ()) = tokio::join!(forward_output(rx, pinned, plain_spool, options), feed);
+        scan.map_err(AgentLoopsError::RenderError)
+    };
+    tokio::select! {
+        scan = run => {
+            let scan = scan?;
+            let success = exit_succeeded(fixture.exit_code, &scan, options);
+            Ok(run_outcome(success, prompt, scan, spool_path, options))
+        }
+        () = options.cancel.cancelled() => Err(AgentLoopsError::Cancelled),
+    }
 }
 
 #[cfg(windows)]
 async fn run_codex_platform(
-    codex_bin: &str,
+    options: &RunOptions,
     args: &[String],
-    pinned_header: Vec<String>,
-) -> std::io::Result<ExitStatus> {
-    // Try running the binary directly first.
-    let mut direct_cmd = Command::new(codex_bin);
+    pinned: PinnedView,
+) -> Result<RunExit, AgentLoopsError> {
+    let codex_bin = options.codex_bin.as_str();
+    // Resolve through PATH/PATHEXT so npm `.cmd` shims are spawned directly; std escapes
+    // arguments for batch files itself, unlike a hand-built `cmd /C` line.
+    let program = launch::resolve_executable(codex_bin).unwrap_or_else(|| PathBuf::from(codex_bin));
+    let mut direct_cmd = Command::new(&program);
     direct_cmd.args(args);
-    match run_command_with_forwarded_output(direct_cmd, Some(pinned_header.clone())).await {
+    match run_command_with_forwarded_output(direct_cmd, Some(pinned.clone()), options).await {
         Ok(status) => Ok(status),
-        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
-            // Fallback: use `cmd /C` which resolves .cmd/.bat shims (e.g. npm-installed CLIs).
-            let mut cmd_args = vec!["/C".to_string(), codex_bin.to_string()];
-            cmd_args.extend(args.iter().cloned());
+        // InvalidInput: std refused an argument it cannot pass to a batch file safely.
+        Err(e)
+            if options.shell_fallback != ShellFallback::Disabled
+                && match &e {
+                    AgentLoopsError::BinaryNotFound { .. } => true,
+                    AgentLoopsError::SpawnFailed { source, .. } => {
+                        source.kind() == std::io::ErrorKind::InvalidInput
+                    }
+                    _ => false,
+                } =>
+        {
+            // Fallback: let `cmd` resolve the name, with every argument quoted and caret-escaped.
             let mut cmd = Command::new("cmd");
-            cmd.args(&cmd_args);
-            run_command_with_forwarded_output(cmd, Some(pinned_header)).await.map_err(|_| {
-                std::io::Error::new(
-                    std::io::ErrorKind::NotFound,
-                    format!("could not execute `{codex_bin}`; it was not found in PATH"),
-                )
-            })
+            cmd.raw_arg(launch::cmd_exe_command_line(codex_bin, args));
+            // A PTY would re-quote the raw command line, so keep this path on pipes.
+            let options = &RunOptions {
+                use_pty: false,
+                ..options.clone()
+            };
+            run_command_with_forwarded_output(cmd, Some(pinned), options)
+                .await
+                .map_err(|e| match e {
+                    AgentLoopsError::Timeout(_) | AgentLoopsError::Cancelled => e,
+                    _ => launch::binary_not_found_error(codex_bin, "it was not found in PATH"),
+                })
         }
+        Err(AgentLoopsError::BinaryNotFound { .. }) => Err(launch::binary_not_found_error(
+            codex_bin,
+            "it was not found and the cmd fallback is disabled",
+        )),
         Err(e) => Err(e),
     }
 }
 
 #[cfg(not(windows))]
 async fn run_codex_platform(
-    codex_bin: &str,
+    options: &RunOptions,
     args: &[String],
-    pinned_header: Vec<String>,
-) -> std::io::Result<ExitStatus> {
-    let mut direct_cmd = Command::new(codex_bin);
+    pinned: PinnedView,
+) -> Result<RunExit, AgentLoopsError> {
+    let codex_bin = options.codex_bin.as_str();
+    let program = launch::resolve_executable(codex_bin).unwrap_or_else(|| PathBuf::from(codex_bin));
+    let mut direct_cmd = Command::new(program);
     direct_cmd.args(args);
-    match run_command_with_forwarded_output(direct_cmd, Some(pinned_header.clone())).await {
+    match run_command_with_forwarded_output(direct_cmd, Some(pinned.clone()), options).await {
         Ok(status) => Ok(status),
-        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
-            match run_codex_via_shell(codex_bin, args, pinned_header.as_slice()).await {
-                Ok(status) => {
-                    if status.code() == Some(127) {
-                        return Err(std::io::Error::new(
-                            std::io::ErrorKind::NotFound,
-                            format!(
-                                "could not execute `{codex_bin}`; it was not found in PATH or shell startup configuration"
-                            ),
-                        ));
-                    }
-                    Ok(status)
+        Err(e @ AgentLoopsError::BinaryNotFound { .. }) => {
+            if options.shell_fallback == ShellFallback::Disabled {
+                return Err(launch::binary_not_found_error(
+                    codex_bin,
+                    "it was not found and the shell fallback is disabled",
+                ));
+            }
+            match run_codex_via_shell(options, args, pinned).await {
+                Ok(exit) if exit.status.code() == Some(127) => Err(launch::binary_not_found_error(
+                    codex_bin,
+                    "it was not found in PATH or shell startup configuration",
+                )),
+                Ok(status) => Ok(status),
+                Err(shell_e @ (AgentLoopsError::Timeout(_) | AgentLoopsError::Cancelled)) => {
+                    Err(shell_e)
                 }
-                Err(shell_e) => Err(std::io::Error::new(
-                    std::io::ErrorKind::NotFound,
-                    format!(
-                        "could not execute `{codex_bin}`; direct launch failed ({e}); shell fallback failed ({shell_e})"
-                    ),
+                Err(shell_e) => Err(launch::binary_not_found_error(
+                    codex_bin,
+                    &format!("direct launch failed ({e}); shell fallback failed ({shell_e})"),
                 )),
             }
         }
@@ -178,17 +876,58 @@ async fn run_codex_platform(
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 enum OutputStream {
     Stdout,
     Stderr,
 }
 
-async fn run_command_with_forwarded_output(
+/// A spawned child whose output is being read into the forwarding channel.
+enum ForwardedChild {
+    Piped {
+        child: tokio::process::Child,
+        stdout_task: tokio::task::JoinHandle<io::Result<()>>,
+        stderr_task: tokio::task::JoinHandle<io::Result<()>>,
+    },
+    #[cfg(feature = "tui")]
+    Pty(pty::PtyChild),
+}
+
+impl ForwardedChild {
+    fn pid(&self) -> Option<u32> {
+        match self {
+            Self::Piped { child, .. } => child.id(),
+            #[cfg(feature = "tui")]
+            Self::Pty(child) => child.pid(),
+        }
+    }
+
+    async fn wait(self) -> io::Result<ExitStatus> {
+        match self {
+            Self::Piped {
+                mut child,
+                stdout_task,
+                stderr_task,
+            } => {
+                await_reader_task(stdout_task, "stdout").await?;
+                await_reader_task(stderr_task, "stderr").await?;
+                child.wait().await
+            }
+            #[cfg(feature = "tui")]
+            Self::Pty(child) => child.wait().await,
+        }
+    }
+}
+
+fn spawn_piped(
     mut cmd: Command,
-    pinned_header: Option<Vec<String>>,
-) -> io::Result<ExitStatus> {
-    cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+    limits: &ResourceLimits,
+    tx: mpsc::UnboundedSender<(OutputStream, Vec<u8>)>,
+) -> io::Result<ForwardedChild> {
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    process_tree::isolate_process_group(&mut cmd, limits);
     let mut child = cmd.spawn()?;
 
     let stdout = child
@@ -200,34 +939,423 @@ async fn run_command_with_forwarded_output(
         .take()
         .ok_or_else(|| io::Error::other("failed to capture child stderr"))?;
 
-    let (tx, mut rx) = mpsc::unbounded_channel::<(OutputStream, Vec<u8>)>();
     let stdout_task = spawn_output_reader(stdout, OutputStream::Stdout, tx.clone());
-    let stderr_task = spawn_output_reader(stderr, OutputStream::Stderr, tx.clone());
-    drop(tx);
+    let stderr_task = spawn_output_reader(stderr, OutputStream::Stderr, tx);
+    Ok(ForwardedChild::Piped {
+        child,
+        stdout_task,
+        stderr_task,
+    })
+}
 
-    if let Some(header_lines) = pinned_header.filter(|_| io::stdout().is_terminal()) {
-        let mut renderer = PinnedOutputRenderer::new(header_lines)?;
-        while let Some((_stream, chunk)) = rx.recv().await {
-            renderer.push_chunk(&chunk)?;
+/// How a child ended, plus what was noticed in its output.
+struct RunExit {
+    status: ExitStatus,
+    scan: OutputScan,
+}
+
+async fn run_command_with_forwarded_output(
+    mut cmd: Command,
+    pinned: Option<PinnedView>,
+    options: &RunOptions,
+) -> Result<RunExit, AgentLoopsError> {
+    // A container or remote machine gets its environment and work dir through the
+    // command's arguments instead.
+    if options.container.is_none() && options.remote.is_none() {
+        apply_env(&mut cmd, options);
+    }
+    if (options.runner_template.is_some() || !options.agent.takes_work_dir_flag())
+        && options.remote.is_none()
+        && let Some(dir) = &options.work_dir
+    {
+        cmd.current_dir(dir);
+    }
+    let plain_spool = pinned
+        .as_ref()
+        .and_then(|view| view.spool_path.clone())
+        .filter(|_| keeps_plain_spool(options));
+    let pinned = pinned.filter(|_| cfg!(feature = "tui") && io::stdout().is_terminal());
+    let program = cmd.as_std().get_program().to_string_lossy().into_owned();
+    let command: Vec<String> = std::iter::once(program.clone())
+        .chain(
+            cmd.as_std()
+                .get_args()
+                .map(|arg| arg.to_string_lossy().into_owned()),
+        )
+        .collect();
+    let (tx, rx) = mpsc::unbounded_channel::<(OutputStream, Vec<u8>)>();
+    let child = match &pinned {
+        // The PTY spawn has no hook to set rlimits in before exec, so limited runs on
+        // Unix use pipes.
+        #[cfg(feature = "tui")]
+        Some(view) if options.use_pty && (cfg!(windows) || options.limits.is_empty()) => {
+            let rows = terminal_rows().saturating_sub(view.height()).max(1);
+            pty::spawn_in_pty(
+                cmd.as_std(),
+                u16::try_from(rows).unwrap_or(u16::MAX),
+                u16::try_from(terminal_cols()).unwrap_or(u16::MAX),
+                options.env_allowlist.is_some(),
+                tx,
+            )
+            .map(ForwardedChild::Pty)
         }
-        renderer.finish()?;
-    } else {
-        let mut out = tokio::io::stdout();
-        let mut err = tokio::io::stderr();
+        _ => spawn_piped(cmd, &options.limits, tx),
+    }
+    .map_err(|source| spawn_error(program.clone(), source))?;
+
+    let span = tracing::info_span!(
+        "child_process",
+        program,
+        pid = child.pid(),
+        exit_code = tracing::field::Empty,
+    );
+    let tree = ProcessTree::new(child.pid(), &options.limits);
+    let (rx, recording) = match &options.record_dir {
+        Some(dir) => {
+            let command = command
+                .iter()
+                .map(|word| {
+                    String::from_utf8_lossy(&options.redactor.redact(word.as_bytes())).into_owned()
+                })
+                .collect();
+            let recorder = FixtureRecorder::new(dir, command);
+            let (rx, recording) = record_output(rx, recorder, options.redactor.clone());
+            (rx, Some(recording))
+        }
+        None => (rx, None),
+    };
+    let run = async {
+        let scan = forward_output(rx, pinned, plain_spool, options)
+            .await
+            .map_err(AgentLoopsError::RenderError)?;
+        if scan.over_budget || scan.output_flood {
+            tree.kill();
+        }
+        let status = child.wait().await.map_err(AgentLoopsError::ChildIo)?;
+        if process_tree::hit_cpu_limit(&status, &options.limits) {
+            tracing::warn!("The agent was stopped for using up its CPU time limit.");
+        }
+        if let Some(code) = status.code() {
+            tracing::Span::current().record("exit_code", code);
+        }
+        if let Some(recording) = recording {
+            let saved = match recording.await {
+                Ok(recorder) => recorder.finish(status.code()),
+                Err(e) => Err(join_error_to_io(e)),
+            };
+            match saved {
+                Ok(path) => tracing::debug!("Recorded the run to {}", path.display()),
+                Err(e) => tracing::warn!("Cannot record the run as a fixture: {e}"),
+            }
+        }
+        Ok(RunExit { status, scan })
+    }
+    .instrument(span);
+    let timeout = async {
+        match options.timeout {
+            Some(limit) => tokio::time::sleep(limit).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::select! {
+        result = run => result,
+        () = timeout => {
+            tree.kill();
+            Err(AgentLoopsError::Timeout(options.timeout.unwrap_or_default()))
+        }
+        () = options.cancel.cancelled() => {
+            tree.kill();
+            Err(AgentLoopsError::Cancelled)
+        }
+    }
+}
+
+/// Pass child output through `recorder` on its way to the forwarder, with the secrets
+/// `redactor` knows masked in what is recorded. The task hands the recorder back once
+/// the child has closed its output.
+fn record_output(
+    mut rx: mpsc::UnboundedReceiver<(OutputStream, Vec<u8>)>,
+    mut recorder: FixtureRecorder,
+    redactor: Redactor,
+) -> (
+    mpsc::UnboundedReceiver<(OutputStream, Vec<u8>)>,
+    tokio::task::JoinHandle<FixtureRecorder>,
+) {
+    let (tx, forwarded) = mpsc::unbounded_channel();
+    let task = tokio::spawn(async move {
+        let mut stdout = StreamRedactor::new(&redactor);
+        let mut stderr = StreamRedactor::new(&redactor);
         while let Some((stream, chunk)) = rx.recv().await {
-            match stream {
-                OutputStream::Stdout => out.write_all(&chunk).await?,
-                OutputStream::Stderr => err.write_all(&chunk).await?,
+            let recorded = match stream {
+                OutputStream::Stdout => stdout.push(&chunk),
+                OutputStream::Stderr => stderr.push(&chunk),
+            };
+            if !recorded.is_empty() {
+                recorder.record(fixture_stream(stream), &recorded);
             }
+            // The forwarder stops early for runs over budget; keep recording regardless.
+            let _ = tx.send((stream, chunk));
+        }
+        for (stream, mut redactor) in [
+            (OutputStream::Stdout, stdout),
+            (OutputStream::Stderr, stderr),
+        ] {
+            let rest = redactor.finish();
+            if !rest.is_empty() {
+                recorder.record(fixture_stream(stream), &rest);
+            }
+        }
+        recorder
+    });
+    (forwarded, task)
+}
+
+fn fixture_stream(stream: OutputStream) -> FixtureStream {
+    match stream {
+        OutputStream::Stdout => FixtureStream::Stdout,
+        OutputStream::Stderr => FixtureStream::Stderr,
+    }
+}
+
+/// Classify a failed spawn; a missing program is reported as [`AgentLoopsError::BinaryNotFound`].
+fn spawn_error(program: String, source: io::Error) -> AgentLoopsError {
+    if source.kind() == io::ErrorKind::NotFound {
+        AgentLoopsError::BinaryNotFound {
+            program,
+            detail: source.to_string(),
+        }
+    } else {
+        AgentLoopsError::SpawnFailed { program, source }
+    }
+}
+
+/// Per-stream processing applied to raw child output before it is displayed:
+/// UTF-8 normalization, then secret redaction. The result is also scanned.
+struct StreamPipeline<'a> {
+    decoder: OutputDecoder,
+    redactor: StreamRedactor<'a>,
+    scanner: OutputScanner,
+    /// Raw bytes received from the child.
+    received: u64,
+    options: &'a RunOptions,
+}
+
+impl<'a> StreamPipeline<'a> {
+    fn new(options: &'a RunOptions) -> Self {
+        Self {
+            decoder: OutputDecoder::for_console(),
+            redactor: StreamRedactor::new(&options.redactor),
+            scanner: OutputScanner::new(
+                options
+                    .runner_template
+                    .as_ref()
+                    .and_then(|template| template.success_pattern.clone()),
+            ),
+            received: 0,
+            options,
+        }
+    }
+
+    /// Whether the spend reported on this stream has passed the per-run cost limit.
+    fn over_budget(&self) -> bool {
+        self.options.max_cost_per_run.is_some_and(|limit| {
+            self.scanner
+                .usage()
+                .cost(self.options.usd_per_1k_tokens)
+                .is_some_and(|cost| cost > limit)
+        })
+    }
+
+    fn push(&mut self, chunk: &[u8]) -> Vec<u8> {
+        self.received += chunk.len() as u64;
+        let decoded = self.decoder.push(chunk);
+        let out = self.redactor.push(&decoded);
+        self.scanner.observe(&out);
+        out
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        let decoded = self.decoder.finish();
+        let mut out = self.redactor.push(&decoded);
+        out.extend(self.redactor.finish());
+        self.scanner.observe(&out);
+        out
+    }
+
+    /// What the scanner noticed; call after [`StreamPipeline::finish`].
+    fn scan(&mut self) -> OutputScan {
+        let scan = self.scanner.finish();
+        OutputScan {
+            over_budget: self.over_budget(),
+            ..scan
+        }
+    }
+}
+
+/// Whether the child has written more than the per-run output limit on its two streams.
+fn output_flood(stdout: &StreamPipeline<'_>, stderr: &StreamPipeline<'_>) -> bool {
+    stdout
+        .options
+        .max_output_bytes
+        .is_some_and(|limit| stdout.received + stderr.received > limit)
+}
+
+/// Whether forwarding should stop because the run is about to be killed.
+pub(crate) fn run_cut_short(stdout: &StreamPipeline<'_>, stderr: &StreamPipeline<'_>) -> bool {
+    stdout.over_budget() || stderr.over_budget() || output_flood(stdout, stderr)
+}
+
+/// The scans of both streams, once forwarding has finished.
+fn finish_scan(stdout: &mut StreamPipeline<'_>, stderr: &mut StreamPipeline<'_>) -> OutputScan {
+    let flood = output_flood(stdout, stderr);
+    let scan = stdout.scan().merge(stderr.scan());
+    OutputScan {
+        output_flood: flood,
+        ..scan
+    }
+}
+
+/// Whether a run outside the pinned view keeps its full output log: for CI output,
+/// for callers that asked for it, and whenever `--grep`/`--grep-v` hide lines.
+fn keeps_plain_spool(options: &RunOptions) -> bool {
+    options.ci_output || options.capture_output || !options.line_filter.is_empty()
+}
+
+/// What happens to one output stream outside the pinned view: filtered, stamped and
+/// copied to the tee file on its way to the console, or only spooled for CI output.
+struct PlainStream<'a> {
+    filter: StreamFilter<'a>,
+    stamper: StreamStamper,
+    tee: StreamTee<'a>,
+    /// Full output log, when one is kept outside the pinned view.
+    spool: Option<StreamTee<'a>>,
+    /// Off for CI output, which only goes to the spool.
+    display: bool,
+}
+
+impl<'a> PlainStream<'a> {
+    fn new(options: &'a RunOptions, started: Option<Instant>, spool: Option<&'a TeeFile>) -> Self {
+        Self {
+            filter: StreamFilter::new(&options.line_filter),
+            stamper: StreamStamper::new(started),
+            tee: StreamTee::new(options.tee.as_ref()),
+            spool: spool.map(|file| StreamTee::new(Some(file))),
+            display: !options.ci_output,
         }
-        out.flush().await?;
-        err.flush().await?;
     }
 
-    await_reader_task(stdout_task, "stdout").await?;
-    await_reader_task(stderr_task, "stderr").await?;
+    /// Accept processed output and return what to display now.
+    fn push(&mut self, chunk: &[u8]) -> Vec<u8> {
+        if let Some(spool) = &mut self.spool {
+            spool.push(chunk);
+        }
+        if !self.display {
+            return Vec::new();
+        }
+        let shown = self.stamper.push(&self.filter.push(chunk));
+        self.tee.push(&shown);
+        shown
+    }
 
-    child.wait().await
+    /// Accept the stream's last output and return the rest to display.
+    fn finish(&mut self, chunk: &[u8]) -> Vec<u8> {
+        if let Some(spool) = &mut self.spool {
+            spool.push(chunk);
+            spool.finish();
+        }
+        if !self.display {
+            return Vec::new();
+        }
+        let mut rest = self.filter.push(chunk);
+        rest.extend(self.filter.finish());
+        let shown = self.stamper.push(&rest);
+        self.tee.push(&shown);
+        self.tee.finish();
+        shown
+    }
+}
+
+/// Drain child output into the pinned view (or plain stdout/stderr) until the child closes it.
+/// Outside the pinned view, output is also written to `plain_spool` when that is set.
+async fn forward_output(
+    mut rx: mpsc::UnboundedReceiver<(OutputStream, Vec<u8>)>,
+    pinned: Option<PinnedView>,
+    plain_spool: Option<PathBuf>,
+    options: &RunOptions,
+) -> io::Result<OutputScan> {
+    let mut stdout_pipeline = StreamPipeline::new(options);
+    let mut stderr_pipeline = StreamPipeline::new(options);
+
+    #[cfg(feature = "tui")]
+    if let Some(pinned) = pinned {
+        tui::forward_pinned(
+            rx,
+            pinned,
+            &mut stdout_pipeline,
+            &mut stderr_pipeline,
+            options,
+        )
+        .await?;
+        return Ok(finish_scan(&mut stdout_pipeline, &mut stderr_pipeline));
+    }
+    #[cfg(not(feature = "tui"))]
+    let _ = pinned;
+
+    let spool = match plain_spool {
+        Some(path) => {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            Some(TeeFile::create(&path)?)
+        }
+        None => None,
+    };
+    let started = options.timestamps.then(Instant::now);
+    let mut stdout_plain = PlainStream::new(options, started, spool.as_ref());
+    let mut stderr_plain = PlainStream::new(options, started, spool.as_ref());
+    let mut out = tokio::io::stdout();
+    let mut err = tokio::io::stderr();
+    let run_started = Instant::now();
+    let mut last_output = run_started;
+    let mut heartbeat_due = options.heartbeat.map(|every| run_started + every);
+    loop {
+        let received = tokio::select! {
+            received = rx.recv() => received,
+            () = tokio::time::sleep_until(heartbeat_due.unwrap_or(run_started).into()), if heartbeat_due.is_some() => {
+                let now = Instant::now();
+                let line = progress::heartbeat_line(now - last_output, now - run_started);
+                err.write_all(format!("{line}\n").as_bytes()).await?;
+                err.flush().await?;
+                heartbeat_due = options.heartbeat.map(|every| now + every);
+                continue;
+            }
+        };
+        let Some((stream, chunk)) = received else {
+            break;
+        };
+        last_output = Instant::now();
+        heartbeat_due = options.heartbeat.map(|every| last_output + every);
+        match stream {
+            OutputStream::Stdout => {
+                let shown = stdout_plain.push(&stdout_pipeline.push(&chunk));
+                out.write_all(&shown).await?;
+            }
+            OutputStream::Stderr => {
+                let shown = stderr_plain.push(&stderr_pipeline.push(&chunk));
+                err.write_all(&shown).await?;
+            }
+        }
+        if run_cut_short(&stdout_pipeline, &stderr_pipeline) {
+            break;
+        }
+    }
+    out.write_all(&stdout_plain.finish(&stdout_pipeline.finish()))
+        .await?;
+    err.write_all(&stderr_plain.finish(&stderr_pipeline.finish()))
+        .await?;
+    out.flush().await?;
+    err.flush().await?;
+    Ok(finish_scan(&mut stdout_pipeline, &mut stderr_pipeline))
 }
 
 fn spawn_output_reader<R>(
@@ -269,27 +1397,24 @@ fn join_error_to_io(err: tokio::task::JoinError) -> io::Error {
 
 #[cfg(not(windows))]
 async fn run_codex_via_shell(
-    codex_bin: &str,
+    options: &RunOptions,
     args: &[String],
-    pinned_header: &[String],
-) -> std::io::Result<std::process::ExitStatus> {
-    let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string());
-    let shell_name = Path::new(&shell)
-        .file_name()
-        .and_then(|s| s.to_str())
-        .unwrap_or_default();
+    pinned: PinnedView,
+) -> Result<RunExit, AgentLoopsError> {
+    let codex_bin = options.codex_bin.as_str();
+    let shell = options
+        .shell
+        .as_ref()
+        .map(|s| s.to_string_lossy().into_owned())
+        .or_else(|| std::env::var("SHELL").ok())
+        .unwrap_or_else(|| "/bin/sh".to_string());
 
     let mut cmd = Command::new(&shell);
-    if shell_name == "zsh" || shell_name == "bash" {
-        // Interactive mode makes alias/function-based codex definitions available.
-        cmd.arg("-i");
-    }
-
-    cmd.arg("-lc")
+    cmd.args(launch::shell_fallback_flags(&shell, options.shell_fallback))
         .arg("\"$0\" \"$@\"")
         .arg(codex_bin)
         .args(args);
-    run_command_with_forwarded_output(cmd, Some(pinned_header.to_vec())).await
+    run_command_with_forwarded_output(cmd, Some(pinned), options).await
 }
 
 fn task_header_lines(
@@ -299,203 +1424,318 @@ fn task_header_lines(
     loops: usize,
     task_idx: usize,
     task_total: usize,
-    prompt: &str,
+    task: &TaskSpec,
 ) -> [String; 4] {
+    let mut position = format!(
+        "Run {run_idx}/{total_runs} | Loop {}/{loops} | Task {}/{}",
+        loop_idx + 1,
+        task_idx + 1,
+        task_total
+    );
+    if task.priority != 0 {
+        position.push_str(&format!(" | Priority {}", task.priority));
+    }
     [
         "=== Agent Loops ===".to_string(),
-        format!(
-            "Run {run_idx}/{total_runs} | Loop {}/{loops} | Task {}/{}",
-            loop_idx + 1,
-            task_idx + 1,
-            task_total
-        ),
+        position,
         format!(
             "Current task: {}",
-            truncate_display(prompt, MAX_CURRENT_TASK_LEN)
+            truncate_display(&task.prompt, current_task_len())
         ),
         "----------------------------------------".to_string(),
     ]
 }
 
-#[derive(Clone, Copy)]
-enum AnsiParseState {
-    Normal,
-    Esc,
-    Csi,
-    Osc,
-    OscEsc,
-}
-
-struct PinnedOutputRenderer {
-    header_lines: Vec<String>,
-    output_lines: VecDeque<String>,
-    current_line: String,
-    ansi_state: AnsiParseState,
-}
-
-impl PinnedOutputRenderer {
-    fn new(header_lines: Vec<String>) -> io::Result<Self> {
-        let renderer = Self {
-            header_lines,
-            output_lines: VecDeque::new(),
-            current_line: String::new(),
-            ansi_state: AnsiParseState::Normal,
-        };
-
-        let mut out = io::stdout();
-        // Hide cursor and clear screen before entering redraw mode.
-        write!(out, "\x1b[?25l\x1b[2J\x1b[H")?;
-        out.flush()?;
-
-        renderer.render()?;
-        Ok(renderer)
+/// Fit a line into `max_cols` terminal columns, ending it with "..." when cut.
+pub fn fit_terminal_line(line: &str, max_cols: usize) -> String {
+    if max_cols == 0 {
+        return String::new();
     }
 
-    fn push_chunk(&mut self, chunk: &[u8]) -> io::Result<()> {
-        let mut sanitized = Vec::with_capacity(chunk.len());
-        for &b in chunk {
-            self.consume_byte(b, &mut sanitized);
-        }
-        if sanitized.is_empty() {
-            return Ok(());
-        }
-
-        let text = String::from_utf8_lossy(&sanitized);
-        for ch in text.chars() {
-            match ch {
-                '\n' => self.push_current_line(),
-                _ => self.current_line.push(ch),
-            }
-        }
+    if display_width(line) <= max_cols {
+        return line.to_string();
+    }
 
-        self.render()
+    if max_cols <= 3 {
+        return ".".repeat(max_cols);
     }
 
-    fn finish(&mut self) -> io::Result<()> {
-        if !self.current_line.is_empty() {
-            self.push_current_line();
-        }
-        self.render()?;
+    format!("{}...", take_width(line, max_cols - 3))
+}
 
-        let mut out = io::stdout();
-        write!(out, "\x1b[?25h")?;
-        out.flush()
+/// Split a line into rows of at most `max_cols` terminal columns without breaking graphemes.
+/// A grapheme wider than `max_cols` gets a row of its own.
+pub fn wrap_terminal_line(line: &str, max_cols: usize) -> Vec<String> {
+    if max_cols == 0 {
+        return Vec::new();
     }
 
-    fn consume_byte(&mut self, b: u8, out: &mut Vec<u8>) {
-        match self.ansi_state {
-            AnsiParseState::Normal => match b {
-                0x1b => self.ansi_state = AnsiParseState::Esc,
-                b'\r' => out.push(b'\n'),
-                b'\n' | b'\t' => out.push(b),
-                0x20..=0x7e | 0x80..=0xff => out.push(b),
-                _ => {}
-            },
-            AnsiParseState::Esc => match b {
-                b'[' => self.ansi_state = AnsiParseState::Csi,
-                b']' => self.ansi_state = AnsiParseState::Osc,
-                _ => self.ansi_state = AnsiParseState::Normal,
-            },
-            AnsiParseState::Csi => {
-                if (0x40..=0x7e).contains(&b) {
-                    self.ansi_state = AnsiParseState::Normal;
-                }
-            }
-            AnsiParseState::Osc => match b {
-                0x07 => self.ansi_state = AnsiParseState::Normal,
-                0x1b => self.ansi_state = AnsiParseState::OscEsc,
-                _ => {}
-            },
-            AnsiParseState::OscEsc => {
-                if b == b'\\' {
-                    self.ansi_state = AnsiParseState::Normal;
-                } else {
-                    self.ansi_state = AnsiParseState::Osc;
-                }
-            }
+    let mut rows = Vec::new();
+    let mut row = String::new();
+    let mut row_width = 0;
+    for grapheme in line.graphemes(true) {
+        let width = display_width(grapheme);
+        if row_width + width > max_cols && !row.is_empty() {
+            rows.push(std::mem::take(&mut row));
+            row_width = 0;
         }
+        row.push_str(grapheme);
+        row_width += width;
     }
+    rows.push(row);
+    rows
+}
 
-    fn push_current_line(&mut self) {
-        self.output_lines
-            .push_back(std::mem::take(&mut self.current_line));
-        while self.output_lines.len() > MAX_RENDERED_OUTPUT_LINES {
-            self.output_lines.pop_front();
-        }
-    }
+fn terminal_rows() -> usize {
+    std::env::var("LINES")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|rows| *rows > 0)
+        .unwrap_or(24)
+}
 
-    fn render(&self) -> io::Result<()> {
-        let rows = terminal_rows();
-        let cols = terminal_cols();
-        let body_rows = rows.saturating_sub(self.header_lines.len());
+fn terminal_cols() -> usize {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|cols| *cols > 0)
+        .unwrap_or(120)
+}
 
-        let mut visible_lines: Vec<&str> = self.output_lines.iter().map(String::as_str).collect();
-        if !self.current_line.is_empty() {
-            visible_lines.push(self.current_line.as_str());
-        }
-        let start = visible_lines.len().saturating_sub(body_rows);
-        let visible_tail = &visible_lines[start..];
+/// Session-level knobs for [`orchestrate_with`].
+#[derive(Debug, Clone, Default)]
+pub struct OrchestrateOptions {
+    /// Open each prompt in an editor right before it is sent.
+    pub edit_prompts: bool,
+    /// Editor command used when `edit_prompts` is set.
+    /// Falls back to `$VISUAL`, then `$EDITOR`, then a platform default.
+    pub editor: Option<String>,
+    /// Stops the session before the next run once cancelled.
+    pub cancel: CancelToken,
+    /// Stop the session once this many runs in a row have failed.
+    pub max_consecutive_failures: Option<usize>,
+    /// At `max_consecutive_failures`, ask on the terminal whether to go on instead of
+    /// stopping. Without a terminal the session stops.
+    pub confirm_failure_streak: bool,
+    /// How many times a rate-limited run is retried before it counts as failed.
+    pub rate_limit_retries: usize,
+    /// Wait before the first rate-limit retry; doubles with every further attempt.
+    pub rate_limit_cooldown: Duration,
+    /// Start at most this many runs, retries included, in any rolling hour.
+    pub max_runs_per_hour: Option<usize>,
+    /// Stop the session once the dollars spent by its runs reach this amount.
+    pub max_session_cost: Option<f64>,
+    /// Git work tree snapshotted around each run to report what the run changed.
+    pub git_work_dir: Option<PathBuf>,
+    /// Mark a run FAILED when it leaves `git_work_dir` unchanged.
+    pub require_changes: bool,
+    /// With `require_changes`, re-run a task that made no changes up to this many times,
+    /// telling the agent its previous attempt changed nothing.
+    pub no_change_retries: usize,
+    /// Checkpoint `git_work_dir` before each run and restore it when the run fails, so
+    /// a bad run's edits do not carry into later ones.
+    pub rollback_on_failure: bool,
+    /// Put the session's work on `agent-loops/<date>/...` branches in `git_work_dir`.
+    pub branch_strategy: Option<BranchStrategy>,
+    /// Daily local-time windows in which no run starts; the session waits them out.
+    pub blackouts: Vec<Blackout>,
+    /// Ring the terminal bell when a run fails.
+    pub bell_on_failure: bool,
+    /// Show a desktop notification when a run fails.
+    pub desktop_notify: bool,
+    /// Receives a copy of the run headers, footers and separators as they are printed.
+    pub tee: Option<TeeFile>,
+    /// Print one summary line per run instead of its header and footer, for CI logs.
+    /// Pair with [`RunOptions::ci_output`].
+    pub ci_output: bool,
+    /// Add the tasks a successful run asks for in its output ([`tasks::parse_added_tasks`])
+    /// to the session, up to this many in all. They run after the current loop's
+    /// remaining tasks, or before them when their [`TaskSpec::priority`] is higher, and
+    /// in every later loop. Needs [`RunOutcome::output_log`], so pair
+    /// with [`RunOptions::capture_output`].
+    pub max_added_tasks: Option<usize>,
+    /// Leave out the runs before this point, keeping the numbering of the rest.
+    pub start_at: Option<StartAt>,
+    /// Check the free disk space before each run.
+    pub disk_guard: Option<DiskGuard>,
+    /// Wait before each run while an attached terminal has paused the session.
+    pub control: Option<SessionControl>,
+    /// Copy the files a run leaves in [`OrchestrateOptions::git_work_dir`] that match
+    /// these patterns to a dir of the run's own.
+    pub artifacts: Option<ArtifactCollector>,
+}
 
-        let mut out = io::stdout();
-        write!(out, "\x1b[H")?;
-        for line in &self.header_lines {
-            writeln!(out, "\x1b[2K{}", fit_terminal_line(line, cols))?;
-        }
-        for line in visible_tail {
-            writeln!(out, "\x1b[2K{}", fit_terminal_line(line, cols))?;
+/// Remove and return the task of `pending` to run next: the one with the highest
+/// [`TaskSpec::priority`], the first in the list among equals.
+fn take_next_task(pending: &mut Vec<usize>, tasks: &[TaskSpec]) -> Option<usize> {
+    let at = (0..pending.len())
+        .min_by_key(|&at| (std::cmp::Reverse(tasks[pending[at]].priority), pending[at]))?;
+    Some(pending.remove(at))
+}
+
+/// The tasks `outcome`'s output adds for a run of `task`, without ones already in
+/// `tasks`. They inherit the agent, model and environment of `task` unless they set
+/// their own.
+fn added_tasks(task: &TaskSpec, outcome: &RunOutcome, tasks: &[TaskSpec]) -> Vec<TaskSpec> {
+    let Some(path) = &outcome.output_log else {
+        return Vec::new();
+    };
+    let output = match std::fs::read(path) {
+        Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+        Err(e) => {
+            tracing::warn!("Cannot read {} for added tasks: {e}", path.display());
+            return Vec::new();
         }
-        for _ in visible_tail.len()..body_rows {
-            writeln!(out, "\x1b[2K")?;
+    };
+    // Agents echo their prompt; markers in it, or in the text wrapped around it, are the
+    // operator's, not the agent's. Without the echo the two cannot be told apart.
+    let sent = outcome.sent_prompt.as_deref().unwrap_or(&task.prompt);
+    let Some(at) = output.rfind(sent) else {
+        tracing::debug!("Not adding tasks: the output does not echo the prompt.");
+        return Vec::new();
+    };
+    let output = &output[at + sent.len()..];
+    let mut added: Vec<TaskSpec> = Vec::new();
+    for new in tasks::parse_added_tasks(output) {
+        if tasks.iter().chain(&added).any(|t| t.prompt == new.prompt) {
+            continue;
         }
-        write!(out, "\x1b[J")?;
-        out.flush()
+        added.push(TaskSpec {
+            codex_bin: new.codex_bin.or_else(|| task.codex_bin.clone()),
+            model: new.model.or_else(|| task.model.clone()),
+            env: if new.env.is_empty() {
+                task.env.clone()
+            } else {
+                new.env
+            },
+            env_allowlist: new.env_allowlist.or_else(|| task.env_allowlist.clone()),
+            ..new
+        });
     }
+    added
 }
 
-impl Drop for PinnedOutputRenderer {
-    fn drop(&mut self) {
-        let mut out = io::stdout();
-        let _ = write!(out, "\x1b[?25h");
-        let _ = out.flush();
+/// Ring the bell and/or show a desktop notification for a failed run, as configured.
+async fn alert_failure(
+    options: &OrchestrateOptions,
+    run_idx: usize,
+    total: usize,
+    task: &TaskSpec,
+) {
+    if options.bell_on_failure {
+        notify::ring_bell();
+    }
+    if options.desktop_notify {
+        let label = task.name.as_deref().unwrap_or(&task.prompt);
+        let body = format!(
+            "Run {run_idx}/{total} failed: {}",
+            truncate_display(label, display_len())
+        );
+        if let Err(e) = notify::desktop_notify("agent-loops", &body).await {
+            tracing::warn!("Cannot show a desktop notification: {e}");
+        }
     }
 }
 
-fn fit_terminal_line(line: &str, max_cols: usize) -> String {
-    if max_cols == 0 {
-        return String::new();
-    }
+/// Appended to the prompt when a run is retried for making no changes.
+const NO_CHANGES_REMINDER: &str = "Your previous attempt at this task made no changes to the repository. Make the required changes to the files now instead of only describing them.";
 
-    let total_chars = line.chars().count();
-    if total_chars <= max_cols {
-        return line.to_string();
-    }
+/// What changed in [`OrchestrateOptions::git_work_dir`] since the `before` snapshot.
+async fn changes_since(options: &OrchestrateOptions, before: Option<&str>) -> Option<DiffStat> {
+    let dir = options.git_work_dir.as_deref()?;
+    let after = git::snapshot(dir).await.ok()?;
+    git::diff_stat(dir, before?, &after).await.ok()
+}
 
-    if max_cols <= 3 {
-        return ".".repeat(max_cols);
-    }
+/// Longest wait between rate-limit retries.
+const MAX_RATE_LIMIT_COOLDOWN: Duration = Duration::from_secs(30 * 60);
+
+/// Wait before rate-limit retry number `attempt` (1-based): `base` doubled per earlier
+/// attempt, capped at [`MAX_RATE_LIMIT_COOLDOWN`].
+pub fn rate_limit_backoff(base: Duration, attempt: usize) -> Duration {
+    let factor = 1u32 << attempt.saturating_sub(1).min(16);
+    base.saturating_mul(factor).min(MAX_RATE_LIMIT_COOLDOWN)
+}
 
-    let mut out = String::new();
-    for ch in line.chars().take(max_cols - 3) {
-        out.push(ch);
+/// Ask `question` on the terminal and wait for a yes/no answer.
+/// Anything but `y`/`yes`, or stdin not being a terminal, counts as no.
+pub(crate) async fn confirm(question: &str) -> bool {
+    if !io::stdin().is_terminal() {
+        return false;
     }
-    out.push_str("...");
-    out
+    print!("{question} [y/N] ");
+    let _ = io::Write::flush(&mut io::stdout());
+    let answer = tokio::task::spawn_blocking(|| {
+        let mut line = String::new();
+        io::stdin().read_line(&mut line).map(|_| line)
+    })
+    .await;
+    matches!(
+        answer,
+        Ok(Ok(line)) if matches!(line.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+    )
 }
 
-fn terminal_rows() -> usize {
-    std::env::var("LINES")
+fn default_editor() -> String {
+    std::env::var("VISUAL")
         .ok()
-        .and_then(|v| v.parse::<usize>().ok())
-        .filter(|rows| *rows > 0)
-        .unwrap_or(24)
+        .filter(|v| !v.trim().is_empty())
+        .or_else(|| {
+            std::env::var("EDITOR")
+                .ok()
+                .filter(|v| !v.trim().is_empty())
+        })
+        .unwrap_or_else(|| {
+            if cfg!(windows) {
+                "notepad".to_string()
+            } else {
+                "vi".to_string()
+            }
+        })
 }
 
-fn terminal_cols() -> usize {
-    std::env::var("COLUMNS")
-        .ok()
-        .and_then(|v| v.parse::<usize>().ok())
-        .filter(|cols| *cols > 0)
-        .unwrap_or(120)
+/// Open `prompt` in `editor` (a command line such as `code --wait`) and return the edited text.
+/// Trailing newlines added by the editor are stripped. An empty result keeps the original prompt.
+pub async fn edit_prompt_in_editor(prompt: &str, editor: Option<&str>) -> io::Result<String> {
+    static EDIT_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let editor = editor.map(ToOwned::to_owned).unwrap_or_else(default_editor);
+    let mut parts = editor.split_whitespace();
+    let program = parts
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "editor command is empty"))?;
+
+    let seq = EDIT_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let path = std::env::temp_dir().join(format!(
+        "agent-loops-prompt-{}-{seq}.md",
+        std::process::id()
+    ));
+    std::fs::write(&path, prompt)?;
+
+    let status = Command::new(program)
+        .args(parts)
+        .arg(&path)
+        .status()
+        .await
+        .and_then(|status| {
+            if status.success() {
+                Ok(())
+            } else {
+                Err(io::Error::other(format!(
+                    "editor `{editor}` exited with {status}"
+                )))
+            }
+        });
+    let edited = status.and_then(|()| std::fs::read_to_string(&path));
+    let _ = std::fs::remove_file(&path);
+
+    let edited = edited?;
+    let edited = edited.trim_end_matches(['\r', '\n']);
+    if edited.trim().is_empty() {
+        Ok(prompt.to_string())
+    } else {
+        Ok(edited.to_string())
+    }
 }
 
 /// Core orchestration logic: run all prompts in order, repeating `loops` times.
@@ -509,41 +1749,628 @@ where
     F: Fn(String) -> Fut,
     Fut: std::future::Future<Output = std::io::Result<bool>>,
 {
+    orchestrate_with(prompts, loops, &OrchestrateOptions::default(), runner)
+        .await
+        .into_iter()
+        .map(|record| (record.loop_idx, record.task_idx, record.outcome.success))
+        .collect()
+}
+
+/// Executes a single prompt for the orchestrator.
+pub trait Runner {
+    fn run(
+        &self,
+        task: &TaskSpec,
+    ) -> impl std::future::Future<Output = Result<RunOutcome, AgentLoopsError>>;
+}
+
+/// A borrowed runner, so wrapping runners can be given one that is still inspected after.
+impl<R: Runner> Runner for &R {
+    fn run(
+        &self,
+        task: &TaskSpec,
+    ) -> impl std::future::Future<Output = Result<RunOutcome, AgentLoopsError>> {
+        (**self).run(task)
+    }
+}
+
+/// [`Runner`] that launches codex through [`run_codex`], with each task's settings
+/// applied on top of its options.
+#[derive(Debug, Clone, Default)]
+pub struct CodexRunner {
+    pub options: RunOptions,
+}
+
+impl CodexRunner {
+    pub fn new(options: RunOptions) -> Self {
+        Self { options }
+    }
+}
+
+impl Runner for CodexRunner {
+    async fn run(&self, task: &TaskSpec) -> Result<RunOutcome, AgentLoopsError> {
+        let options = self.options.for_task(task);
+        let work_dir = options.work_dir.as_deref().unwrap_or(Path::new("."));
+        if options.raw_prompts {
+            return run_codex(&task.prompt, &options).await;
+        }
+        let prompt = options.prompt_wrap.apply(&task.prompt);
+        let prompt = prompt::render_prompt(&prompt, &options.prompt_vars, work_dir)
+            .map_err(AgentLoopsError::Prompt)?;
+        run_codex(&prompt, &options).await
+    }
+}
+
+/// Adapts a closure returning `io::Result` to [`Runner`].
+struct FnRunner<F>(F);
+
+impl<F, Fut, O> Runner for FnRunner<F>
+where
+    F: Fn(String) -> Fut,
+    Fut: std::future::Future<Output = std::io::Result<O>>,
+    O: Into<RunOutcome>,
+{
+    async fn run(&self, task: &TaskSpec) -> Result<RunOutcome, AgentLoopsError> {
+        Ok((self.0)(task.prompt.clone()).await?.into())
+    }
+}
+
+/// Result, time taken, check result, kill reason, changes, cost and log location of a
+/// finished run, then a blank line.
+fn print_run_footer(
+    options: &OrchestrateOptions,
+    run_idx: usize,
+    total_runs: usize,
+    outcome: &RunOutcome,
+    took: Duration,
+) {
+    let report = |line: &str| println_tee(options.tee.as_ref(), line);
+    report(&format!(
+        "[Run {run_idx}/{total_runs}] Result: {}{}",
+        theme::theme().outcome(outcome.success),
+        if outcome.cached { " (cached)" } else { "" }
+    ));
+    report(&format!(
+        "[Run {run_idx}/{total_runs}] Took: {}",
+        progress::format_run_time(took)
+    ));
+    match outcome.check_passed {
+        Some(true) => report(&format!("[Run {run_idx}/{total_runs}] Check: passed")),
+        Some(false) => report(&format!("[Run {run_idx}/{total_runs}] Check: failed")),
+        None => {}
+    }
+    if outcome.over_budget {
+        report(&format!(
+            "[Run {run_idx}/{total_runs}] Killed: spend exceeded the per-run cost limit"
+        ));
+    }
+    if outcome.output_flood {
+        report(&format!(
+            "[Run {run_idx}/{total_runs}] Killed: output exceeded the per-run size limit"
+        ));
+    }
+    match &outcome.changes {
+        Some(changes) if changes.is_empty() => {
+            report(&format!("[Run {run_idx}/{total_runs}] Changes: none"));
+        }
+        Some(changes) => report(&format!("[Run {run_idx}/{total_runs}] Changes: {changes}")),
+        None => {}
+    }
+    match (outcome.cost_usd, outcome.usage.tokens) {
+        (Some(cost), Some(tokens)) => report(&format!(
+            "[Run {run_idx}/{total_runs}] Cost: ${cost:.4} ({tokens} tokens)"
+        )),
+        (Some(cost), None) => report(&format!("[Run {run_idx}/{total_runs}] Cost: ${cost:.4}")),
+        (None, Some(tokens)) => report(&format!("[Run {run_idx}/{total_runs}] Tokens: {tokens}")),
+        (None, None) => {}
+    }
+    if let Some(message) = &outcome.final_message {
+        report(&format!("[Run {run_idx}/{total_runs}] Final message:"));
+        for line in message.lines() {
+            report(&format!("  {line}"));
+        }
+    }
+    if let Some(dir) = &outcome.artifacts {
+        report(&format!(
+            "[Run {run_idx}/{total_runs}] Artifacts: {}",
+            dir.display()
+        ));
+    }
+    if let Some(path) = &outcome.output_log {
+        report(&format!(
+            "[Run {run_idx}/{total_runs}] Full output: {}",
+            path.display()
+        ));
+    }
+    report("");
+}
+
+/// One line per run for [`OrchestrateOptions::ci_output`], e.g.
+/// `[12/60] task 3 loop 4 OK 4m02s`, followed by the cost and, for failed runs, the log.
+fn print_run_summary_line(
+    options: &OrchestrateOptions,
+    run_idx: usize,
+    total_runs: usize,
+    loop_idx: usize,
+    task_idx: usize,
+    outcome: &RunOutcome,
+    took: Duration,
+) {
+    let mut line = format!(
+        "[{run_idx}/{total_runs}] task {} loop {} {} {}",
+        task_idx + 1,
+        loop_idx + 1,
+        theme::theme().outcome(outcome.success),
+        progress::format_run_time(took)
+    );
+    if outcome.cached {
+        line.push_str(" cached");
+    }
+    if outcome.over_budget {
+        line.push_str(" over-budget");
+    }
+    if outcome.output_flood {
+        line.push_str(" output-flood");
+    }
+    if outcome.check_passed == Some(false) {
+        line.push_str(" check-failed");
+    }
+    if let Some(cost) = outcome.cost_usd {
+        line.push_str(&format!(" ${cost:.4}"));
+    }
+    if !outcome.success
+        && let Some(path) = &outcome.output_log
+    {
+        line.push_str(&format!(" log {}", path.display()));
+    }
+    println_tee(options.tee.as_ref(), &line);
+}
+
+/// Like [`orchestrate`], with session options applied, returning full run records.
+/// Prompts edited during the session replace the originals for all later loops.
+/// An `Interrupted` error from `runner` cancels the session.
+pub async fn orchestrate_with<F, Fut, O>(
+    prompts: &[String],
+    loops: usize,
+    options: &OrchestrateOptions,
+    runner: F,
+) -> Vec<RunRecord>
+where
+    F: Fn(String) -> Fut,
+    Fut: std::future::Future<Output = std::io::Result<O>>,
+    O: Into<RunOutcome>,
+{
+    let tasks: Vec<TaskSpec> = prompts.iter().map(TaskSpec::new).collect();
+    orchestrate_runner(&tasks, loops, options, &FnRunner(runner)).await
+}
+
+/// A tracing event from a session, also printed the way the library did before it used
+/// tracing (info on stdout, the rest on stderr) when the program installed no subscriber,
+/// so library users still see why a session stopped.
+macro_rules! session_event {
+    ($level:ident, parent: $span:expr, $($arg:tt)+) => {{
+        tracing::$level!(parent: $span, $($arg)+);
+        session_event!(@print $level, $($arg)+);
+    }};
+    ($level:ident, $($arg:tt)+) => {{
+        tracing::$level!($($arg)+);
+        session_event!(@print $level, $($arg)+);
+    }};
+    (@print info, $($arg:tt)+) => {
+        if no_subscriber() {
+            println!($($arg)+);
+        }
+    };
+    (@print $level:ident, $($arg:tt)+) => {
+        if no_subscriber() {
+            eprintln!($($arg)+);
+        }
+    };
+}
+
+/// Whether tracing events go nowhere: no subscriber is installed, globally or for the
+/// current thread.
+fn no_subscriber() -> bool {
+    tracing::dispatcher::get_default(|dispatch| dispatch.is::<tracing::subscriber::NoSubscriber>())
+}
+
+/// What a session has used up against its limits: its spend, its runs in the last hour
+/// and its current streak of failed runs.
+#[derive(Debug, Clone, Default)]
+pub struct SessionState {
+    pub cost_usd: f64,
+    pub consecutive_failures: usize,
+    throttle: Option<RunThrottle>,
+    /// A limit stopped the session; later task lists sharing the state do not start.
+    pub stopped: bool,
+}
+
+/// Like [`orchestrate_with`], driving a [`Runner`] over full task specs.
+/// [`AgentLoopsError::Cancelled`] from the runner cancels the session.
+pub async fn orchestrate_runner<R: Runner>(
+    tasks: &[TaskSpec],
+    loops: usize,
+    options: &OrchestrateOptions,
+    runner: &R,
+) -> Vec<RunRecord> {
+    orchestrate_runner_in(tasks, loops, options, runner, &mut SessionState::default()).await
+}
+
+/// [`orchestrate_runner`] as part of a larger session, such as one repository of a
+/// workspace: the session cost, run limit per hour and failure streak carry on from
+/// `state`, and are left there for the next part.
+pub async fn orchestrate_runner_in<R: Runner>(
+    tasks: &[TaskSpec],
+    loops: usize,
+    options: &OrchestrateOptions,
+    runner: &R,
+    state: &mut SessionState,
+) -> Vec<RunRecord> {
+    if state.stopped {
+        return Vec::new();
+    }
+    if state.throttle.is_none() {
+        state.throttle = options.max_runs_per_hour.map(RunThrottle::per_hour);
+    }
+    let mut tasks = tasks.to_vec();
     let mut results = Vec::new();
-    let total_runs = prompts.len() * loops;
+    let mut total_runs = tasks.len() * loops;
+    let mut run_idx = 0;
+    match options.start_at {
+        Some(StartAt::Run(run)) if run > total_runs => session_event!(
+            warn,
+            "The session starts at run {run}, but it only has {total_runs} run(s)."
+        ),
+        Some(StartAt::Task(task)) if task > tasks.len() => session_event!(
+            warn,
+            "The session starts at task {task}, but it only has {} task(s).",
+            tasks.len()
+        ),
+        Some(StartAt::Loop(loop_number)) if loop_number > loops => session_event!(
+            warn,
+            "The session starts at loop {loop_number}, but it only has {loops} loop(s)."
+        ),
+        Some(StartAt::Run(1) | StartAt::Task(1) | StartAt::Loop(1)) | None => {}
+        Some(start) => session_event!(info, "Starting at {start}."),
+    }
+    let mut added_count = 0;
+    let mut estimator = ProgressEstimator::new();
+    let mut branches = match (options.branch_strategy, &options.git_work_dir) {
+        (Some(strategy), Some(dir)) => match SessionBranches::start(dir, strategy).await {
+            Ok(branches) => Some(branches),
+            Err(e) => {
+                session_event!(
+                    warn,
+                    "Cannot set up session branches, staying on the current branch: {e}"
+                );
+                None
+            }
+        },
+        _ => None,
+    };
 
-    for loop_idx in 0..loops {
-        for (task_idx, prompt) in prompts.iter().enumerate() {
-            let run_idx = loop_idx * prompts.len() + task_idx + 1;
+    let session_span = tracing::info_span!("session", tasks = tasks.len(), loops, total_runs);
+    'session: for loop_idx in 0..loops {
+        let loop_span = tracing::info_span!(parent: &session_span, "loop", index = loop_idx + 1);
+        let mut pending: Vec<usize> = (0..tasks.len()).collect();
+        while let Some(task_idx) = take_next_task(&mut pending, &tasks) {
+            if options.cancel.is_cancelled() {
+                session_event!(info, "Session cancelled; skipping remaining runs.");
+                break 'session;
+            }
+            run_idx += 1;
+            if options
+                .start_at
+                .is_some_and(|start| start.skips(run_idx, loop_idx, task_idx))
+            {
+                continue;
+            }
+            if let Some(guard) = &options.disk_guard
+                && !guard.wait_for_space(&options.cancel).await
+            {
+                break 'session;
+            }
+            if let Some(control) = &options.control
+                && !control.wait_while_paused(&options.cancel).await
+            {
+                session_event!(info, "Session cancelled; skipping remaining runs.");
+                break 'session;
+            }
+            if options.edit_prompts {
+                let prompt = tasks[task_idx].for_loop(loop_idx).prompt;
+                match edit_prompt_in_editor(&prompt, options.editor.as_deref()).await {
+                    Ok(edited) => tasks[task_idx].set_loop_prompt(loop_idx, edited),
+                    Err(e) => {
+                        session_event!(warn, "Failed to edit prompt, sending it unchanged: {e}")
+                    }
+                }
+            }
+            let task = &tasks[task_idx].for_loop(loop_idx);
             let header = task_header_lines(
                 run_idx,
                 total_runs,
                 loop_idx,
                 loops,
                 task_idx,
-                prompts.len(),
-                prompt,
+                tasks.len(),
+                task,
             );
-            for line in &header {
-                println!("{line}");
+            if !options.ci_output {
+                for line in &header {
+                    println_tee(options.tee.as_ref(), &theme::theme().header.paint(line));
+                }
             }
-            let task_header_guard = CurrentTaskHeaderGuard::new(header.to_vec());
+            let task_header_guard = CurrentTaskHeaderGuard::new(
+                header.to_vec(),
+                RunProgress {
+                    average: estimator.average(),
+                    runs_after: total_runs - run_idx,
+                },
+            );
+            let run_started = Instant::now();
+            let run_span = tracing::info_span!(
+                parent: &loop_span,
+                "run",
+                index = run_idx,
+                task = task_idx + 1,
+                name = task.name.as_deref(),
+                model = task.model.as_deref(),
+                attempts = tracing::field::Empty,
+                success = tracing::field::Empty,
+                cost_usd = tracing::field::Empty,
+            );
+            let mut attempts = 0;
 
-            let success = match runner(prompt.clone()).await {
-                Ok(s) => s,
-                Err(e) => {
-                    eprintln!("Error launching codex: {e}");
-                    false
+            if let Some(branches) = &mut branches {
+                match branches.enter(loop_idx, task_idx).await {
+                    Ok(Some(branch)) => session_event!(info, "Switched to branch {branch}"),
+                    Ok(None) => {}
+                    Err(e) => session_event!(warn, "Cannot switch session branch: {e}"),
+                }
+            }
+            let checkpoint = match &options.git_work_dir {
+                Some(dir) if options.rollback_on_failure => {
+                    match git::Checkpoint::create(dir).await {
+                        Ok(checkpoint) => Some(checkpoint),
+                        Err(e) => {
+                            session_event!(
+                                warn,
+                                "Cannot checkpoint the work dir, rollback disabled for this run: {e}"
+                            );
+                            None
+                        }
+                    }
                 }
+                _ => None,
             };
+            let before = match (&checkpoint, &options.git_work_dir) {
+                (Some(checkpoint), _) => Some(checkpoint.tree.clone()),
+                (None, Some(dir)) => git::snapshot(dir).await.ok(),
+                (None, None) => None,
+            };
+            let mut attempt = 0;
+            let mut nudges = 0;
+            let mut attempt_task = task.clone();
+            let mut attempts_cost = None;
+            let mut outcome = loop {
+                if let Some(until) = blackout_end(&options.blackouts, Local::now().naive_local()) {
+                    session_event!(info,
+                        parent: &run_span,
+                        "[Run {run_idx}/{total_runs}] Inside a no-run window; waiting until {}",
+                        until.format("%Y-%m-%d %H:%M")
+                    );
+                    if !schedule::sleep_until_local(until, &options.cancel).await {
+                        session_event!(info, "Session cancelled; skipping remaining runs.");
+                        break 'session;
+                    }
+                }
+                if let Some(throttle) = &mut state.throttle {
+                    if let Some(delay) = throttle.delay_at(Instant::now()) {
+                        session_event!(info,
+                            parent: &run_span,
+                            "[Run {run_idx}/{total_runs}] Run limit per hour reached; waiting {}s",
+                            delay.as_secs()
+                        );
+                        tokio::select! {
+                            _ = tokio::time::sleep(delay) => {}
+                            _ = options.cancel.cancelled() => {
+                                session_event!(info, "Session cancelled; skipping remaining runs.");
+                                break 'session;
+                            }
+                        }
+                    }
+                    throttle.record_start(Instant::now());
+                }
+                attempts += 1;
+                let mut outcome = match runner.run(&attempt_task).instrument(run_span.clone()).await
+                {
+                    Ok(outcome) => outcome,
+                    Err(e @ AgentLoopsError::Cancelled) => {
+                        session_event!(warn, parent: &run_span, "Run interrupted: {e}");
+                        options.cancel.cancel();
+                        RunOutcome::default()
+                    }
+                    Err(e) => {
+                        session_event!(error, parent: &run_span, "Error launching codex: {e}");
+                        RunOutcome::default()
+                    }
+                };
+                if let Some(cost) = outcome.cost_usd {
+                    *attempts_cost.get_or_insert(0.0) += cost;
+                }
+                if !outcome.success
+                    && outcome.rate_limited
+                    && attempt < options.rate_limit_retries
+                    && !options.cancel.is_cancelled()
+                {
+                    attempt += 1;
+                    let delay = rate_limit_backoff(options.rate_limit_cooldown, attempt);
+                    session_event!(warn,
+                        parent: &run_span,
+                        "[Run {run_idx}/{total_runs}] Rate limit detected; retrying in {}s (attempt {attempt}/{})",
+                        delay.as_secs(),
+                        options.rate_limit_retries
+                    );
+                    tokio::select! {
+                        _ = tokio::time::sleep(delay) => continue,
+                        _ = options.cancel.cancelled() => break outcome,
+                    }
+                }
+                if !options.require_changes || !outcome.success {
+                    break outcome;
+                }
+                outcome.changes = changes_since(options, before.as_deref()).await;
+                if outcome.changes.is_none_or(|changes| !changes.is_empty()) {
+                    break outcome;
+                }
+                outcome.success = false;
+                if nudges >= options.no_change_retries || options.cancel.is_cancelled() {
+                    session_event!(warn, parent: &run_span, "[Run {run_idx}/{total_runs}] The run made no changes");
+                    break outcome;
+                }
+                nudges += 1;
+                session_event!(info,
+                    parent: &run_span,
+                    "[Run {run_idx}/{total_runs}] The run made no changes; retrying with a reminder (attempt {nudges}/{})",
+                    options.no_change_retries
+                );
+                attempt_task.prompt = format!("{}\n\n{NO_CHANGES_REMINDER}", task.prompt);
+            };
+
+            // Rate-limited attempts that were retried still cost money.
+            outcome.cost_usd = attempts_cost;
+            if outcome.changes.is_none() {
+                outcome.changes = changes_since(options, before.as_deref()).await;
+            }
+            if let (Some(collector), Some(dir)) = (&options.artifacts, &options.git_work_dir) {
+                match collector.collect(dir, run_idx) {
+                    Ok(0) => {}
+                    Ok(_) => outcome.artifacts = Some(collector.run_dir(run_idx)),
+                    Err(e) => session_event!(warn,
+                        parent: &run_span,
+                        "[Run {run_idx}/{total_runs}] Cannot collect the run's artifacts: {e}"
+                    ),
+                }
+            }
 
+            if let Some(checkpoint) = &checkpoint
+                && !outcome.success
+                && !options.cancel.is_cancelled()
+            {
+                match checkpoint.restore().await {
+                    Ok(()) => session_event!(info,
+                        parent: &run_span,
+                        "[Run {run_idx}/{total_runs}] Rolled back the work dir to its state before the run"
+                    ),
+                    Err(e) => {
+                        session_event!(error, parent: &run_span, "[Run {run_idx}/{total_runs}] Rollback failed: {e}")
+                    }
+                }
+            }
+
+            let took = run_started.elapsed();
+            estimator.record(took);
+            run_span.record("attempts", attempts);
+            run_span.record("success", outcome.success);
+            if let Some(cost) = outcome.cost_usd {
+                run_span.record("cost_usd", cost);
+            }
             drop(task_header_guard);
-            let status_label = if success { "OK" } else { "FAILED" };
-            println!("[Run {run_idx}/{total_runs}] Result: {status_label}\n");
-            results.push((loop_idx, task_idx, success));
+            if let Some(cost) = outcome.cost_usd {
+                state.cost_usd += cost;
+            }
+            if options.ci_output {
+                print_run_summary_line(
+                    options, run_idx, total_runs, loop_idx, task_idx, &outcome, took,
+                );
+            } else {
+                print_run_footer(options, run_idx, total_runs, &outcome, took);
+            }
+            if !outcome.success && !options.cancel.is_cancelled() {
+                alert_failure(options, run_idx, total_runs, task).await;
+            }
+            let success = outcome.success;
+            let mut added = match options.max_added_tasks {
+                Some(max) if success && added_count < max => {
+                    let mut added = added_tasks(task, &outcome, &tasks);
+                    if added.len() > max - added_count {
+                        session_event!(
+                            warn,
+                            "[Run {run_idx}/{total_runs}] Dropping {} added task(s) over the limit of {max}",
+                            added.len() - (max - added_count)
+                        );
+                        added.truncate(max - added_count);
+                    }
+                    added
+                }
+                _ => Vec::new(),
+            };
+            results.push(RunRecord::new(loop_idx, task_idx, outcome, took));
+            if !added.is_empty() {
+                println_tee(
+                    options.tee.as_ref(),
+                    &format!(
+                        "[Run {run_idx}/{total_runs}] Added {} task(s) from the output:",
+                        added.len()
+                    ),
+                );
+                for (idx, task) in added.iter().enumerate() {
+                    println_tee(
+                        options.tee.as_ref(),
+                        &format!(
+                            "  {}. {}",
+                            tasks.len() + idx + 1,
+                            truncate_display(&task.prompt, current_task_len())
+                        ),
+                    );
+                }
+                added_count += added.len();
+                total_runs += added.len() * (loops - loop_idx);
+                pending.extend(tasks.len()..tasks.len() + added.len());
+                tasks.append(&mut added);
+            }
+
+            if let Some(limit) = options.max_session_cost
+                && state.cost_usd >= limit
+            {
+                session_event!(
+                    warn,
+                    "Stopping: session cost ${:.2} reached the ${limit:.2} limit.",
+                    state.cost_usd
+                );
+                state.stopped = true;
+                break 'session;
+            }
+            if success {
+                state.consecutive_failures = 0;
+                continue;
+            }
+            state.consecutive_failures += 1;
+            let streak = state.consecutive_failures;
+            if let Some(limit) = options.max_consecutive_failures
+                && streak >= limit
+                && !options.cancel.is_cancelled()
+            {
+                let question = format!("{streak} runs in a row failed. Continue?");
+                if options.confirm_failure_streak && confirm(&question).await {
+                    state.consecutive_failures = 0;
+                } else {
+                    session_event!(warn, "Stopping: {streak} consecutive runs failed.");
+                    state.stopped = true;
+                    break 'session;
+                }
+            }
         }
     }
 
-    println!("=== All loops completed ===");
+    if let Some(branches) = &mut branches
+        && let Err(e) = branches.finish().await
+    {
+        session_event!(warn, "Cannot commit the last session branch: {e}");
+    }
+    println_tee(
+        options.tee.as_ref(),
+        &theme::theme().header.paint("=== All loops completed ==="),
+    );
     results
 }
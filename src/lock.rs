@@ -0,0 +1,183 @@
+//! Lock file keeping two sessions from editing the same checkout at once.
+//!
+//! In a git work tree the lock lives in the git dir, so it never shows up as an
+//! untracked file or gets committed; elsewhere it is `.agent-loops.lock` in the work
+//! dir. It records the holder's PID and start time, and a lock whose process is gone
+//! is taken over.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use chrono::Local;
+
+use crate::git;
+
+/// Lock file name inside the git dir.
+const GIT_LOCK_NAME: &str = "agent-loops.lock";
+/// Lock file name in a work dir that is not a git work tree.
+const LOCK_NAME: &str = ".agent-loops.lock";
+
+/// Locks taken by this process so far; tells apart two locks held by one process.
+static LOCKS_TAKEN: AtomicU64 = AtomicU64::new(0);
+
+/// Session recorded in a lock file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockHolder {
+    pub pid: u32,
+    /// Local start time, as written by the holder.
+    pub started: String,
+    /// Which of the holder's locks this is.
+    pub serial: u64,
+}
+
+impl LockHolder {
+    pub fn parse(content: &str) -> Option<Self> {
+        let mut pid = None;
+        let mut started = String::new();
+        let mut serial = 0;
+        for line in content.lines() {
+            match line.split_once('=') {
+                Some(("pid", value)) => pid = value.trim().parse().ok(),
+                Some(("started", value)) => started = value.trim().to_string(),
+                Some(("serial", value)) => serial = value.trim().parse().unwrap_or_default(),
+                _ => {}
+            }
+        }
+        Some(Self {
+            pid: pid?,
+            started,
+            serial,
+        })
+    }
+
+    fn current() -> Self {
+        Self {
+            pid: std::process::id(),
+            started: Local::now().format("%Y-%m-%d %H:%M:%S %:z").to_string(),
+            serial: LOCKS_TAKEN.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    fn render(&self) -> String {
+        format!(
+            "pid={}\nstarted={}\nserial={}\n",
+            self.pid, self.started, self.serial
+        )
+    }
+}
+
+/// Where the lock for `work_dir` lives.
+pub async fn lock_path(work_dir: &Path) -> PathBuf {
+    match git::git(work_dir, &["rev-parse", "--absolute-git-dir"]).await {
+        Ok(git_dir) if !git_dir.is_empty() => PathBuf::from(git_dir).join(GIT_LOCK_NAME),
+        _ => work_dir.join(LOCK_NAME),
+    }
+}
+
+/// A held session lock; dropping it removes the lock file.
+#[derive(Debug)]
+pub struct SessionLock {
+    path: PathBuf,
+    holder: LockHolder,
+}
+
+impl SessionLock {
+    /// Lock `work_dir` for this process. Fails when another live session holds it,
+    /// unless `force` is set.
+    pub async fn acquire(work_dir: &Path, force: bool) -> io::Result<Self> {
+        let path = lock_path(work_dir).await;
+        let holder = LockHolder::current();
+        // Two attempts: the second follows removing a stale or overridden lock.
+        for _ in 0..2 {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    file.write_all(holder.render().as_bytes())?;
+                    return Ok(Self { path, holder });
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e),
+            }
+            let existing = fs::read_to_string(&path)
+                .ok()
+                .and_then(|content| LockHolder::parse(&content));
+            match existing {
+                Some(other) if process_alive(other.pid) && !force => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!(
+                            "{} is in use by another agent-loops session (pid {}, started {}); pass --force to start anyway",
+                            work_dir.display(),
+                            other.pid,
+                            other.started
+                        ),
+                    ));
+                }
+                Some(other) if process_alive(other.pid) => tracing::warn!(
+                    "Taking over the lock of session pid {} because of --force",
+                    other.pid
+                ),
+                _ => tracing::info!("Removing stale session lock {}", path.display()),
+            }
+            match fs::remove_file(&path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("could not take the session lock {}", path.display()),
+        ))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for SessionLock {
+    fn drop(&mut self) {
+        // Leave a lock that a forced session has since taken over.
+        let ours = fs::read_to_string(&self.path)
+            .ok()
+            .and_then(|content| LockHolder::parse(&content))
+            .is_some_and(|holder| holder == self.holder);
+        if ours {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Whether a process with this PID is running.
+pub fn process_alive(pid: u32) -> bool {
+    #[cfg(unix)]
+    {
+        let Ok(pid) = libc::pid_t::try_from(pid) else {
+            return false;
+        };
+        // SAFETY: signal 0 only checks that the process exists and may be signalled.
+        if unsafe { libc::kill(pid, 0) } == 0 {
+            return true;
+        }
+        io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+    #[cfg(windows)]
+    {
+        use windows_sys::Win32::Foundation::{CloseHandle, STILL_ACTIVE};
+        use windows_sys::Win32::System::Threading::{
+            GetExitCodeProcess, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION,
+        };
+        // SAFETY: plain Win32 calls on a handle owned by this block.
+        unsafe {
+            let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+            if process.is_null() {
+                return false;
+            }
+            let mut code = 0u32;
+            let queried = GetExitCodeProcess(process, &mut code);
+            CloseHandle(process);
+            queried != 0 && i32::try_from(code) == Ok(STILL_ACTIVE)
+        }
+    }
+}
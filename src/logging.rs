@@ -0,0 +1,121 @@
+//! Operational log events (`-v`/`-q`, `--log-format`, `--log-file`).
+//!
+//! Run headers, agent output and summaries are written by the renderer; everything else
+//! the session reports (retries, waits, branch switches, warnings) is a `tracing` event,
+//! so it can be filtered, emitted as JSON or sent to a file on its own.
+
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::Layer;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::registry::LookupSpan;
+
+/// How log events are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable lines; on the console just the message.
+    #[default]
+    Text,
+    /// One JSON object per event, with level, timestamp and fields.
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.to_ascii_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!(
+                "unknown log format `{text}`, expected text or json"
+            )),
+        }
+    }
+}
+
+/// Level shown for `-v`/`-q` counts: info by default, each `-v` one level more verbose,
+/// each `-q` one level quieter, down to errors only.
+pub fn verbosity(verbose: u8, quiet: u8) -> LevelFilter {
+    match i16::from(verbose) - i16::from(quiet) {
+        ..=-2 => LevelFilter::ERROR,
+        -1 => LevelFilter::WARN,
+        0 => LevelFilter::INFO,
+        1 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LogSettings {
+    pub level: LevelFilter,
+    pub format: LogFormat,
+    /// Append events to this file instead of writing them to stderr.
+    pub file: Option<PathBuf>,
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        Self {
+            level: LevelFilter::INFO,
+            format: LogFormat::default(),
+            file: None,
+        }
+    }
+}
+
+/// Layer writing events at `settings.level` and above in the chosen format.
+pub fn log_layer<S>(settings: &LogSettings) -> io::Result<Box<dyn Layer<S> + Send + Sync>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let writer = match &settings.file {
+        Some(path) => {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            BoxMakeWriter::new(Mutex::new(file))
+        }
+        None => BoxMakeWriter::new(io::stderr),
+    };
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(settings.file.is_none() && io::stderr().is_terminal());
+    let layer = match (settings.format, &settings.file) {
+        (LogFormat::Json, _) => layer.json().boxed(),
+        (LogFormat::Text, Some(_)) => layer.boxed(),
+        (LogFormat::Text, None) => layer.event_format(ConsoleFormat).boxed(),
+    };
+    Ok(layer.with_filter(settings.level).boxed())
+}
+
+/// Console lines as the session used to print them: the message and any extra fields,
+/// with warnings and errors labelled.
+struct ConsoleFormat;
+
+impl<S, N> FormatEvent<S, N> for ConsoleFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        match *event.metadata().level() {
+            Level::ERROR => write!(writer, "Error: ")?,
+            Level::WARN => write!(writer, "Warning: ")?,
+            _ => {}
+        }
+        ctx.field_format().format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
@@ -1,102 +1,3025 @@
-use agent_loops::{orchestrate, print_plan, run_codex};
-use clap::Parser;
-use std::fs;
-use std::io;
-use std::path::Path;
-use std::process::ExitCode;
+use agent_loops::agent::{Agent, RunnerTemplate};
+#[cfg(feature = "api")]
+use agent_loops::api::{ApiClient, ApiModel};
+use agent_loops::artifacts::{ArtifactCollector, Glob};
+use agent_loops::attach;
+use agent_loops::benchmark::{ModelResult, benchmark_tasks, format_benchmark};
+use agent_loops::cache::{CachedRunner, RunCache};
+use agent_loops::compare::{
+    CheckedRunner, format_comparison, load_variant, trial_order, trial_tasks, variant_letter,
+    variant_results,
+};
+use agent_loops::config::{self, CONFIG_FILE, Starter, load_config};
+use agent_loops::container::Container;
+use agent_loops::disk::{DiskGuard, LowDiskAction};
+use agent_loops::doctor::{CheckStatus, DoctorOptions, format_checklist, run_doctor};
+use agent_loops::git::{self, BranchStrategy, CleanPolicy};
+use agent_loops::lock::SessionLock;
+use agent_loops::logging::{self, LogFormat, LogSettings};
+use agent_loops::notify;
+use agent_loops::plan::{
+    confirm_plan, format_plan, judge_prompt, parse_plan, parse_verdict, plan_prompt,
+    read_output_log, step_tasks,
+};
+use agent_loops::prompt::{
+    PromptIssue, PromptVars, PromptWrap, confirm_refined, format_refined, format_task,
+    format_task_list, lint_prompts, load_vars_file, parse_refined, refine_prompt,
+};
+use agent_loops::remote::Remote;
+use agent_loops::replay;
+use agent_loops::schedule::{Blackout, CronSchedule, sleep_until_local};
+use agent_loops::service::{Service, service_args};
+use agent_loops::sessions::{
+    RunEstimate, SessionRecord, SessionStatus, SessionStore, confirm_run_count, default_data_dir,
+    format_session, format_session_list,
+};
+use agent_loops::stats::{
+    format_session_summary, format_task_stats, session_summary, stats_csv, stats_json, task_stats,
+    task_successes,
+};
+use agent_loops::tasks::{DedupeMode, TaskFilter, dedupe_tasks, filter_tasks};
+use agent_loops::testing::FixtureRunner;
+use agent_loops::theme::{self, ColorChoice, Theme};
+use agent_loops::vote::{Sample, format_vote, pick_consensus};
+use agent_loops::watch::{self, PathWatcher};
+use agent_loops::workspace::{
+    Workspace, format_workspace_summary, load_workspace, orchestrate_workspace,
+};
+use agent_loops::{
+    AgentLoopsError, CancelToken, CodexRunner, ExitPolicy, LineFilter, MAX_CURRENT_TASK_LEN,
+    MAX_DISPLAY_LEN, OrchestrateOptions, Redactor, ResourceLimits, RunOptions, RunOutcome,
+    RunRecord, Runner, ShellFallback, StartAt, TaskSpec, TeeFile, VersionReq, current_task_len,
+    default_spool_dir, launch, load_tasks, orchestrate_runner, print_plan, prune_spool,
+    set_display_lengths, shutdown, truncate_display, version,
+};
+use chrono::Local;
+use clap::builder::RangedU64ValueParser;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{ArgAction, CommandFactory, FromArgMatches, Parser, Subcommand};
+use regex::Regex;
+use std::fs::File;
+use std::hash::{BuildHasher, RandomState};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::{ExitCode, Stdio};
+use std::time::Duration;
+#[cfg(unix)]
+use tokio::signal::unix::{Signal, SignalKind, signal};
+use tracing::{error, info, warn};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
 
 #[derive(Parser, Debug)]
 #[command(name = "agent-loops", about = "Orchestrate codex CLI tasks with cyclic execution")]
+#[command(
+    after_help = "Each option can also be set through the environment variable shown with it, \
+                  and in agent-loops.toml (see `init`). The command line wins over the \
+                  environment, which wins over the config file."
+)]
+#[command(subcommand_negates_reqs = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Prompts to execute sequentially, each in its own codex conversation.
-    #[arg(short, long, num_args = 1.., required_unless_present = "prompts_file")]
+    /// Required unless `--prompts-file` or `--workspace` is given. Prompts are MiniJinja
+    /// templates rendered when their run starts, with the `--var` variables and
+    /// `{{file:PATH}}` and `{{glob:PATTERN:paths}}` (or `:contents`) read from the work dir.
+    #[arg(short, long, num_args = 1.., global = true)]
     prompts: Vec<String>,
 
-    /// Load prompts from a UTF-8 text file, one prompt per non-empty line.
-    #[arg(long = "prompts-file", value_name = "FILE")]
+    /// Load prompts from a UTF-8 text file, one prompt per non-empty line,
+    /// or from a `.toml` task file with one `[[task]]` table per task. A text line
+    /// `#include FILE` adds the tasks of FILE, relative to the including file.
+    #[arg(long = "prompts-file", value_name = "FILE", global = true)]
     prompts_file: Option<String>,
 
+    /// File of standing instructions or repository context put before every task prompt,
+    /// ahead of `--prompt-prefix`.
+    #[arg(long = "preamble-file", value_name = "FILE", global = true)]
+    preamble_file: Option<PathBuf>,
+
+    /// Text put before every task prompt, e.g. `'Follow the style of the surrounding
+    /// code.'`.
+    #[arg(long = "prompt-prefix", value_name = "TEXT", global = true)]
+    prompt_prefix: Option<String>,
+
+    /// Text put after every task prompt, e.g. `'Run the tests before you finish.'`.
+    #[arg(long = "prompt-suffix", value_name = "TEXT", global = true)]
+    prompt_suffix: Option<String>,
+
+    /// Set a variable for the prompt templates, e.g. `--var crate=parser` for
+    /// `{{ crate }}`. May be repeated; wins over `--vars-file`.
+    #[arg(long = "var", value_name = "KEY=VALUE", value_parser = parse_env_assignment, global = true)]
+    vars: Vec<(String, String)>,
+
+    /// TOML file of variables for the prompt templates; values may be lists and tables
+    /// for `{% for %}` loops.
+    #[arg(long = "vars-file", value_name = "FILE", global = true)]
+    vars_file: Option<PathBuf>,
+
+    /// Refuse to start when a prompt, once rendered, is longer than this many characters.
+    #[arg(long = "max-prompt-chars", value_name = "N", default_value_t = 100_000)]
+    max_prompt_chars: usize,
+
+    /// Read options and tasks from this file instead of `agent-loops.toml` in the current
+    /// directory, if there is one. Options given on the command line win.
+    #[arg(long, value_name = "FILE", global = true)]
+    config: Option<PathBuf>,
+
+    /// Tasks from the config file's `[[task]]` tables.
+    #[arg(skip)]
+    config_tasks: Vec<TaskSpec>,
+
     /// Number of times to loop through the full prompt list.
-    #[arg(short, long, default_value_t = 1)]
+    #[arg(short, long, default_value_t = 1, global = true)]
     loops: usize,
 
+    /// Workspace file listing several repositories, each with its own tasks, to run in one
+    /// session.
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["prompts", "prompts_file", "work_dir", "require_clean", "auto_stash"]
+    )]
+    workspace: Option<String>,
+
+    /// Run only the tasks matching one of these filters: `tag=<tag>` for tasks with that
+    /// tag in the task file, `name=<name>`, or task numbers such as `3` or `3,5`.
+    #[arg(long, value_name = "FILTER", value_delimiter = ',')]
+    only: Vec<TaskFilter>,
+
+    /// Leave out the tasks matching any of these filters (`tag=<tag>`, `name=<name>` or
+    /// task numbers such as `3,5`).
+    #[arg(long, value_name = "FILTER", value_delimiter = ',')]
+    skip: Vec<TaskFilter>,
+
+    /// Drop tasks repeating an earlier one, e.g. a prompt given both with `-p` and in
+    /// `--prompts-file`, with a warning: `exact` repeats, or `whitespace` for the same
+    /// words however spaced.
+    #[arg(
+        long = "dedupe-prompts",
+        value_name = "MODE",
+        num_args = 0..=1,
+        default_missing_value = "exact"
+    )]
+    dedupe_prompts: Option<DedupeMode>,
+
+    /// Leave out the runs before this one, e.g. to pick up a session that died at run 37:
+    /// a run number (`37`), `task:<n>` for a task of the first loop or `loop:<n>`. Later
+    /// runs keep their numbers.
+    #[arg(
+        long = "start-at",
+        value_name = "START",
+        conflicts_with_all = ["workspace", "schedule"]
+    )]
+    start_at: Option<StartAt>,
+
     /// Working directory for codex to operate in.
-    #[arg(short = 'C', long = "cd")]
+    #[arg(short = 'C', long = "cd", global = true)]
     work_dir: Option<String>,
 
     /// Codex executable path or command name. Defaults to `codex`.
-    #[arg(long = "codex-bin")]
+    #[arg(long = "codex-bin", global = true)]
     codex_bin: Option<String>,
+
+    /// The agent CLI to drive: `codex`, `aider` or `gemini`. `--codex-bin` then names
+    /// that agent's binary, which defaults to the agent's name. Task files can pick
+    /// another agent per task.
+    #[arg(long, value_name = "AGENT", default_value = "codex", global = true)]
+    agent: Agent,
+
+    /// Drive any other agent CLI through this command line instead of `--agent`, e.g.
+    /// `'mytool run --task {prompt} --dir {work_dir}'`. `{prompt}`, `{work_dir}` and
+    /// `{model}` are filled in per run, each as a single argument; the command is started
+    /// in the work dir. Tasks naming an agent still run that agent.
+    #[arg(long, value_name = "COMMAND", global = true)]
+    runner_template: Option<RunnerTemplate>,
+
+    /// Exit codes of the runner template's command that count as success, e.g. `0,3`.
+    /// Defaults to `0`.
+    #[arg(
+        long,
+        value_name = "CODES",
+        value_delimiter = ',',
+        requires = "runner_template",
+        global = true
+    )]
+    runner_success_codes: Vec<i32>,
+
+    /// A run of the runner template's command only succeeds if one of its output lines
+    /// also matches this regex, e.g. `^All tests passed`.
+    #[arg(
+        long,
+        value_name = "REGEX",
+        requires = "runner_template",
+        global = true
+    )]
+    runner_success_pattern: Option<Regex>,
+
+    /// Model passed to codex as `--model`. Task files can override it per task.
+    #[arg(short = 'm', long)]
+    model: Option<String>,
+
+    /// Open each prompt in an editor before it is sent; edits carry over to later loops.
+    #[arg(long = "edit-prompts")]
+    edit_prompts: bool,
+
+    /// Editor command for `--edit-prompts`. Defaults to `$VISUAL`, then `$EDITOR`.
+    #[arg(long, value_name = "CMD")]
+    editor: Option<String>,
+
+    /// Mask matches of this regex in codex output. May be repeated.
+    #[arg(long = "redact", value_name = "REGEX")]
+    redact_patterns: Vec<String>,
+
+    /// Show only codex output lines matching this regex; may be repeated to show lines
+    /// matching any of them. Hidden lines are still written to the full output log.
+    #[arg(long = "grep", value_name = "REGEX")]
+    grep: Vec<String>,
+
+    /// Hide codex output lines matching this regex, e.g. progress bar spam. May be
+    /// repeated; hidden lines are still written to the full output log.
+    #[arg(long = "grep-v", value_name = "REGEX")]
+    grep_v: Vec<String>,
+
+    /// Do not mask values of secret-looking environment variables (`*_API_KEY`, `*_TOKEN`, ...).
+    #[arg(long = "no-redact-env")]
+    no_redact_env: bool,
+
+    /// Directory for per-run full output logs. Defaults to `agent-loops` under the temp dir.
+    #[arg(long = "spool-dir", value_name = "DIR")]
+    spool_dir: Option<String>,
+
+    /// When a session starts, delete run logs in the spool dir older than this many days.
+    /// 0 keeps them all.
+    #[arg(
+        long = "spool-retention-days",
+        value_name = "DAYS",
+        default_value_t = 7
+    )]
+    spool_retention_days: u64,
+
+    /// After each run, copy the files in the work dir matching these patterns to the run's
+    /// own dir under the spool dir, e.g. `target/criterion/**,reports/*.json`. `*` stays
+    /// within a path component, `**` spans several.
+    #[arg(long, value_name = "PATTERNS", value_delimiter = ',')]
+    collect: Vec<Glob>,
+
+    /// Wrap long output lines in the live view instead of truncating them.
+    #[arg(long)]
+    wrap: bool,
+
+    /// Scroll the live view with the mouse wheel. The view then receives mouse events, so
+    /// selecting text needs Shift held in most terminals.
+    #[arg(long)]
+    mouse: bool,
+
+    /// Copy run output, headers and results to this file as they appear, so the session
+    /// can be followed with `tail -f` while the live view owns the terminal.
+    #[arg(long, value_name = "FILE")]
+    tee: Option<PathBuf>,
+
+    /// Prefix each output line, on screen and in the full output log, with the time since
+    /// the run started.
+    #[arg(long)]
+    timestamps: bool,
+
+    /// Treat loops as repeated trials: print each task's success rate and the mean,
+    /// median, 90th percentile and spread of its run durations at the end.
+    #[arg(long)]
+    stats: bool,
+
+    /// Write the `--stats` figures to this file, as CSV when it ends in `.csv` and as JSON
+    /// otherwise.
+    #[arg(long = "stats-out", value_name = "FILE")]
+    stats_out: Option<PathBuf>,
+
+    /// When the session exits with a failure code: `any-failure` (default), `threshold:<pct>`
+    /// to fail only when more than that percentage of runs failed, or `always-zero`.
+    #[arg(
+        long = "exit-policy",
+        value_name = "POLICY",
+        default_value = "any-failure"
+    )]
+    exit_policy: ExitPolicy,
+
+    /// When stdout is not a terminal, print one line per run (`[12/60] task 3 loop 4 OK
+    /// 4m02s`) instead of the agent's output, which goes to the full output log only.
+    #[arg(long = "ci-output")]
+    ci_output: bool,
+
+    /// Start with a planning run per prompt: the agent writes a step-by-step plan, and
+    /// once it is approved the steps run as the session's tasks.
+    #[arg(long = "plan-first", conflicts_with_all = ["workspace", "schedule"])]
+    plan_first: bool,
+
+    /// Approve the plan without asking.
+    #[arg(
+        long = "plan-auto-approve",
+        requires = "plan_first",
+        conflicts_with = "plan_judge"
+    )]
+    plan_auto_approve: bool,
+
+    /// Have an agent run review the plan with these instructions instead of asking on
+    /// the terminal; the plan runs when it answers `VERDICT: APPROVE`.
+    #[arg(long = "plan-judge", value_name = "PROMPT", requires = "plan_first")]
+    plan_judge: Option<String>,
+
+    /// Before the session, have an agent run (or `--aux-model`) rewrite each prompt into a
+    /// more explicit instruction, shown for approval; a rejected rewrite keeps the prompt.
+    #[arg(long = "refine-prompts", conflicts_with_all = ["workspace", "schedule"])]
+    refine_prompts: bool,
+
+    /// Use the refined prompts without asking.
+    #[arg(long = "refine-auto-approve", requires = "refine_prompts")]
+    refine_auto_approve: bool,
+
+    /// Let a successful run add tasks to the session by printing `AGENT_LOOPS_ADD_TASK:
+    /// <prompt>` lines or a fenced `agent-loops-tasks` block with a JSON array of prompts
+    /// or task objects with a `prompt` and optionally a `name` and `priority`. Only output
+    /// after the agent's echo of its prompt counts.
+    #[arg(long = "dynamic-tasks")]
+    dynamic_tasks: bool,
+
+    /// Most tasks `--dynamic-tasks` adds over the whole session.
+    #[arg(
+        long = "max-added-tasks",
+        value_name = "N",
+        default_value_t = 20,
+        requires = "dynamic_tasks"
+    )]
+    max_added_tasks: usize,
+
+    /// Use plain pipes instead of a pseudo-terminal for the live view.
+    #[arg(long = "no-pty")]
+    no_pty: bool,
+
+    /// Kill a run, including any processes it started, after this many seconds.
+    #[arg(long, value_name = "SECS")]
+    timeout: Option<u64>,
+
+    /// Once a run has written nothing for this many seconds, show how long it has been
+    /// idle and running, and again as long as it stays quiet. 0 turns this off.
+    #[arg(long, value_name = "SECS", default_value_t = 60)]
+    heartbeat: u64,
+
+    /// Before each run, check that the work dir and the log dir have at least this much
+    /// free space, e.g. `2G`.
+    #[arg(long = "min-free-space", value_name = "SIZE", value_parser = parse_size)]
+    min_free_space: Option<u64>,
+
+    /// What to do when `--min-free-space` is not met: `abort` the session (default) or
+    /// `pause` until space is freed.
+    #[arg(
+        long = "on-low-disk",
+        value_name = "ACTION",
+        default_value = "abort",
+        requires = "min_free_space"
+    )]
+    on_low_disk: LowDiskAction,
+
+    /// Cap the memory of the agent and the processes it starts, e.g. `4G` or `512M`:
+    /// per process on Linux, for the whole tree on Windows.
+    #[arg(long = "memory-limit", value_name = "SIZE", value_parser = parse_size)]
+    memory_limit: Option<u64>,
+
+    /// Cap the CPU time in seconds of the agent and the processes it starts: per process
+    /// on Linux, for the whole tree on Windows.
+    #[arg(long = "cpu-limit", value_name = "SECS", value_parser = RangedU64ValueParser::<u64>::new().range(1..))]
+    cpu_limit: Option<u64>,
+
+    /// Shell used to retry a codex command that is not on PATH (alias or function). Defaults to `$SHELL`.
+    #[arg(long, value_name = "PATH")]
+    shell: Option<String>,
+
+    /// Fail immediately when the codex command is not on PATH instead of retrying through a shell.
+    #[arg(long = "no-shell-fallback", conflicts_with = "shell_non_interactive")]
+    no_shell_fallback: bool,
+
+    /// Retry through a login shell without `-i`, so interactive rc files are not sourced.
+    #[arg(long = "shell-non-interactive")]
+    shell_non_interactive: bool,
+
+    /// Set an environment variable for every codex run. May be repeated; task files can
+    /// override it per task.
+    #[arg(long = "env", value_name = "KEY=VALUE", value_parser = parse_env_assignment)]
+    env: Vec<(String, String)>,
+
+    /// Start codex from an empty environment instead of ours, passing through only the
+    /// variables named by `--pass-env`. Task files can set their own `env_allowlist`.
+    #[arg(long = "clean-env")]
+    clean_env: bool,
+
+    /// Variables `--clean-env` passes through, e.g. `PATH,HOME,OPENAI_API_KEY`.
+    #[arg(
+        long = "pass-env",
+        value_name = "NAMES",
+        value_delimiter = ',',
+        requires = "clean_env"
+    )]
+    pass_env: Vec<String>,
+
+    /// Run each agent invocation in a container of this image, e.g. one with codex
+    /// installed, with the work dir mounted at the same path. The agent then cannot change
+    /// anything else on the host. Only `--env`, `--container-env` and `--pass-env`
+    /// variables reach it.
+    #[arg(long = "in-container", value_name = "IMAGE", global = true)]
+    in_container: Option<String>,
+
+    /// Run each agent invocation on this machine over SSH, e.g. `me@build-server`, with
+    /// its output streamed back. `-C` and `--codex-bin` are then the remote machine's,
+    /// and `--env` variables are set there. Git features need a local checkout and are
+    /// not available.
+    #[arg(
+        long = "ssh",
+        value_name = "DESTINATION",
+        conflicts_with_all = [
+            "in_container",
+            "workspace",
+            "require_clean",
+            "auto_stash",
+            "require_changes",
+            "rollback_on_failure",
+            "git_branch_strategy",
+            "collect",
+            "check",
+            "cache"
+        ]
+    )]
+    ssh: Option<String>,
+
+    /// Container engine for `--in-container`, e.g. `podman`.
+    #[arg(
+        long = "container-engine",
+        value_name = "CMD",
+        default_value = "docker",
+        requires = "in_container",
+        global = true
+    )]
+    container_engine: String,
+
+    /// Variables passed from our environment into the container, e.g. `OPENAI_API_KEY`.
+    #[arg(
+        long = "container-env",
+        value_name = "NAMES",
+        value_delimiter = ',',
+        requires = "in_container",
+        global = true
+    )]
+    container_env: Vec<String>,
+
+    /// Stop the session once this many runs in a row have failed.
+    #[arg(long = "max-consecutive-failures", value_name = "N", value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    max_consecutive_failures: Option<usize>,
+
+    /// At `--max-consecutive-failures`, ask whether to continue instead of stopping.
+    #[arg(long = "confirm-on-failures", requires = "max_consecutive_failures")]
+    confirm_on_failures: bool,
+
+    /// Retry a run whose output reports a rate limit or exhausted quota up to N times.
+    #[arg(long = "rate-limit-retries", value_name = "N", default_value_t = 3)]
+    rate_limit_retries: usize,
+
+    /// Seconds to wait before the first rate-limit retry; doubled for each further attempt.
+    #[arg(
+        long = "rate-limit-cooldown",
+        value_name = "SECS",
+        default_value_t = 60
+    )]
+    rate_limit_cooldown: u64,
+
+    /// Start at most N runs, retries included, in any rolling hour; waits between runs
+    /// once the limit is reached.
+    #[arg(long = "max-runs-per-hour", value_name = "N", value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    max_runs_per_hour: Option<usize>,
+
+    /// Refuse to start while the work dir has uncommitted changes (offers to stash them
+    /// when run from a terminal).
+    #[arg(long = "require-clean", conflicts_with = "auto_stash")]
+    require_clean: bool,
+
+    /// Stash uncommitted changes in the work dir before the session and restore them
+    /// afterwards.
+    #[arg(long = "auto-stash")]
+    auto_stash: bool,
+
+    /// Mark a run FAILED when it leaves the work tree unchanged.
+    #[arg(long = "require-changes")]
+    require_changes: bool,
+
+    /// With `--require-changes`, retry a run that changed nothing up to N times, telling
+    /// the agent it made no changes.
+    #[arg(
+        long = "no-change-retries",
+        value_name = "N",
+        default_value_t = 0,
+        requires = "require_changes"
+    )]
+    no_change_retries: usize,
+
+    /// Command run through the shell in the work dir after each successful run; the run
+    /// only counts as a success when it passes, e.g. `cargo test`.
+    #[arg(long, value_name = "COMMAND")]
+    check: Option<String>,
+
+    /// Skip runs whose rendered prompt already succeeded in the same work dir at the same
+    /// git HEAD in an earlier session, as kept with the session records, so re-running a
+    /// session after a partial failure does not redo finished work.
+    #[arg(long)]
+    cache: bool,
+
+    /// Checkpoint the work dir before each run and restore it when the run fails.
+    #[arg(long = "rollback-on-failure")]
+    rollback_on_failure: bool,
+
+    /// Work on new `agent-loops/<date>/...` branches: one per `session`, `loop` or `task`.
+    /// Leftover changes are committed before moving to the next branch.
+    #[arg(long = "git-branch-strategy", value_name = "STRATEGY")]
+    git_branch_strategy: Option<BranchStrategy>,
+
+    /// Stay resident and start a full session whenever this cron expression fires, e.g.
+    /// `0 2 * * *` for 02:00 local time every day.
+    #[arg(long, value_name = "CRON")]
+    schedule: Option<CronSchedule>,
+
+    /// Start no runs inside this daily local-time window, e.g. `09:00-18:00`; the session
+    /// sleeps until it closes. Windows may wrap past midnight and the flag may be repeated.
+    #[arg(long = "no-run-between", value_name = "HH:MM-HH:MM")]
+    no_run_between: Vec<Blackout>,
+
+    /// Name recorded with the session, usable with `sessions show`.
+    #[arg(long = "session-name", value_name = "NAME")]
+    session_name: Option<String>,
+
+    /// Start the session in the background and return at once, printing its ID. Its
+    /// output goes to `session.log` in the session's directory under the data dir. A
+    /// detached session cannot ask anything, so `--yes` is required.
+    #[arg(long, requires = "yes")]
+    detach: bool,
+
+    /// Record the session under this ID reserved by `--detach`.
+    #[arg(long = "session-id", value_name = "ID", hide = true)]
+    session_id: Option<String>,
+
+    /// Where session records are kept [default: the per-user data directory, or
+    /// `$AGENT_LOOPS_DATA_DIR`].
+    #[arg(long = "data-dir", value_name = "DIR", global = true)]
+    data_dir: Option<PathBuf>,
+
+    /// Start even when another session holds the work dir's lock.
+    #[arg(long)]
+    force: bool,
+
+    /// Start without asking to confirm the work dir and the number of runs. The question
+    /// is only asked on a terminal, as codex runs without its sandbox, or for sessions
+    /// above `--confirm-above`, which otherwise do not start without a terminal.
+    #[arg(short = 'y', long)]
+    yes: bool,
+
+    /// Ask before starting more than this many runs (tasks times loops), with the time and
+    /// spend past sessions suggest they take; 0 never asks.
+    #[arg(long = "confirm-above", value_name = "RUNS", default_value_t = 200)]
+    confirm_above: usize,
+
+    /// Ring the terminal bell whenever a run fails.
+    #[arg(long = "bell-on-failure")]
+    bell_on_failure: bool,
+
+    /// Show a desktop notification whenever a run fails (`notify-send` on Linux).
+    #[arg(long = "desktop-notify")]
+    desktop_notify: bool,
+
+    /// Kill a run once the spend it reports exceeds this many dollars.
+    #[arg(long = "max-cost-per-run", value_name = "USD", value_parser = parse_usd)]
+    max_cost_per_run: Option<f64>,
+
+    /// Kill a run once it has written more than this much output, e.g. `50M`, so an agent
+    /// stuck printing in a loop cannot fill the memory and the logs.
+    #[arg(long = "max-output-bytes", value_name = "SIZE", value_parser = parse_size)]
+    max_output_bytes: Option<u64>,
+
+    /// Stop the session once its runs have spent this many dollars in total.
+    #[arg(long = "max-session-cost", value_name = "USD", value_parser = parse_usd)]
+    max_session_cost: Option<f64>,
+
+    /// Price of 1000 tokens, used to cost runs whose agent reports only a token count
+    /// (codex does).
+    #[arg(long = "usd-per-1k-tokens", value_name = "USD", value_parser = parse_usd)]
+    usd_per_1k_tokens: Option<f64>,
+
+    /// Refuse to start unless `codex --version` satisfies this requirement, e.g. `>=0.30`.
+    #[arg(
+        long = "require-codex-version",
+        value_name = "REQ",
+        conflicts_with = "in_container",
+        global = true
+    )]
+    require_codex_version: Option<VersionReq>,
+
+    /// Only warn when `--require-codex-version` is not met.
+    #[arg(long = "codex-version-warn-only", requires = "require_codex_version")]
+    codex_version_warn_only: bool,
+
+    /// Export session, loop, run and agent process spans to this OTLP/HTTP collector,
+    /// e.g. `http://localhost:4318`.
+    #[cfg(feature = "otlp")]
+    #[arg(long = "otlp-endpoint", value_name = "URL")]
+    otlp_endpoint: Option<String>,
+
+    /// Answer auxiliary prompts, such as the `--plan-judge` review, through this model's
+    /// API instead of an agent run, e.g. `openai:gpt-4o-mini`,
+    /// `anthropic:claude-3-5-haiku-latest` or, offline, `ollama:llama3.1` from a local
+    /// Ollama server. The key comes from `OPENAI_API_KEY` or `ANTHROPIC_API_KEY`.
+    #[cfg(feature = "api")]
+    #[arg(long = "aux-model", value_name = "PROVIDER:MODEL", global = true)]
+    aux_model: Option<ApiModel>,
+
+    /// Save each run's raw output and exit status as a fixture in this directory.
+    #[arg(
+        long = "record-fixtures",
+        value_name = "DIR",
+        conflicts_with = "replay_fixtures"
+    )]
+    record_fixtures: Option<PathBuf>,
+
+    /// Play back the fixtures in this directory, one per run, instead of launching codex.
+    #[arg(long = "replay-fixtures", value_name = "DIR")]
+    replay_fixtures: Option<PathBuf>,
+
+    /// Log more detail; repeat for trace-level events.
+    #[arg(short, long, action = ArgAction::Count, global = true, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Log less: `-q` keeps warnings and errors, `-qq` only errors. Run output and
+    /// summaries are not affected.
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    quiet: u8,
+
+    /// Format of log events: `text` or `json`.
+    #[arg(long = "log-format", value_name = "FORMAT", default_value = "text")]
+    log_format: LogFormat,
+
+    /// Append log events to this file instead of writing them to stderr.
+    #[arg(long = "log-file", value_name = "FILE")]
+    log_file: Option<PathBuf>,
+
+    /// Color headers, OK/FAILED and the status row: `auto` (on a terminal, unless
+    /// NO_COLOR is set), `always` or `never`.
+    #[arg(long, value_name = "WHEN", default_value = "auto", global = true)]
+    color: ColorChoice,
+
+    /// Styles for parts of the output, e.g. `header=bold blue,failed=bright-red`. Roles:
+    /// header, ok, failed, status, stderr; styles: none, bold, dim, italic, underline,
+    /// reverse and colors such as cyan or bright-red. In the config file, a `[theme]`
+    /// table of roles and styles.
+    #[arg(long, value_name = "ROLE=STYLE,...", global = true)]
+    theme: Option<Theme>,
+
+    /// Columns a task's prompt or name gets in the plan and the summaries before it is
+    /// cut short.
+    #[arg(long = "display-len", value_name = "COLUMNS", default_value_t = MAX_DISPLAY_LEN, global = true)]
+    display_len: usize,
+
+    /// Columns the prompt gets in the current-task header before it is cut short.
+    #[arg(long = "current-task-len", value_name = "COLUMNS", default_value_t = MAX_CURRENT_TASK_LEN, global = true)]
+    current_task_len: usize,
+
+    /// Show every prompt in full in the plan printed before the session.
+    #[arg(long = "plan-full")]
+    plan_full: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check the agent binary, its version, the work dir and the terminal before a session.
+    Doctor,
+    /// Rerun the task list whenever files under the watched paths change.
+    ///
+    /// Prompts, task files and the loop count may follow `watch`; other session options
+    /// go before it.
+    Watch {
+        /// File or directory to watch; may be repeated.
+        #[arg(long = "path", value_name = "PATH", required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Quiet period after the last change before a pass starts, in milliseconds.
+        #[arg(long = "debounce-ms", value_name = "MS", default_value_t = 1000)]
+        debounce_ms: u64,
+    },
+    /// Play back a recorded session's run output in the live view.
+    Replay {
+        /// Session ID, or the name given with `--session-name`.
+        id: String,
+
+        /// Playback speed relative to the original pace, e.g. `4` or `0.5`.
+        #[arg(long, value_name = "FACTOR", default_value_t = 1.0, value_parser = parse_speed)]
+        speed: f64,
+
+        /// Replay only this run (1-based, in session order).
+        #[arg(long = "run", value_name = "N", value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+        run: Option<usize>,
+    },
+    /// Compare prompt variants: run each one once per loop and report which succeeds most
+    /// often, with Fisher's exact test against the runner-up.
+    ///
+    /// The work dir is reset after every run so each trial starts from the same tree.
+    Compare {
+        /// File holding one variant's prompt; give at least two.
+        #[arg(long = "variant", value_name = "FILE", required = true)]
+        variants: Vec<PathBuf>,
+
+        /// Command run through the shell in the work dir after each successful run; the
+        /// trial only counts as a success when it passes, e.g. `cargo test`.
+        #[arg(long, value_name = "CMD")]
+        check: Option<String>,
+
+        /// Run the variants of each loop in random order instead of rotating which one
+        /// goes first.
+        #[arg(long)]
+        shuffle: bool,
+    },
+    /// Run the task list once per model, each in its own worktree of the work dir started
+    /// from `HEAD`, and compare success rate, duration and cost.
+    Benchmark {
+        /// Model to benchmark, passed to codex as `--model`; give at least two.
+        #[arg(long = "model", value_name = "MODEL", required = true)]
+        models: Vec<String>,
+
+        /// Leave each model's worktree in place to inspect its work, instead of removing
+        /// it when the model's runs are done.
+        #[arg(long = "keep-worktrees")]
+        keep_worktrees: bool,
+    },
+    /// Run one prompt several times, each in its own worktree of the work dir, verify
+    /// every sample with a check command, and apply the agreed-on work only when enough
+    /// samples pass.
+    Vote {
+        /// Number of times to run the prompt.
+        #[arg(long, value_name = "N", default_value_t = 3, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+        samples: usize,
+
+        /// Samples that must pass the check before anything is applied.
+        #[arg(long, value_name = "K", default_value_t = 2, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+        required: usize,
+
+        /// Command run through the shell in each sample's worktree after the run; the
+        /// sample passes when it succeeds, e.g. `cargo test`.
+        #[arg(long, value_name = "CMD")]
+        check: String,
+
+        /// Leave the samples' worktrees in place to inspect them.
+        #[arg(long = "keep-worktrees")]
+        keep_worktrees: bool,
+    },
+    /// Follow a running session's output, typically one started with `--detach`, in the
+    /// pinned view on a terminal. Press `p` to pause it before its next run or resume it,
+    /// `s` to stop it and `q` (or Ctrl-C) to stop following and leave it running.
+    Attach {
+        /// Session ID, or the name given with `--session-name`.
+        id: String,
+    },
+    /// Install the options given before `install-service` as a recurring job that
+    /// survives reboots: a user-level systemd service and timer, or on Windows a Scheduled
+    /// Task started at logon.
+    InstallService {
+        /// Cron expression the job runs on, e.g. `0 3 * * 1-5` for 03:00 on weekdays.
+        #[arg(long, value_name = "CRON")]
+        schedule: CronSchedule,
+
+        /// Name of the units or task, e.g. `agent-loops` for `agent-loops.service`.
+        #[arg(long, default_value = "agent-loops", value_parser = parse_service_name)]
+        name: String,
+
+        /// Print the units instead of installing them.
+        #[arg(long)]
+        print: bool,
+    },
+    /// Print a completion script for a shell, e.g. `agent-loops completions bash >
+    /// ~/.local/share/bash-completion/completions/agent-loops`.
+    Completions {
+        /// bash, elvish, fish, powershell or zsh.
+        shell: clap_complete::Shell,
+    },
+    /// Create a starter `agent-loops.toml` in the current directory, asking for the
+    /// tasks, the loop count, a check command and git options.
+    Init {
+        /// Replace an existing `agent-loops.toml`.
+        #[arg(long)]
+        force: bool,
+    },
+    /// Print the man page, e.g. `agent-loops manpage > ~/.local/share/man/man1/agent-loops.1`.
+    Manpage,
+    /// Inspect past and running sessions.
+    Sessions {
+        #[command(subcommand)]
+        command: SessionsCommand,
+    },
+    /// Show the session's tasks with their prompts rendered as they would be sent, in
+    /// full.
+    Tasks {
+        #[command(subcommand)]
+        command: TasksCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum TasksCommand {
+    /// Print every task's prompt as its first run would send it.
+    List,
+    /// Show one task's options and the prompt of each of its loops.
+    Show {
+        /// Task number, from 1.
+        #[arg(value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+        number: usize,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SessionsCommand {
+    /// List recorded sessions, oldest first.
+    List,
+    /// Show a session's plan, status, runs and log locations.
+    Show {
+        /// Session ID, or the name given with `--session-name`.
+        id: String,
+    },
 }
 
 #[tokio::main]
 async fn main() -> ExitCode {
-    let cli = Cli::parse();
-    let mut prompts = cli.prompts.clone();
+    let cli = parse_cli();
+    shutdown::install_panic_hook();
+    #[cfg(feature = "otlp")]
+    let (otlp_layer, otlp_error, _otlp_guard) = match cli.otlp_endpoint.clone() {
+        Some(endpoint) => {
+            let init =
+                tokio::task::spawn_blocking(move || agent_loops::telemetry::otlp_layer(&endpoint))
+                    .await
+                    .unwrap_or_else(|e| Err(e.to_string()));
+            match init {
+                // The guard is held for the whole session and flushes the spans on exit.
+                Ok((layer, guard)) => (Some(layer), None, Some(guard)),
+                Err(e) => (None, Some(e), None),
+            }
+        }
+        None => (None, None, None),
+    };
+    #[cfg(feature = "otlp")]
+    let subscriber = tracing_subscriber::registry().with(otlp_layer);
+    #[cfg(not(feature = "otlp"))]
+    let subscriber = tracing_subscriber::registry();
+    let log_settings = LogSettings {
+        level: logging::verbosity(cli.verbose, cli.quiet),
+        format: cli.log_format,
+        file: cli.log_file.clone(),
+    };
+    match logging::log_layer(&log_settings) {
+        Ok(layer) => subscriber.with(layer).init(),
+        Err(e) => {
+            eprintln!("Cannot open the log file: {e}");
+            return ExitCode::FAILURE;
+        }
+    }
+    #[cfg(feature = "otlp")]
+    if let Some(e) = otlp_error {
+        error!("Cannot set up OTLP trace export: {e}");
+        return ExitCode::FAILURE;
+    }
+    theme::set_theme(cli.theme.clone().unwrap_or_default(), cli.color);
+    set_display_lengths(cli.display_len, cli.current_task_len, cli.plan_full);
+    if let Some(Command::Doctor) = &cli.command {
+        return doctor(&cli).await;
+    }
+    if let Some(Command::Init { force }) = &cli.command {
+        return init(*force);
+    }
+    if let Some(Command::Completions { shell }) = &cli.command {
+        clap_complete::generate(*shell, &mut cli_command(), "agent-loops", &mut io::stdout());
+        return ExitCode::SUCCESS;
+    }
+    if let Some(Command::Manpage) = &cli.command {
+        return match clap_mangen::Man::new(cli_command()).render(&mut io::stdout()) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                error!("Cannot write the man page: {e}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+    if let Some(Command::Attach { id }) = &cli.command {
+        return attach(&cli, id).await;
+    }
+    if let Some(Command::Sessions { command }) = &cli.command {
+        return sessions(&cli, command);
+    }
+    if let Some(Command::Replay { id, speed, run }) = &cli.command {
+        return replay(&cli, id, *speed, *run).await;
+    }
+    // The detached session itself gets a `--session-id`; `detach` from the environment
+    // or the config file must not send it to the background again.
+    if cli.detach && cli.session_id.is_none() {
+        return detach(&cli);
+    }
+    let comparing = matches!(cli.command, Some(Command::Compare { .. }));
+    if comparing
+        && (!cli.prompts.is_empty() || cli.prompts_file.is_some() || cli.workspace.is_some())
+    {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "compare takes its prompts from --variant, not --prompts, --prompts-file or --workspace",
+            )
+            .exit();
+    }
+    if let Some(Command::Compare { variants, .. }) = &cli.command
+        && variants.len() < 2
+    {
+        Cli::command()
+            .error(
+                ErrorKind::TooFewValues,
+                "compare needs at least two --variant files",
+            )
+            .exit();
+    }
+    if let Some(Command::Benchmark { models, .. }) = &cli.command {
+        if cli.workspace.is_some() {
+            Cli::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "benchmark runs one repository; it cannot be combined with --workspace",
+                )
+                .exit();
+        }
+        if models.len() < 2 {
+            Cli::command()
+                .error(
+                    ErrorKind::TooFewValues,
+                    "benchmark needs at least two --model values",
+                )
+                .exit();
+        }
+    }
+    if let Some(Command::Vote {
+        samples, required, ..
+    }) = &cli.command
+    {
+        if cli.workspace.is_some() {
+            Cli::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "vote runs one repository; it cannot be combined with --workspace",
+                )
+                .exit();
+        }
+        if required > samples {
+            Cli::command()
+                .error(
+                    ErrorKind::ValueValidation,
+                    "--required cannot exceed --samples",
+                )
+                .exit();
+        }
+    }
+    if !comparing
+        && cli.prompts.is_empty()
+        && cli.prompts_file.is_none()
+        && cli.workspace.is_none()
+        && cli.config_tasks.is_empty()
+    {
+        Cli::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "one of --prompts, --prompts-file or --workspace is required, or tasks in agent-loops.toml",
+            )
+            .exit();
+    }
+    if cli.plan_first && cli.command.is_some() {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--plan-first only applies to a plain session, not to a subcommand",
+            )
+            .exit();
+    }
+    if cli.refine_prompts && cli.command.is_some() {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--refine-prompts only applies to a plain session, not to a subcommand",
+            )
+            .exit();
+    }
+    if cli.schedule.is_some() && matches!(cli.command, Some(Command::Watch { .. })) {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--schedule cannot be combined with watch",
+            )
+            .exit();
+    }
+    if cli.schedule.is_some() && matches!(cli.command, Some(Command::InstallService { .. })) {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "install-service takes the schedule as its own --schedule, after the subcommand",
+            )
+            .exit();
+    }
+    if let Some(Command::InstallService {
+        schedule,
+        name,
+        print,
+    }) = &cli.command
+    {
+        return install_service(schedule, name, *print);
+    }
+    if cli.ssh.is_some()
+        && matches!(
+            cli.command,
+            Some(Command::Compare { .. } | Command::Benchmark { .. } | Command::Vote { .. })
+        )
+    {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--ssh cannot be combined with compare, benchmark or vote, which need a local checkout",
+            )
+            .exit();
+    }
+    // Held for the whole session; restores the console codepage on exit.
+    let _console_codepage = agent_loops::encoding::force_utf8_console();
+    let mut tasks: Vec<TaskSpec> = cli.prompts.iter().map(TaskSpec::new).collect();
+    if tasks.is_empty() && cli.prompts_file.is_none() && cli.workspace.is_none() {
+        tasks = cli.config_tasks.clone();
+    }
 
     if let Some(prompts_file) = cli.prompts_file.as_deref() {
-        match load_prompts_file(Path::new(prompts_file)) {
-            Ok(mut file_prompts) => prompts.append(&mut file_prompts),
+        match load_tasks(Path::new(prompts_file)) {
+            Ok(file_tasks) => tasks.extend(file_tasks),
+            Err(e) => {
+                error!("Failed to read prompts file `{prompts_file}`: {e}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    let mut workspace = match cli.workspace.as_deref() {
+        Some(file) => match load_workspace(Path::new(file)) {
+            Ok(workspace) => Some(workspace),
             Err(e) => {
-                eprintln!("Failed to read prompts file `{prompts_file}`: {e}");
+                error!("Failed to read workspace file `{file}`: {e}");
                 return ExitCode::FAILURE;
             }
+        },
+        None => None,
+    };
+
+    if !cli.only.is_empty() || !cli.skip.is_empty() {
+        let mut total = tasks.len();
+        tasks = filter_tasks(tasks, &cli.only, &cli.skip);
+        let mut left = tasks.len();
+        for repo in workspace.iter_mut().flat_map(|w| &mut w.repo) {
+            total += repo.task.len();
+            repo.task = filter_tasks(std::mem::take(&mut repo.task), &cli.only, &cli.skip);
+            left += repo.task.len();
+        }
+        info!("--only/--skip left {left} of {total} task(s).");
+    }
+
+    if let Some(mode) = cli.dedupe_prompts {
+        let dropped;
+        (tasks, dropped) = dedupe_tasks(tasks, mode);
+        for (task, first) in dropped {
+            warn!("Dropped task {task}: it repeats task {first}.");
         }
     }
 
     if cli.loops == 0 {
-        println!("Loop count is 0 — nothing to do.");
+        info!("Loop count is 0 — nothing to do.");
         return ExitCode::SUCCESS;
     }
 
-    if prompts.is_empty() {
-        println!("No prompts provided — nothing to do.");
+    if !comparing
+        && tasks.is_empty()
+        && workspace
+            .as_ref()
+            .is_none_or(|w| w.repo.iter().all(|repo| repo.task.is_empty()))
+    {
+        info!("No prompts provided — nothing to do.");
         return ExitCode::SUCCESS;
     }
 
+    if let Some(start) = cli.start_at {
+        // A workspace's repos, --plan-first and the subcommands bring their own tasks,
+        // so only the loop count is known this early.
+        let known_tasks = (workspace.is_none() && cli.command.is_none() && !cli.plan_first)
+            .then_some(tasks.len());
+        let (number, limit) = match start {
+            StartAt::Loop(n) => (n, Some((cli.loops, "loop(s)"))),
+            StartAt::Task(n) => (n, known_tasks.map(|count| (count, "task(s)"))),
+            StartAt::Run(n) => (n, known_tasks.map(|count| (count * cli.loops, "run(s)"))),
+        };
+        if let Some((count, unit)) = limit
+            && number > count
+        {
+            Cli::command()
+                .error(
+                    ErrorKind::ValueValidation,
+                    format!("--start-at {start} is past the end of the session, which has {count} {unit}"),
+                )
+                .exit();
+        }
+    }
+
     if let Some(dir) = cli.work_dir.as_deref() {
         let path = Path::new(dir);
         if !path.exists() {
-            eprintln!("Working directory does not exist: {dir}");
+            error!("Working directory does not exist: {dir}");
             return ExitCode::FAILURE;
         }
         if !path.is_dir() {
-            eprintln!("Working directory is not a directory: {dir}");
+            error!("Working directory is not a directory: {dir}");
             return ExitCode::FAILURE;
         }
     }
 
-    print_plan(&prompts, cli.loops, cli.work_dir.as_deref());
+    let cancel = CancelToken::new();
+    spawn_interrupt_handler(cancel.clone());
+
+    let mut redactor = Redactor::new();
+    if !cli.no_redact_env {
+        redactor = redactor.with_env_secrets();
+    }
+    for pattern in &cli.redact_patterns {
+        redactor = match redactor.with_pattern(pattern) {
+            Ok(r) => r,
+            Err(e) => {
+                error!("Invalid --redact pattern `{pattern}`: {e}");
+                return ExitCode::FAILURE;
+            }
+        };
+    }
+
+    let mut line_filter = LineFilter::new();
+    for pattern in &cli.grep {
+        line_filter = match line_filter.with_include(pattern) {
+            Ok(f) => f,
+            Err(e) => {
+                error!("Invalid --grep pattern `{pattern}`: {e}");
+                return ExitCode::FAILURE;
+            }
+        };
+    }
+    for pattern in &cli.grep_v {
+        line_filter = match line_filter.with_exclude(pattern) {
+            Ok(f) => f,
+            Err(e) => {
+                error!("Invalid --grep-v pattern `{pattern}`: {e}");
+                return ExitCode::FAILURE;
+            }
+        };
+    }
+
+    if (cli.max_cost_per_run.is_some() || cli.max_session_cost.is_some())
+        && cli.usd_per_1k_tokens.is_none()
+    {
+        warn!(
+            "Cost limits only see runs that report a dollar cost; pass --usd-per-1k-tokens to price codex token counts."
+        );
+    }
+
+    if cli.clean_env
+        && !cli
+            .pass_env
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case("PATH"))
+    {
+        warn!(
+            "--clean-env without PATH in --pass-env: codex and the commands it runs may not be found."
+        );
+    }
 
-    let work_dir = cli.work_dir.clone();
-    let codex_bin = cli
-        .codex_bin
+    let tee = match &cli.tee {
+        Some(path) => match TeeFile::create(path) {
+            Ok(tee) => Some(tee),
+            Err(e) => {
+                error!("Cannot create the tee file {}: {e}", path.display());
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+
+    let preamble = match &cli.preamble_file {
+        Some(path) => match std::fs::read_to_string(path) {
+            Ok(preamble) => Some(preamble),
+            Err(e) => {
+                error!("Failed to read preamble file `{}`: {e}", path.display());
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+    let mut prompt_vars = match &cli.vars_file {
+        Some(path) => match load_vars_file(path) {
+            Ok(vars) => vars,
+            Err(e) => {
+                error!("Failed to read vars file `{}`: {e}", path.display());
+                return ExitCode::FAILURE;
+            }
+        },
+        None => PromptVars::new(),
+    };
+    prompt_vars.extend(
+        cli.vars
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone().into())),
+    );
+    let ci_output = cli.ci_output && !io::stdout().is_terminal();
+    let run_options = RunOptions {
+        agent: cli.agent,
+        runner_template: runner_template(&cli),
+        container: cli.in_container.as_deref().map(|image| Container {
+            engine: cli.container_engine.clone(),
+            pass_env: cli.container_env.clone(),
+            ..Container::new(image)
+        }),
+        remote: cli.ssh.as_deref().map(Remote::new),
+        codex_bin: codex_bin(&cli),
+        work_dir: cli.work_dir.as_deref().map(Into::into),
+        model: cli.model.clone(),
+        redactor,
+        line_filter,
+        spool_dir: Some(
+            cli.spool_dir
+                .as_deref()
+                .map(Into::into)
+                .unwrap_or_else(default_spool_dir),
+        ),
+        wrap_lines: cli.wrap,
+        mouse: cli.mouse,
+        timestamps: cli.timestamps,
+        tee,
+        ci_output,
+        capture_output: cli.plan_first || cli.refine_prompts || cli.dynamic_tasks,
+        use_pty: !cli.no_pty,
+        timeout: cli.timeout.map(Duration::from_secs),
+        heartbeat: (cli.heartbeat > 0).then(|| Duration::from_secs(cli.heartbeat)),
+        limits: ResourceLimits {
+            memory_bytes: cli.memory_limit,
+            cpu_time: cli.cpu_limit.map(Duration::from_secs),
+        },
+        cancel: cancel.clone(),
+        shell: cli.shell.as_deref().map(Into::into),
+        shell_fallback: if cli.no_shell_fallback {
+            ShellFallback::Disabled
+        } else if cli.shell_non_interactive {
+            ShellFallback::NonInteractive
+        } else {
+            ShellFallback::Interactive
+        },
+        env: cli.env.clone(),
+        env_allowlist: cli.clean_env.then(|| cli.pass_env.clone()),
+        usd_per_1k_tokens: cli.usd_per_1k_tokens,
+        max_cost_per_run: cli.max_cost_per_run,
+        max_output_bytes: cli.max_output_bytes,
+        record_dir: cli.record_fixtures.clone(),
+        prompt_wrap: PromptWrap {
+            preamble,
+            prefix: cli.prompt_prefix.clone(),
+            suffix: cli.prompt_suffix.clone(),
+        },
+        prompt_vars,
+        raw_prompts: false,
+    };
+    if let Some(Command::Tasks { command }) = &cli.command {
+        return show_tasks(&cli, &tasks, workspace.is_some(), &run_options, command);
+    }
+    if !preflight(&cli, &tasks, workspace.as_ref(), &run_options) {
+        return ExitCode::FAILURE;
+    }
+    if cli.spool_retention_days > 0
+        && let Some(dir) = &run_options.spool_dir
+    {
+        let max_age = Duration::from_secs(cli.spool_retention_days * 24 * 60 * 60);
+        match prune_spool(dir, max_age) {
+            Ok(0) => {}
+            Ok(removed) => info!("Deleted {removed} old run log(s) from {}.", dir.display()),
+            Err(e) => warn!("Cannot clean up old run logs in {}: {e}", dir.display()),
+        }
+    }
+    // With --in-container or --ssh, the agent is elsewhere and only the engine or the SSH
+    // client runs here.
+    let host_program = match (&run_options.container, &run_options.remote) {
+        (Some(container), _) => &container.engine,
+        (None, Some(remote)) => &remote.ssh,
+        (None, None) => &run_options.codex_bin,
+    };
+    if cli.replay_fixtures.is_none()
+        && run_options.shell_fallback == ShellFallback::Disabled
+        && launch::resolve_executable(host_program).is_none()
+    {
+        error!(
+            "Cannot find `{host_program}`.\n{}",
+            launch::not_found_diagnostic(host_program)
+        );
+        return ExitCode::FAILURE;
+    }
+    if cli.require_codex_version.is_some() && cli.ssh.is_some() {
+        warn!("--require-codex-version is not checked on the --ssh machine.");
+    } else if let Some(req) = cli
+        .require_codex_version
+        .as_ref()
+        .filter(|_| cli.replay_fixtures.is_none())
+    {
+        let checked = match launch::resolve_executable(&run_options.codex_bin) {
+            Some(program) => version::check_agent_version(&program, req).await,
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "not found on PATH, so its version cannot be checked",
+            )),
+        };
+        if let Err(e) = checked {
+            if cli.codex_version_warn_only {
+                warn!("`{}` version check failed: {e}", run_options.codex_bin);
+            } else {
+                error!(
+                    "`{}` version check failed: {e}\nUpgrade codex or pass --codex-version-warn-only to continue anyway.",
+                    run_options.codex_bin
+                );
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    let locked_dirs: Vec<PathBuf> = match &workspace {
+        Some(workspace) => workspace
+            .repo
+            .iter()
+            .map(|repo| repo.path.clone())
+            .collect(),
+        // Nothing here to lock when the work dir is on another machine.
+        None if cli.ssh.is_some() => Vec::new(),
+        None => vec![cli.work_dir.as_deref().unwrap_or(".").into()],
+    };
+    let runs = planned_runs(&cli, &tasks, workspace.as_ref());
+    if !cli.yes && cli.confirm_above > 0 && runs > cli.confirm_above {
+        let store = SessionStore::new(&cli.data_dir.clone().unwrap_or_else(default_data_dir));
+        let mut notice = format!(
+            "This session is set to start {runs} runs, more than --confirm-above {}.",
+            cli.confirm_above
+        );
+        match store
+            .list()
+            .ok()
+            .and_then(|records| RunEstimate::from_history(&records))
+        {
+            Some(estimate) => notice.push_str(&format!(
+                " Past sessions suggest {}.",
+                estimate.describe(runs)
+            )),
+            None => notice.push_str(" No past session to estimate its time and spend from."),
+        }
+        if !io::stdin().is_terminal() {
+            error!("{notice} Pass --yes to start it anyway.");
+            return ExitCode::FAILURE;
+        }
+        println!("{notice}");
+        if !confirm_run_count().await {
+            info!("Not confirmed; nothing was run.");
+            return ExitCode::FAILURE;
+        }
+    }
+    // A runner template's command gets no approval-skipping flag from us.
+    if !cli.yes
+        && cli.replay_fixtures.is_none()
+        && cli.runner_template.is_none()
+        && io::stdin().is_terminal()
+    {
+        let mut work_dirs: Vec<PathBuf> = locked_dirs
+            .iter()
+            .map(|dir| std::path::absolute(dir).unwrap_or_else(|_| dir.clone()))
+            .collect();
+        if let Some(destination) = &cli.ssh {
+            let dir = cli.work_dir.as_deref().unwrap_or("~");
+            work_dirs.push(format!("{destination}:{dir}").into());
+        }
+        println!(
+            "{}",
+            launch::sandbox_bypass_notice(cli.agent, &work_dirs, runs)
+        );
+        if !launch::confirm_sandbox_bypass().await {
+            info!("Not confirmed; nothing was run.");
+            return ExitCode::FAILURE;
+        }
+    }
+    // Held until exit; two sessions editing one checkout corrupt each other's work.
+    let mut locks = Vec::new();
+    for dir in &locked_dirs {
+        match SessionLock::acquire(dir, cli.force).await {
+            Ok(lock) => locks.push(lock),
+            Err(e) => {
+                error!("Cannot start: {e}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    match &cli.command {
+        Some(Command::Compare {
+            variants,
+            check,
+            shuffle,
+        }) => {
+            compare(
+                &cli,
+                variants,
+                check.as_deref(),
+                *shuffle,
+                &run_options,
+                &cancel,
+            )
+            .await
+        }
+        Some(Command::Benchmark {
+            models,
+            keep_worktrees,
+        }) => benchmark(&cli, models, *keep_worktrees, &tasks, &run_options, &cancel).await,
+        Some(Command::Vote {
+            samples,
+            required,
+            check,
+            keep_worktrees,
+        }) => {
+            vote(
+                &cli,
+                VoteSettings {
+                    samples: *samples,
+                    required: *required,
+                    check,
+                    keep_worktrees: *keep_worktrees,
+                },
+                &tasks,
+                &run_options,
+                &cancel,
+            )
+            .await
+        }
+        Some(Command::Watch { paths, debounce_ms }) => {
+            watch(
+                &cli,
+                paths,
+                Duration::from_millis(*debounce_ms),
+                &tasks,
+                workspace.as_ref(),
+                &run_options,
+                &cancel,
+            )
+            .await
+        }
+        _ => match &cli.schedule {
+            Some(schedule) => {
+                run_scheduled(
+                    &cli,
+                    schedule,
+                    &tasks,
+                    workspace.as_ref(),
+                    &run_options,
+                    &cancel,
+                )
+                .await
+            }
+            None => run_session(&cli, &tasks, workspace.as_ref(), &run_options, &cancel).await,
+        },
+    }
+}
+
+/// Lint every prompt as its run would render it; `false` when the session must not start.
+fn preflight(
+    cli: &Cli,
+    tasks: &[TaskSpec],
+    workspace: Option<&Workspace>,
+    run_options: &RunOptions,
+) -> bool {
+    let work_dir = Path::new(cli.work_dir.as_deref().unwrap_or("."));
+    let mut lists = vec![(None, work_dir, tasks)];
+    for repo in workspace.iter().flat_map(|w| &w.repo) {
+        lists.push((Some(repo.display_name()), &repo.path, &repo.task));
+    }
+    let mut fatal = false;
+    for (repo, work_dir, tasks) in lists {
+        let lint = |prompts: &[&str]| {
+            lint_prompts(
+                prompts,
+                &run_options.prompt_wrap,
+                &run_options.prompt_vars,
+                work_dir,
+                cli.max_prompt_chars,
+            )
+        };
+        let prompts: Vec<&str> = tasks.iter().map(|task| task.prompt.as_str()).collect();
+        let mut issues = lint(&prompts);
+        // Later loops of tasks with `loop_prompts` send other prompts, checked the same
+        // way but not for repeats.
+        for loop_idx in 1..cli.loops {
+            let (numbers, prompts): (Vec<usize>, Vec<&str>) = (1..)
+                .zip(tasks)
+                .filter_map(|(n, task)| Some((n, task.loop_prompts.get(loop_idx)?.as_str())))
+                .unzip();
+            issues.extend(
+                lint(&prompts)
+                    .into_iter()
+                    .filter(|i| i.fatal)
+                    .map(|i| PromptIssue {
+                        task: numbers[i.task - 1],
+                        message: format!("loop {}: {}", loop_idx + 1, i.message),
+                        ..i
+                    }),
+            );
+        }
+        for issue in issues {
+            let text = match &repo {
+                Some(repo) => format!("{repo}: {issue}"),
+                None => issue.to_string(),
+            };
+            if issue.fatal {
+                error!("Prompt check failed for {text}");
+                fatal = true;
+            } else {
+                warn!("Prompt check: {text}");
+            }
+        }
+    }
+    !fatal
+}
+
+/// Runs the session is set to start, for the confirmation before it: per pass for `watch`
+/// and `--schedule`, and not counting plan steps or tasks the agent adds.
+fn planned_runs(cli: &Cli, tasks: &[TaskSpec], workspace: Option<&Workspace>) -> usize {
+    let tasks =
+        tasks.len() + workspace.map_or(0, |w| w.repo.iter().map(|repo| repo.task.len()).sum());
+    match &cli.command {
+        Some(Command::Compare { variants, .. }) => variants.len() * cli.loops,
+        Some(Command::Benchmark { models, .. }) => models.len() * tasks * cli.loops,
+        Some(Command::Vote { samples, .. }) => *samples,
+        _ if cli.plan_first => 1,
+        _ => tasks * cli.loops,
+    }
+}
+
+/// Stay resident and run a session each time `schedule` fires, until interrupted.
+/// Each session spools its run logs into its own directory.
+async fn run_scheduled(
+    cli: &Cli,
+    schedule: &CronSchedule,
+    tasks: &[TaskSpec],
+    workspace: Option<&Workspace>,
+    run_options: &RunOptions,
+    cancel: &CancelToken,
+) -> ExitCode {
+    let spool_base = run_options
+        .spool_dir
         .clone()
-        .or_else(|| std::env::var("AGENT_LOOPS_CODEX_BIN").ok())
-        .unwrap_or_else(|| "codex".to_string());
-    let results = orchestrate(&prompts, cli.loops, |prompt| {
-        let dir = work_dir.clone();
-        let codex_bin = codex_bin.clone();
-        async move { run_codex(&prompt, dir.as_deref().map(Path::new), &codex_bin).await }
+        .unwrap_or_else(default_spool_dir);
+    let mut exit = ExitCode::SUCCESS;
+    loop {
+        let Some(next) = schedule.next_after(Local::now().naive_local()) else {
+            error!("Schedule `{schedule}` never fires.");
+            return ExitCode::FAILURE;
+        };
+        info!(
+            "Next session at {} (schedule `{schedule}`, Ctrl-C to stop).",
+            next.format("%Y-%m-%d %H:%M")
+        );
+        if !sleep_until_local(next, cancel).await {
+            return exit;
+        }
+        let session_dir = spool_base.join(format!("session-{}", next.format("%Y%m%d-%H%M")));
+        info!("Session logs: {}", session_dir.display());
+        // Nobody watches a scheduled session, so its runs' output is always kept.
+        let session_options = RunOptions {
+            spool_dir: Some(session_dir),
+            capture_output: true,
+            ..run_options.clone()
+        };
+        exit = run_session(cli, tasks, workspace, &session_options, cancel).await;
+        if cancel.is_cancelled() {
+            return exit;
+        }
+    }
+}
+
+/// Rerun the session every time the watched paths change, until interrupted.
+async fn watch(
+    cli: &Cli,
+    paths: &[PathBuf],
+    debounce: Duration,
+    tasks: &[TaskSpec],
+    workspace: Option<&Workspace>,
+    run_options: &RunOptions,
+    cancel: &CancelToken,
+) -> ExitCode {
+    let mut watcher = PathWatcher::new(paths.to_vec());
+    let mut exit = ExitCode::SUCCESS;
+    loop {
+        let watched: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+        info!(
+            "Watching {} for changes (Ctrl-C to stop)...",
+            watched.join(", ")
+        );
+        let Some(changed) = watcher
+            .wait_for_change(watch::POLL_INTERVAL, debounce, cancel)
+            .await
+        else {
+            return exit;
+        };
+        info!(
+            "{} file(s) changed, e.g. {}; starting a pass.",
+            changed.len(),
+            changed[0].display()
+        );
+        exit = run_session(cli, tasks, workspace, run_options, cancel).await;
+        if cancel.is_cancelled() {
+            return exit;
+        }
+        // Edits made by the pass itself must not trigger the next one.
+        watcher.rebaseline();
+    }
+}
+
+/// `agent-loops sessions list|show`.
+fn sessions(cli: &Cli, command: &SessionsCommand) -> ExitCode {
+    let store = SessionStore::new(&cli.data_dir.clone().unwrap_or_else(default_data_dir));
+    match command {
+        SessionsCommand::List => match store.list() {
+            Ok(records) => print!("{}", format_session_list(&records)),
+            Err(e) => {
+                error!("Cannot read the session records: {e}");
+                return ExitCode::FAILURE;
+            }
+        },
+        SessionsCommand::Show { id } => match store.find(id) {
+            Ok(record) => print!("{}", format_session(&record)),
+            Err(e) => {
+                error!("{e}");
+                return ExitCode::FAILURE;
+            }
+        },
+    }
+    ExitCode::SUCCESS
+}
+
+/// `agent-loops tasks list|show`.
+fn show_tasks(
+    cli: &Cli,
+    tasks: &[TaskSpec],
+    workspace: bool,
+    run_options: &RunOptions,
+    command: &TasksCommand,
+) -> ExitCode {
+    if workspace {
+        error!("`tasks` shows the task list of one work dir, not a --workspace.");
+        return ExitCode::FAILURE;
+    }
+    let work_dir = Path::new(cli.work_dir.as_deref().unwrap_or("."));
+    let (wrap, vars) = (&run_options.prompt_wrap, &run_options.prompt_vars);
+    match command {
+        TasksCommand::List => print!("{}", format_task_list(tasks, wrap, vars, work_dir)),
+        TasksCommand::Show { number } => {
+            let Some(task) = tasks.get(number - 1) else {
+                error!("There is no task {number}; the list has {}.", tasks.len());
+                return ExitCode::FAILURE;
+            };
+            print!("{}", format_task(*number, task, wrap, vars, work_dir));
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// `agent-loops replay`: play back each spooled run of a session, or just run `only`.
+async fn replay(cli: &Cli, id: &str, speed: f64, only: Option<usize>) -> ExitCode {
+    let store = SessionStore::new(&cli.data_dir.clone().unwrap_or_else(default_data_dir));
+    let record = match store.find(id) {
+        Ok(record) => record,
+        Err(e) => {
+            error!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    if only.is_some_and(|n| n > record.runs.len()) {
+        error!("Session {} has {} run(s).", record.id, record.runs.len());
+        return ExitCode::FAILURE;
+    }
+    let cancel = CancelToken::new();
+    spawn_interrupt_handler(cancel.clone());
+    let total = record.runs.len();
+    for (i, run) in record.runs.iter().enumerate() {
+        if only.is_some_and(|n| n != i + 1) || cancel.is_cancelled() {
+            continue;
+        }
+        let Some(log) = &run.output_log else {
+            warn!("Run {}/{total} has no output log.", i + 1);
+            continue;
+        };
+        let lines = match replay::load_replay(log) {
+            Ok(lines) => lines,
+            Err(e) => {
+                warn!("Cannot read {}: {e}", log.display());
+                continue;
+            }
+        };
+        let prompt = record
+            .tasks
+            .get(run.task_number - 1)
+            .map(String::as_str)
+            .unwrap_or_default();
+        let header = vec![
+            format!(
+                "[Replay {} {}/{total}] Loop {} | Task {} | {}",
+                record.id,
+                i + 1,
+                run.loop_number,
+                run.task_number,
+                if run.success { "OK" } else { "FAILED" }
+            ),
+            format!("Task: {}", truncate_display(prompt, current_task_len())),
+            "-".repeat(40),
+        ];
+        if let Err(e) = replay::replay(header, &lines, speed, cli.wrap, &cancel).await {
+            error!("Replay failed: {e}");
+            return ExitCode::FAILURE;
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// `--detach`: start this command again in the background under a reserved session ID,
+/// with its output going to the session's log, and print the ID.
+fn detach(cli: &Cli) -> ExitCode {
+    let store = SessionStore::new(&cli.data_dir.clone().unwrap_or_else(default_data_dir));
+    let started = store.reserve().and_then(|id| {
+        let log = store.log_path(&id);
+        let stdout = File::create(&log)?;
+        let stderr = stdout.try_clone()?;
+        let mut cmd = std::process::Command::new(std::env::current_exe()?);
+        // Top-level options go before any subcommand, so the ID goes first.
+        cmd.args(["--session-id", &id])
+            .args(std::env::args_os().skip(1).filter(|arg| arg != "--detach"))
+            .env_remove("AGENT_LOOPS_DETACH")
+            .stdin(Stdio::null())
+            .stdout(stdout)
+            .stderr(stderr);
+        detach_from_terminal(&mut cmd);
+        let child = cmd.spawn()?;
+        Ok((id, child.id(), log))
+    });
+    match started {
+        Ok((id, pid, log)) => {
+            println!("Session {id} started in the background (pid {pid}).");
+            println!("Output: {}", log.display());
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            error!("Cannot start the detached session: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Parse the command line, filling in the options it leaves out from the config file.
+fn parse_cli() -> Cli {
+    let args: Vec<std::ffi::OsString> = std::env::args_os().collect();
+    let matches = cli_command().get_matches_from(&args);
+    if matches!(
+        matches.subcommand_name(),
+        Some("init" | "completions" | "manpage")
+    ) {
+        return cli_from_matches(&matches, Vec::new());
+    }
+    let path = match matches.get_one::<PathBuf>("config") {
+        Some(path) => path.clone(),
+        None if Path::new(CONFIG_FILE).is_file() => PathBuf::from(CONFIG_FILE),
+        None => return cli_from_matches(&matches, Vec::new()),
+    };
+    let config_error = |e: String| -> ! {
+        Cli::command()
+            .error(ErrorKind::InvalidValue, format!("{}: {e}", path.display()))
+            .exit()
+    };
+    let config = load_config(&path).unwrap_or_else(|e| config_error(e.to_string()));
+    let command = cli_command();
+    let given = |id: &str| {
+        matches!(
+            matches.value_source(id),
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+        )
+    };
+    let config_args =
+        config::config_args(&command, &config.options, given).unwrap_or_else(|e| config_error(e));
+    let matches = command.get_matches_from(
+        args.iter()
+            .take(1)
+            .cloned()
+            .chain(config_args.into_iter().map(Into::into))
+            .chain(args.iter().skip(1).cloned()),
+    );
+    cli_from_matches(&matches, config.tasks)
+}
+
+/// The CLI definition with an `AGENT_LOOPS_*` environment variable for each option, named
+/// after its long flag, e.g. `AGENT_LOOPS_LOOPS` for `--loops`.
+fn cli_command() -> clap::Command {
+    Cli::command().mut_args(|arg| {
+        let env = arg.get_long().map(|long| {
+            format!(
+                "AGENT_LOOPS_{}",
+                long.to_ascii_uppercase().replace('-', "_")
+            )
+        });
+        match env {
+            Some(env)
+                if !arg.is_hide_set()
+                    && !matches!(arg.get_action(), ArgAction::Help | ArgAction::Version) =>
+            {
+                arg.env(env)
+            }
+            _ => arg,
+        }
     })
-    .await;
+}
 
-    let failures: Vec<_> = results.iter().filter(|(_, _, ok)| !ok).collect();
-    if failures.is_empty() {
+fn cli_from_matches(matches: &clap::ArgMatches, config_tasks: Vec<TaskSpec>) -> Cli {
+    let mut cli = Cli::from_arg_matches(matches).unwrap_or_else(|e| e.exit());
+    // `AGENT_LOOPS_CODEX_BIN` predates the other variables and only ever named codex.
+    if cli.agent != Agent::Codex
+        && matches.value_source("codex_bin") == Some(ValueSource::EnvVariable)
+    {
+        cli.codex_bin = None;
+    }
+    cli.config_tasks = config_tasks;
+    cli
+}
+
+/// `init`: ask for the basics and write a starter config file.
+fn init(force: bool) -> ExitCode {
+    if Path::new(CONFIG_FILE).exists() && !force {
+        error!("{CONFIG_FILE} already exists; pass --force to replace it.");
+        return ExitCode::FAILURE;
+    }
+    let mut lines = io::stdin().lines().map_while(Result::ok);
+    let mut ask = |question: &str| {
+        print!("{question} ");
+        let _ = io::stdout().flush();
+        lines
+            .next()
+            .map(|line| line.trim().to_string())
+            .unwrap_or_default()
+    };
+    println!("Creating {CONFIG_FILE}; press Enter to take the [default].");
+    println!("Tasks, one prompt per line; an empty line ends the list:");
+    let mut prompts = Vec::new();
+    loop {
+        let prompt = ask(">");
+        if prompt.is_empty() {
+            break;
+        }
+        prompts.push(prompt);
+    }
+    let loops = loop {
+        let answer = ask("Loops through the task list [1]:");
+        match answer.parse::<usize>() {
+            _ if answer.is_empty() => break 1,
+            Ok(loops @ 1..) => break loops,
+            _ => println!("Enter a number of at least 1."),
+        }
+    };
+    let check = ask("Command checking each run's work, e.g. `cargo test` [none]:");
+    let require_clean = loop {
+        match ask("Refuse to start with uncommitted changes? [Y/n]:")
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "" | "y" | "yes" => break true,
+            "n" | "no" => break false,
+            _ => println!("Answer y or n."),
+        }
+    };
+    let git_branch_strategy = loop {
+        let answer = ask("New git branch per session, loop or task? [none]:");
+        match answer.as_str() {
+            "" | "none" => break None,
+            strategy => match strategy.parse::<BranchStrategy>() {
+                Ok(_) => break Some(answer),
+                Err(e) => println!("{e}"),
+            },
+        }
+    };
+    let starter = Starter {
+        prompts,
+        loops,
+        check: (!check.is_empty()).then_some(check),
+        require_clean,
+        git_branch_strategy,
+    };
+    match std::fs::write(CONFIG_FILE, starter.to_toml()) {
+        Ok(()) => {
+            println!("Wrote {CONFIG_FILE}; run `agent-loops` here to start a session.");
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            error!("Cannot write {CONFIG_FILE}: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// `install-service`: install this command line, less the subcommand, as a recurring job.
+fn install_service(schedule: &CronSchedule, name: &str, print: bool) -> ExitCode {
+    let args: Vec<String> = std::env::args_os()
+        .skip(1)
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    let service = std::env::current_exe()
+        .and_then(|exe| Ok((exe, std::env::current_dir()?)))
+        .map(|(exe, work_dir)| Service {
+            name: name.to_string(),
+            schedule: schedule.clone(),
+            command: std::iter::once(exe.to_string_lossy().into_owned())
+                .chain(service_args(&args))
+                .collect(),
+            work_dir,
+            path: std::env::var("PATH").ok(),
+        });
+    let service = match service {
+        Ok(service) => service,
+        Err(e) => {
+            error!("Cannot install the service: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    if print {
+        if cfg!(windows) {
+            print!("{}", service.windows_script());
+        } else {
+            println!("# {name}.service");
+            print!("{}", service.systemd_service());
+            println!("\n# {name}.timer");
+            print!("{}", service.systemd_timer());
+        }
+        return ExitCode::SUCCESS;
+    }
+    match service.install() {
+        Ok(files) => {
+            for file in files {
+                println!("Wrote {}", file.display());
+            }
+            if cfg!(windows) {
+                println!("Scheduled Task `{name}` starts at logon and runs on `{schedule}`.");
+            } else {
+                println!("Enabled {name}.timer; it runs on `{schedule}`.");
+                println!(
+                    "To run it while you are logged out, enable lingering: loginctl enable-linger"
+                );
+            }
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            error!("Cannot install the service: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_service_name(name: &str) -> Result<String, String> {
+    Service::validate_name(name).map(|()| name.to_string())
+}
+
+/// `attach`: follow the session's log until it ends or we detach.
+async fn attach(cli: &Cli, id: &str) -> ExitCode {
+    let store = SessionStore::new(&cli.data_dir.clone().unwrap_or_else(default_data_dir));
+    let record = match store.find(id) {
+        Ok(record) => record,
+        Err(e) => {
+            error!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    if record.current_status() == SessionStatus::Running {
+        println!(
+            "Attached to session {}: p pauses or resumes, s stops, q detaches.",
+            record.id
+        );
+    }
+    let cancel = CancelToken::new();
+    spawn_interrupt_handler(cancel.clone());
+    match attach::follow_stdout(&store, &record.id, &cancel).await {
+        Ok(Some(status)) => {
+            println!("Session {} {}.", record.id, status.as_str());
+            ExitCode::SUCCESS
+        }
+        Ok(None) => {
+            println!("Detached; session {} keeps running.", record.id);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            error!("Cannot follow session {}: {e}", record.id);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Run `cmd` in a session of its own, so closing the terminal does not stop it.
+fn detach_from_terminal(cmd: &mut std::process::Command) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        // SAFETY: setsid is async-signal-safe and touches no state of ours.
+        unsafe {
+            cmd.pre_exec(|| {
+                libc::setsid();
+                Ok(())
+            });
+        }
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        use windows_sys::Win32::System::Threading::{CREATE_NEW_PROCESS_GROUP, DETACHED_PROCESS};
+        cmd.creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP);
+    }
+}
+
+/// Record a starting session under the data dir; a failure only costs the record.
+fn record_session_start(
+    cli: &Cli,
+    tasks: &[TaskSpec],
+    workspace: Option<&Workspace>,
+    run_options: &RunOptions,
+) -> Option<(SessionStore, SessionRecord)> {
+    let (work_dirs, prompts) = match workspace {
+        Some(workspace) => (
+            workspace
+                .repo
+                .iter()
+                .map(|repo| repo.path.clone())
+                .collect(),
+            workspace
+                .repo
+                .iter()
+                .flat_map(|repo| {
+                    let name = repo.display_name();
+                    repo.task
+                        .iter()
+                        .map(move |task| format!("{name}: {}", task.prompt))
+                })
+                .collect(),
+        ),
+        None => (
+            vec![cli.work_dir.as_deref().unwrap_or(".").into()],
+            tasks.iter().map(|task| task.prompt.clone()).collect(),
+        ),
+    };
+    let mut record = SessionRecord::new(cli.session_name.clone(), work_dirs, cli.loops, prompts);
+    record.spool_dir = run_options.spool_dir.clone();
+    let store = SessionStore::new(&cli.data_dir.clone().unwrap_or_else(default_data_dir));
+    let stored = match &cli.session_id {
+        Some(id) => {
+            record.id = id.clone();
+            store.save(&record)
+        }
+        None => store.create(&mut record),
+    };
+    match stored {
+        Ok(()) => {
+            info!("Session {}", record.id);
+            Some((store, record))
+        }
+        Err(e) => {
+            warn!("Cannot record the session: {e}");
+            None
+        }
+    }
+}
+
+/// `options` taking pause requests from `attach` for the recorded session, whose stop
+/// requests are watched for from now on.
+fn with_session_control(
+    options: &OrchestrateOptions,
+    session: Option<&(SessionStore, SessionRecord)>,
+) -> OrchestrateOptions {
+    let control = session.map(|(store, record)| store.control(&record.id));
+    if let Some(control) = &control {
+        tokio::spawn(control.clone().watch_for_stop(options.cancel.clone()));
+    }
+    OrchestrateOptions {
+        control,
+        ..options.clone()
+    }
+}
+
+/// Launches codex, or plays back recorded fixtures for `--replay-fixtures`.
+enum SessionRunner<'a> {
+    Codex(Box<CodexRunner>),
+    Fixtures(&'a FixtureRunner),
+}
+
+impl Runner for SessionRunner<'_> {
+    async fn run(&self, task: &TaskSpec) -> Result<RunOutcome, AgentLoopsError> {
+        match self {
+            Self::Codex(runner) => runner.run(task).await,
+            Self::Fixtures(runner) => runner.run(task).await,
+        }
+    }
+}
+
+/// The session's orchestration options, built from the CLI flags.
+fn orchestrate_options(
+    cli: &Cli,
+    run_options: &RunOptions,
+    cancel: &CancelToken,
+) -> OrchestrateOptions {
+    OrchestrateOptions {
+        edit_prompts: cli.edit_prompts,
+        editor: cli.editor.clone(),
+        cancel: cancel.clone(),
+        max_consecutive_failures: cli.max_consecutive_failures,
+        confirm_failure_streak: cli.confirm_on_failures,
+        rate_limit_retries: cli.rate_limit_retries,
+        rate_limit_cooldown: Duration::from_secs(cli.rate_limit_cooldown),
+        max_runs_per_hour: cli.max_runs_per_hour,
+        max_session_cost: cli.max_session_cost,
+        git_work_dir: cli
+            .ssh
+            .is_none()
+            .then(|| cli.work_dir.as_deref().unwrap_or(".").into()),
+        require_changes: cli.require_changes,
+        no_change_retries: cli.no_change_retries,
+        rollback_on_failure: cli.rollback_on_failure,
+        branch_strategy: cli.git_branch_strategy,
+        blackouts: cli.no_run_between.clone(),
+        bell_on_failure: cli.bell_on_failure,
+        desktop_notify: cli.desktop_notify,
+        tee: run_options.tee.clone(),
+        ci_output: run_options.ci_output,
+        max_added_tasks: cli.dynamic_tasks.then_some(cli.max_added_tasks),
+        start_at: None,
+        disk_guard: cli.min_free_space.map(|min_free| DiskGuard {
+            min_free,
+            dirs: [
+                Some(PathBuf::from(cli.work_dir.as_deref().unwrap_or("."))),
+                run_options.spool_dir.clone(),
+            ]
+            .into_iter()
+            .flatten()
+            .collect(),
+            action: cli.on_low_disk,
+        }),
+        control: None,
+        artifacts: (!cli.collect.is_empty()).then(|| ArtifactCollector {
+            patterns: cli.collect.clone(),
+            dir: run_options
+                .spool_dir
+                .clone()
+                .unwrap_or_else(default_spool_dir)
+                .join(format!("artifacts-{}", std::process::id())),
+        }),
+    }
+}
+
+/// The `--replay-fixtures` runner, when replaying.
+fn load_fixture_runner(cli: &Cli, run_options: &RunOptions) -> Result<Option<FixtureRunner>, ()> {
+    let Some(dir) = &cli.replay_fixtures else {
+        return Ok(None);
+    };
+    match FixtureRunner::load(dir, run_options.clone()) {
+        Ok(fixtures) => Ok(Some(fixtures)),
+        Err(e) => {
+            error!("Cannot load fixtures from {}: {e}", dir.display());
+            Err(())
+        }
+    }
+}
+
+/// Run `prompt` once and return its output, or `None` when the run failed. `runner` must
+/// capture output ([`RunOptions::capture_output`]).
+async fn capture_run(
+    name: &str,
+    prompt: String,
+    runner: &SessionRunner<'_>,
+    options: &OrchestrateOptions,
+) -> Option<String> {
+    let task = TaskSpec {
+        name: Some(name.to_string()),
+        ..TaskSpec::new(prompt)
+    };
+    let results = orchestrate_runner(std::slice::from_ref(&task), 1, options, runner).await;
+    let outcome = &results.first()?.outcome;
+    if !outcome.success {
+        error!("The {name} run failed.");
+        return None;
+    }
+    let Some(path) = &outcome.output_log else {
+        error!("The {name} run left no output log to read.");
+        return None;
+    };
+    match read_output_log(path) {
+        Ok(output) => Some(output),
+        Err(e) => {
+            error!("Cannot read the {name} output {}: {e}", path.display());
+            None
+        }
+    }
+}
+
+/// The answer to the auxiliary `prompt`: from the `--aux-model` API when one is given,
+/// otherwise from an agent run ([`capture_run`]). `None` when there is no answer.
+async fn ask(
+    cli: &Cli,
+    name: &str,
+    prompt: String,
+    runner: &SessionRunner<'_>,
+    options: &OrchestrateOptions,
+) -> Option<String> {
+    #[cfg(feature = "api")]
+    if let Some(model) = &cli.aux_model {
+        let client = match ApiClient::from_env(model.clone()) {
+            Ok(client) => client,
+            Err(e) => {
+                error!("Cannot ask {model} for the {name}: {e}");
+                return None;
+            }
+        };
+        info!("Asking {model} for the {name}.");
+        let answer = tokio::select! {
+            answer = client.complete(&prompt) => answer,
+            () = options.cancel.cancelled() => return None,
+        };
+        return match answer {
+            Ok(answer) => Some(answer),
+            Err(e) => {
+                error!("The {name} by {model} failed: {e}");
+                None
+            }
+        };
+    }
+    #[cfg(not(feature = "api"))]
+    let _ = cli;
+    capture_run(name, prompt, runner, options).await
+}
+
+/// `--plan-first`: plan each task, get the plan approved and return the steps of all
+/// plans as the session's tasks; `None` when a plan fails or is rejected.
+async fn plan_session(
+    cli: &Cli,
+    tasks: &[TaskSpec],
+    runner: &SessionRunner<'_>,
+    options: &OrchestrateOptions,
+) -> Option<Vec<TaskSpec>> {
+    // Nothing to track in git: planning and review runs only write text.
+    let options = OrchestrateOptions {
+        edit_prompts: false,
+        git_work_dir: None,
+        max_added_tasks: None,
+        start_at: None,
+        ..options.clone()
+    };
+    let tee = |line: &str| {
+        if let Some(tee) = &options.tee {
+            tee.write_line(line);
+        }
+    };
+    let mut steps_tasks = Vec::new();
+    for goal in tasks {
+        let output = capture_run("planning", plan_prompt(&goal.prompt), runner, &options).await?;
+        let steps = parse_plan(&output);
+        if steps.is_empty() {
+            error!("The plan for `{}` has no `STEP <n>:` lines.", goal.prompt);
+            return None;
+        }
+        let plan = format_plan(&steps);
+        print!("{plan}");
+        for line in plan.lines() {
+            tee(line);
+        }
+        let approved = if cli.plan_auto_approve {
+            true
+        } else if let Some(instructions) = &cli.plan_judge {
+            let prompt = judge_prompt(instructions, &goal.prompt, &steps);
+            let output = ask(cli, "plan review", prompt, runner, &options).await?;
+            match parse_verdict(&output) {
+                Some(verdict) => verdict,
+                None => {
+                    error!("The plan review gave no `VERDICT:` line.");
+                    return None;
+                }
+            }
+        } else if io::stdin().is_terminal() {
+            confirm_plan().await
+        } else {
+            error!(
+                "Cannot ask for approval without a terminal; pass --plan-auto-approve or --plan-judge."
+            );
+            return None;
+        };
+        if !approved {
+            error!("The plan was not approved; nothing else runs.");
+            return None;
+        }
+        info!("Plan approved: {} step(s).", steps.len());
+        steps_tasks.extend(step_tasks(goal, &steps));
+    }
+    Some(steps_tasks)
+}
+
+/// `--refine-prompts`: have each task's prompt rewritten and, once approved, use the
+/// rewrite; `None` when a rewrite cannot be had.
+async fn refine_session(
+    cli: &Cli,
+    tasks: &[TaskSpec],
+    runner: &SessionRunner<'_>,
+    options: &OrchestrateOptions,
+) -> Option<Vec<TaskSpec>> {
+    // As for planning, the refining runs only write text.
+    let options = OrchestrateOptions {
+        edit_prompts: false,
+        git_work_dir: None,
+        max_added_tasks: None,
+        start_at: None,
+        ..options.clone()
+    };
+    if !cli.refine_auto_approve && !io::stdin().is_terminal() {
+        error!("Cannot ask for approval without a terminal; pass --refine-auto-approve.");
+        return None;
+    }
+    let mut refined_tasks = Vec::new();
+    for (idx, task) in tasks.iter().enumerate() {
+        // Refine what the first loop sends, which is a `loop_prompts` entry if there are any.
+        let prompt = task.for_loop(0).prompt;
+        let output = ask(
+            cli,
+            "prompt refinement",
+            refine_prompt(&prompt),
+            runner,
+            &options,
+        )
+        .await?;
+        let Some(refined) = parse_refined(&output) else {
+            error!(
+                "The refinement of task {} gave no `BEGIN PROMPT` block.",
+                idx + 1
+            );
+            return None;
+        };
+        let shown = format_refined(idx + 1, &prompt, &refined);
+        print!("{shown}");
+        if let Some(tee) = &options.tee {
+            for line in shown.lines() {
+                tee.write_line(line);
+            }
+        }
+        if cli.refine_auto_approve || confirm_refined().await {
+            let mut task = task.clone();
+            task.set_loop_prompt(0, refined);
+            refined_tasks.push(task);
+        } else {
+            info!("Keeping the original prompt of task {}.", idx + 1);
+            refined_tasks.push(task.clone());
+        }
+    }
+    Some(refined_tasks)
+}
+
+/// Run each prompt variant once per loop through [`CheckedRunner`] and print which one
+/// succeeded most often.
+async fn compare(
+    cli: &Cli,
+    variant_files: &[PathBuf],
+    check: Option<&str>,
+    shuffle: bool,
+    run_options: &RunOptions,
+    cancel: &CancelToken,
+) -> ExitCode {
+    let mut variants = Vec::new();
+    for path in variant_files {
+        match load_variant(path) {
+            Ok(variant) => variants.push(variant),
+            Err(e) => {
+                error!("Cannot read the variant {}: {e}", path.display());
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    let Ok(fixtures) = load_fixture_runner(cli, run_options) else {
+        return ExitCode::FAILURE;
+    };
+    let seed = shuffle.then(|| RandomState::new().hash_one(std::process::id()));
+    let order = trial_order(variants.len(), cli.loops, seed);
+    let tasks = trial_tasks(&variants, &order);
+
+    println!("\n=== Agent Loops Comparison ===");
+    println!(
+        "Work dir: {}",
+        cli.work_dir.as_deref().unwrap_or("(current directory)")
+    );
+    println!("Loops: {} | Trials: {}", cli.loops, tasks.len());
+    for (idx, variant) in variants.iter().enumerate() {
+        println!(
+            "  {} {}: {}",
+            variant_letter(idx),
+            variant.label,
+            truncate_display(&variant.prompt, current_task_len())
+        );
+    }
+    if let Some(check) = check {
+        println!("Check: {check}");
+    }
+    println!("==============================\n");
+
+    let inner = match &fixtures {
+        Some(fixtures) => SessionRunner::Fixtures(fixtures),
+        None => SessionRunner::Codex(Box::new(CodexRunner::new(run_options.clone()))),
+    };
+    let runner = CheckedRunner::new(
+        inner,
+        check.map(Into::into),
+        cli.work_dir.as_deref().unwrap_or(".").into(),
+    );
+    let options = orchestrate_options(cli, run_options, cancel);
+    let results = orchestrate_runner(&tasks, 1, &options, &runner).await;
+
+    let summary = format_comparison(&variant_results(&variants, &order, &results));
+    print!("{summary}");
+    if let Some(tee) = &run_options.tee {
+        for line in summary.lines() {
+            tee.write_line(line);
+        }
+    }
+    if cancel.is_cancelled() {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Run the task list once per model, each in a fresh worktree of the work dir, and
+/// print how the models compare.
+async fn benchmark(
+    cli: &Cli,
+    models: &[String],
+    keep_worktrees: bool,
+    tasks: &[TaskSpec],
+    run_options: &RunOptions,
+    cancel: &CancelToken,
+) -> ExitCode {
+    let repo = Path::new(cli.work_dir.as_deref().unwrap_or("."));
+    if git::is_dirty(repo).await.unwrap_or(false) {
+        warn!(
+            "The work dir has uncommitted changes; the benchmark worktrees start from HEAD without them."
+        );
+    }
+    let Ok(fixtures) = load_fixture_runner(cli, run_options) else {
+        return ExitCode::FAILURE;
+    };
+    let tasks = benchmark_tasks(tasks);
+    let prompts: Vec<String> = tasks.iter().map(|task| task.prompt.clone()).collect();
+    print_plan(&prompts, cli.loops, cli.work_dir.as_deref());
+
+    let mut compared = Vec::new();
+    for (idx, model) in models.iter().enumerate() {
+        if cancel.is_cancelled() {
+            break;
+        }
+        let worktree = match git::Worktree::add_temp(repo, "benchmark").await {
+            Ok(worktree) => worktree,
+            Err(e) => {
+                error!("Cannot create a worktree for {model}: {e}");
+                return ExitCode::FAILURE;
+            }
+        };
+        let line = format!(
+            "=== Model {}/{}: {model} ({}) ===",
+            idx + 1,
+            models.len(),
+            worktree.path.display()
+        );
+        println!("{line}");
+        if let Some(tee) = &run_options.tee {
+            tee.write_line(&line);
+        }
+        let model_options = RunOptions {
+            model: Some(model.clone()),
+            work_dir: Some(worktree.path.clone()),
+            ..run_options.clone()
+        };
+        let options = OrchestrateOptions {
+            git_work_dir: Some(worktree.path.clone()),
+            ..orchestrate_options(cli, &model_options, cancel)
+        };
+        let runner = match &fixtures {
+            Some(fixtures) => SessionRunner::Fixtures(fixtures),
+            None => SessionRunner::Codex(Box::new(CodexRunner::new(model_options))),
+        };
+        let results = orchestrate_runner(&tasks, cli.loops, &options, &runner).await;
+        compared.push(ModelResult::new(model, &results));
+        if keep_worktrees {
+            info!("Kept {model}'s worktree at {}", worktree.path.display());
+        } else if let Err(e) = worktree.remove().await {
+            warn!("Cannot remove {model}'s worktree: {e}");
+        }
+    }
+
+    let table = format_benchmark(&compared);
+    print!("{table}");
+    if let Some(tee) = &run_options.tee {
+        for line in table.lines() {
+            tee.write_line(line);
+        }
+    }
+    if cancel.is_cancelled() {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Options of the `vote` subcommand.
+struct VoteSettings<'a> {
+    samples: usize,
+    required: usize,
+    check: &'a str,
+    keep_worktrees: bool,
+}
+
+/// Run the single prompt in a fresh worktree per sample, verify each with the check and
+/// apply the consensus sample's changes to the work dir when enough samples passed.
+async fn vote(
+    cli: &Cli,
+    settings: VoteSettings<'_>,
+    tasks: &[TaskSpec],
+    run_options: &RunOptions,
+    cancel: &CancelToken,
+) -> ExitCode {
+    let [task] = tasks else {
+        error!(
+            "vote runs exactly one prompt, but {} were given.",
+            tasks.len()
+        );
+        return ExitCode::FAILURE;
+    };
+    let repo = Path::new(cli.work_dir.as_deref().unwrap_or("."));
+    if git::is_dirty(repo).await.unwrap_or(false) {
+        warn!("The work dir has uncommitted changes; the samples start from HEAD without them.");
+    }
+    let Ok(fixtures) = load_fixture_runner(cli, run_options) else {
+        return ExitCode::FAILURE;
+    };
+    let tee = |line: &str| {
+        if let Some(tee) = &run_options.tee {
+            tee.write_line(line);
+        }
+    };
+
+    let mut samples = Vec::new();
+    let mut worktrees = Vec::new();
+    for idx in 0..settings.samples {
+        if cancel.is_cancelled() {
+            break;
+        }
+        let worktree = match git::Worktree::add_temp(repo, "vote").await {
+            Ok(worktree) => worktree,
+            Err(e) => {
+                error!("Cannot create a worktree for sample {}: {e}", idx + 1);
+                break;
+            }
+        };
+        let line = format!(
+            "=== Sample {}/{} ({}) ===",
+            idx + 1,
+            settings.samples,
+            worktree.path.display()
+        );
+        println!("{line}");
+        tee(&line);
+        let sample_options = RunOptions {
+            work_dir: Some(worktree.path.clone()),
+            ..run_options.clone()
+        };
+        let options = OrchestrateOptions {
+            git_work_dir: Some(worktree.path.clone()),
+            ..orchestrate_options(cli, &sample_options, cancel)
+        };
+        let inner = match &fixtures {
+            Some(fixtures) => SessionRunner::Fixtures(fixtures),
+            None => SessionRunner::Codex(Box::new(CodexRunner::new(sample_options))),
+        };
+        let runner = CheckedRunner::new(
+            inner,
+            Some(settings.check.to_string()),
+            worktree.path.clone(),
+        )
+        .keep_changes();
+        let results = orchestrate_runner(std::slice::from_ref(task), 1, &options, &runner).await;
+        let record = results.first();
+        samples.push(Sample {
+            passed: record.is_some_and(|r| r.outcome.success),
+            tree: git::snapshot(&worktree.path).await.ok(),
+            changes: record.and_then(|r| r.outcome.changes),
+        });
+        worktrees.push(worktree);
+    }
+
+    let chosen = pick_consensus(&samples);
+    let summary = format_vote(&samples, settings.required, chosen);
+    print!("{summary}");
+    for line in summary.lines() {
+        tee(line);
+    }
+    let passed = samples.iter().filter(|s| s.passed).count();
+    let exit = match chosen.filter(|_| passed >= settings.required && !cancel.is_cancelled()) {
+        Some(idx) => {
+            let tree = samples[idx].tree.as_deref().unwrap_or_default();
+            match git::apply_changes(repo, "HEAD", tree).await {
+                Ok(()) => {
+                    info!("Applied sample {}'s changes to the work dir.", idx + 1);
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    error!(
+                        "Cannot apply sample {}'s changes to the work dir: {e}\nRerun with --keep-worktrees to copy them over by hand.",
+                        idx + 1
+                    );
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        None => ExitCode::FAILURE,
+    };
+    for worktree in worktrees {
+        let path = worktree.path.clone();
+        if settings.keep_worktrees {
+            info!("Kept the worktree {}", path.display());
+        } else if let Err(e) = worktree.remove().await {
+            warn!("Cannot remove the worktree {}: {e}", path.display());
+        }
+    }
+    exit
+}
+
+/// The `--cache` of successful runs, from the stored sessions.
+fn run_cache(cli: &Cli) -> Option<RunCache> {
+    if !cli.cache {
+        return None;
+    }
+    let store = SessionStore::new(&cli.data_dir.clone().unwrap_or_else(default_data_dir));
+    Some(RunCache::load(&store).unwrap_or_else(|e| {
+        warn!("Cannot read the stored sessions for --cache: {e}");
+        RunCache::default()
+    }))
+}
+
+/// Run the tasks (or the workspace) once and print the summary.
+async fn run_session(
+    cli: &Cli,
+    tasks: &[TaskSpec],
+    workspace: Option<&Workspace>,
+    run_options: &RunOptions,
+    cancel: &CancelToken,
+) -> ExitCode {
+    let options = OrchestrateOptions {
+        start_at: cli.start_at,
+        ..orchestrate_options(cli, run_options, cancel)
+    };
+    let Ok(fixtures) = load_fixture_runner(cli, run_options) else {
+        return ExitCode::FAILURE;
+    };
+    let runner = |options: RunOptions| match &fixtures {
+        Some(fixtures) => SessionRunner::Fixtures(fixtures),
+        None => SessionRunner::Codex(Box::new(CodexRunner::new(options))),
+    };
+    let refined;
+    let tasks = if cli.refine_prompts {
+        let raw = RunOptions {
+            raw_prompts: true,
+            ..run_options.clone()
+        };
+        match refine_session(cli, tasks, &runner(raw), &options).await {
+            Some(tasks) => {
+                refined = tasks;
+                &refined[..]
+            }
+            None => return ExitCode::FAILURE,
+        }
+    } else {
+        tasks
+    };
+    let planned;
+    let tasks = if cli.plan_first {
+        match plan_session(cli, tasks, &runner(run_options.clone()), &options).await {
+            Some(steps) => {
+                planned = steps;
+                &planned[..]
+            }
+            None => return ExitCode::FAILURE,
+        }
+    } else {
+        tasks
+    };
+    let mut session;
+    let results = if let Some(workspace) = workspace {
+        session = record_session_start(cli, tasks, Some(workspace), run_options);
+        let options = with_session_control(&options, session.as_ref());
+        let repos = orchestrate_workspace(workspace, cli.loops, &options, |repo| {
+            let options = RunOptions {
+                work_dir: Some(repo.path.clone()),
+                ..run_options.clone()
+            };
+            let checked = CheckedRunner::new(
+                runner(options.clone()),
+                cli.check.clone(),
+                repo.path.clone(),
+            )
+            .keep_changes();
+            CachedRunner::new(checked, run_cache(cli), options)
+        })
+        .await;
+        print!("{}", format_workspace_summary(&repos));
+        repos.into_iter().flat_map(|repo| repo.results).collect()
+    } else {
+        let work_dir = Path::new(cli.work_dir.as_deref().unwrap_or("."));
+        let clean_policy = if cli.auto_stash {
+            Some(CleanPolicy::AutoStash)
+        } else if cli.require_clean {
+            Some(CleanPolicy::Require)
+        } else {
+            None
+        };
+        let stash = match clean_policy {
+            Some(policy) => match git::ensure_clean(work_dir, policy).await {
+                Ok(stash) => stash,
+                Err(e) => {
+                    error!("Cannot start: {e}");
+                    return ExitCode::FAILURE;
+                }
+            },
+            None => None,
+        };
+        if let Some(stash) = &stash
+            && !stash.restore_after
+        {
+            info!("Stashed uncommitted changes; restore them with `git stash pop`.");
+        }
+
+        let prompts: Vec<String> = tasks.iter().map(|task| task.prompt.clone()).collect();
+        print_plan(&prompts, cli.loops, cli.work_dir.as_deref());
+        session = record_session_start(cli, tasks, None, run_options);
+        let options = with_session_control(&options, session.as_ref());
+
+        let checked = CheckedRunner::new(
+            runner(run_options.clone()),
+            cli.check.clone(),
+            work_dir.to_path_buf(),
+        )
+        .keep_changes();
+        let runner = CachedRunner::new(checked, run_cache(cli), run_options.clone());
+        let results = orchestrate_runner(tasks, cli.loops, &options, &runner).await;
+
+        if let Some(stash) = stash.filter(|stash| stash.restore_after) {
+            match stash.restore().await {
+                Ok(()) => info!("Restored the changes stashed before the session."),
+                Err(e) => warn!(
+                    "Could not restore the changes stashed before the session ({e}); they are still in `git stash list`."
+                ),
+            }
+        }
+
+        results
+    };
+
+    if let Some((store, record)) = &mut session {
+        record.finish(&results, cancel.is_cancelled());
+        if let Err(e) = store.save(record) {
+            warn!("Cannot record the session results: {e}");
+        }
+    }
+    let tee = |line: &str| {
+        if let Some(tee) = &run_options.tee {
+            tee.write_line(line);
+        }
+    };
+    let costs: Vec<f64> = results.iter().filter_map(|r| r.outcome.cost_usd).collect();
+    if !costs.is_empty() {
+        let line = format!(
+            "Session cost: ${:.2} over {} run(s) reporting usage.",
+            costs.iter().sum::<f64>(),
+            costs.len()
+        );
+        println!("{line}");
+        tee(&line);
+    }
+    let successes = task_successes(tasks, &results);
+    let stats = task_stats(tasks, &results);
+    let summary = session_summary(tasks, &results);
+    let mut tables = format_session_summary(&summary);
+    if cli.stats {
+        tables.push_str(&format_task_stats(&stats));
+    }
+    print!("{tables}");
+    for line in tables.lines() {
+        tee(line);
+    }
+    if cli.desktop_notify
+        && let Err(e) = notify::desktop_notify(
+            "agent-loops",
+            &format!("Session finished: {}", summary.headline()),
+        )
+        .await
+    {
+        warn!("Cannot show a desktop notification: {e}");
+    }
+    if let Some(path) = &cli.stats_out {
+        let is_csv = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+        let content = if is_csv {
+            stats_csv(&stats)
+        } else {
+            stats_json(&stats)
+        };
+        match std::fs::write(path, content) {
+            Ok(()) => info!("Wrote statistics to {}", path.display()),
+            Err(e) => warn!("Cannot write statistics to {}: {e}", path.display()),
+        }
+    }
+    let unmet: Vec<_> = successes.iter().filter(|task| !task.met()).collect();
+    if !unmet.is_empty() {
+        let line = format!(
+            "{} task(s) below their min_successes: {}.",
+            unmet.len(),
+            unmet
+                .iter()
+                .map(|task| (task.task_idx + 1).to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        eprintln!("{line}");
+        tee(&line);
+    }
+    // Tasks with `min_successes` were judged above; their failed runs are expected.
+    let judged_per_run: Vec<RunRecord> = results
+        .into_iter()
+        .filter(|r| {
+            tasks
+                .get(r.task_idx)
+                .is_none_or(|task| task.min_successes.is_none())
+        })
+        .collect();
+    let failures: Vec<_> = judged_per_run
+        .iter()
+        .filter(|r| !r.outcome.success)
+        .collect();
+    if failures.is_empty() && unmet.is_empty() {
         println!("All tasks completed successfully.");
+        tee("All tasks completed successfully.");
+        return ExitCode::SUCCESS;
+    }
+    if failures.is_empty() {
+        return ExitCode::FAILURE;
+    }
+    // The summary above shows which runs failed.
+    if unmet.is_empty() && cli.exit_policy.passes(&judged_per_run) {
+        let line = format!(
+            "{} run(s) failed; exiting successfully under --exit-policy {}.",
+            failures.len(),
+            cli.exit_policy
+        );
+        eprintln!("{line}");
+        tee(&line);
         ExitCode::SUCCESS
     } else {
-        eprintln!("{} task(s) failed.", failures.len());
         ExitCode::FAILURE
     }
 }
 
-fn load_prompts_file(path: &Path) -> io::Result<Vec<String>> {
-    let content = fs::read_to_string(path)?;
-    Ok(content
-        .lines()
-        .map(str::trim)
-        .filter(|line| !line.is_empty())
-        .map(ToOwned::to_owned)
-        .collect())
+async fn doctor(cli: &Cli) -> ExitCode {
+    let options = DoctorOptions {
+        codex_bin: codex_bin(cli),
+        work_dir: cli.work_dir.as_deref().map(Into::into),
+        prompts_file: cli.prompts_file.as_deref().map(Into::into),
+        ..DoctorOptions::default()
+    };
+    let options = match &cli.require_codex_version {
+        Some(req) => DoctorOptions {
+            version_req: req.clone(),
+            ..options
+        },
+        None => options,
+    };
+    let checks = run_doctor(&options).await;
+    print!("{}", format_checklist(&checks));
+    if checks.iter().any(|c| c.status == CheckStatus::Fail) {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Agent binary from `--codex-bin` (or for codex `AGENT_LOOPS_CODEX_BIN`), then the
+/// agent's name.
+fn codex_bin(cli: &Cli) -> String {
+    if let Some(template) = &cli.runner_template {
+        return template.program().to_string();
+    }
+    cli.codex_bin
+        .clone()
+        .unwrap_or_else(|| cli.agent.default_bin().to_string())
+}
+
+/// The runner template with its success codes and pattern.
+fn runner_template(cli: &Cli) -> Option<RunnerTemplate> {
+    let mut template = cli.runner_template.clone()?;
+    if !cli.runner_success_codes.is_empty() {
+        template.success_codes = cli.runner_success_codes.clone();
+    }
+    template.success_pattern = cli.runner_success_pattern.clone();
+    Some(template)
+}
+
+fn parse_speed(text: &str) -> Result<f64, String> {
+    match text.trim_end_matches('x').parse::<f64>() {
+        Ok(value) if value.is_finite() && value > 0.0 => Ok(value),
+        _ => Err(format!("expected a positive speed factor, got `{text}`")),
+    }
+}
+
+fn parse_usd(text: &str) -> Result<f64, String> {
+    match text.trim_start_matches('$').parse::<f64>() {
+        Ok(value) if value.is_finite() && value > 0.0 => Ok(value),
+        _ => Err(format!("expected a positive dollar amount, got `{text}`")),
+    }
+}
+
+/// A byte count with an optional `K`, `M` or `G` suffix (powers of 1024).
+fn parse_size(text: &str) -> Result<u64, String> {
+    let upper = text.trim().to_ascii_uppercase();
+    let digits = upper.trim_end_matches(['B', 'I']);
+    let (digits, unit) = match digits.char_indices().last() {
+        Some((at, 'K')) => (&digits[..at], 1 << 10),
+        Some((at, 'M')) => (&digits[..at], 1 << 20),
+        Some((at, 'G')) => (&digits[..at], 1 << 30),
+        _ => (digits, 1),
+    };
+    match digits.trim().parse::<u64>() {
+        Ok(value) if value > 0 => value
+            .checked_mul(unit)
+            .ok_or_else(|| format!("size `{text}` is too large")),
+        _ => Err(format!("expected a size such as 512M or 4G, got `{text}`")),
+    }
+}
+
+fn parse_env_assignment(text: &str) -> Result<(String, String), String> {
+    match text.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got `{text}`")),
+    }
+}
+
+/// First Ctrl-C (or SIGTERM) cancels the running child tree and the session, which then
+/// rolls back and records itself as usual; a second one exits at once.
+fn spawn_interrupt_handler(cancel: CancelToken) {
+    tokio::spawn(async move {
+        #[cfg(unix)]
+        let mut terminate = signal(SignalKind::terminate()).ok();
+        loop {
+            #[cfg(unix)]
+            let received = tokio::select! {
+                result = tokio::signal::ctrl_c() => result.is_ok(),
+                () = terminated(&mut terminate) => true,
+            };
+            #[cfg(not(unix))]
+            let received = tokio::signal::ctrl_c().await.is_ok();
+            if !received {
+                break;
+            }
+            if cancel.is_cancelled() {
+                shutdown::exit_now(130);
+            }
+            warn!(
+                "Interrupt received, stopping the current run (press Ctrl-C again to force exit)."
+            );
+            cancel.cancel();
+        }
+    });
+}
+
+/// Resolves when SIGTERM arrives; never when it cannot be listened for.
+#[cfg(unix)]
+async fn terminated(terminate: &mut Option<Signal>) {
+    if let Some(signal) = terminate {
+        if signal.recv().await.is_some() {
+            return;
+        }
+        *terminate = None;
+    }
+    std::future::pending().await
 }
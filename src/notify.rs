@@ -0,0 +1,73 @@
+//! Alerting the user when a run fails: a terminal bell or an OS desktop notification.
+
+use std::io::{self, Write};
+use std::process::Stdio;
+use std::time::Duration;
+
+use tokio::process::Command;
+
+/// How long the notification helper may take before it is abandoned.
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Ring the terminal bell on stderr.
+pub fn ring_bell() {
+    let mut stderr = io::stderr();
+    let _ = stderr.write_all(b"\x07");
+    let _ = stderr.flush();
+}
+
+/// Command showing a desktop notification: `notify-send` on Linux and the BSDs,
+/// AppleScript on macOS, a tray balloon through PowerShell on Windows.
+pub fn notification_command(title: &str, body: &str) -> Command {
+    if cfg!(target_os = "macos") {
+        let mut cmd = Command::new("osascript");
+        cmd.arg("-e").arg(format!(
+            "display notification {} with title {}",
+            applescript_string(body),
+            applescript_string(title)
+        ));
+        cmd
+    } else if cfg!(windows) {
+        let script = format!(
+            "Add-Type -AssemblyName System.Windows.Forms; \
+             $n = New-Object System.Windows.Forms.NotifyIcon; \
+             $n.Icon = [System.Drawing.SystemIcons]::Warning; $n.Visible = $true; \
+             $n.ShowBalloonTip(10000, {}, {}, 'Warning'); Start-Sleep -Seconds 5; $n.Dispose()",
+            powershell_string(title),
+            powershell_string(body)
+        );
+        let mut cmd = Command::new("powershell");
+        cmd.args(["-NoProfile", "-NonInteractive", "-Command", &script]);
+        cmd
+    } else {
+        let mut cmd = Command::new("notify-send");
+        cmd.args(["--app-name=agent-loops", "--urgency=critical", title, body]);
+        cmd
+    }
+}
+
+/// Show a desktop notification and wait briefly for the helper to finish.
+pub async fn desktop_notify(title: &str, body: &str) -> io::Result<()> {
+    let mut cmd = notification_command(title, body);
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true);
+    let mut child = cmd.spawn()?;
+    match tokio::time::timeout(NOTIFY_TIMEOUT, child.wait()).await {
+        Ok(Err(e)) => Err(e),
+        Ok(Ok(status)) if !status.success() => Err(io::Error::other(format!(
+            "the notification helper exited with {status}"
+        ))),
+        // A slow helper (e.g. the PowerShell balloon) may still be showing it.
+        _ => Ok(()),
+    }
+}
+
+fn applescript_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn powershell_string(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
@@ -0,0 +1,131 @@
+//! Plan-then-execute sessions (`--plan-first`): a first run asks the agent for a plan,
+//! the operator or a judge prompt approves it, and the plan's steps become the tasks
+//! the session runs.
+
+use std::fmt::Write as _;
+use std::io;
+use std::path::Path;
+
+use crate::TaskSpec;
+use crate::scan::ansi_escape_pattern;
+
+/// Prompt for the planning run: the goal, and how the steps must be written so
+/// [`parse_plan`] can find them.
+pub fn plan_prompt(goal: &str) -> String {
+    format!(
+        "Do not change any files yet. Write a step-by-step plan for the goal below, \
+         each step small enough for one agent run. Write each step on its own line as \
+         `STEP <n>: <what to do>`, numbered from 1, and nothing else after the last step.\n\n\
+         Goal: {goal}"
+    )
+}
+
+/// The steps of the plan in a planning run's output, in order. Agents echo their
+/// prompt, so only the last block numbered from `STEP 1:` counts.
+pub fn parse_plan(output: &str) -> Vec<String> {
+    let mut steps: Vec<String> = Vec::new();
+    for line in output.lines() {
+        let line = ansi_escape_pattern().replace_all(line, "");
+        let Some((number, step)) = line
+            .trim()
+            .trim_start_matches(['*', '-', '#', ' '])
+            .strip_prefix("STEP ")
+            .and_then(|rest| rest.split_once(':'))
+        else {
+            continue;
+        };
+        let step = step.trim().trim_matches('*').trim();
+        match number.trim().parse::<usize>() {
+            Ok(1) => steps = vec![step.to_string()],
+            Ok(n) if n == steps.len() + 1 && !steps.is_empty() => steps.push(step.to_string()),
+            _ => {}
+        }
+    }
+    steps.retain(|step| !step.is_empty());
+    steps
+}
+
+/// The plan as shown for approval, e.g. `  2. Add the tests`.
+pub fn format_plan(steps: &[String]) -> String {
+    let mut out = format!("{}\n", crate::theme::theme().header.paint("=== Plan ==="));
+    for (idx, step) in steps.iter().enumerate() {
+        let _ = writeln!(out, "{:>3}. {step}", idx + 1);
+    }
+    out
+}
+
+/// One task per step of the plan for `goal`. Each step's prompt carries the goal and the
+/// whole plan, so the agent knows what earlier steps did and what later ones will do.
+/// The tasks keep `goal`'s options.
+pub fn step_tasks(goal: &TaskSpec, steps: &[String]) -> Vec<TaskSpec> {
+    let plan: String = steps
+        .iter()
+        .enumerate()
+        .map(|(idx, step)| format!("{}. {step}\n", idx + 1))
+        .collect();
+    steps
+        .iter()
+        .enumerate()
+        .map(|(idx, step)| {
+            let label = format!("step {}/{}", idx + 1, steps.len());
+            TaskSpec {
+                name: Some(match &goal.name {
+                    Some(name) => format!("{name} {label}"),
+                    None => label,
+                }),
+                prompt: format!(
+                    "Goal: {}\n\nPlan:\n{plan}\nDo step {} now: {step}",
+                    goal.prompt,
+                    idx + 1
+                ),
+                loop_prompts: Vec::new(),
+                ..goal.clone()
+            }
+        })
+        .collect()
+}
+
+/// Prompt for the `--plan-judge` run: the operator's `instructions`, then the goal and the
+/// plan, asking for a verdict [`parse_verdict`] can read.
+pub fn judge_prompt(instructions: &str, goal: &str, steps: &[String]) -> String {
+    let mut prompt = format!(
+        "Do not change any files. Review the plan below for the goal. {instructions}\n\n\
+         Goal: {goal}\n\nPlan:\n"
+    );
+    for (idx, step) in steps.iter().enumerate() {
+        let _ = writeln!(prompt, "{}. {step}", idx + 1);
+    }
+    prompt.push_str(
+        "\nFinish with a line reading `VERDICT: APPROVE` if the plan should be carried out, \
+         or `VERDICT: REJECT` if not.",
+    );
+    prompt
+}
+
+/// The judge's verdict: the last line starting with `VERDICT:`; `None` without one.
+pub fn parse_verdict(output: &str) -> Option<bool> {
+    output.lines().rev().find_map(|line| {
+        let line = ansi_escape_pattern().replace_all(line, "");
+        let verdict = line
+            .trim()
+            .trim_matches(['*', '`'])
+            .strip_prefix("VERDICT:")?
+            .trim()
+            .trim_matches(['*', '`', '.']);
+        match verdict.to_ascii_uppercase().as_str() {
+            "APPROVE" | "APPROVED" => Some(true),
+            "REJECT" | "REJECTED" => Some(false),
+            _ => None,
+        }
+    })
+}
+
+/// A run's output log, as text.
+pub fn read_output_log(path: &Path) -> io::Result<String> {
+    Ok(String::from_utf8_lossy(&std::fs::read(path)?).into_owned())
+}
+
+/// Ask the operator on the terminal whether to run the plan; no terminal means no.
+pub async fn confirm_plan() -> bool {
+    crate::confirm("Run this plan?").await
+}
@@ -0,0 +1,314 @@
+//! Tracking and terminating a child together with everything it spawned.
+//!
+//! On Unix the child leads its own process group (set at spawn), so signalling the
+//! group reaches shells and test runners started by codex. On Windows the child is
+//! placed in a Job Object that is terminated as a whole.
+//!
+//! [`ResourceLimits`] are applied as rlimits the child sets on itself before it execs on
+//! Linux, inherited by what it starts, and as limits of the whole Job Object on Windows.
+
+#[cfg(unix)]
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+/// Grace period between the polite and the forced kill on Unix.
+#[cfg(unix)]
+const KILL_GRACE: Duration = Duration::from_secs(3);
+
+/// Process groups of the trees alive now, for [`kill_live_trees`].
+#[cfg(unix)]
+static LIVE_GROUPS: Mutex<Vec<i32>> = Mutex::new(Vec::new());
+
+/// Caps on what a run's processes may use (`--memory-limit`, `--cpu-limit`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceLimits {
+    /// Memory in bytes: the data segment and private mappings of each process on Linux,
+    /// the committed memory of the whole tree on Windows.
+    pub memory_bytes: Option<u64>,
+    /// CPU time of each process on Linux, user-mode time of the whole tree on Windows.
+    pub cpu_time: Option<Duration>,
+}
+
+impl ResourceLimits {
+    pub fn is_empty(&self) -> bool {
+        self.memory_bytes.is_none() && self.cpu_time.is_none()
+    }
+}
+
+/// Handle to a child process and its descendants.
+pub(crate) struct ProcessTree {
+    #[cfg(unix)]
+    pgid: Option<i32>,
+    #[cfg(windows)]
+    job: Option<windows_job::Job>,
+}
+
+impl ProcessTree {
+    /// Track the tree rooted at `pid`, which must have been spawned by a command set up
+    /// with [`isolate_process_group`], and on Windows apply `limits` to it.
+    pub(crate) fn new(pid: Option<u32>, limits: &ResourceLimits) -> Self {
+        #[cfg(unix)]
+        {
+            let _ = limits;
+            let pgid = pid.and_then(|p| i32::try_from(p).ok());
+            if let Some(pgid) = pgid {
+                live_groups().push(pgid);
+            }
+            Self { pgid }
+        }
+        #[cfg(windows)]
+        {
+            let job = pid.and_then(|p| match windows_job::Job::assign(p, limits) {
+                Ok(job) => Some(job),
+                Err(e) => {
+                    if !limits.is_empty() {
+                        tracing::warn!("Cannot apply the resource limits to the agent: {e}");
+                    }
+                    None
+                }
+            });
+            Self { job }
+        }
+    }
+
+    /// Terminate the whole tree. On Unix, SIGTERM is followed by SIGKILL after a grace period.
+    pub(crate) fn kill(&self) {
+        #[cfg(unix)]
+        if let Some(pgid) = self.pgid {
+            // SAFETY: signalling a process group has no memory-safety preconditions.
+            unsafe {
+                libc::kill(-pgid, libc::SIGTERM);
+            }
+            std::thread::spawn(move || {
+                std::thread::sleep(KILL_GRACE);
+                // SAFETY: as above; ESRCH is expected if the group already exited.
+                unsafe {
+                    libc::kill(-pgid, libc::SIGKILL);
+                }
+            });
+        }
+        #[cfg(windows)]
+        if let Some(job) = &self.job {
+            job.terminate();
+        }
+    }
+}
+
+impl Drop for ProcessTree {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(pgid) = self.pgid {
+            live_groups().retain(|&live| live != pgid);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn live_groups() -> std::sync::MutexGuard<'static, Vec<i32>> {
+    LIVE_GROUPS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Kill every tree still alive at once, for when the session exits without waiting for
+/// its runs. On Windows, closing the Job Objects on exit does this.
+pub(crate) fn kill_live_trees() {
+    #[cfg(unix)]
+    for &pgid in live_groups().iter() {
+        // SAFETY: signalling a process group has no memory-safety preconditions.
+        unsafe {
+            libc::kill(-pgid, libc::SIGKILL);
+        }
+    }
+}
+
+/// Set the rlimits for `limits` on the calling process.
+#[cfg(target_os = "linux")]
+fn set_rlimits(limits: &ResourceLimits) -> std::io::Result<()> {
+    let set = |resource, value: u64| {
+        let limit = libc::rlimit {
+            rlim_cur: value as libc::rlim_t,
+            rlim_max: value as libc::rlim_t,
+        };
+        // SAFETY: `limit` outlives the call.
+        if unsafe { libc::setrlimit(resource, &limit) } == 0 {
+            Ok(())
+        } else {
+            Err(std::io::Error::last_os_error())
+        }
+    };
+    if let Some(bytes) = limits.memory_bytes {
+        set(libc::RLIMIT_DATA, bytes)?;
+    }
+    if let Some(cpu) = limits.cpu_time {
+        set(libc::RLIMIT_CPU, cpu.as_secs().max(1))?;
+    }
+    Ok(())
+}
+
+/// Whether the run ended because it used up its [`ResourceLimits::cpu_time`].
+pub(crate) fn hit_cpu_limit(status: &std::process::ExitStatus, limits: &ResourceLimits) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        limits.cpu_time.is_some() && status.signal() == Some(libc::SIGXCPU)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (status, limits);
+        false
+    }
+}
+
+/// Give `cmd` its own process group so the whole tree can be signalled. On Linux the
+/// child also sets the rlimits for `limits` on itself before it execs, so they hold from
+/// its first instruction; a limit it cannot set fails the spawn.
+pub(crate) fn isolate_process_group(cmd: &mut tokio::process::Command, limits: &ResourceLimits) {
+    #[cfg(unix)]
+    cmd.process_group(0);
+    #[cfg(target_os = "linux")]
+    if !limits.is_empty() {
+        let limits = *limits;
+        // SAFETY: the hook only calls setrlimit, which is async-signal-safe.
+        unsafe {
+            cmd.pre_exec(move || set_rlimits(&limits));
+        }
+    }
+    #[cfg(all(unix, not(target_os = "linux")))]
+    if !limits.is_empty() {
+        tracing::warn!(
+            "Cannot apply the resource limits to the agent: they are only supported on Linux and Windows"
+        );
+    }
+    #[cfg(not(unix))]
+    let _ = (cmd, limits);
+}
+
+/// Shared cancellation flag for runs that should be aborted (for example on Ctrl-C).
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    inner: std::sync::Arc<CancelInner>,
+}
+
+#[derive(Debug, Default)]
+struct CancelInner {
+    cancelled: std::sync::atomic::AtomicBool,
+    notify: tokio::sync::Notify,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mark the token cancelled and wake every waiter.
+    pub fn cancel(&self) {
+        self.inner
+            .cancelled
+            .store(true, std::sync::atomic::Ordering::SeqCst);
+        self.inner.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner
+            .cancelled
+            .load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    /// Resolve once the token is cancelled.
+    pub async fn cancelled(&self) {
+        loop {
+            let notified = self.inner.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+#[cfg(windows)]
+mod windows_job {
+    use std::io;
+    use std::ptr;
+
+    use super::ResourceLimits;
+
+    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JOB_OBJECT_LIMIT_JOB_MEMORY,
+        JOB_OBJECT_LIMIT_JOB_TIME, JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+        JOBOBJECT_EXTENDED_LIMIT_INFORMATION, JobObjectExtendedLimitInformation,
+        SetInformationJobObject, TerminateJobObject,
+    };
+    use windows_sys::Win32::System::Threading::{
+        OpenProcess, PROCESS_SET_QUOTA, PROCESS_TERMINATE,
+    };
+
+    pub(crate) struct Job(HANDLE);
+
+    // SAFETY: job handles may be used from any thread.
+    unsafe impl Send for Job {}
+    unsafe impl Sync for Job {}
+
+    impl Job {
+        /// Create a kill-on-close job with `limits` and put process `pid` into it.
+        pub(crate) fn assign(pid: u32, limits: &ResourceLimits) -> io::Result<Self> {
+            // SAFETY: plain Win32 calls on handles owned by this function.
+            unsafe {
+                let job = CreateJobObjectW(ptr::null(), ptr::null());
+                if job.is_null() {
+                    return Err(io::Error::last_os_error());
+                }
+                let job = Job(job);
+
+                let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = std::mem::zeroed();
+                info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+                if let Some(bytes) = limits.memory_bytes {
+                    info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_JOB_MEMORY;
+                    info.JobMemoryLimit = usize::try_from(bytes).unwrap_or(usize::MAX);
+                }
+                if let Some(cpu) = limits.cpu_time {
+                    info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_JOB_TIME;
+                    // In 100-nanosecond ticks.
+                    info.BasicLimitInformation.PerJobUserTimeLimit =
+                        i64::try_from(cpu.as_nanos() / 100).unwrap_or(i64::MAX);
+                }
+                if SetInformationJobObject(
+                    job.0,
+                    JobObjectExtendedLimitInformation,
+                    ptr::from_ref(&info).cast(),
+                    u32::try_from(std::mem::size_of_val(&info)).unwrap_or(u32::MAX),
+                ) == 0
+                {
+                    return Err(io::Error::last_os_error());
+                }
+
+                let process = OpenProcess(PROCESS_SET_QUOTA | PROCESS_TERMINATE, 0, pid);
+                if process.is_null() {
+                    return Err(io::Error::last_os_error());
+                }
+                let assigned = AssignProcessToJobObject(job.0, process);
+                CloseHandle(process);
+                if assigned == 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(job)
+            }
+        }
+
+        pub(crate) fn terminate(&self) {
+            // SAFETY: `self.0` is a valid job handle until drop.
+            unsafe {
+                TerminateJobObject(self.0, 1);
+            }
+        }
+    }
+
+    impl Drop for Job {
+        fn drop(&mut self) {
+            // SAFETY: closing our own handle; kill-on-close reaps leftover descendants.
+            unsafe {
+                CloseHandle(self.0);
+            }
+        }
+    }
+}
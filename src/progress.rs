@@ -0,0 +1,109 @@
+//! Session progress shown under the run header: elapsed time of the current run,
+//! average run duration and the estimated time left, and the heartbeat of a quiet run.
+
+use std::time::Duration;
+
+/// Frames of the activity spinner in front of the status line.
+const SPINNER_FRAMES: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+/// How long each spinner frame shows.
+pub const SPINNER_FRAME_TIME: Duration = Duration::from_millis(100);
+
+/// Running average of finished runs' durations.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProgressEstimator {
+    finished: u32,
+    total: Duration,
+}
+
+impl ProgressEstimator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a finished run that took `took`, retries included.
+    pub fn record(&mut self, took: Duration) {
+        self.finished += 1;
+        self.total += took;
+    }
+
+    /// Average duration of the finished runs; `None` before the first one.
+    pub fn average(&self) -> Option<Duration> {
+        (self.finished > 0).then(|| self.total / self.finished)
+    }
+}
+
+/// Where the session stands while one run is in progress.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunProgress {
+    /// Average run duration so far.
+    pub average: Option<Duration>,
+    /// Runs still to start after the current one.
+    pub runs_after: usize,
+}
+
+impl RunProgress {
+    /// Time until the session ends, when the current run has been going for `elapsed`:
+    /// what the average leaves of this run plus the average for each later run.
+    pub fn eta(&self, elapsed: Duration) -> Option<Duration> {
+        let average = self.average?;
+        let runs_after = u32::try_from(self.runs_after).unwrap_or(u32::MAX);
+        Some(average.saturating_sub(elapsed) + average.saturating_mul(runs_after))
+    }
+
+    /// E.g. `Elapsed 02:13 | Avg run 05:40 | ETA 1:12:00 (13 runs left)`.
+    pub fn status_line(&self, elapsed: Duration) -> String {
+        let mut line = format!("Elapsed {}", format_clock(elapsed));
+        match (self.average, self.eta(elapsed)) {
+            (Some(average), Some(eta)) => line.push_str(&format!(
+                " | Avg run {} | ETA {}",
+                format_clock(average),
+                format_clock(eta)
+            )),
+            _ => line.push_str(" | ETA --"),
+        }
+        let left = self.runs_after + 1;
+        line.push_str(&format!(
+            " ({left} run{} left)",
+            if left == 1 { "" } else { "s" }
+        ));
+        line
+    }
+}
+
+/// The spinner frame to show `elapsed` into a run.
+pub fn spinner_frame(elapsed: Duration) -> char {
+    let frame = elapsed.as_millis() / SPINNER_FRAME_TIME.as_millis();
+    SPINNER_FRAMES[(frame % SPINNER_FRAMES.len() as u128) as usize]
+}
+
+/// Shown while a run has written nothing for a while, so a quiet agent can be told from
+/// a hung one: e.g. `Still running, idle 3m00s, elapsed 22m10s`.
+pub fn heartbeat_line(idle: Duration, elapsed: Duration) -> String {
+    format!(
+        "Still running, idle {}, elapsed {}",
+        format_run_time(idle),
+        format_run_time(elapsed)
+    )
+}
+
+/// `MM:SS`, or `H:MM:SS` from an hour on.
+pub fn format_clock(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{:02}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+/// Compact run duration: `45s`, `4m02s` or `1h02m03s`.
+pub fn format_run_time(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}h{:02}m{:02}s", secs / 3600, secs / 60 % 60, secs % 60)
+    } else if secs >= 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{secs}s")
+    }
+}
@@ -0,0 +1,368 @@
+//! Shaping task prompts before they reach the agent: shared text around every prompt
+//! (`--preamble-file`, `--prompt-prefix`, `--prompt-suffix`), so boilerplate such as
+//! coding standards is not copied into every task, then rendering the result as a
+//! [MiniJinja](https://docs.rs/minijinja) template when the run starts.
+//!
+//! Templates see the variables from `--var` and `--vars-file`, and can use conditionals,
+//! loops and `{% include "path" %}` of files under the work dir. Two functions read the
+//! work dir as it is at that moment:
+//!
+//! - `file("docs/spec.md")`: the file's contents, also written `{{file:docs/spec.md}}`;
+//! - `glob("src/**/*.rs")`: the matching paths, one per line, or with `"contents"` as
+//!   second argument each file's path and contents; also written
+//!   `{{glob:src/**/*.rs:paths}}` or `{{glob:src/**/*.rs:contents}}`.
+//!
+//! Variables a template uses must be defined, so a typo fails the run instead of sending
+//! a prompt with a hole in it.
+//!
+//! With `--refine-prompts`, each task's prompt is first rewritten by an agent run (or the
+//! `--aux-model`) into a more explicit instruction, used once the operator approves it.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Write as _};
+use std::io;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use minijinja::{Environment, UndefinedBehavior, Value};
+use regex::{Captures, Regex};
+
+use crate::TaskSpec;
+use crate::artifacts::Glob;
+use crate::scan::ansi_escape_pattern;
+use crate::theme::theme;
+
+/// Variables prompts are rendered with, by name.
+pub type PromptVars = BTreeMap<String, toml::Value>;
+
+/// Text wrapped around every task prompt.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PromptWrap {
+    /// Standing instructions or repository context, first of all.
+    pub preamble: Option<String>,
+    /// Put right before the prompt.
+    pub prefix: Option<String>,
+    /// Put right after the prompt.
+    pub suffix: Option<String>,
+}
+
+impl PromptWrap {
+    /// `prompt` after the preamble and prefix and before the suffix, each part separated
+    /// by a blank line. Blank parts are left out.
+    pub fn apply(&self, prompt: &str) -> String {
+        [
+            self.preamble.as_deref(),
+            self.prefix.as_deref(),
+            Some(prompt),
+            self.suffix.as_deref(),
+        ]
+        .into_iter()
+        .flatten()
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+    }
+}
+
+/// `prompt` rendered as a template with `vars`, reading files under `work_dir`.
+pub fn render_prompt(prompt: &str, vars: &PromptVars, work_dir: &Path) -> Result<String, String> {
+    let mut env = Environment::new();
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+    env.set_keep_trailing_newline(true);
+    env.set_loader(minijinja::path_loader(work_dir));
+    let dir = work_dir.to_path_buf();
+    env.add_function("file", move |path: String| {
+        read_file(&dir, &path).map_err(template_error)
+    });
+    let dir = work_dir.to_path_buf();
+    env.add_function("glob", move |pattern: String, mode: Option<String>| {
+        let contents = match mode.as_deref() {
+            None | Some("paths") => false,
+            Some("contents") => true,
+            Some(mode) => {
+                return Err(template_error(format!(
+                    "unknown glob mode `{mode}` (expected paths or contents)"
+                )));
+            }
+        };
+        expand_glob(&dir, &pattern, contents).map_err(template_error)
+    });
+    env.render_str(&desugar(prompt), Value::from_serialize(vars))
+        .map_err(|e| e.to_string())
+}
+
+/// Read `--vars-file`: a TOML table of variables.
+pub fn load_vars_file(path: &Path) -> io::Result<PromptVars> {
+    toml::from_str(&std::fs::read_to_string(path)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+/// `{{file:PATH}}` and `{{glob:PATTERN:MODE}}` as calls of the template functions.
+fn desugar(prompt: &str) -> String {
+    static SHORTHAND: OnceLock<Regex> = OnceLock::new();
+    let shorthand = SHORTHAND
+        .get_or_init(|| Regex::new(r"\{\{\s*(file|glob):([^}]*?)\s*\}\}").expect("valid regex"));
+    let literal = |text: &str| serde_json::Value::from(text.trim()).to_string();
+    shorthand
+        .replace_all(prompt, |caps: &Captures| match &caps[1] {
+            "file" => format!("{{{{ file({}) }}}}", literal(&caps[2])),
+            _ => match caps[2].rsplit_once(':') {
+                Some((pattern, mode @ ("paths" | "contents"))) => {
+                    format!("{{{{ glob({}, {}) }}}}", literal(pattern), literal(mode))
+                }
+                _ => format!("{{{{ glob({}) }}}}", literal(&caps[2])),
+            },
+        })
+        .into_owned()
+}
+
+fn template_error(message: String) -> minijinja::Error {
+    minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, message)
+}
+
+fn read_file(work_dir: &Path, path: &str) -> Result<String, String> {
+    std::fs::read_to_string(work_dir.join(path))
+        .map(|content| content.trim_end().to_string())
+        .map_err(|e| format!("cannot read `{path}`: {e}"))
+}
+
+fn expand_glob(work_dir: &Path, pattern: &str, contents: bool) -> Result<String, String> {
+    let glob: Glob = pattern.parse()?;
+    let files = glob
+        .files(work_dir)
+        .map_err(|e| format!("cannot list `{glob}`: {e}"))?;
+    let paths = files
+        .iter()
+        .map(|path| path.to_string_lossy().replace('\\', "/"));
+    if !contents {
+        return Ok(paths.collect::<Vec<_>>().join("\n"));
+    }
+    let mut out = Vec::new();
+    for path in paths {
+        let content = read_file(work_dir, &path)?;
+        out.push(format!("--- {path} ---\n{content}"));
+    }
+    Ok(out.join("\n\n"))
+}
+
+/// A problem with a task's prompt found before the session starts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PromptIssue {
+    /// Position in the task list, from 1.
+    pub task: usize,
+    /// Whether the session should not start; otherwise it is only worth a warning.
+    pub fatal: bool,
+    pub message: String,
+}
+
+impl fmt::Display for PromptIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "task {}: {}", self.task, self.message)
+    }
+}
+
+/// Render every prompt as its run would, with `wrap` and `vars` in `work_dir`, and report
+/// prompts that fail to render, come out empty or longer than `max_chars`, and repeats
+/// of an earlier prompt.
+pub fn lint_prompts(
+    prompts: &[&str],
+    wrap: &PromptWrap,
+    vars: &PromptVars,
+    work_dir: &Path,
+    max_chars: usize,
+) -> Vec<PromptIssue> {
+    let mut issues = Vec::new();
+    let mut seen: BTreeMap<&str, usize> = BTreeMap::new();
+    for (task, prompt) in (1..).zip(prompts) {
+        let mut issue = |fatal, message| {
+            issues.push(PromptIssue {
+                task,
+                fatal,
+                message,
+            })
+        };
+        match render_prompt(&wrap.apply(prompt), vars, work_dir) {
+            Err(e) => issue(true, e),
+            Ok(rendered) if rendered.trim().is_empty() => {
+                issue(true, "the prompt is empty once rendered".to_string())
+            }
+            Ok(rendered) if rendered.chars().count() > max_chars => issue(
+                true,
+                format!(
+                    "the rendered prompt has {} characters, more than the limit of {max_chars}",
+                    rendered.chars().count()
+                ),
+            ),
+            Ok(_) => {}
+        }
+        match seen.get(prompt.trim()) {
+            Some(first) => issue(false, format!("same prompt as task {first}")),
+            None => {
+                seen.insert(prompt.trim(), task);
+            }
+        }
+    }
+    issues
+}
+
+/// Prompt for the `--refine-prompts` run rewriting `prompt`, asking for the result in a
+/// block [`parse_refined`] can find.
+pub fn refine_prompt(prompt: &str) -> String {
+    format!(
+        "Do not change any files. Rewrite the task prompt below for a coding agent so it is \
+         explicit and unambiguous: state the goal, the scope and how to tell the work is \
+         done, without adding requirements of your own. Keep `{{{{ ... }}}}` and \
+         `{{% ... %}}` placeholders exactly as written. Write the rewritten prompt between a \
+         line reading `BEGIN PROMPT` and a line reading `END PROMPT`, and nothing else \
+         after it.\n\nPrompt: {prompt}"
+    )
+}
+
+/// The rewritten prompt in a refining run's output: the last block between
+/// `BEGIN PROMPT` and `END PROMPT` lines, trimmed; `None` without a non-empty one.
+pub fn parse_refined(output: &str) -> Option<String> {
+    let mut refined = None;
+    let mut block: Option<Vec<String>> = None;
+    for line in output.lines() {
+        let line = ansi_escape_pattern().replace_all(line, "");
+        match (line.trim().trim_matches(['*', '`']), &mut block) {
+            ("END PROMPT", Some(lines)) => {
+                refined = Some(lines.join("\n").trim().to_string());
+                block = None;
+            }
+            ("BEGIN PROMPT", _) => block = Some(Vec::new()),
+            (_, Some(lines)) => lines.push(line.trim_end().to_string()),
+            (_, None) => {}
+        }
+    }
+    refined.filter(|prompt| !prompt.is_empty())
+}
+
+/// The original and rewritten prompt of a task, as shown for approval.
+pub fn format_refined(task: usize, original: &str, refined: &str) -> String {
+    let header = format!("=== Refined prompt, task {task} ===");
+    format!(
+        "{}\nBefore: {original}\nAfter:\n{refined}\n",
+        theme().header.paint(&header)
+    )
+}
+
+/// Ask the operator on the terminal whether to use the rewritten prompt; no terminal
+/// means no.
+pub async fn confirm_refined() -> bool {
+    crate::confirm("Use the refined prompt?").await
+}
+
+/// `prompt` as a run would send it, or why it cannot be rendered.
+fn render_or_explain(
+    prompt: &str,
+    wrap: &PromptWrap,
+    vars: &PromptVars,
+    work_dir: &Path,
+) -> String {
+    match render_prompt(&wrap.apply(prompt), vars, work_dir) {
+        Ok(rendered) => rendered.trim_end().to_string(),
+        Err(e) => format!("(cannot render the prompt: {e})"),
+    }
+}
+
+/// Every task's prompt as its first run would send it, untruncated, for
+/// `agent-loops tasks list`.
+pub fn format_task_list(
+    tasks: &[TaskSpec],
+    wrap: &PromptWrap,
+    vars: &PromptVars,
+    work_dir: &Path,
+) -> String {
+    if tasks.is_empty() {
+        return "No tasks.\n".to_string();
+    }
+    let mut out = String::new();
+    for (number, task) in (1..).zip(tasks) {
+        let mut header = format!("=== Task {number}");
+        if let Some(name) = &task.name {
+            let _ = write!(header, ": {name}");
+        }
+        header.push_str(" ===");
+        let _ = writeln!(out, "{}", theme().header.paint(&header));
+        let _ = writeln!(
+            out,
+            "{}",
+            render_or_explain(&task.prompt, wrap, vars, work_dir)
+        );
+        if task.loop_prompts.len() > 1 {
+            let _ = writeln!(
+                out,
+                "(loop 1 of {} loop prompts; `tasks show {number}` shows them all)",
+                task.loop_prompts.len()
+            );
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Task `number` with its options and the prompt of each loop as sent, for
+/// `agent-loops tasks show <n>`.
+pub fn format_task(
+    number: usize,
+    task: &TaskSpec,
+    wrap: &PromptWrap,
+    vars: &PromptVars,
+    work_dir: &Path,
+) -> String {
+    let mut out = format!("Task {number}");
+    if let Some(name) = &task.name {
+        let _ = write!(out, ": {name}");
+    }
+    out.push('\n');
+    if let Some(agent) = task.agent {
+        let _ = writeln!(out, "Agent: {agent}");
+    }
+    if let Some(codex_bin) = &task.codex_bin {
+        let _ = writeln!(out, "Binary: {codex_bin}");
+    }
+    if let Some(model) = &task.model {
+        let _ = writeln!(out, "Model: {model}");
+    }
+    if !task.env.is_empty() {
+        let names: Vec<&str> = task.env.keys().map(String::as_str).collect();
+        let _ = writeln!(out, "Env: {}", names.join(", "));
+    }
+    if let Some(allowlist) = &task.env_allowlist {
+        let _ = writeln!(out, "Env allowlist: {}", allowlist.join(", "));
+    }
+    if let Some(min) = task.min_successes {
+        let _ = writeln!(out, "Min successes: {min}");
+    }
+    if !task.tags.is_empty() {
+        let _ = writeln!(out, "Tags: {}", task.tags.join(", "));
+    }
+    if task.priority != 0 {
+        let _ = writeln!(out, "Priority: {}", task.priority);
+    }
+    let prompts: Vec<(String, &str)> = match task.loop_prompts.len() {
+        0 => vec![("Prompt".to_string(), task.prompt.as_str())],
+        len => (1..)
+            .zip(&task.loop_prompts)
+            .map(|(loop_number, prompt)| {
+                let label = if loop_number == len && len > 1 {
+                    format!("Loop {loop_number} and later")
+                } else {
+                    format!("Loop {loop_number}")
+                };
+                (label, prompt.as_str())
+            })
+            .collect(),
+    };
+    for (label, prompt) in prompts {
+        let _ = writeln!(
+            out,
+            "\n{}\n{}",
+            theme().header.paint(&format!("=== {label} ===")),
+            render_or_explain(prompt, wrap, vars, work_dir)
+        );
+    }
+    out
+}
@@ -0,0 +1,138 @@
+//! Running the child under a pseudo-terminal so it sees a real TTY.
+
+use std::io::{self, Read};
+use std::process::ExitStatus;
+
+use portable_pty::{CommandBuilder, MasterPty, PtySize, native_pty_system};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::{OutputStream, join_error_to_io};
+
+/// A child spawned on the slave side of a PTY.
+pub(crate) struct PtyChild {
+    waiter: JoinHandle<io::Result<ExitStatus>>,
+    reader: JoinHandle<io::Result<()>>,
+    pid: Option<u32>,
+    // Kept open until the reader has drained the terminal.
+    _master: Box<dyn MasterPty + Send>,
+}
+
+impl PtyChild {
+    /// Process id of the child, which also leads its own session and process group.
+    pub(crate) fn pid(&self) -> Option<u32> {
+        self.pid
+    }
+
+    /// Wait for the child to exit and its output to be fully read.
+    pub(crate) async fn wait(self) -> io::Result<ExitStatus> {
+        let status = self.waiter.await.map_err(join_error_to_io)??;
+        self.reader
+            .await
+            .map_err(join_error_to_io)?
+            .map_err(|e| io::Error::new(e.kind(), format!("failed reading child pty: {e}")))?;
+        Ok(status)
+    }
+}
+
+/// Spawn `cmd` attached to a new PTY of the given size, forwarding its output into `tx`.
+/// The PTY merges stdout and stderr, so everything arrives as [`OutputStream::Stdout`].
+/// `env_clear` must mirror whether `cmd` had its environment cleared, which std does not expose.
+pub(crate) fn spawn_in_pty(
+    cmd: &std::process::Command,
+    rows: u16,
+    cols: u16,
+    env_clear: bool,
+    tx: mpsc::UnboundedSender<(OutputStream, Vec<u8>)>,
+) -> io::Result<PtyChild> {
+    let pair = native_pty_system()
+        .openpty(PtySize {
+            rows,
+            cols,
+            pixel_width: 0,
+            pixel_height: 0,
+        })
+        .map_err(anyhow_to_io)?;
+
+    let mut builder = CommandBuilder::new(cmd.get_program());
+    builder.args(cmd.get_args());
+    if env_clear {
+        builder.env_clear();
+    }
+    match cmd.get_current_dir() {
+        Some(dir) => builder.cwd(dir),
+        None => builder.cwd(std::env::current_dir()?),
+    }
+    for (key, value) in cmd.get_envs() {
+        match value {
+            Some(value) => builder.env(key, value),
+            None => builder.env_remove(key),
+        }
+    }
+
+    let mut child = pair.slave.spawn_command(builder).map_err(anyhow_to_io)?;
+    // Only the child may hold the slave side, otherwise reads never see EOF.
+    drop(pair.slave);
+
+    let mut reader = pair.master.try_clone_reader().map_err(anyhow_to_io)?;
+    let reader = tokio::task::spawn_blocking(move || {
+        let mut buf = [0_u8; 8192];
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                // Linux reports EIO once the slave side is closed.
+                Err(e) if e.raw_os_error() == Some(5) => break,
+                Err(e) => return Err(e),
+            };
+            if tx.send((OutputStream::Stdout, buf[..n].to_vec())).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    });
+    let pid = child.process_id();
+    let waiter = tokio::task::spawn_blocking(move || child.wait().map(exit_status_from_pty));
+
+    Ok(PtyChild {
+        waiter,
+        reader,
+        pid,
+        _master: pair.master,
+    })
+}
+
+fn anyhow_to_io(err: anyhow::Error) -> io::Error {
+    if let Some(io_err) = err.downcast_ref::<io::Error>() {
+        return io::Error::new(io_err.kind(), format!("{err:#}"));
+    }
+    let message = format!("{err:#}");
+    // portable-pty resolves the program itself and reports misses as plain text.
+    let kind = if message.contains("No viable candidates")
+        || message.contains("does not exist")
+        || message.contains("doesn't exist")
+    {
+        io::ErrorKind::NotFound
+    } else {
+        io::ErrorKind::Other
+    };
+    io::Error::new(kind, message)
+}
+
+#[cfg(unix)]
+fn exit_status_from_pty(status: portable_pty::ExitStatus) -> ExitStatus {
+    use std::os::unix::process::ExitStatusExt;
+    let code = if status.signal().is_some() {
+        1
+    } else {
+        status.exit_code()
+    };
+    ExitStatus::from_raw(((code & 0xff) << 8) as i32)
+}
+
+#[cfg(windows)]
+fn exit_status_from_pty(status: portable_pty::ExitStatus) -> ExitStatus {
+    use std::os::windows::process::ExitStatusExt;
+    ExitStatus::from_raw(status.exit_code())
+}
@@ -0,0 +1,133 @@
+//! Masking of secrets in forwarded child output.
+
+use regex::bytes::Regex;
+
+/// Environment variable name fragments that mark a value as secret.
+const SECRET_ENV_MARKERS: &[&str] = &[
+    "API_KEY",
+    "APIKEY",
+    "TOKEN",
+    "SECRET",
+    "PASSWORD",
+    "PASSWD",
+    "CREDENTIAL",
+    "PRIVATE_KEY",
+    "ACCESS_KEY",
+];
+/// Shorter env values are too likely to collide with ordinary output.
+const MIN_SECRET_ENV_VALUE_LEN: usize = 8;
+/// Flush a partial line anyway once it grows past this many bytes.
+const MAX_PENDING_BYTES: usize = 16 * 1024;
+
+/// Replaces secret values in output before it is displayed or persisted.
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    rules: Vec<(Regex, String)>,
+    /// Length of the longest literal secret.
+    longest_literal: usize,
+}
+
+impl Redactor {
+    /// A redactor that masks nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mask values of environment variables whose names look like credentials.
+    pub fn with_env_secrets(mut self) -> Self {
+        let mut vars: Vec<(String, String)> = std::env::vars()
+            .filter(|(name, value)| {
+                is_secret_env_name(name) && value.len() >= MIN_SECRET_ENV_VALUE_LEN
+            })
+            .collect();
+        // Longer values first so a secret containing another secret is masked whole.
+        vars.sort_by_key(|(_, value)| std::cmp::Reverse(value.len()));
+        for (name, value) in vars {
+            self = self.with_literal(&value, &format!("[REDACTED:{name}]"));
+        }
+        self
+    }
+
+    /// Mask every occurrence of `value` with `replacement`.
+    pub fn with_literal(mut self, value: &str, replacement: &str) -> Self {
+        if !value.is_empty() {
+            let re = Regex::new(&regex::escape(value)).expect("escaped literal is a valid regex");
+            self.rules.push((re, replacement.to_string()));
+            self.longest_literal = self.longest_literal.max(value.len());
+        }
+        self
+    }
+
+    /// Mask every match of a user-supplied regex with `[REDACTED]`.
+    pub fn with_pattern(mut self, pattern: &str) -> Result<Self, regex::Error> {
+        self.rules
+            .push((Regex::new(pattern)?, "[REDACTED]".to_string()));
+        Ok(self)
+    }
+
+    /// Whether any rule is configured.
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Apply all rules to `input`.
+    pub fn redact(&self, input: &[u8]) -> Vec<u8> {
+        let mut out = input.to_vec();
+        for (re, replacement) in &self.rules {
+            if re.is_match(&out) {
+                out = re.replace_all(&out, replacement.as_bytes()).into_owned();
+            }
+        }
+        out
+    }
+}
+
+/// Whether an environment variable name looks like it holds a credential.
+pub fn is_secret_env_name(name: &str) -> bool {
+    let upper = name.to_ascii_uppercase();
+    SECRET_ENV_MARKERS
+        .iter()
+        .any(|marker| upper.contains(marker))
+}
+
+/// Line-buffers one output stream so secrets split across chunks are still masked.
+pub(crate) struct StreamRedactor<'a> {
+    redactor: &'a Redactor,
+    pending: Vec<u8>,
+}
+
+impl<'a> StreamRedactor<'a> {
+    pub(crate) fn new(redactor: &'a Redactor) -> Self {
+        Self {
+            redactor,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Accept a chunk and return the redacted bytes that are safe to emit now.
+    pub(crate) fn push(&mut self, chunk: &[u8]) -> Vec<u8> {
+        if self.redactor.is_empty() {
+            return chunk.to_vec();
+        }
+        self.pending.extend_from_slice(chunk);
+        let split = match self.pending.iter().rposition(|&b| b == b'\n' || b == b'\r') {
+            Some(idx) => idx + 1,
+            None if self.pending.len() > MAX_PENDING_BYTES => {
+                // Keep back what could be the start of a secret still arriving.
+                let mut redacted = self.redactor.redact(&self.pending);
+                let keep = self.redactor.longest_literal.saturating_sub(1);
+                self.pending = redacted.split_off(redacted.len().saturating_sub(keep));
+                return redacted;
+            }
+            None => return Vec::new(),
+        };
+        let ready: Vec<u8> = self.pending.drain(..split).collect();
+        self.redactor.redact(&ready)
+    }
+
+    /// Return whatever is still buffered, redacted.
+    pub(crate) fn finish(&mut self) -> Vec<u8> {
+        let rest = std::mem::take(&mut self.pending);
+        self.redactor.redact(&rest)
+    }
+}
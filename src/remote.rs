@@ -0,0 +1,69 @@
+//! Running the agent on another machine over SSH (`--ssh user@host`): the local `ssh`
+//! client is the child process, so the remote output streams back through the usual
+//! pipeline and renderer. The work dir, agent binary and task files' paths are the remote
+//! machine's.
+
+use crate::RunOptions;
+
+/// Where runs execute and the client that gets them there.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remote {
+    /// `user@host`, `host` or a `Host` alias from the SSH config.
+    pub destination: String,
+    /// The SSH client, `ssh` unless configured.
+    pub ssh: String,
+}
+
+impl Remote {
+    pub fn new(destination: &str) -> Self {
+        Self {
+            destination: destination.to_string(),
+            ssh: "ssh".to_string(),
+        }
+    }
+
+    /// SSH client arguments running `program` with `args` on the remote machine.
+    ///
+    /// The remote command gets a terminal, so it is hung up on when the run is killed
+    /// here, and its stderr arrives merged into stdout. It starts in `options.work_dir`
+    /// with `options.env` set; the allow-list does not apply remotely. The client never
+    /// asks for a password, as there is no one to answer.
+    pub fn run_args(&self, program: &str, args: &[String], options: &RunOptions) -> Vec<String> {
+        let mut command = String::new();
+        if let Some(dir) = &options.work_dir {
+            command.push_str(&format!("cd {} && ", shell_quote(&dir.to_string_lossy())));
+        }
+        command.push_str("exec");
+        if !options.env.is_empty() {
+            command.push_str(" env");
+            for (name, value) in &options.env {
+                command.push(' ');
+                command.push_str(&shell_quote(&format!("{name}={value}")));
+            }
+        }
+        for word in std::iter::once(program).chain(args.iter().map(String::as_str)) {
+            command.push(' ');
+            command.push_str(&shell_quote(word));
+        }
+        vec![
+            "-tt".to_string(),
+            "-o".to_string(),
+            "BatchMode=yes".to_string(),
+            self.destination.clone(),
+            "--".to_string(),
+            command,
+        ]
+    }
+}
+
+/// `word` quoted for a POSIX shell, which is how the remote side reads the command.
+pub fn shell_quote(word: &str) -> String {
+    if !word.is_empty()
+        && word
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-_./=:@,+%".contains(&b))
+    {
+        return word.to_string();
+    }
+    format!("'{}'", word.replace('\'', r"'\''"))
+}
@@ -0,0 +1,99 @@
+//! Replaying a run's spooled output (`agent-loops replay`).
+//!
+//! Next to each spooled output log the pinned view writes a `.timing` file holding, for
+//! every line, the milliseconds since the run started. Replay feeds the lines back
+//! through the pinned view at their original pace, scaled by a speed factor, with long
+//! idle stretches shortened. Logs without timing are replayed at a steady rate.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::CancelToken;
+
+/// Pace for logs that have no timing file.
+const UNTIMED_LINE_INTERVAL: Duration = Duration::from_millis(30);
+/// Longest pause between two replayed lines, before the speed factor.
+const MAX_REPLAY_GAP: Duration = Duration::from_secs(2);
+
+/// Timing sidecar of the output log at `log`.
+pub fn timing_path(log: &Path) -> PathBuf {
+    log.with_extension("timing")
+}
+
+/// One output line and when it appeared, relative to the start of the run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayLine {
+    pub at: Duration,
+    pub text: String,
+}
+
+/// Lines of the output log at `log`, timed from its sidecar when there is one.
+pub fn load_replay(log: &Path) -> io::Result<Vec<ReplayLine>> {
+    let content = fs::read(log)?;
+    let content = String::from_utf8_lossy(&content);
+    let timing: Vec<u64> = fs::read_to_string(timing_path(log))
+        .map(|timing| {
+            timing
+                .lines()
+                .map_while(|line| line.trim().parse().ok())
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut last = Duration::ZERO;
+    Ok(content
+        .lines()
+        .enumerate()
+        .map(|(i, text)| {
+            let at = match timing.get(i) {
+                Some(&ms) => Duration::from_millis(ms),
+                None => last + UNTIMED_LINE_INTERVAL,
+            };
+            last = at;
+            ReplayLine {
+                at,
+                text: text.to_string(),
+            }
+        })
+        .collect())
+}
+
+/// Pause before each line: the recorded gap divided by `speed`, with gaps longer than
+/// two seconds cut down to two.
+pub fn replay_delays(lines: &[ReplayLine], speed: f64) -> Vec<Duration> {
+    let mut previous = Duration::ZERO;
+    lines
+        .iter()
+        .map(|line| {
+            let gap = line.at.saturating_sub(previous).min(MAX_REPLAY_GAP);
+            previous = line.at;
+            gap.div_f64(speed)
+        })
+        .collect()
+}
+
+/// Replay `lines` under `header_lines`: in the pinned view on a terminal, otherwise
+/// printed as plain lines without pauses. Returns early once `cancel` fires.
+pub async fn replay(
+    header_lines: Vec<String>,
+    lines: &[ReplayLine],
+    speed: f64,
+    wrap_lines: bool,
+    cancel: &CancelToken,
+) -> io::Result<()> {
+    #[cfg(feature = "tui")]
+    if std::io::IsTerminal::is_terminal(&io::stdout()) {
+        let delays = replay_delays(lines, speed);
+        return crate::tui::replay_pinned(header_lines, lines, &delays, wrap_lines, cancel).await;
+    }
+    let _ = (speed, wrap_lines, cancel);
+    let mut out = io::stdout().lock();
+    for line in header_lines {
+        writeln!(out, "{line}")?;
+    }
+    for line in lines {
+        writeln!(out, "{}", line.text)?;
+    }
+    out.flush()
+}
@@ -0,0 +1,260 @@
+//! Watching agent output for conditions that change how a run is treated.
+
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+/// Longest partial line kept while waiting for its newline.
+const MAX_PENDING_LINE: usize = 4096;
+
+/// Longest final message kept; the rest is dropped.
+const MAX_FINAL_MESSAGE: usize = 16 * 1024;
+
+/// The agent CLIs' own rate-limit errors: error lines about rate limits or quotas, a
+/// leading 429 status, the providers' messages and error codes. Agents also talk about
+/// rate limits in their work, so the words alone are not enough.
+fn rate_limit_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(concat!(
+            r"(?i)^\s*(?:\[[^\]]*\]\s*)?(?:error|fatal)\b.*(?:too many requests|rate[ _-]?limit|quota)",
+            r"|^\s*(?:unexpected )?(?:status|http)\W{0,3}429\b|^\s*429\W+too many requests",
+            r"|^\s*(?:rate[ _-]limit (?:exceeded|reached)|quota exceeded for)\b",
+            r"|\brate_limit_exceeded\b|\binsufficient_quota\b|exceeded your current quota",
+        ))
+        .expect("rate-limit pattern is valid")
+    })
+}
+
+pub(crate) fn ansi_escape_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(r"\x1b\[[0-9;?]*[ -/]*[@-~]|\x1b\][^\x07\x1b]*(?:\x07|\x1b\\)|\x1b[@-_]")
+            .expect("escape pattern is valid")
+    })
+}
+
+fn tokens_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(
+            r"(?i)\btokens used\b:?\s*([0-9][0-9,]*)?\s*$|\btotal tokens\b[\s:│|]*([0-9][0-9,]*)",
+        )
+        .expect("tokens pattern is valid")
+    })
+}
+
+fn cost_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(r"(?i)\bcost:?\s*\$\s*([0-9]+(?:\.[0-9]+)?)").expect("cost pattern is valid")
+    })
+}
+
+/// Section headings of the codex transcript: a bare word on its own line, or anything
+/// after a `[timestamp]` in older versions. The heading is captured.
+fn transcript_heading_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(
+            r"^(?:\[\d{4}-\d\d-\d\dT[^\]]*\]\s*(.*)|(codex|user|thinking|exec|apply_patch|file update:?|turn diff:?|User instructions:|tokens used(?::?\s*[0-9,]+)?))$",
+        )
+        .expect("heading pattern is valid")
+    })
+}
+
+/// The agent's message in a line of codex's `--json` output, if it holds one.
+fn json_agent_message(line: &str) -> Option<String> {
+    let event: serde_json::Value = serde_json::from_str(line).ok()?;
+    let (item, text) = match event.get("item") {
+        Some(item) => (item, "text"),
+        None => (event.get("msg")?, "message"),
+    };
+    if item.get("type")?.as_str()? != "agent_message" {
+        return None;
+    }
+    Some(item.get(text)?.as_str()?.to_string())
+}
+
+/// Whether `line` looks like a rate-limit or quota error from the agent's API.
+pub fn is_rate_limit_message(line: &str) -> bool {
+    rate_limit_pattern().is_match(line)
+}
+
+/// Token and cost figures reported by the agent. Agents print running totals, so the
+/// latest figure wins.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Usage {
+    pub tokens: Option<u64>,
+    /// Cost in US dollars, when the agent reports one itself.
+    pub cost_usd: Option<f64>,
+}
+
+impl Usage {
+    /// Usage figures found in one output line, e.g. `tokens used: 12,345` (codex),
+    /// `Cost: $0.04 message` (aider) or `│ Total Tokens  12,345 │` (gemini).
+    pub fn parse_line(line: &str) -> Usage {
+        Usage {
+            tokens: tokens_pattern()
+                .captures(line)
+                .and_then(|c| c.get(1).or_else(|| c.get(2)))
+                .and_then(|m| parse_count(m.as_str())),
+            cost_usd: cost_pattern()
+                .captures(line)
+                .and_then(|c| c[1].parse().ok()),
+        }
+    }
+
+    /// `self` with any figures present in `newer` replaced.
+    pub fn update(self, newer: Usage) -> Usage {
+        Usage {
+            tokens: newer.tokens.or(self.tokens),
+            cost_usd: newer.cost_usd.or(self.cost_usd),
+        }
+    }
+
+    /// Spend in US dollars: the reported cost, else tokens priced at `usd_per_1k_tokens`.
+    pub fn cost(&self, usd_per_1k_tokens: Option<f64>) -> Option<f64> {
+        self.cost_usd.or_else(|| {
+            let tokens = self.tokens?;
+            Some(tokens as f64 / 1000.0 * usd_per_1k_tokens?)
+        })
+    }
+}
+
+fn parse_count(text: &str) -> Option<u64> {
+    text.replace(',', "").parse().ok()
+}
+
+/// Conditions noticed in a run's output.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct OutputScan {
+    pub(crate) rate_limited: bool,
+    pub(crate) usage: Usage,
+    /// Reported spend passed the per-run cost limit and the run was cut short.
+    pub(crate) over_budget: bool,
+    /// The run wrote more than the per-run output limit and was cut short.
+    pub(crate) output_flood: bool,
+    /// The agent's last message in the transcript.
+    pub(crate) final_message: Option<String>,
+    /// A line matched the runner template's success pattern.
+    pub(crate) success_pattern_seen: bool,
+}
+
+impl OutputScan {
+    pub(crate) fn merge(self, other: OutputScan) -> OutputScan {
+        OutputScan {
+            rate_limited: self.rate_limited || other.rate_limited,
+            usage: self.usage.update(other.usage),
+            over_budget: self.over_budget || other.over_budget,
+            output_flood: self.output_flood || other.output_flood,
+            final_message: self.final_message.or(other.final_message),
+            success_pattern_seen: self.success_pattern_seen || other.success_pattern_seen,
+        }
+    }
+}
+
+/// Line-oriented scanner for one output stream.
+#[derive(Debug, Default)]
+pub(crate) struct OutputScanner {
+    pending: Vec<u8>,
+    scan: OutputScan,
+    /// The previous line was a bare `tokens used` heading; the count follows on its own
+    /// line.
+    expect_token_count: bool,
+    /// The agent message being read, after a `codex` heading.
+    message: Option<String>,
+    /// Sets [`OutputScan::success_pattern_seen`] when a line matches.
+    success_pattern: Option<Regex>,
+}
+
+impl OutputScanner {
+    pub(crate) fn new(success_pattern: Option<Regex>) -> Self {
+        Self {
+            success_pattern,
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn observe(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            if b == b'\n' || b == b'\r' {
+                self.check_pending();
+            } else if self.pending.len() < MAX_PENDING_LINE {
+                self.pending.push(b);
+            }
+        }
+    }
+
+    pub(crate) fn finish(&mut self) -> OutputScan {
+        self.check_pending();
+        self.end_message();
+        self.scan.clone()
+    }
+
+    /// Usage figures from the complete lines seen so far.
+    pub(crate) fn usage(&self) -> Usage {
+        self.scan.usage
+    }
+
+    fn check_pending(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let line = String::from_utf8_lossy(&self.pending);
+        let line = ansi_escape_pattern().replace_all(&line, "").into_owned();
+        self.read_message(line.trim_end());
+        let line = line.trim();
+        if is_rate_limit_message(line) {
+            self.scan.rate_limited = true;
+        }
+        if let Some(pattern) = &self.success_pattern
+            && pattern.is_match(line)
+        {
+            self.scan.success_pattern_seen = true;
+        }
+        if std::mem::take(&mut self.expect_token_count)
+            && let Some(tokens) = parse_count(line)
+        {
+            self.scan.usage.tokens = Some(tokens);
+        }
+        let usage = Usage::parse_line(line);
+        self.expect_token_count =
+            usage.tokens.is_none() && line.eq_ignore_ascii_case("tokens used");
+        self.scan.usage = self.scan.usage.update(usage);
+        self.pending.clear();
+    }
+
+    /// Follow the agent's messages: a `codex` heading starts one, any other heading ends
+    /// it, and the last one becomes [`OutputScan::final_message`].
+    fn read_message(&mut self, line: &str) {
+        if line.starts_with('{')
+            && let Some(message) = json_agent_message(line)
+        {
+            self.scan.final_message = Some(message).filter(|m| !m.trim().is_empty());
+            return;
+        }
+        if let Some(heading) = transcript_heading_pattern().captures(line.trim()) {
+            self.end_message();
+            let heading = heading.get(1).or(heading.get(2)).map_or("", |m| m.as_str());
+            if heading.trim() == "codex" {
+                self.message = Some(String::new());
+            }
+            return;
+        }
+        if let Some(message) = &mut self.message
+            && message.len() + line.len() < MAX_FINAL_MESSAGE
+        {
+            if !message.is_empty() {
+                message.push('\n');
+            }
+            message.push_str(line);
+        }
+    }
+
+    fn end_message(&mut self) {
+        if let Some(message) = self.message.take().filter(|m| !m.is_empty()) {
+            self.scan.final_message = Some(message);
+        }
+    }
+}
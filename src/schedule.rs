@@ -0,0 +1,344 @@
+//! Cron expressions for `--schedule` and daily no-run windows for `--no-run-between`.
+//!
+//! The standard five fields are supported: minute, hour, day of month, month and day of
+//! week, each a `*`, a number, a range `a-b`, a step `*/n` or `a-b/n`, or a
+//! comma-separated list of those. Months and weekdays also accept three-letter English
+//! names. As in classic cron, when both day fields are restricted a day matching
+//! either one counts.
+
+use std::fmt;
+
+use chrono::{Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+
+use crate::CancelToken;
+
+/// Longest single sleep while waiting for a wall-clock time, so suspend/resume and clock
+/// changes are noticed.
+const MAX_SLEEP_SLICE: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// How far ahead [`CronSchedule::next_after`] looks before giving up, e.g. on
+/// `0 0 30 2 *`.
+const MAX_LOOKAHEAD_DAYS: i64 = 366 * 5;
+
+/// A parsed cron expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    source: String,
+    minutes: u64,
+    hours: u32,
+    days_of_month: u32,
+    months: u16,
+    /// Bit 0 is Sunday.
+    days_of_week: u8,
+    day_of_month_any: bool,
+    day_of_week_any: bool,
+}
+
+const MONTH_NAMES: &[&str] = &[
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAY_NAMES: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+impl CronSchedule {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        let [minute, hour, dom, month, dow] = fields[..] else {
+            return Err(format!(
+                "expected 5 fields (minute hour day-of-month month day-of-week), got {}",
+                fields.len()
+            ));
+        };
+        let days_of_week = parse_field(dow, 0, 7, Some((WEEKDAY_NAMES, 0)))?;
+        // Both 0 and 7 mean Sunday.
+        let days_of_week = (days_of_week | (days_of_week >> 7)) & 0x7f;
+        Ok(Self {
+            source: fields.join(" "),
+            minutes: parse_field(minute, 0, 59, None)?,
+            hours: parse_field(hour, 0, 23, None)? as u32,
+            days_of_month: parse_field(dom, 1, 31, None)? as u32,
+            months: parse_field(month, 1, 12, Some((MONTH_NAMES, 1)))? as u16,
+            days_of_week: days_of_week as u8,
+            day_of_month_any: dom == "*",
+            day_of_week_any: dow == "*",
+        })
+    }
+
+    /// Whether the schedule fires at the minute containing `time`.
+    pub fn matches(&self, time: NaiveDateTime) -> bool {
+        self.matches_date(time.date())
+            && bit(u64::from(self.hours), time.hour())
+            && bit(self.minutes, time.minute())
+    }
+
+    /// First firing time strictly after `time`, at whole-minute precision.
+    pub fn next_after(&self, time: NaiveDateTime) -> Option<NaiveDateTime> {
+        let start = time.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let mut date = start.date();
+        for _ in 0..MAX_LOOKAHEAD_DAYS {
+            if self.matches_date(date) {
+                let from = if date == start.date() {
+                    start.time()
+                } else {
+                    NaiveTime::MIN
+                };
+                if let Some(at) = self.first_time_from(from) {
+                    return Some(date.and_time(at));
+                }
+            }
+            date = date.succ_opt()?;
+        }
+        None
+    }
+
+    /// The schedule as systemd `OnCalendar=` expressions; the timer fires when any of
+    /// them does. Two are needed when both day fields are restricted, as systemd requires
+    /// both to match where cron takes either.
+    pub fn on_calendar(&self) -> Vec<String> {
+        let time = format!(
+            "{}:{}:00",
+            calendar_list(u64::from(self.hours), 0, 23),
+            calendar_list(self.minutes, 0, 59)
+        );
+        let months = calendar_list(u64::from(self.months), 1, 12);
+        let days_of_month = calendar_list(u64::from(self.days_of_month), 1, 31);
+        let weekdays = (0..7)
+            .filter(|&day| bit(u64::from(self.days_of_week), day))
+            .map(|day| {
+                let name = WEEKDAY_NAMES[day as usize];
+                name[..1].to_ascii_uppercase() + &name[1..]
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        match (self.day_of_month_any, self.day_of_week_any) {
+            (_, true) => vec![format!("*-{months}-{days_of_month} {time}")],
+            (true, false) => vec![format!("{weekdays} *-{months}-* {time}")],
+            (false, false) => vec![
+                format!("*-{months}-{days_of_month} {time}"),
+                format!("{weekdays} *-{months}-* {time}"),
+            ],
+        }
+    }
+
+    fn matches_date(&self, date: NaiveDate) -> bool {
+        if !bit(u64::from(self.months), date.month()) {
+            return false;
+        }
+        let dom = bit(u64::from(self.days_of_month), date.day());
+        let dow = bit(
+            u64::from(self.days_of_week),
+            date.weekday().num_days_from_sunday(),
+        );
+        match (self.day_of_month_any, self.day_of_week_any) {
+            (true, true) => true,
+            (true, false) => dow,
+            (false, true) => dom,
+            (false, false) => dom || dow,
+        }
+    }
+
+    fn first_time_from(&self, from: NaiveTime) -> Option<NaiveTime> {
+        (from.hour()..24)
+            .filter(|&hour| bit(u64::from(self.hours), hour))
+            .find_map(|hour| {
+                let first_minute = if hour == from.hour() {
+                    from.minute()
+                } else {
+                    0
+                };
+                (first_minute..60)
+                    .find(|&minute| bit(self.minutes, minute))
+                    .and_then(|minute| NaiveTime::from_hms_opt(hour, minute, 0))
+            })
+    }
+}
+
+impl fmt::Display for CronSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl std::str::FromStr for CronSchedule {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Self::parse(text)
+    }
+}
+
+/// A daily local-time window in which no run may start, e.g. `09:00-18:00`.
+/// Windows whose end is before their start wrap past midnight (`22:00-06:00`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Blackout {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl Blackout {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let parse_time = |part: &str| {
+            NaiveTime::parse_from_str(part.trim(), "%H:%M")
+                .map_err(|_| format!("invalid time `{}` in `{text}`, expected HH:MM", part.trim()))
+        };
+        let (start, end) = text
+            .split_once('-')
+            .ok_or_else(|| format!("expected HH:MM-HH:MM, got `{text}`"))?;
+        let (start, end) = (parse_time(start)?, parse_time(end)?);
+        if start == end {
+            return Err(format!("window `{text}` is empty"));
+        }
+        Ok(Self { start, end })
+    }
+
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start < self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+
+    /// When the window containing `now` closes, or `None` when `now` is outside it.
+    pub fn end_after(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        if !self.contains(now.time()) {
+            return None;
+        }
+        let end = now.date().and_time(self.end);
+        Some(if end > now {
+            end
+        } else {
+            end + Duration::days(1)
+        })
+    }
+}
+
+impl fmt::Display for Blackout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
+
+impl std::str::FromStr for Blackout {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Self::parse(text)
+    }
+}
+
+/// Earliest time at or after `now` outside all `windows`, or `None` when `now` already
+/// is. Back-to-back windows are chained.
+pub fn blackout_end(windows: &[Blackout], now: NaiveDateTime) -> Option<NaiveDateTime> {
+    let mut at = now;
+    // Every window can push the time forward at most once before it repeats.
+    for _ in 0..=windows.len() {
+        match windows.iter().find_map(|window| window.end_after(at)) {
+            Some(end) => at = end,
+            None => break,
+        }
+    }
+    (at != now).then_some(at)
+}
+
+/// Sleep until the local wall clock reaches `until`. Returns `false` if `cancel` fired
+/// first.
+pub async fn sleep_until_local(until: NaiveDateTime, cancel: &CancelToken) -> bool {
+    loop {
+        let remaining = until - Local::now().naive_local();
+        let Ok(remaining) = remaining.to_std() else {
+            return true;
+        };
+        if remaining.is_zero() {
+            return true;
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(remaining.min(MAX_SLEEP_SLICE)) => {}
+            _ = cancel.cancelled() => return false,
+        }
+    }
+}
+
+fn bit(set: u64, n: u32) -> bool {
+    set & (1 << n) != 0
+}
+
+/// The values in `set` as a systemd calendar list, `*` when all of `min..=max` are in it.
+fn calendar_list(set: u64, min: u32, max: u32) -> String {
+    let values: Vec<u32> = (min..=max).filter(|&n| bit(set, n)).collect();
+    if values.len() == (min..=max).count() {
+        return "*".to_string();
+    }
+    values
+        .iter()
+        .map(|n| format!("{n:02}"))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Parse one field into a bit set of allowed values in `min..=max`.
+/// `names` lists value names starting at the given number.
+fn parse_field(
+    field: &str,
+    min: u32,
+    max: u32,
+    names: Option<(&[&str], u32)>,
+) -> Result<u64, String> {
+    let value = |text: &str| -> Result<u32, String> {
+        let named = names.and_then(|(names, first)| {
+            names
+                .iter()
+                .position(|name| name.eq_ignore_ascii_case(text))
+                .map(|i| i as u32 + first)
+        });
+        let n = match named {
+            Some(n) => n,
+            None => text
+                .parse()
+                .map_err(|_| format!("invalid value `{text}` in `{field}`"))?,
+        };
+        if (min..=max).contains(&n) {
+            Ok(n)
+        } else {
+            Err(format!("`{n}` is outside {min}-{max} in `{field}`"))
+        }
+    };
+
+    let mut set = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .ok()
+                    .filter(|&s| s > 0)
+                    .ok_or_else(|| format!("invalid step in `{field}`"))?;
+                (range, step)
+            }
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((a, b)) => (value(a)?, value(b)?),
+                // `a/n` means from a to the end of the range.
+                None if step > 1 => (value(range)?, max),
+                None => {
+                    let n = value(range)?;
+                    (n, n)
+                }
+            },
+        };
+        if start > end {
+            return Err(format!("range `{range}` runs backwards in `{field}`"));
+        }
+        for n in (start..=end).step_by(step as usize) {
+            set |= 1 << n;
+        }
+    }
+    Ok(set)
+}
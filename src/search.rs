@@ -0,0 +1,65 @@
+//! Searching a run's output from the pinned view: `/` and a query jump to its newest
+//! match, `n` and `N` to older and newer ones. Lines no longer buffered for the view are
+//! searched in the run's output log.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::ops::Range;
+use std::path::Path;
+
+/// Which way a search goes from where the view is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Older,
+    Newer,
+}
+
+/// Whether `line` contains `query`. The match ignores case unless the query has capitals.
+pub fn line_matches(line: &str, query: &str) -> bool {
+    if query.chars().any(char::is_uppercase) {
+        line.contains(query)
+    } else {
+        line.to_lowercase().contains(&query.to_lowercase())
+    }
+}
+
+/// The number (0-based) of the line within `range` of the log at `path` that matches
+/// `query` and is closest to where the search starts: the newest for
+/// [`Direction::Older`], the oldest for [`Direction::Newer`].
+pub fn search_log(
+    path: &Path,
+    query: &str,
+    range: Range<u64>,
+    direction: Direction,
+) -> io::Result<Option<u64>> {
+    let mut found = None;
+    for (number, line) in log_lines(path)?.take(to_usize(range.end)).enumerate() {
+        let number = number as u64;
+        if number >= range.start && line_matches(&line?, query) {
+            found = Some(number);
+            if direction == Direction::Newer {
+                break;
+            }
+        }
+    }
+    Ok(found)
+}
+
+/// Lines `range` of the log at `path`; fewer when the log is shorter.
+pub fn read_log_lines(path: &Path, range: Range<u64>) -> io::Result<Vec<String>> {
+    log_lines(path)?
+        .skip(to_usize(range.start))
+        .take(to_usize(range.end.saturating_sub(range.start)))
+        .collect()
+}
+
+fn log_lines(path: &Path) -> io::Result<impl Iterator<Item = io::Result<String>>> {
+    // Lossy, as the log holds whatever the agent wrote.
+    Ok(BufReader::new(File::open(path)?)
+        .split(b'\n')
+        .map(|line| line.map(|bytes| String::from_utf8_lossy(&bytes).into_owned())))
+}
+
+fn to_usize(n: u64) -> usize {
+    usize::try_from(n).unwrap_or(usize::MAX)
+}
@@ -0,0 +1,216 @@
+//! Installing the current configuration as a recurring job that survives reboots
+//! (`agent-loops install-service --schedule ...`): a user-level systemd service and timer
+//! on Linux, a Scheduled Task on Windows.
+//!
+//! Scheduled Tasks cannot express every cron schedule, so on Windows the task starts at
+//! logon and stays resident with `--schedule`, as a session would when run by hand.
+
+use std::fs;
+use std::io;
+#[cfg(not(windows))]
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::schedule::CronSchedule;
+
+/// Options of `install-service` itself, left out of the command the job runs.
+const OWN_OPTIONS: &[&str] = &["--schedule", "--name"];
+const OWN_FLAGS: &[&str] = &["--print"];
+
+/// A recurring job running agent-loops with a fixed command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Service {
+    /// Unit or task name, e.g. `agent-loops` for `agent-loops.service`.
+    pub name: String,
+    pub schedule: CronSchedule,
+    /// The agent-loops binary followed by its arguments.
+    pub command: Vec<String>,
+    /// Where the job starts, so relative paths in `command` still resolve.
+    pub work_dir: PathBuf,
+    /// `PATH` for the job; services otherwise get a minimal one that may not find the
+    /// agent.
+    pub path: Option<String>,
+}
+
+impl Service {
+    /// Check that `name` is usable as a unit file or task name.
+    pub fn validate_name(name: &str) -> Result<(), String> {
+        if !name.is_empty()
+            && name
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"-_.".contains(&b))
+        {
+            Ok(())
+        } else {
+            Err(format!(
+                "invalid service name `{name}`: use letters, digits, `-`, `_` and `.`"
+            ))
+        }
+    }
+
+    /// The `.service` unit, run once each time the timer fires.
+    pub fn systemd_service(&self) -> String {
+        let mut unit = format!(
+            "[Unit]\nDescription=agent-loops session ({name})\n\n[Service]\nType=oneshot\nWorkingDirectory={dir}\n",
+            name = self.name,
+            dir = systemd_escape(&self.work_dir.to_string_lossy()),
+        );
+        if let Some(path) = &self.path {
+            unit.push_str(&format!(
+                "Environment={}\n",
+                systemd_quote(&format!("PATH={path}"))
+            ));
+        }
+        let exec: Vec<String> = self
+            .command
+            .iter()
+            .map(|word| systemd_quote(word))
+            .collect();
+        unit.push_str(&format!("ExecStart={}\n", exec.join(" ")));
+        unit
+    }
+
+    /// The `.timer` unit. Runs missed while the machine was off happen at the next boot.
+    pub fn systemd_timer(&self) -> String {
+        let mut unit = format!(
+            "[Unit]\nDescription=Schedule `{}` for {}.service\n\n[Timer]\n",
+            self.schedule, self.name
+        );
+        for calendar in self.schedule.on_calendar() {
+            unit.push_str(&format!("OnCalendar={calendar}\n"));
+        }
+        unit.push_str("Persistent=true\n\n[Install]\nWantedBy=timers.target\n");
+        unit
+    }
+
+    /// The batch script the Scheduled Task starts: agent-loops, resident with
+    /// `--schedule`.
+    pub fn windows_script(&self) -> String {
+        let mut script = format!(
+            "@echo off\r\ncd /d {}\r\n",
+            cmd_quote(&self.work_dir.to_string_lossy())
+        );
+        if let Some(path) = &self.path {
+            script.push_str(&format!("set {}\r\n", cmd_quote(&format!("PATH={path}"))));
+        }
+        let (program, args) = self.command.split_first().expect("command is never empty");
+        let mut line = vec![cmd_quote(program), "--schedule".to_string()];
+        line.push(cmd_quote(&self.schedule.to_string()));
+        line.extend(args.iter().map(|arg| cmd_quote(arg)));
+        script.push_str(&line.join(" "));
+        script.push_str("\r\n");
+        script
+    }
+
+    /// Write the units to the user's systemd directory and enable the timer. Returns the
+    /// files written.
+    #[cfg(not(windows))]
+    pub fn install(&self) -> io::Result<Vec<PathBuf>> {
+        let dir = systemd_user_dir()?;
+        fs::create_dir_all(&dir)?;
+        let service = dir.join(format!("{}.service", self.name));
+        let timer = dir.join(format!("{}.timer", self.name));
+        fs::write(&service, self.systemd_service())?;
+        fs::write(&timer, self.systemd_timer())?;
+        systemctl(&["--user", "daemon-reload"])?;
+        systemctl(&["--user", "enable", "--now", &format!("{}.timer", self.name)])?;
+        Ok(vec![service, timer])
+    }
+
+    /// Write the script under the data dir and register a Scheduled Task starting it at
+    /// logon. Returns the files written.
+    #[cfg(windows)]
+    pub fn install(&self) -> io::Result<Vec<PathBuf>> {
+        let dir = crate::sessions::default_data_dir().join("services");
+        fs::create_dir_all(&dir)?;
+        let script = dir.join(format!("{}.cmd", self.name));
+        fs::write(&script, self.windows_script())?;
+        let status = std::process::Command::new("schtasks")
+            .args(["/Create", "/F", "/SC", "ONLOGON", "/TN", &self.name, "/TR"])
+            .arg(format!("\"{}\"", script.display()))
+            .status()?;
+        if !status.success() {
+            return Err(io::Error::other(format!("schtasks failed ({status})")));
+        }
+        Ok(vec![script])
+    }
+}
+
+/// The agent-loops arguments to install: `args` without the `install-service`
+/// subcommand and its own options.
+pub fn service_args(args: &[String]) -> Vec<String> {
+    let Some(at) = args.iter().position(|arg| arg == "install-service") else {
+        return args.to_vec();
+    };
+    let mut kept = args[..at].to_vec();
+    let mut rest = args[at + 1..].iter();
+    while let Some(arg) = rest.next() {
+        if OWN_OPTIONS.contains(&arg.as_str()) {
+            rest.next();
+        } else if !OWN_FLAGS.contains(&arg.as_str())
+            && !OWN_OPTIONS
+                .iter()
+                .any(|option| arg.starts_with(&format!("{option}=")))
+        {
+            kept.push(arg.clone());
+        }
+    }
+    kept
+}
+
+/// `word` as one argument of a systemd command line.
+pub fn systemd_quote(word: &str) -> String {
+    let word = systemd_escape(word).replace('$', "$$");
+    if !word.is_empty()
+        && word
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-_./=:@,+%$".contains(&b))
+    {
+        return word;
+    }
+    format!("\"{}\"", word.replace('\\', r"\\").replace('"', "\\\""))
+}
+
+/// `text` with systemd's `%` specifiers disarmed.
+fn systemd_escape(text: &str) -> String {
+    text.replace('%', "%%")
+}
+
+/// `word` as one argument of a batch file line.
+fn cmd_quote(word: &str) -> String {
+    let word = word.replace('%', "%%");
+    if !word.is_empty()
+        && word
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-_./=:@,+\\".contains(&b))
+    {
+        return word;
+    }
+    format!("\"{}\"", word.replace('"', "\\\""))
+}
+
+/// `$XDG_CONFIG_HOME/systemd/user`, where user units live.
+#[cfg(not(windows))]
+fn systemd_user_dir() -> io::Result<PathBuf> {
+    let config = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .ok_or_else(|| io::Error::other("neither XDG_CONFIG_HOME nor HOME is set"))?;
+    Ok(config.join("systemd/user"))
+}
+
+#[cfg(not(windows))]
+fn systemctl(args: &[&str]) -> io::Result<()> {
+    let status = std::process::Command::new("systemctl")
+        .args(args)
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "`systemctl {}` failed ({status})",
+            args.join(" ")
+        )))
+    }
+}
@@ -0,0 +1,405 @@
+//! Session records kept under the data dir for `agent-loops sessions list|show`.
+//!
+//! Every session gets an ID (its local start time, e.g. `20240601-020000`) and an
+//! optional name. Its plan is written to `<data dir>/sessions/<id>/session.toml` when it
+//! starts and its results when it ends, so running sessions can be inspected too.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{Local, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+
+use crate::attach::SessionControl;
+use crate::lock::process_alive;
+use crate::progress::format_run_time;
+use crate::{RunRecord, display_len, truncate_display};
+
+/// Overrides the data dir location.
+pub const DATA_DIR_ENV: &str = "AGENT_LOOPS_DATA_DIR";
+
+const RECORD_FILE: &str = "session.toml";
+
+/// Output of a session started with `--detach`, in its directory.
+const LOG_FILE: &str = "session.log";
+
+/// Where session records are kept: `$AGENT_LOOPS_DATA_DIR`, else the platform's
+/// per-user data directory.
+pub fn default_data_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os(DATA_DIR_ENV).filter(|dir| !dir.is_empty()) {
+        return dir.into();
+    }
+    let home = || std::env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" });
+    let base = if cfg!(windows) {
+        std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home().map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        std::env::var_os("XDG_DATA_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| home().map(|home| PathBuf::from(home).join(".local/share")))
+    };
+    base.unwrap_or_else(std::env::temp_dir).join("agent-loops")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStatus {
+    Running,
+    Succeeded,
+    Failed,
+    /// Stopped by Ctrl-C.
+    Cancelled,
+    /// Recorded as running, but its process is gone.
+    Interrupted,
+}
+
+impl SessionStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Running => "running",
+            Self::Succeeded => "succeeded",
+            Self::Failed => "failed",
+            Self::Cancelled => "cancelled",
+            Self::Interrupted => "interrupted",
+        }
+    }
+}
+
+/// One finished run of a session.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunEntry {
+    /// 1-based loop and task numbers.
+    pub loop_number: usize,
+    pub task_number: usize,
+    pub success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_log: Option<PathBuf>,
+    /// The agent's closing message.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub final_message: Option<String>,
+    /// Skipped as already done (`--cache`).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cached: bool,
+    /// What `--cache` knows the run by.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_key: Option<String>,
+}
+
+impl From<&RunRecord> for RunEntry {
+    fn from(record: &RunRecord) -> Self {
+        Self {
+            loop_number: record.loop_idx + 1,
+            task_number: record.task_idx + 1,
+            success: record.outcome.success,
+            cost_usd: record.outcome.cost_usd,
+            output_log: record.outcome.output_log.clone(),
+            final_message: record.outcome.final_message.clone(),
+            cached: record.outcome.cached,
+            cache_key: record.outcome.cache_key.clone(),
+        }
+    }
+}
+
+/// What is persisted about a session.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionRecord {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub pid: u32,
+    pub started: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished: Option<String>,
+    pub status: SessionStatus,
+    pub work_dirs: Vec<PathBuf>,
+    pub loops: usize,
+    pub tasks: Vec<String>,
+    /// Directory the runs' full output logs are spooled to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spool_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub runs: Vec<RunEntry>,
+}
+
+impl SessionRecord {
+    /// A running session of this process, not yet stored.
+    pub fn new(
+        name: Option<String>,
+        work_dirs: Vec<PathBuf>,
+        loops: usize,
+        tasks: Vec<String>,
+    ) -> Self {
+        Self {
+            id: String::new(),
+            name,
+            pid: std::process::id(),
+            started: now(),
+            finished: None,
+            status: SessionStatus::Running,
+            work_dirs,
+            loops,
+            tasks,
+            spool_dir: None,
+            runs: Vec::new(),
+        }
+    }
+
+    /// Record the session's results and final status.
+    pub fn finish(&mut self, results: &[RunRecord], cancelled: bool) {
+        self.runs = results.iter().map(RunEntry::from).collect();
+        self.finished = Some(now());
+        self.status = if cancelled {
+            SessionStatus::Cancelled
+        } else if results.iter().all(|r| r.outcome.success) {
+            SessionStatus::Succeeded
+        } else {
+            SessionStatus::Failed
+        };
+    }
+
+    /// The stored status, with running sessions whose process is gone reported as
+    /// interrupted.
+    pub fn current_status(&self) -> SessionStatus {
+        if self.status == SessionStatus::Running && !process_alive(self.pid) {
+            SessionStatus::Interrupted
+        } else {
+            self.status
+        }
+    }
+}
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+fn now() -> String {
+    Local::now().format(TIME_FORMAT).to_string()
+}
+
+/// What one run took on average in past sessions, to estimate a new session by.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RunEstimate {
+    /// Wall-clock time per run, retries and waits included.
+    pub duration: Duration,
+    /// Spend per run, from the runs that reported it.
+    pub cost_usd: Option<f64>,
+}
+
+impl RunEstimate {
+    /// The average over the finished sessions in `records`; `None` without any.
+    pub fn from_history(records: &[SessionRecord]) -> Option<Self> {
+        let parse = |time: &str| NaiveDateTime::parse_from_str(time, TIME_FORMAT).ok();
+        let mut took = Duration::ZERO;
+        let mut runs = 0;
+        let mut costs = Vec::new();
+        for record in records.iter().filter(|record| !record.runs.is_empty()) {
+            let Some(finished) = record.finished.as_deref().and_then(parse) else {
+                continue;
+            };
+            let Some(started) = parse(&record.started) else {
+                continue;
+            };
+            took += (finished - started).to_std().unwrap_or_default();
+            runs += record.runs.len();
+            costs.extend(record.runs.iter().filter_map(|run| run.cost_usd));
+        }
+        (runs > 0).then(|| Self {
+            duration: took / runs as u32,
+            cost_usd: (!costs.is_empty()).then(|| costs.iter().sum::<f64>() / costs.len() as f64),
+        })
+    }
+
+    /// `about 2h05m00s and $12.40` for `runs` runs.
+    pub fn describe(&self, runs: usize) -> String {
+        let mut text = format!(
+            "about {}",
+            format_run_time(self.duration.saturating_mul(runs as u32))
+        );
+        if let Some(cost) = self.cost_usd {
+            let _ = write!(text, " and ${:.2}", cost * runs as f64);
+        }
+        text
+    }
+}
+
+/// Ask the operator on the terminal whether to start a large session; no terminal means
+/// no.
+pub async fn confirm_run_count() -> bool {
+    crate::confirm("Start it?").await
+}
+
+/// Session records under `<data dir>/sessions`.
+#[derive(Debug, Clone)]
+pub struct SessionStore {
+    dir: PathBuf,
+}
+
+impl SessionStore {
+    pub fn new(data_dir: &Path) -> Self {
+        Self {
+            dir: data_dir.join("sessions"),
+        }
+    }
+
+    /// Assign `record` a fresh ID and store it.
+    pub fn create(&self, record: &mut SessionRecord) -> io::Result<()> {
+        record.id = self.reserve()?;
+        self.save(record)
+    }
+
+    /// A fresh session ID with its directory created, for a record saved later.
+    pub fn reserve(&self) -> io::Result<String> {
+        fs::create_dir_all(&self.dir)?;
+        let base = Local::now().format("%Y%m%d-%H%M%S").to_string();
+        for n in 1.. {
+            let id = if n == 1 {
+                base.clone()
+            } else {
+                format!("{base}-{n}")
+            };
+            match fs::create_dir(self.dir.join(&id)) {
+                Ok(()) => return Ok(id),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e),
+            }
+        }
+        unreachable!("session IDs are unbounded")
+    }
+
+    /// Pause and stop requests for session `id`.
+    pub fn control(&self, id: &str) -> SessionControl {
+        SessionControl::new(&self.dir.join(id))
+    }
+
+    /// Where the output of the detached session `id` goes.
+    pub fn log_path(&self, id: &str) -> PathBuf {
+        self.dir.join(id).join(LOG_FILE)
+    }
+
+    pub fn save(&self, record: &SessionRecord) -> io::Result<()> {
+        let content = toml::to_string(record)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        fs::write(self.dir.join(&record.id).join(RECORD_FILE), content)
+    }
+
+    /// All stored sessions, oldest first. Unreadable records are skipped.
+    pub fn list(&self) -> io::Result<Vec<SessionRecord>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut records: Vec<SessionRecord> = entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| read_record(&entry.path().join(RECORD_FILE)).ok())
+            .collect();
+        records.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(records)
+    }
+
+    /// The session with this ID, or else the latest one with this name.
+    pub fn find(&self, id_or_name: &str) -> io::Result<SessionRecord> {
+        if let Ok(record) = read_record(&self.dir.join(id_or_name).join(RECORD_FILE)) {
+            return Ok(record);
+        }
+        self.list()?
+            .into_iter()
+            .rev()
+            .find(|record| record.name.as_deref() == Some(id_or_name))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no session with ID or name `{id_or_name}`"),
+                )
+            })
+    }
+}
+
+fn read_record(path: &Path) -> io::Result<SessionRecord> {
+    toml::from_str(&fs::read_to_string(path)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+/// One line per session: ID, status, start time, passed runs and name.
+pub fn format_session_list(records: &[SessionRecord]) -> String {
+    if records.is_empty() {
+        return "No sessions recorded.\n".to_string();
+    }
+    let mut out = format!(
+        "{:<20} {:<12} {:<20} {:<8} NAME\n",
+        "ID", "STATUS", "STARTED", "RUNS"
+    );
+    for record in records {
+        let passed = record.runs.iter().filter(|run| run.success).count();
+        let _ = writeln!(
+            out,
+            "{:<20} {:<12} {:<20} {:<8} {}",
+            record.id,
+            record.current_status().as_str(),
+            record.started,
+            format!("{passed}/{}", record.runs.len()),
+            record.name.as_deref().unwrap_or("-")
+        );
+    }
+    out
+}
+
+/// Plan, status, runs and log locations of one session.
+pub fn format_session(record: &SessionRecord) -> String {
+    let mut out = format!("Session {}", record.id);
+    if let Some(name) = &record.name {
+        let _ = write!(out, " ({name})");
+    }
+    let _ = writeln!(out);
+    let _ = writeln!(out, "Status: {}", record.current_status().as_str());
+    let _ = writeln!(out, "PID: {}", record.pid);
+    let _ = writeln!(out, "Started: {}", record.started);
+    if let Some(finished) = &record.finished {
+        let _ = writeln!(out, "Finished: {finished}");
+    }
+    for dir in &record.work_dirs {
+        let _ = writeln!(out, "Work dir: {}", dir.display());
+    }
+    if let Some(dir) = &record.spool_dir {
+        let _ = writeln!(out, "Logs: {}", dir.display());
+    }
+    let _ = writeln!(out, "Loops: {}", record.loops);
+    let _ = writeln!(out, "Tasks:");
+    for (i, task) in record.tasks.iter().enumerate() {
+        let _ = writeln!(
+            out,
+            "  {}. {}",
+            i + 1,
+            truncate_display(task, display_len())
+        );
+    }
+    if !record.runs.is_empty() {
+        let _ = writeln!(out, "Runs:");
+    }
+    for run in &record.runs {
+        let _ = write!(
+            out,
+            "  loop {} task {}: {}",
+            run.loop_number,
+            run.task_number,
+            if run.success { "OK" } else { "FAILED" }
+        );
+        if let Some(cost) = run.cost_usd {
+            let _ = write!(out, " (${cost:.4})");
+        }
+        if let Some(log) = &run.output_log {
+            let _ = write!(out, " {}", log.display());
+        }
+        let _ = writeln!(out);
+        if let Some(message) = &run.final_message {
+            let first = message.lines().next().unwrap_or_default();
+            let _ = writeln!(out, "    > {}", truncate_display(first, display_len()));
+        }
+    }
+    out
+}
@@ -0,0 +1,30 @@
+//! Leaving when the session cannot finish normally: after a panic, or when a second
+//! interrupt asks to exit at once. The terminal is put back and the agents are killed, so
+//! neither a broken screen nor an orphaned agent is left behind.
+
+use crate::process_tree;
+
+/// Restore the terminal and kill the running agents before a panic is reported, so the
+/// message lands on the primary screen and the process does not leave children behind.
+pub fn install_panic_hook() {
+    let report = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        release();
+        report(info);
+    }));
+}
+
+/// Exit with `code` right away, without finishing the current run.
+pub fn exit_now(code: i32) -> ! {
+    release();
+    std::process::exit(code)
+}
+
+fn release() {
+    #[cfg(feature = "tui")]
+    {
+        crate::keys::restore_terminal_mode();
+        crate::tui::restore_screen();
+    }
+    process_tree::kill_live_trees();
+}
@@ -0,0 +1,520 @@
+//! Per-task results across a session's loops: the summary table closing every session,
+//! and for sessions that use loops as repeated trials of the same prompts, success rates,
+//! `min_successes` and the duration statistics of `--stats`.
+
+use std::fmt::Write as _;
+use std::time::Duration;
+
+use crate::progress::format_run_time;
+use crate::theme::theme;
+use crate::{RunRecord, TaskSpec, display_len, display_width, truncate_display};
+
+/// How one task fared over all its runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskSuccess {
+    /// Zero-based position in the task list.
+    pub task_idx: usize,
+    /// The task's name, or its prompt.
+    pub label: String,
+    pub runs: usize,
+    pub successes: usize,
+    /// The task's [`TaskSpec::min_successes`].
+    pub min_successes: Option<usize>,
+    /// The task's [`TaskSpec::priority`].
+    pub priority: i64,
+}
+
+impl TaskSuccess {
+    /// Share of the runs that succeeded, from 0 to 100; 0 when the task never ran.
+    pub fn success_rate(&self) -> f64 {
+        if self.runs == 0 {
+            0.0
+        } else {
+            self.successes as f64 * 100.0 / self.runs as f64
+        }
+    }
+
+    /// Whether the task reached its `min_successes`; tasks without one always do.
+    pub fn met(&self) -> bool {
+        self.min_successes.is_none_or(|min| self.successes >= min)
+    }
+}
+
+/// Success counts for each of `tasks` from a session's `results`.
+pub fn task_successes(tasks: &[TaskSpec], results: &[RunRecord]) -> Vec<TaskSuccess> {
+    tasks
+        .iter()
+        .enumerate()
+        .map(|(task_idx, task)| {
+            let runs = results.iter().filter(|r| r.task_idx == task_idx);
+            TaskSuccess {
+                task_idx,
+                label: task.name.clone().unwrap_or_else(|| task.prompt.clone()),
+                runs: runs.clone().count(),
+                successes: runs.filter(|r| r.outcome.success).count(),
+                min_successes: task.min_successes,
+                priority: task.priority,
+            }
+        })
+        .collect()
+}
+
+/// The per-task success rate table printed at the end of a session, e.g.
+/// `  2. lint  3/5 (60%)  needs 2: met`.
+pub fn format_task_successes(tasks: &[TaskSuccess]) -> String {
+    let labels: Vec<String> = tasks
+        .iter()
+        .map(|task| truncate_display(&task.label, display_len()))
+        .collect();
+    let width = labels.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+    let mut out = String::from("=== Task success rates ===\n");
+    for (task, label) in tasks.iter().zip(&labels) {
+        let _ = write!(
+            out,
+            "{:>3}. {label:<width$}  {}/{} ({:.0}%)",
+            task.task_idx + 1,
+            task.successes,
+            task.runs,
+            task.success_rate()
+        );
+        if let Some(min) = task.min_successes {
+            let _ = write!(
+                out,
+                "  needs {min}: {}",
+                if task.met() { "met" } else { "NOT MET" }
+            );
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// One task's row of the session summary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskSummary {
+    pub success: TaskSuccess,
+    /// Time of all the task's runs together.
+    pub total_time: Duration,
+    /// Whether the task's latest run succeeded; `None` when it never ran.
+    pub last_success: Option<bool>,
+}
+
+/// One loop's row of the session summary, over all tasks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoopSummary {
+    /// Zero-based loop number.
+    pub loop_idx: usize,
+    pub runs: usize,
+    pub successes: usize,
+    /// Runs skipped as already done (`--cache`), counted in `runs` and `successes`.
+    pub cached: usize,
+    pub total_time: Duration,
+}
+
+/// How a session went, per task and per loop.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionSummary {
+    pub tasks: Vec<TaskSummary>,
+    /// The loops that ran, in order.
+    pub loops: Vec<LoopSummary>,
+}
+
+impl SessionSummary {
+    pub fn runs(&self) -> usize {
+        self.loops.iter().map(|l| l.runs).sum()
+    }
+
+    pub fn successes(&self) -> usize {
+        self.loops.iter().map(|l| l.successes).sum()
+    }
+
+    pub fn cached(&self) -> usize {
+        self.loops.iter().map(|l| l.cached).sum()
+    }
+
+    pub fn total_time(&self) -> Duration {
+        self.loops.iter().map(|l| l.total_time).sum()
+    }
+
+    /// The summary in one line, for notifications, e.g.
+    /// `5/6 runs OK (2 cached) in 21m14s; failed: 1. lint (2/3)`.
+    pub fn headline(&self) -> String {
+        let mut line = format!("{}/{} runs OK", self.successes(), self.runs());
+        if self.cached() > 0 {
+            let _ = write!(line, " ({} cached)", self.cached());
+        }
+        let _ = write!(line, " in {}", format_run_time(self.total_time()));
+        let failed: Vec<String> = self
+            .tasks
+            .iter()
+            .map(|task| &task.success)
+            .filter(|s| s.successes < s.runs)
+            .map(|s| {
+                format!(
+                    "{}. {} ({}/{})",
+                    s.task_idx + 1,
+                    truncate_display(&s.label, display_len()),
+                    s.successes,
+                    s.runs
+                )
+            })
+            .collect();
+        if !failed.is_empty() {
+            let _ = write!(line, "; failed: {}", failed.join(", "));
+        }
+        line
+    }
+}
+
+/// The summary of a session that ran `tasks` with these `results`.
+pub fn session_summary(tasks: &[TaskSpec], results: &[RunRecord]) -> SessionSummary {
+    let tasks = task_successes(tasks, results)
+        .into_iter()
+        .map(|success| {
+            let mut runs = results.iter().filter(|r| r.task_idx == success.task_idx);
+            TaskSummary {
+                total_time: runs.clone().map(|r| r.duration).sum(),
+                last_success: runs.next_back().map(|r| r.outcome.success),
+                success,
+            }
+        })
+        .collect();
+    let mut loops: Vec<LoopSummary> = Vec::new();
+    for record in results {
+        let row = match loops.iter_mut().find(|l| l.loop_idx == record.loop_idx) {
+            Some(row) => row,
+            None => {
+                loops.push(LoopSummary {
+                    loop_idx: record.loop_idx,
+                    runs: 0,
+                    successes: 0,
+                    cached: 0,
+                    total_time: Duration::ZERO,
+                });
+                loops.last_mut().expect("just pushed")
+            }
+        };
+        row.runs += 1;
+        row.successes += usize::from(record.outcome.success);
+        row.cached += usize::from(record.outcome.cached);
+        row.total_time += record.duration;
+    }
+    loops.sort_by_key(|l| l.loop_idx);
+    SessionSummary { tasks, loops }
+}
+
+/// The table closing a session: per task its successes, time and latest result, then,
+/// with more than one loop, a row per loop and the total, and how many runs were cached.
+pub fn format_session_summary(summary: &SessionSummary) -> String {
+    let labels: Vec<String> = summary
+        .tasks
+        .iter()
+        .map(|task| {
+            let label = truncate_display(&task.success.label, display_len());
+            match task.success.priority {
+                0 => label,
+                priority => format!("{label} [priority {priority}]"),
+            }
+        })
+        .collect();
+    let loop_rows: Vec<(String, usize, usize, Duration)> = if summary.loops.len() > 1 {
+        summary
+            .loops
+            .iter()
+            .map(|l| {
+                (
+                    format!("loop {}", l.loop_idx + 1),
+                    l.successes,
+                    l.runs,
+                    l.total_time,
+                )
+            })
+            .chain([(
+                "all".to_string(),
+                summary.successes(),
+                summary.runs(),
+                summary.total_time(),
+            )])
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let width = labels
+        .iter()
+        .chain(loop_rows.iter().map(|(label, ..)| label))
+        .map(|l| display_width(l))
+        .chain([4])
+        .max()
+        .unwrap_or(0);
+    let pad = |text: &str, width: usize| {
+        format!(
+            "{text}{}",
+            " ".repeat(width.saturating_sub(display_width(text)))
+        )
+    };
+    let mut out = format!("{}\n", theme().header.paint("=== Session summary ==="));
+    let _ = writeln!(
+        out,
+        "{:>4} {}  {:>7} {:>9}  last",
+        "#",
+        pad("task", width),
+        "ok",
+        "time"
+    );
+    for (task, label) in summary.tasks.iter().zip(&labels) {
+        let success = &task.success;
+        let time = if success.runs == 0 {
+            "-".to_string()
+        } else {
+            format_run_time(task.total_time)
+        };
+        let last = task
+            .last_success
+            .map_or_else(|| "-".to_string(), |ok| theme().outcome(ok));
+        let _ = write!(
+            out,
+            "{:>3}. {}  {:>7} {time:>9}  {last}",
+            success.task_idx + 1,
+            pad(label, width),
+            format!("{}/{}", success.successes, success.runs),
+        );
+        if let Some(min) = success.min_successes {
+            // `last` may carry escape sequences, so pad it by its visible width.
+            let shown = match task.last_success {
+                Some(true) => "OK".len(),
+                Some(false) => "FAILED".len(),
+                None => 1,
+            };
+            let _ = write!(
+                out,
+                "{}  needs {min}: {}",
+                " ".repeat("FAILED".len() - shown),
+                if success.met() { "met" } else { "NOT MET" }
+            );
+        }
+        out.push('\n');
+    }
+    for (label, successes, runs, time) in &loop_rows {
+        let _ = writeln!(
+            out,
+            "     {}  {:>7} {:>9}",
+            pad(label, width),
+            format!("{successes}/{runs}"),
+            format_run_time(*time)
+        );
+    }
+    match summary.cached() {
+        0 => {}
+        1 => out.push_str("1 run skipped as already done (--cache)\n"),
+        cached => {
+            let _ = writeln!(out, "{cached} runs skipped as already done (--cache)");
+        }
+    }
+    out
+}
+
+/// How long one task's runs took.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DurationStats {
+    pub mean: Duration,
+    /// Nearest-rank percentiles.
+    pub p50: Duration,
+    pub p90: Duration,
+    /// Variance in seconds squared.
+    pub variance: f64,
+}
+
+impl DurationStats {
+    /// Statistics of `durations`; `None` when there are none.
+    pub fn of(durations: &[Duration]) -> Option<Self> {
+        if durations.is_empty() {
+            return None;
+        }
+        let mut sorted = durations.to_vec();
+        sorted.sort();
+        let secs: Vec<f64> = sorted.iter().map(Duration::as_secs_f64).collect();
+        let mean = secs.iter().sum::<f64>() / secs.len() as f64;
+        let variance = secs.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / secs.len() as f64;
+        let percentile = |pct: usize| sorted[(sorted.len() * pct).div_ceil(100).max(1) - 1];
+        Some(Self {
+            mean: Duration::from_secs_f64(mean),
+            p50: percentile(50),
+            p90: percentile(90),
+            variance,
+        })
+    }
+
+    /// Standard deviation, i.e. the square root of the variance.
+    pub fn std_dev(&self) -> Duration {
+        Duration::from_secs_f64(self.variance.sqrt())
+    }
+}
+
+/// One task's line of `--stats`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaskStats {
+    pub success: TaskSuccess,
+    pub durations: Option<DurationStats>,
+}
+
+/// `--stats` figures for each of `tasks` from a session's `results`. Cached runs
+/// (`--cache`) count as successes but not towards the durations.
+pub fn task_stats(tasks: &[TaskSpec], results: &[RunRecord]) -> Vec<TaskStats> {
+    task_successes(tasks, results)
+        .into_iter()
+        .map(|success| {
+            let durations: Vec<Duration> = results
+                .iter()
+                .filter(|r| r.task_idx == success.task_idx && !r.outcome.cached)
+                .map(|r| r.duration)
+                .collect();
+            TaskStats {
+                durations: DurationStats::of(&durations),
+                success,
+            }
+        })
+        .collect()
+}
+
+/// The `--stats` table printed at the end of a session.
+pub fn format_task_stats(stats: &[TaskStats]) -> String {
+    let labels: Vec<String> = stats
+        .iter()
+        .map(|task| truncate_display(&task.success.label, display_len()))
+        .collect();
+    let width = labels
+        .iter()
+        .map(|l| l.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(4);
+    let mut out = String::from("=== Task statistics ===\n");
+    let _ = writeln!(
+        out,
+        "{:>4} {:<width$}  {:>7} {:>5} {:>9} {:>9} {:>9} {:>9}",
+        "#", "task", "ok", "rate", "mean", "p50", "p90", "stddev"
+    );
+    for (task, label) in stats.iter().zip(&labels) {
+        let success = &task.success;
+        let _ = write!(
+            out,
+            "{:>3}. {label:<width$}  {:>7} {:>4.0}%",
+            success.task_idx + 1,
+            format!("{}/{}", success.successes, success.runs),
+            success.success_rate()
+        );
+        match &task.durations {
+            Some(d) => {
+                for duration in [d.mean, d.p50, d.p90, d.std_dev()] {
+                    let _ = write!(out, " {:>9}", format_run_time(duration));
+                }
+            }
+            None => out.push_str(&format!(" {:>9}", "-").repeat(4)),
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// `--stats-out` as JSON: an array with one object per task, durations in seconds.
+pub fn stats_json(stats: &[TaskStats]) -> String {
+    let tasks: Vec<serde_json::Value> = stats
+        .iter()
+        .map(|task| {
+            let success = &task.success;
+            let d = task.durations;
+            serde_json::json!({
+                "task": success.task_idx + 1,
+                "label": success.label,
+                "runs": success.runs,
+                "successes": success.successes,
+                "success_rate": success.success_rate(),
+                "min_successes": success.min_successes,
+                "mean_secs": d.map(|d| d.mean.as_secs_f64()),
+                "p50_secs": d.map(|d| d.p50.as_secs_f64()),
+                "p90_secs": d.map(|d| d.p90.as_secs_f64()),
+                "variance_secs2": d.map(|d| d.variance),
+            })
+        })
+        .collect();
+    let mut json = serde_json::to_string_pretty(&tasks).unwrap_or_default();
+    json.push('\n');
+    json
+}
+
+/// `--stats-out` as CSV, with the same columns as [`stats_json`].
+pub fn stats_csv(stats: &[TaskStats]) -> String {
+    let mut out = String::from(
+        "task,label,runs,successes,success_rate,min_successes,mean_secs,p50_secs,p90_secs,variance_secs2\n",
+    );
+    let secs = |d: Option<Duration>| {
+        d.map(|d| format!("{:.3}", d.as_secs_f64()))
+            .unwrap_or_default()
+    };
+    for task in stats {
+        let success = &task.success;
+        let d = task.durations;
+        let _ = writeln!(
+            out,
+            "{},{},{},{},{:.1},{},{},{},{},{}",
+            success.task_idx + 1,
+            csv_field(&success.label),
+            success.runs,
+            success.successes,
+            success.success_rate(),
+            success
+                .min_successes
+                .map(|m| m.to_string())
+                .unwrap_or_default(),
+            secs(d.map(|d| d.mean)),
+            secs(d.map(|d| d.p50)),
+            secs(d.map(|d| d.p90)),
+            d.map(|d| format!("{:.3}", d.variance)).unwrap_or_default(),
+        );
+    }
+    out
+}
+
+/// Quote a CSV field when it holds a separator, quote or line break.
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// Two-sided p-value of Fisher's exact test for two success counts, `a_successes` of
+/// `a_trials` against `b_successes` of `b_trials`: the probability, with the margins
+/// fixed, of a split at most as likely as the observed one.
+pub fn fisher_exact(
+    a_successes: usize,
+    a_trials: usize,
+    b_successes: usize,
+    b_trials: usize,
+) -> f64 {
+    let successes = a_successes + b_successes;
+    let total = a_trials + b_trials;
+    let log_factorials: Vec<f64> = std::iter::once(0.0)
+        .chain((1..=total).scan(0.0, |acc: &mut f64, n| {
+            *acc += (n as f64).ln();
+            Some(*acc)
+        }))
+        .collect();
+    let log_choose =
+        |n: usize, k: usize| log_factorials[n] - log_factorials[k] - log_factorials[n - k];
+    // Probability that `a` gets `k` of the successes.
+    let probability = |k: usize| {
+        (log_choose(a_trials, k) + log_choose(b_trials, successes - k)
+            - log_choose(total, successes))
+        .exp()
+    };
+    let observed = probability(a_successes);
+    let low = successes.saturating_sub(b_trials);
+    let high = successes.min(a_trials);
+    let p: f64 = (low..=high)
+        .map(probability)
+        .filter(|&p| p <= observed * (1.0 + 1e-7))
+        .sum();
+    p.min(1.0)
+}
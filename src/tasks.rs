@@ -0,0 +1,397 @@
+//! Loading prompts and task definitions from files.
+//!
+//! Two formats are accepted:
+//! - plain text: one prompt per non-empty line, surrounding whitespace trimmed;
+//! - TOML task files (`.toml`): one `[[task]]` table per task.
+//!
+//! A plain-text line `#include FILE` is replaced by the tasks of `FILE`, in either format,
+//! resolved from the including file's directory. Included files may include others; a
+//! file including itself, directly or not, is an error.
+//!
+//! ```toml
+//! [[task]]
+//! name = "lint"
+//! prompt = "Fix all clippy warnings"
+//! model = "gpt-5-mini"
+//! env = { OPENAI_BASE_URL = "http://localhost:8080/v1" }
+//! min_successes = 2
+//! tags = ["lint", "fast"]
+//! priority = 1
+//!
+//! [[task]]
+//! name = "parser"
+//! loop_prompts = ["Implement the parser", "Review and harden the parser", "Test the parser"]
+//! ```
+//!
+//! A run can also add tasks to its session through its output (see
+//! [`parse_added_tasks`]).
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::agent::Agent;
+use crate::scan::ansi_escape_pattern;
+
+/// One task of a session.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TaskSpec {
+    /// Prompt sent to the agent. In a task file it may be left out when `loop_prompts`
+    /// is given, and is then the first of them.
+    #[serde(default)]
+    pub prompt: String,
+    /// Prompt for each loop in turn instead of `prompt`, so the loops go through stages
+    /// (implement, review, test) rather than repeat; loops past the last use the last.
+    #[serde(default)]
+    pub loop_prompts: Vec<String>,
+    /// Short label for the task.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Agent CLI for this task instead of the session's. Without a `codex_bin` of its
+    /// own, a task switching agents runs the agent's default binary.
+    #[serde(default)]
+    pub agent: Option<Agent>,
+    /// Agent executable for this task instead of the session's.
+    #[serde(default)]
+    pub codex_bin: Option<String>,
+    /// Model for this task, passed to the agent as `--model`.
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Environment variables set for this task's agent process.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Start this task's agent from an empty environment, passing through only these
+    /// variables.
+    #[serde(default)]
+    pub env_allowlist: Option<Vec<String>>,
+    /// Runs of this task that must succeed across all loops. When set, the task is judged
+    /// by this count at the end of the session instead of by each failed run.
+    #[serde(default)]
+    pub min_successes: Option<usize>,
+    /// Labels for picking tasks with `--only` and `--skip` (see [`TaskFilter`]).
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Tasks with a higher priority run first in each loop; equal ones keep their order.
+    /// A task added during the session with a higher priority than those left in the
+    /// loop runs next, once the current run finishes.
+    #[serde(default)]
+    pub priority: i64,
+}
+
+impl TaskSpec {
+    pub fn new(prompt: impl Into<String>) -> Self {
+        Self {
+            prompt: prompt.into(),
+            loop_prompts: Vec::new(),
+            name: None,
+            agent: None,
+            codex_bin: None,
+            model: None,
+            env: BTreeMap::new(),
+            env_allowlist: None,
+            min_successes: None,
+            tags: Vec::new(),
+            priority: 0,
+        }
+    }
+
+    /// The task as run in loop `loop_idx` (from 0): its prompt is the loop's entry of
+    /// `loop_prompts`, if any.
+    pub fn for_loop(&self, loop_idx: usize) -> TaskSpec {
+        let mut task = self.clone();
+        if let Some(prompt) = self.loop_prompts.get(loop_idx).or(self.loop_prompts.last()) {
+            task.prompt = prompt.clone();
+        }
+        task
+    }
+
+    /// Replace the prompt the task sends in loop `loop_idx`: its entry of `loop_prompts`
+    /// (the last one for later loops), or else `prompt`.
+    pub fn set_loop_prompt(&mut self, loop_idx: usize, prompt: String) {
+        match self.loop_prompts.len() {
+            0 => self.prompt = prompt,
+            len => self.loop_prompts[loop_idx.min(len - 1)] = prompt,
+        }
+    }
+}
+
+/// A `--only`/`--skip` filter: `tag=<tag>`, `name=<name>` or a task number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TaskFilter {
+    Tag(String),
+    Name(String),
+    /// Position in the task list, from 1.
+    Number(usize),
+}
+
+impl TaskFilter {
+    /// Whether the filter picks `task`, the `number`th of the task list.
+    pub fn matches(&self, number: usize, task: &TaskSpec) -> bool {
+        match self {
+            Self::Tag(tag) => task.tags.iter().any(|t| t == tag),
+            Self::Name(name) => task.name.as_deref() == Some(name.as_str()),
+            Self::Number(n) => *n == number,
+        }
+    }
+}
+
+impl fmt::Display for TaskFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tag(tag) => write!(f, "tag={tag}"),
+            Self::Name(name) => write!(f, "name={name}"),
+            Self::Number(number) => write!(f, "{number}"),
+        }
+    }
+}
+
+impl FromStr for TaskFilter {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.split_once('=') {
+            Some((_, "")) => Err(format!("`{text}` has an empty value")),
+            Some(("tag", tag)) => Ok(Self::Tag(tag.to_string())),
+            Some(("name", name)) => Ok(Self::Name(name.to_string())),
+            None if let Ok(number @ 1..) = text.parse::<usize>() => Ok(Self::Number(number)),
+            _ => Err(format!(
+                "expected tag=<tag>, name=<name> or a task number, got `{text}`"
+            )),
+        }
+    }
+}
+
+/// The tasks that match any of `only` (all when it is empty) and none of `skip`, in order.
+pub fn filter_tasks(
+    tasks: Vec<TaskSpec>,
+    only: &[TaskFilter],
+    skip: &[TaskFilter],
+) -> Vec<TaskSpec> {
+    tasks
+        .into_iter()
+        .zip(1..)
+        .filter(|(task, n)| only.is_empty() || only.iter().any(|f| f.matches(*n, task)))
+        .filter(|(task, n)| !skip.iter().any(|f| f.matches(*n, task)))
+        .map(|(task, _)| task)
+        .collect()
+}
+
+/// What counts as a repeated prompt for `--dedupe-prompts`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DedupeMode {
+    /// The same text.
+    Exact,
+    /// The same words, however they are spaced or broken into lines.
+    Whitespace,
+}
+
+impl DedupeMode {
+    fn normalize(self, prompt: &str) -> String {
+        match self {
+            Self::Exact => prompt.to_string(),
+            Self::Whitespace => prompt.split_whitespace().collect::<Vec<_>>().join(" "),
+        }
+    }
+}
+
+impl FromStr for DedupeMode {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "exact" => Ok(Self::Exact),
+            "whitespace" => Ok(Self::Whitespace),
+            _ => Err(format!("expected `exact` or `whitespace`, got `{text}`")),
+        }
+    }
+}
+
+/// `tasks` without the ones repeating an earlier task: the same prompt under `mode` and
+/// otherwise identical, so a prompt deliberately given to two models stays. Returns the
+/// tasks kept and, for each one dropped, its number and the number of the task it
+/// repeats, both from 1.
+pub fn dedupe_tasks(
+    tasks: Vec<TaskSpec>,
+    mode: DedupeMode,
+) -> (Vec<TaskSpec>, Vec<(usize, usize)>) {
+    let mut kept: Vec<(usize, TaskSpec)> = Vec::new();
+    let mut dropped = Vec::new();
+    let key = |task: &TaskSpec| TaskSpec {
+        prompt: mode.normalize(&task.prompt),
+        ..task.clone()
+    };
+    for (number, task) in (1..).zip(tasks) {
+        let normalized = key(&task);
+        match kept.iter().find(|(_, earlier)| key(earlier) == normalized) {
+            Some((first, _)) => dropped.push((number, *first)),
+            None => kept.push((number, task)),
+        }
+    }
+    (kept.into_iter().map(|(_, task)| task).collect(), dropped)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TaskFile {
+    #[serde(default)]
+    task: Vec<TaskSpec>,
+}
+
+/// Prompts from plain text: one per non-empty line, trimmed.
+pub fn parse_prompts(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+/// Read a UTF-8 prompts file, one prompt per non-empty line.
+pub fn load_prompts_file(path: &Path) -> io::Result<Vec<String>> {
+    Ok(parse_prompts(&fs::read_to_string(path)?))
+}
+
+/// Tasks from a TOML task file's contents.
+pub fn parse_task_file(content: &str) -> io::Result<Vec<TaskSpec>> {
+    let mut file: TaskFile = toml::from_str(content)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    for task in &mut file.task {
+        if task.prompt.is_empty()
+            && let Some(first) = task.loop_prompts.first()
+        {
+            task.prompt = first.clone();
+        }
+    }
+    if let Some(idx) = file.task.iter().position(|t| {
+        t.prompt.trim().is_empty() || t.loop_prompts.iter().any(|p| p.trim().is_empty())
+    }) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("task {} has an empty prompt", idx + 1),
+        ));
+    }
+    Ok(file.task)
+}
+
+/// Line prefix an agent prints to add a task to the session, e.g.
+/// `AGENT_LOOPS_ADD_TASK: Add tests for the parser`.
+pub const ADD_TASK_MARKER: &str = "AGENT_LOOPS_ADD_TASK:";
+
+/// Info string of a fenced block holding tasks to add as a JSON array of prompts or task
+/// objects, e.g. `["Add tests", {"prompt": "Bump the version", "name": "release"}]`.
+/// Task objects may only set `prompt`, `name` and `priority`.
+pub const ADD_TASKS_FENCE: &str = "agent-loops-tasks";
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AddedTask {
+    Prompt(String),
+    Task(AddedTaskSpec),
+}
+
+/// The fields of a task an agent may set: a run's output must not choose the binary,
+/// model or environment of the runs it adds.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AddedTaskSpec {
+    prompt: String,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    priority: i64,
+}
+
+/// Tasks a run's output asks to add to the session: one per [`ADD_TASK_MARKER`] line
+/// and per entry of each [`ADD_TASKS_FENCE`] block, in order. Blocks that are not valid
+/// JSON are skipped with a warning.
+pub fn parse_added_tasks(output: &str) -> Vec<TaskSpec> {
+    let mut tasks = Vec::new();
+    let mut block: Option<String> = None;
+    for line in output.lines() {
+        let line = ansi_escape_pattern().replace_all(line, "");
+        let line = line.trim();
+        if let Some(json) = &mut block {
+            if line.starts_with("```") {
+                match serde_json::from_str::<Vec<AddedTask>>(json) {
+                    Ok(added) => tasks.extend(added.into_iter().map(|task| match task {
+                        AddedTask::Prompt(prompt) => TaskSpec::new(prompt),
+                        AddedTask::Task(task) => TaskSpec {
+                            name: task.name,
+                            priority: task.priority,
+                            ..TaskSpec::new(task.prompt)
+                        },
+                    })),
+                    Err(e) => tracing::warn!("Ignoring an invalid {ADD_TASKS_FENCE} block: {e}"),
+                }
+                block = None;
+            } else {
+                json.push_str(line);
+                json.push('\n');
+            }
+        } else if line.strip_prefix("```").map(str::trim) == Some(ADD_TASKS_FENCE) {
+            block = Some(String::new());
+        } else if let Some(prompt) = line.strip_prefix(ADD_TASK_MARKER) {
+            tasks.push(TaskSpec::new(prompt.trim()));
+        }
+    }
+    tasks.retain(|task| !task.prompt.trim().is_empty());
+    tasks
+}
+
+/// Directive of a plain-text prompts file pulling in another file's tasks.
+const INCLUDE_DIRECTIVE: &str = "#include";
+
+/// Load tasks from `path`: a TOML task file when it ends in `.toml`, otherwise a plain
+/// prompts file, with its `#include` lines expanded.
+pub fn load_tasks(path: &Path) -> io::Result<Vec<TaskSpec>> {
+    load_tasks_included(path, &mut Vec::new())
+}
+
+/// [`load_tasks`] for a file included through the files in `including`, outermost
+/// first.
+fn load_tasks_included(path: &Path, including: &mut Vec<PathBuf>) -> io::Result<Vec<TaskSpec>> {
+    let canonical = fs::canonicalize(path)?;
+    if let Some(at) = including.iter().position(|file| *file == canonical) {
+        let chain: Vec<String> = including[at..]
+            .iter()
+            .chain([&canonical])
+            .map(|file| file.display().to_string())
+            .collect();
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("include cycle: {}", chain.join(" -> ")),
+        ));
+    }
+    let content = fs::read_to_string(path)?;
+    if path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("toml"))
+    {
+        return parse_task_file(&content);
+    }
+    including.push(canonical);
+    let mut tasks = Vec::new();
+    for line in parse_prompts(&content) {
+        let included = line
+            .strip_prefix(INCLUDE_DIRECTIVE)
+            .filter(|rest| rest.starts_with(char::is_whitespace));
+        match included {
+            Some(file) => {
+                let file = path.parent().unwrap_or(Path::new("")).join(file.trim());
+                let included = load_tasks_included(&file, including)
+                    .map_err(|e| io::Error::new(e.kind(), format!("`{}`: {e}", file.display())))?;
+                tasks.extend(included);
+            }
+            None => tasks.push(TaskSpec::new(line)),
+        }
+    }
+    including.pop();
+    Ok(tasks)
+}
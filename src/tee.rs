@@ -0,0 +1,84 @@
+//! Copy of the session's output in a file (`--tee`), written line by line as it happens
+//! so it can be followed with `tail -f` while the pinned view owns the terminal.
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::filter::visible_text;
+
+/// Shared handle to the tee file; clones write to the same file.
+#[derive(Debug, Clone)]
+pub struct TeeFile {
+    path: PathBuf,
+    file: Arc<Mutex<File>>,
+}
+
+impl TeeFile {
+    /// Create `path`, replacing any previous content.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Arc::new(Mutex::new(File::create(path)?)),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append `line` as a terminal would show it, without escape sequences or the text
+    /// a carriage return writes over. Write errors are ignored: losing the copy must not
+    /// end the run.
+    pub fn write_line(&self, line: &str) {
+        let line = visible_text(line.as_bytes());
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = writeln!(file, "{line}");
+    }
+}
+
+/// Print `line` to stdout and copy it to `tee`.
+pub(crate) fn println_tee(tee: Option<&TeeFile>, line: &str) {
+    println!("{line}");
+    if let Some(tee) = tee {
+        tee.write_line(line);
+    }
+}
+
+/// Assembles one raw output stream into lines for [`TeeFile::write_line`].
+pub(crate) struct StreamTee<'a> {
+    tee: Option<&'a TeeFile>,
+    pending: Vec<u8>,
+}
+
+impl<'a> StreamTee<'a> {
+    pub(crate) fn new(tee: Option<&'a TeeFile>) -> Self {
+        Self {
+            tee,
+            pending: Vec::new(),
+        }
+    }
+
+    pub(crate) fn push(&mut self, chunk: &[u8]) {
+        let Some(tee) = self.tee else {
+            return;
+        };
+        self.pending.extend_from_slice(chunk);
+        if let Some(idx) = self.pending.iter().rposition(|&b| b == b'\n') {
+            for line in self.pending[..idx].split(|&b| b == b'\n') {
+                tee.write_line(&String::from_utf8_lossy(line));
+            }
+            self.pending.drain(..=idx);
+        }
+    }
+
+    /// Write out a final line that never got its newline.
+    pub(crate) fn finish(&mut self) {
+        if let Some(tee) = self.tee
+            && !self.pending.is_empty()
+        {
+            tee.write_line(&String::from_utf8_lossy(&std::mem::take(&mut self.pending)));
+        }
+    }
+}
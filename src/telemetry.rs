@@ -0,0 +1,63 @@
+//! Exporting the session's tracing spans to an OpenTelemetry collector over OTLP/HTTP.
+//!
+//! Sessions, loops, runs and agent processes are recorded as nested spans, so they show
+//! up in the same traces as the CI jobs they trigger. Standard `OTEL_EXPORTER_OTLP_*`
+//! variables such as `OTEL_EXPORTER_OTLP_HEADERS` are honoured.
+
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::trace::{SdkTracerProvider, Tracer};
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::Registry;
+
+/// Service name reported with every span.
+const SERVICE_NAME: &str = "agent-loops";
+
+/// Path of the trace endpoint below a collector's base URL.
+const TRACES_PATH: &str = "/v1/traces";
+
+/// Trace endpoint for a collector base URL such as `http://localhost:4318`. A URL that
+/// already names the trace endpoint is kept.
+pub fn traces_endpoint(base: &str) -> String {
+    let base = base.trim_end_matches('/');
+    if base.ends_with(TRACES_PATH) {
+        base.to_string()
+    } else {
+        format!("{base}{TRACES_PATH}")
+    }
+}
+
+/// Keeps the exporter alive; dropping it flushes the remaining spans.
+pub struct OtlpGuard {
+    provider: SdkTracerProvider,
+}
+
+impl Drop for OtlpGuard {
+    fn drop(&mut self) {
+        if let Err(e) = self.provider.shutdown() {
+            tracing::warn!("Failed to flush trace spans: {e}");
+        }
+    }
+}
+
+/// Layer exporting spans to the collector at `endpoint`, to be installed directly on the
+/// [`Registry`].
+///
+/// The exporter uses a blocking HTTP client, so call this outside the async runtime,
+/// e.g. from `tokio::task::spawn_blocking`.
+pub fn otlp_layer(
+    endpoint: &str,
+) -> Result<(OpenTelemetryLayer<Registry, Tracer>, OtlpGuard), String> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(traces_endpoint(endpoint))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(SERVICE_NAME).build())
+        .build();
+    let layer = tracing_opentelemetry::layer().with_tracer(provider.tracer(SERVICE_NAME));
+    Ok((layer, OtlpGuard { provider }))
+}
@@ -0,0 +1,255 @@
+//! Test doubles for driving the orchestrator without a real agent binary.
+//!
+//! [`MockRunner`] plays back a script of [`MockStep`]s, one per run, and records every
+//! call so tests can assert on what the orchestrator sent and in which order.
+//! [`FixtureRunner`] plays back runs recorded with [`RunOptions::record_dir`], through
+//! the same output handling as a live agent.
+
+use std::collections::BTreeSet;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use crate::fixture::{Fixture, load_fixtures};
+use crate::scan::{Usage, is_rate_limit_message};
+use crate::{AgentLoopsError, RunOptions, RunOutcome, Runner, TaskSpec, replay_fixture};
+
+/// What a scripted run does.
+enum StepResult {
+    Success,
+    Failure,
+    Error(Box<dyn Fn() -> AgentLoopsError + Send + Sync>),
+}
+
+/// One scripted run: output printed to stdout, an optional delay, then a result.
+/// The output is scanned like the real runner's: rate-limit messages and reported usage
+/// (e.g. `Cost: $0.10`) end up in the outcome.
+pub struct MockStep {
+    output: String,
+    delay: Duration,
+    result: StepResult,
+}
+
+impl MockStep {
+    /// A run that prints `output` and exits with status zero.
+    pub fn success(output: impl Into<String>) -> Self {
+        Self::with_result(output.into(), StepResult::Success)
+    }
+
+    /// A run that prints `output` and exits with a non-zero status.
+    pub fn failure(output: impl Into<String>) -> Self {
+        Self::with_result(output.into(), StepResult::Failure)
+    }
+
+    /// A run that fails to complete with the error built by `make`.
+    pub fn error(make: impl Fn() -> AgentLoopsError + Send + Sync + 'static) -> Self {
+        Self::with_result(String::new(), StepResult::Error(Box::new(make)))
+    }
+
+    /// Wait `delay` before the run completes.
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    fn with_result(output: String, result: StepResult) -> Self {
+        Self {
+            output,
+            delay: Duration::ZERO,
+            result,
+        }
+    }
+}
+
+/// A run observed by [`MockRunner`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockCall {
+    /// 1-based position of the call.
+    pub number: usize,
+    pub prompt: String,
+    /// `Ok(success)` for runs that completed, `Err(message)` for runner errors.
+    pub result: Result<bool, String>,
+}
+
+/// Scripted [`Runner`]: steps are used in order, then the fallback step repeats.
+/// Without any configuration every run succeeds silently.
+pub struct MockRunner {
+    steps: Vec<MockStep>,
+    fallback: MockStep,
+    fail_on: BTreeSet<usize>,
+    calls: Mutex<Vec<MockCall>>,
+}
+
+impl Default for MockRunner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockRunner {
+    pub fn new() -> Self {
+        Self {
+            steps: Vec::new(),
+            fallback: MockStep::success(""),
+            fail_on: BTreeSet::new(),
+            calls: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Append a step to the script.
+    pub fn step(mut self, step: MockStep) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    /// Step used once the script is exhausted.
+    pub fn fallback(mut self, step: MockStep) -> Self {
+        self.fallback = step;
+        self
+    }
+
+    /// Force the given 1-based call numbers to fail, whatever their step says.
+    pub fn fail_on(mut self, calls: impl IntoIterator<Item = usize>) -> Self {
+        self.fail_on.extend(calls);
+        self
+    }
+
+    /// Every call made so far, in order.
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Prompts received so far, in order.
+    pub fn prompts(&self) -> Vec<String> {
+        self.calls().into_iter().map(|call| call.prompt).collect()
+    }
+
+    pub fn call_count(&self) -> usize {
+        self.calls.lock().unwrap().len()
+    }
+
+    /// Panic unless exactly `expected` prompts were received, in this order.
+    #[track_caller]
+    pub fn assert_prompts(&self, expected: &[&str]) {
+        let actual = self.prompts();
+        assert_eq!(actual, expected, "MockRunner received unexpected prompts");
+    }
+
+    /// Panic unless the calls completed with exactly these success flags, in order.
+    /// Runner errors count as `false`.
+    #[track_caller]
+    pub fn assert_results(&self, expected: &[bool]) {
+        let actual: Vec<bool> = self
+            .calls()
+            .iter()
+            .map(|call| call.result == Ok(true))
+            .collect();
+        assert_eq!(actual, expected, "MockRunner calls had unexpected results");
+    }
+}
+
+impl Runner for MockRunner {
+    async fn run(&self, task: &TaskSpec) -> Result<RunOutcome, AgentLoopsError> {
+        let number = {
+            let mut calls = self.calls.lock().unwrap();
+            let number = calls.len() + 1;
+            calls.push(MockCall {
+                number,
+                prompt: task.prompt.clone(),
+                result: Ok(false),
+            });
+            number
+        };
+        let step = self.steps.get(number - 1).unwrap_or(&self.fallback);
+
+        if !step.delay.is_zero() {
+            tokio::time::sleep(step.delay).await;
+        }
+        if !step.output.is_empty() {
+            println!("{}", step.output);
+        }
+        let result = match &step.result {
+            _ if self.fail_on.contains(&number) => Ok(false),
+            StepResult::Success => Ok(true),
+            StepResult::Failure => Ok(false),
+            StepResult::Error(make) => Err(make()),
+        };
+
+        self.calls.lock().unwrap()[number - 1].result = match &result {
+            Ok(success) => Ok(*success),
+            Err(e) => Err(e.to_string()),
+        };
+        let usage = step
+            .output
+            .lines()
+            .map(Usage::parse_line)
+            .fold(Usage::default(), Usage::update);
+        result.map(|success| RunOutcome {
+            success,
+            rate_limited: step.output.lines().any(is_rate_limit_message),
+            usage,
+            cost_usd: usage.cost(None),
+            ..RunOutcome::default()
+        })
+    }
+}
+
+/// [`Runner`] replaying recorded fixtures, one per run in order, as if the agent had
+/// written their output and exited with their status. Runs past the last fixture fail
+/// with [`AgentLoopsError::ChildIo`].
+pub struct FixtureRunner {
+    fixtures: Vec<Fixture>,
+    options: RunOptions,
+    paced: bool,
+    next: AtomicUsize,
+}
+
+impl FixtureRunner {
+    /// Output is rendered and scanned according to `options`; nothing is launched.
+    pub fn new(fixtures: Vec<Fixture>, options: RunOptions) -> Self {
+        Self {
+            fixtures,
+            options,
+            paced: false,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Replay the fixtures recorded in `dir`.
+    pub fn load(dir: &Path, options: RunOptions) -> io::Result<Self> {
+        Ok(Self::new(load_fixtures(dir)?, options))
+    }
+
+    /// Pause between chunks as long as the recorded agent did.
+    pub fn paced(mut self) -> Self {
+        self.paced = true;
+        self
+    }
+
+    /// Runs replayed so far, including any that found no fixture left.
+    pub fn runs(&self) -> usize {
+        self.next.load(Ordering::Relaxed)
+    }
+}
+
+impl Runner for FixtureRunner {
+    async fn run(&self, task: &TaskSpec) -> Result<RunOutcome, AgentLoopsError> {
+        let index = self.next.fetch_add(1, Ordering::Relaxed);
+        let fixture = self.fixtures.get(index).ok_or_else(|| {
+            AgentLoopsError::ChildIo(io::Error::other(format!(
+                "no fixture left for run {} ({} recorded)",
+                index + 1,
+                self.fixtures.len()
+            )))
+        })?;
+        replay_fixture(
+            fixture,
+            &task.prompt,
+            &self.options.for_task(task),
+            self.paced,
+        )
+        .await
+    }
+}
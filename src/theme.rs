@@ -0,0 +1,252 @@
+//! Colors of the session's own output (`--color`, `--theme`): the headers of the plan and
+//! the pinned view, OK and FAILED in run footers, the view's status row and the stderr
+//! gutter. Agent output keeps whatever colors the agent chose.
+
+use std::fmt;
+use std::io::{self, IsTerminal};
+use std::sync::OnceLock;
+
+/// Style words and their SGR codes; the `bool` marks colors, which `NO_COLOR` drops.
+const WORDS: &[(&str, u8, bool)] = &[
+    ("bold", 1, false),
+    ("dim", 2, false),
+    ("italic", 3, false),
+    ("underline", 4, false),
+    ("reverse", 7, false),
+    ("black", 30, true),
+    ("red", 31, true),
+    ("green", 32, true),
+    ("yellow", 33, true),
+    ("blue", 34, true),
+    ("magenta", 35, true),
+    ("cyan", 36, true),
+    ("white", 37, true),
+    ("bright-black", 90, true),
+    ("bright-red", 91, true),
+    ("bright-green", 92, true),
+    ("bright-yellow", 93, true),
+    ("bright-blue", 94, true),
+    ("bright-magenta", 95, true),
+    ("bright-cyan", 96, true),
+    ("bright-white", 97, true),
+];
+
+/// Attributes and colors for one kind of text, written as words such as `bold cyan`;
+/// `none` leaves the text as it is.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Style {
+    codes: Vec<u8>,
+}
+
+impl Style {
+    /// `text` wrapped in this style's escape sequences.
+    pub fn paint(&self, text: &str) -> String {
+        if self.codes.is_empty() || text.is_empty() {
+            return text.to_string();
+        }
+        let codes: Vec<String> = self.codes.iter().map(u8::to_string).collect();
+        format!("\x1b[{}m{text}\x1b[0m", codes.join(";"))
+    }
+
+    /// The style with its colors removed and its attributes kept.
+    fn without_colors(&self) -> Self {
+        Self {
+            codes: self
+                .codes
+                .iter()
+                .copied()
+                .filter(|&code| !WORDS.iter().any(|&(_, c, color)| c == code && color))
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for Style {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.codes.is_empty() {
+            return f.write_str("none");
+        }
+        let words: Vec<&str> = self
+            .codes
+            .iter()
+            .filter_map(|&code| WORDS.iter().find(|&&(_, c, _)| c == code))
+            .map(|&(word, _, _)| word)
+            .collect();
+        f.write_str(&words.join(" "))
+    }
+}
+
+impl std::str::FromStr for Style {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut codes = Vec::new();
+        for word in text.split([' ', '+']).filter(|word| !word.is_empty()) {
+            let word = word.to_ascii_lowercase();
+            if word == "none" {
+                continue;
+            }
+            match WORDS.iter().find(|&&(w, _, _)| w == word) {
+                Some(&(_, code, _)) => codes.push(code),
+                None => {
+                    return Err(format!(
+                        "unknown style `{word}`, expected none, bold, dim, italic, underline, reverse or a color such as cyan or bright-red"
+                    ));
+                }
+            }
+        }
+        Ok(Self { codes })
+    }
+}
+
+/// What each kind of the session's text looks like.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Theme {
+    /// Plan and pinned view headers, and the closing banner.
+    pub header: Style,
+    /// `OK` in run footers and summaries.
+    pub ok: Style,
+    /// `FAILED` in run footers and summaries.
+    pub failed: Style,
+    /// The pinned view's status row while scrolled back or searching.
+    pub status: Style,
+    /// The bar marking stderr lines in the pinned view.
+    pub stderr: Style,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        let style = |words: &str| words.parse().expect("default styles are valid");
+        Self {
+            header: style("bold cyan"),
+            ok: style("bold green"),
+            failed: style("bold red"),
+            status: style("reverse"),
+            stderr: style("dim red"),
+        }
+    }
+}
+
+impl Theme {
+    /// No styling at all, as output to files and pipes gets.
+    pub fn plain() -> Self {
+        Self {
+            header: Style::default(),
+            ok: Style::default(),
+            failed: Style::default(),
+            status: Style::default(),
+            stderr: Style::default(),
+        }
+    }
+
+    /// The theme with colors removed and attributes such as bold and reverse kept, for
+    /// `NO_COLOR` and `--color never`.
+    pub fn without_colors(&self) -> Self {
+        Self {
+            header: self.header.without_colors(),
+            ok: self.ok.without_colors(),
+            failed: self.failed.without_colors(),
+            status: self.status.without_colors(),
+            stderr: self.stderr.without_colors(),
+        }
+    }
+
+    /// `OK` or `FAILED` in the matching style.
+    pub fn outcome(&self, success: bool) -> String {
+        if success {
+            self.ok.paint("OK")
+        } else {
+            self.failed.paint("FAILED")
+        }
+    }
+}
+
+/// Overrides of the default theme, e.g. `header=bold blue,failed=bright-red`.
+impl std::str::FromStr for Theme {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut theme = Self::default();
+        for entry in text.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let Some((role, style)) = entry.split_once('=') else {
+                return Err(format!("expected ROLE=STYLE, got `{entry}`"));
+            };
+            let style = style.trim().parse()?;
+            match role.trim().to_ascii_lowercase().as_str() {
+                "header" => theme.header = style,
+                "ok" => theme.ok = style,
+                "failed" => theme.failed = style,
+                "status" => theme.status = style,
+                "stderr" => theme.stderr = style,
+                other => {
+                    return Err(format!(
+                        "unknown theme role `{other}`, expected header, ok, failed, status or stderr"
+                    ));
+                }
+            }
+        }
+        Ok(theme)
+    }
+}
+
+/// When to color the session's output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// On a terminal, unless `NO_COLOR` is set.
+    #[default]
+    Auto,
+    Always,
+    /// Attributes such as bold and reverse only.
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether colors are used, given whether `NO_COLOR` is set to something.
+    pub fn use_colors(self, no_color: bool) -> bool {
+        match self {
+            Self::Auto => !no_color,
+            Self::Always => true,
+            Self::Never => false,
+        }
+    }
+}
+
+impl std::str::FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.to_ascii_lowercase().as_str() {
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            _ => Err(format!(
+                "unknown color choice `{text}`, expected auto, always or never"
+            )),
+        }
+    }
+}
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+/// Use `theme` for the session's output from now on, as far as `choice` and `NO_COLOR`
+/// allow. Output that is not a terminal stays plain unless `choice` is
+/// [`ColorChoice::Always`]. Only the first call has an effect.
+pub fn set_theme(theme: Theme, choice: ColorChoice) {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    let theme = if choice == ColorChoice::Auto && !io::stdout().is_terminal() {
+        Theme::plain()
+    } else if choice.use_colors(no_color) {
+        theme
+    } else {
+        theme.without_colors()
+    };
+    let _ = THEME.set(theme);
+}
+
+/// The session's theme; plain until [`set_theme`] is called.
+pub fn theme() -> &'static Theme {
+    static PLAIN: OnceLock<Theme> = OnceLock::new();
+    THEME
+        .get()
+        .unwrap_or_else(|| PLAIN.get_or_init(Theme::plain))
+}
@@ -0,0 +1,48 @@
+//! Spreading runs out over time to stay under API plan quotas.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Sliding-window limit on how many runs may start within `window`.
+#[derive(Debug, Clone)]
+pub struct RunThrottle {
+    max_runs: usize,
+    window: Duration,
+    starts: VecDeque<Instant>,
+}
+
+impl RunThrottle {
+    pub fn new(max_runs: usize, window: Duration) -> Self {
+        Self {
+            max_runs: max_runs.max(1),
+            window,
+            starts: VecDeque::new(),
+        }
+    }
+
+    /// At most `max_runs` starts in any rolling hour.
+    pub fn per_hour(max_runs: usize) -> Self {
+        Self::new(max_runs, Duration::from_secs(60 * 60))
+    }
+
+    /// How long to wait at `now` before another run may start, if at all.
+    pub fn delay_at(&mut self, now: Instant) -> Option<Duration> {
+        while self
+            .starts
+            .front()
+            .is_some_and(|start| now.duration_since(*start) >= self.window)
+        {
+            self.starts.pop_front();
+        }
+        if self.starts.len() < self.max_runs {
+            return None;
+        }
+        let oldest = self.starts[self.starts.len() - self.max_runs];
+        Some(self.window - now.duration_since(oldest))
+    }
+
+    /// Note that a run started at `now`.
+    pub fn record_start(&mut self, now: Instant) {
+        self.starts.push_back(now);
+    }
+}
@@ -0,0 +1,46 @@
+//! Elapsed-time prefixes on output lines (`--timestamps`).
+
+use std::time::{Duration, Instant};
+
+/// `[HH:MM:SS] `, the prefix for a line that appeared `elapsed` into the run.
+pub(crate) fn line_prefix(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    format!(
+        "[{:02}:{:02}:{:02}] ",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Prefixes each line of one output stream with the time since `started`, taken when
+/// the line's first byte arrives.
+pub(crate) struct StreamStamper {
+    started: Option<Instant>,
+    at_line_start: bool,
+}
+
+impl StreamStamper {
+    /// Stamps lines when `started` is set; passes output through untouched otherwise.
+    pub(crate) fn new(started: Option<Instant>) -> Self {
+        Self {
+            started,
+            at_line_start: true,
+        }
+    }
+
+    pub(crate) fn push(&mut self, chunk: &[u8]) -> Vec<u8> {
+        let Some(started) = self.started else {
+            return chunk.to_vec();
+        };
+        let mut out = Vec::with_capacity(chunk.len());
+        for &b in chunk {
+            if self.at_line_start {
+                out.extend_from_slice(line_prefix(started.elapsed()).as_bytes());
+            }
+            out.push(b);
+            self.at_line_start = b == b'\n';
+        }
+        out
+    }
+}
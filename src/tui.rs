@@ -0,0 +1,843 @@
+//! The pinned live view: a fixed task header above a scrolling window of output.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+use crate::attach::{AttachCommand, POLL_INTERVAL, SessionControl, SessionLog, run_command};
+use crate::keys::{Key, KeyReader};
+use crate::progress::{
+    RunProgress, SPINNER_FRAME_TIME, format_clock, heartbeat_line, spinner_frame,
+};
+use crate::replay::{ReplayLine, timing_path};
+use crate::search::{self, Direction, line_matches};
+use crate::sessions::SessionStatus;
+use crate::theme::theme;
+use crate::timestamps::line_prefix;
+use crate::{
+    CancelToken, LineFilter, OutputStream, PinnedView, RunOptions, StreamPipeline, TeeFile,
+    fit_terminal_line, run_cut_short, terminal_cols, terminal_rows, wrap_terminal_line,
+};
+
+/// Keep a bounded amount of task output in memory while redrawing.
+const MAX_RENDERED_OUTPUT_LINES: usize = 4000;
+/// Coalesce redraws of the pinned view to at most one per interval.
+const RENDER_DEBOUNCE: Duration = Duration::from_millis(40);
+/// Marks rows of stderr output: a bar in the theme's stderr style and a space.
+const STDERR_GUTTER: &str = "\u{2502} ";
+const STDERR_GUTTER_WIDTH: usize = 2;
+/// Lines scrolled per mouse wheel notch.
+const WHEEL_LINES: i64 = 3;
+const ENTER_ALT_SCREEN: &str = "\x1b[?1049h";
+const LEAVE_ALT_SCREEN: &str = "\x1b[?1049l";
+
+/// Whether the pinned view is on the alternate screen.
+static ON_ALT_SCREEN: AtomicBool = AtomicBool::new(false);
+
+/// Go back to the primary screen and show the cursor, if the pinned view left them
+/// otherwise.
+pub(crate) fn restore_screen() {
+    if ON_ALT_SCREEN.swap(false, Ordering::SeqCst) {
+        let mut out = io::stdout();
+        let _ = write!(out, "{LEAVE_ALT_SCREEN}\x1b[?25h");
+        let _ = out.flush();
+    }
+}
+
+/// Drain child output into the pinned view until the child closes it.
+pub(crate) async fn forward_pinned(
+    mut rx: mpsc::UnboundedReceiver<(OutputStream, Vec<u8>)>,
+    pinned: PinnedView,
+    stdout_pipeline: &mut StreamPipeline<'_>,
+    stderr_pipeline: &mut StreamPipeline<'_>,
+    options: &RunOptions,
+) -> io::Result<()> {
+    let spool = match pinned.spool_path.as_deref() {
+        Some(path) => Some(Spool {
+            path: path.to_path_buf(),
+            log: open_spool_file(path)?,
+            timing: open_spool_file(&timing_path(path))?,
+            started: Instant::now(),
+        }),
+        None => None,
+    };
+    let settings = ViewSettings {
+        filter: options.line_filter.clone(),
+        wrap_lines: options.wrap_lines,
+        timestamps: options.timestamps,
+        progress: pinned.progress,
+        heartbeat: options.heartbeat,
+        spinner: true,
+    };
+    let mut renderer =
+        PinnedOutputRenderer::new(pinned.header_lines, spool, settings, options.tee.clone())?;
+    // Keeps the spinner, the status line's clock and the heartbeat moving while the agent
+    // is quiet.
+    let mut status_tick = tokio::time::interval(SPINNER_FRAME_TIME);
+    status_tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    let mut keys = KeyReader::start(options.mouse);
+    loop {
+        let deadline = renderer.pending_render_deadline();
+        tokio::select! {
+            Some(key) = next_key(&mut keys) => {
+                renderer.handle_key(key)?;
+            }
+            received = rx.recv() => {
+                let Some((stream, chunk)) = received else {
+                    break;
+                };
+                let chunk = match stream {
+                    OutputStream::Stdout => stdout_pipeline.push(&chunk),
+                    OutputStream::Stderr => stderr_pipeline.push(&chunk),
+                };
+                renderer.push_chunk(stream, &chunk)?;
+                if run_cut_short(stdout_pipeline, stderr_pipeline) {
+                    break;
+                }
+            }
+            _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                renderer.render()?;
+            }
+            _ = status_tick.tick() => {
+                renderer.render()?;
+            }
+        }
+    }
+    renderer.push_chunk(OutputStream::Stdout, &stdout_pipeline.finish())?;
+    renderer.push_chunk(OutputStream::Stderr, &stderr_pipeline.finish())?;
+    renderer.finish()?;
+    Ok(())
+}
+
+/// The next key from `keys`; never resolves once there are no more.
+async fn next_key(keys: &mut Option<KeyReader>) -> Option<Key> {
+    if let Some(reader) = keys {
+        if let Some(key) = reader.next().await {
+            return Some(key);
+        }
+        *keys = None;
+    }
+    std::future::pending().await
+}
+
+/// Feed recorded lines through the pinned view, pausing `delays[i]` before line `i`.
+pub(crate) async fn replay_pinned(
+    header_lines: Vec<String>,
+    lines: &[ReplayLine],
+    delays: &[Duration],
+    wrap_lines: bool,
+    cancel: &CancelToken,
+) -> io::Result<()> {
+    let settings = ViewSettings {
+        wrap_lines,
+        ..ViewSettings::default()
+    };
+    let mut renderer = PinnedOutputRenderer::new(header_lines, None, settings, None)?;
+    for (line, delay) in lines.iter().zip(delays) {
+        if !delay.is_zero() {
+            renderer.render()?;
+            tokio::select! {
+                _ = tokio::time::sleep(*delay) => {}
+                _ = cancel.cancelled() => break,
+            }
+        }
+        renderer.push_chunk(OutputStream::Stdout, line.text.as_bytes())?;
+        renderer.push_chunk(OutputStream::Stdout, b"\n")?;
+    }
+    renderer.finish()
+}
+
+/// Follow a session's log in the pinned view under `header_lines`, as
+/// [`attach::follow`](crate::attach::follow) does on plain output. Keys the view does not
+/// use for scrolling and searching become [`AttachCommand`]s.
+pub(crate) async fn follow_pinned(
+    header_lines: Vec<String>,
+    log: &mut SessionLog<'_>,
+    control: &SessionControl,
+    cancel: &CancelToken,
+) -> io::Result<Option<SessionStatus>> {
+    let mut renderer =
+        PinnedOutputRenderer::new(header_lines, None, ViewSettings::default(), None)?;
+    let mut keys = KeyReader::start(false);
+    let mut poll = tokio::time::interval(POLL_INTERVAL);
+    let mut buf = Vec::new();
+    let ended = loop {
+        let deadline = renderer.pending_render_deadline();
+        tokio::select! {
+            _ = poll.tick() => {
+                let status = log.poll(&mut buf)?;
+                renderer.push_chunk(OutputStream::Stdout, &buf)?;
+                if status != SessionStatus::Running {
+                    break Some(status);
+                }
+            }
+            _ = cancel.cancelled() => break None,
+            Some(key) = next_key(&mut keys) => {
+                let command = match key {
+                    Key::Char(c) if !renderer.typing_search() => AttachCommand::for_key(c),
+                    _ => None,
+                };
+                let Some(command) = command else {
+                    renderer.handle_key(key)?;
+                    continue;
+                };
+                match run_command(control, command)? {
+                    Some(note) => renderer.push_chunk(OutputStream::Stdout, format!("{note}\n").as_bytes())?,
+                    None => break None,
+                }
+            }
+            _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                renderer.render()?;
+            }
+        }
+    };
+    renderer.finish()?;
+    Ok(ended)
+}
+
+/// Full output log of a run, with the time each line appeared for replay.
+struct Spool {
+    path: PathBuf,
+    log: BufWriter<File>,
+    /// Milliseconds since `started`, one line per output line.
+    timing: BufWriter<File>,
+    started: Instant,
+}
+
+fn open_spool_file(path: &Path) -> io::Result<BufWriter<File>> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    Ok(BufWriter::new(File::create(path)?))
+}
+
+/// How the view is drawn.
+#[derive(Default)]
+struct ViewSettings {
+    /// Lines it hides are spooled but never displayed.
+    filter: LineFilter,
+    /// Wrap long lines instead of truncating them.
+    wrap_lines: bool,
+    /// Prefix lines, displayed and spooled, with the time since the view opened.
+    timestamps: bool,
+    /// Session progress for the status line under the header.
+    progress: Option<RunProgress>,
+    /// Show a heartbeat line under the header once no output has come for this long.
+    heartbeat: Option<Duration>,
+    /// Show an activity spinner and the elapsed time under the header, for a live run.
+    spinner: bool,
+}
+
+/// A completed output line and the stream it was written to.
+struct OutputLine {
+    text: String,
+    stream: OutputStream,
+    /// Position among all completed lines, shown or not; also its line in the spool log.
+    number: u64,
+}
+
+/// A search typed into the view.
+struct Search {
+    query: String,
+    /// The query is still being typed.
+    editing: bool,
+    /// Number of the line of the current match.
+    hit: Option<u64>,
+    /// The last search found nothing more.
+    missed: bool,
+}
+
+#[derive(Clone, Copy)]
+enum AnsiParseState {
+    Normal,
+    Esc,
+    Csi,
+    Osc,
+    OscEsc,
+}
+
+struct PinnedOutputRenderer {
+    header_lines: Vec<String>,
+    output_lines: VecDeque<OutputLine>,
+    current_line: String,
+    /// Stream `current_line` came from.
+    current_stream: OutputStream,
+    ansi_state: AnsiParseState,
+    /// Receives every completed line, including those evicted from `output_lines`.
+    spool: Option<Spool>,
+    settings: ViewSettings,
+    /// Receives every displayed line.
+    tee: Option<TeeFile>,
+    started: Instant,
+    /// When the first character of `current_line` arrived, relative to `started`.
+    current_line_at: Option<Duration>,
+    /// When output last arrived, for the heartbeat.
+    last_output: Instant,
+    /// Rows as last written to the terminal, used to skip unchanged rows.
+    last_frame: Vec<String>,
+    last_render: Option<Instant>,
+    dirty: bool,
+    /// A `\r` was seen; it is a line rewind unless `\n` follows.
+    pending_cr: bool,
+    /// Completed lines so far; the next line's number.
+    lines_seen: u64,
+    /// Number of the bottom line when scrolled back; `None` follows the newest output.
+    scroll: Option<u64>,
+    search: Option<Search>,
+    /// Output rows in the last frame, for paging.
+    body_rows: usize,
+    /// Lines read back from the spool log for scrolling past `output_lines`: the
+    /// number of the first, and the lines.
+    spooled: Option<(u64, Vec<String>)>,
+}
+
+impl PinnedOutputRenderer {
+    fn new(
+        header_lines: Vec<String>,
+        spool: Option<Spool>,
+        settings: ViewSettings,
+        tee: Option<TeeFile>,
+    ) -> io::Result<Self> {
+        let mut renderer = Self {
+            header_lines,
+            output_lines: VecDeque::new(),
+            current_line: String::new(),
+            current_stream: OutputStream::Stdout,
+            ansi_state: AnsiParseState::Normal,
+            spool,
+            settings,
+            tee,
+            started: Instant::now(),
+            current_line_at: None,
+            last_output: Instant::now(),
+            last_frame: Vec::new(),
+            last_render: None,
+            dirty: true,
+            pending_cr: false,
+            lines_seen: 0,
+            scroll: None,
+            search: None,
+            body_rows: 0,
+            spooled: None,
+        };
+
+        let mut out = io::stdout();
+        // Draw on the alternate screen, so the user's scrollback is left as it was.
+        write!(out, "{ENTER_ALT_SCREEN}\x1b[?25l\x1b[2J\x1b[H")?;
+        out.flush()?;
+        ON_ALT_SCREEN.store(true, Ordering::SeqCst);
+
+        renderer.render()?;
+        Ok(renderer)
+    }
+
+    fn push_chunk(&mut self, stream: OutputStream, chunk: &[u8]) -> io::Result<()> {
+        if !chunk.is_empty() {
+            self.last_output = Instant::now();
+        }
+        // Lines from the two streams are kept apart: a partial line ends when the other
+        // stream writes.
+        if stream != self.current_stream && !chunk.is_empty() {
+            if !self.current_line.is_empty() {
+                self.push_current_line()?;
+            }
+            self.current_stream = stream;
+            self.ansi_state = AnsiParseState::Normal;
+            self.pending_cr = false;
+        }
+        let mut sanitized = Vec::with_capacity(chunk.len());
+        for &b in chunk {
+            self.consume_byte(b, &mut sanitized);
+        }
+        if sanitized.is_empty() {
+            return Ok(());
+        }
+
+        let text = String::from_utf8_lossy(&sanitized);
+        for ch in text.chars() {
+            match ch {
+                '\n' => {
+                    self.pending_cr = false;
+                    self.push_current_line()?;
+                }
+                '\r' => self.pending_cr = true,
+                _ => {
+                    // A bare carriage return rewinds the line (spinners, progress bars).
+                    if std::mem::take(&mut self.pending_cr) {
+                        self.current_line.clear();
+                    }
+                    if self.current_line.is_empty() {
+                        self.current_line_at = Some(self.started.elapsed());
+                    }
+                    self.current_line.push(ch);
+                }
+            }
+        }
+
+        self.dirty = true;
+        if self
+            .pending_render_deadline()
+            .is_some_and(|at| at <= Instant::now())
+        {
+            self.render()?;
+        }
+        Ok(())
+    }
+
+    /// When the next debounced redraw is due, if there is anything to redraw.
+    fn pending_render_deadline(&self) -> Option<Instant> {
+        if !self.dirty {
+            return None;
+        }
+        Some(
+            self.last_render
+                .map_or_else(Instant::now, |at| at + RENDER_DEBOUNCE),
+        )
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if !self.current_line.is_empty() {
+            self.push_current_line()?;
+        }
+        self.scroll = None;
+        self.search = None;
+        if let Some(spool) = self.spool.as_mut() {
+            spool.log.flush()?;
+            spool.timing.flush()?;
+        }
+        self.render()?;
+
+        // Back on the primary screen, print the final frame where the session's output
+        // goes on, so the run's tail stays above its footer.
+        restore_screen();
+        let mut out = io::BufWriter::new(io::stdout().lock());
+        let rows = self
+            .last_frame
+            .iter()
+            .rposition(|row| !row.is_empty())
+            .map_or(0, |last| last + 1);
+        for row in &self.last_frame[..rows] {
+            write!(out, "{row}\r\n")?;
+        }
+        out.flush()
+    }
+
+    fn consume_byte(&mut self, b: u8, out: &mut Vec<u8>) {
+        match self.ansi_state {
+            AnsiParseState::Normal => match b {
+                0x1b => self.ansi_state = AnsiParseState::Esc,
+                b'\r' | b'\n' | b'\t' => out.push(b),
+                0x20..=0x7e | 0x80..=0xff => out.push(b),
+                _ => {}
+            },
+            AnsiParseState::Esc => match b {
+                b'[' => self.ansi_state = AnsiParseState::Csi,
+                b']' => self.ansi_state = AnsiParseState::Osc,
+                _ => self.ansi_state = AnsiParseState::Normal,
+            },
+            AnsiParseState::Csi => {
+                if (0x40..=0x7e).contains(&b) {
+                    self.ansi_state = AnsiParseState::Normal;
+                    // Spinners (gemini's among them) go back to the first column this way.
+                    if b == b'G' {
+                        out.push(b'\r');
+                    }
+                }
+            }
+            AnsiParseState::Osc => match b {
+                0x07 => self.ansi_state = AnsiParseState::Normal,
+                0x1b => self.ansi_state = AnsiParseState::OscEsc,
+                _ => {}
+            },
+            AnsiParseState::OscEsc => {
+                if b == b'\\' {
+                    self.ansi_state = AnsiParseState::Normal;
+                } else {
+                    self.ansi_state = AnsiParseState::Osc;
+                }
+            }
+        }
+    }
+
+    fn push_current_line(&mut self) -> io::Result<()> {
+        let line = std::mem::take(&mut self.current_line);
+        let shown = self.settings.filter.shows(&line);
+        let at = self.current_line_at.take();
+        let line = self.stamped(&line, at);
+        let number = self.lines_seen;
+        self.lines_seen += 1;
+        if let Some(spool) = self.spool.as_mut() {
+            writeln!(spool.log, "{line}")?;
+            writeln!(spool.timing, "{}", spool.started.elapsed().as_millis())?;
+        }
+        if !shown {
+            return Ok(());
+        }
+        if let Some(tee) = &self.tee {
+            tee.write_line(&line);
+        }
+        self.output_lines.push_back(OutputLine {
+            text: line,
+            stream: self.current_stream,
+            number,
+        });
+        while self.output_lines.len() > MAX_RENDERED_OUTPUT_LINES {
+            self.output_lines.pop_front();
+        }
+        Ok(())
+    }
+
+    /// `line` with its timestamp, when timestamps are on. Lines without a start time
+    /// (empty ones) are stamped now.
+    fn stamped(&self, line: &str, at: Option<Duration>) -> String {
+        if !self.settings.timestamps {
+            return line.to_string();
+        }
+        let at = at.unwrap_or_else(|| self.started.elapsed());
+        format!("{}{line}", line_prefix(at))
+    }
+
+    /// Search, scroll or go back to the newest output.
+    fn handle_key(&mut self, key: Key) -> io::Result<()> {
+        let page = self.body_rows.max(2) as i64 - 1;
+        if let Some(search) = self.search.as_mut().filter(|search| search.editing) {
+            match key {
+                Key::Char(c) => search.query.push(c),
+                Key::Backspace => {
+                    search.query.pop();
+                }
+                Key::Enter if search.query.is_empty() => self.search = None,
+                Key::Enter => {
+                    search.editing = false;
+                    self.find(Direction::Older, self.view_bottom())?;
+                }
+                Key::Esc => self.search = None,
+                _ => return Ok(()),
+            }
+        } else {
+            let hit = self.search.as_ref().and_then(|search| search.hit);
+            match key {
+                Key::Char('/') => {
+                    self.search = Some(Search {
+                        query: String::new(),
+                        editing: true,
+                        hit: None,
+                        missed: false,
+                    });
+                }
+                Key::Char('n') if let Some(search) = self.search.as_mut() => match hit {
+                    Some(0) => search.missed = true,
+                    Some(hit) => self.find(Direction::Older, hit - 1)?,
+                    None => self.find(Direction::Older, self.view_bottom())?,
+                },
+                Key::Char('N') if self.search.is_some() => match hit {
+                    Some(hit) => self.find(Direction::Newer, hit + 1)?,
+                    None => self.find(Direction::Newer, self.view_bottom())?,
+                },
+                Key::Up => self.scroll_by(-1),
+                Key::Down => self.scroll_by(1),
+                Key::WheelUp => self.scroll_by(-WHEEL_LINES),
+                Key::WheelDown => self.scroll_by(WHEEL_LINES),
+                Key::PageUp => self.scroll_by(-page),
+                Key::PageDown => self.scroll_by(page),
+                Key::Home | Key::Char('g') => self.scroll_by(i64::MIN),
+                Key::End | Key::Char('G') | Key::Esc => {
+                    self.scroll = None;
+                    self.search = None;
+                }
+                _ => return Ok(()),
+            }
+        }
+        self.dirty = true;
+        self.render()
+    }
+
+    /// Whether keys go to a search being typed.
+    fn typing_search(&self) -> bool {
+        self.search.as_ref().is_some_and(|search| search.editing)
+    }
+
+    /// Number of the last completed line.
+    fn last_line(&self) -> u64 {
+        self.lines_seen.saturating_sub(1)
+    }
+
+    /// Number of the oldest line that can be shown: the spool log has them all.
+    fn first_line(&self) -> u64 {
+        match (&self.spool, self.output_lines.front()) {
+            (None, Some(line)) => line.number,
+            _ => 0,
+        }
+    }
+
+    /// Number of the oldest line still in `output_lines`.
+    fn first_buffered(&self) -> u64 {
+        self.output_lines
+            .front()
+            .map_or(self.lines_seen, |line| line.number)
+    }
+
+    fn view_bottom(&self) -> u64 {
+        self.scroll.unwrap_or_else(|| self.last_line())
+    }
+
+    /// Move the view `delta` lines; reaching the newest output follows it again.
+    fn scroll_by(&mut self, delta: i64) {
+        let lowest =
+            (self.first_line() + self.body_rows.saturating_sub(2) as u64).min(self.last_line());
+        let bottom = self.view_bottom().saturating_add_signed(delta);
+        self.scroll = if bottom >= self.last_line() && self.search.is_none() {
+            None
+        } else {
+            Some(bottom.clamp(lowest, self.last_line()))
+        };
+    }
+
+    /// Find the closest line matching the search `direction` of line `from`, that one
+    /// included, and bring it into view.
+    fn find(&mut self, direction: Direction, from: u64) -> io::Result<()> {
+        let Some(query) = self.search.as_ref().map(|search| search.query.clone()) else {
+            return Ok(());
+        };
+        let buffered = |line: &&OutputLine| line_matches(&line.text, &query);
+        let in_buffer = match direction {
+            Direction::Older => self
+                .output_lines
+                .iter()
+                .rev()
+                .filter(|line| line.number <= from)
+                .find(buffered),
+            Direction::Newer => self
+                .output_lines
+                .iter()
+                .filter(|line| line.number >= from)
+                .find(buffered),
+        }
+        .map(|line| line.number);
+        let first_buffered = self.first_buffered();
+        let older = from.saturating_add(1).min(first_buffered);
+        let in_spool = |spool: &mut Spool| -> io::Result<Option<u64>> {
+            spool.log.flush()?;
+            match direction {
+                Direction::Older => search::search_log(&spool.path, &query, 0..older, direction),
+                Direction::Newer => {
+                    search::search_log(&spool.path, &query, from..first_buffered, direction)
+                }
+            }
+        };
+        let hit = match (direction, in_buffer, self.spool.as_mut()) {
+            (Direction::Older, Some(hit), _) => Some(hit),
+            (Direction::Older, None, Some(spool)) => in_spool(spool)?,
+            (Direction::Newer, _, Some(spool)) if from < first_buffered => {
+                in_spool(spool)?.or(in_buffer)
+            }
+            (_, hit, _) => hit,
+        };
+        if let Some(search) = self.search.as_mut() {
+            search.missed = hit.is_none();
+            search.hit = hit.or(search.hit);
+        }
+        if let Some(hit) = hit {
+            let half = (self.body_rows / 2) as u64;
+            self.scroll = Some(hit.saturating_add(half).min(self.last_line()));
+        }
+        Ok(())
+    }
+
+    /// Make sure `spooled` holds the spool log lines `range`, reading them if needed.
+    fn load_spooled(&mut self, range: std::ops::Range<u64>) -> io::Result<()> {
+        let covered = self.spooled.as_ref().is_some_and(|(first, lines)| {
+            *first <= range.start && first + lines.len() as u64 >= range.end
+        });
+        if covered || range.is_empty() {
+            return Ok(());
+        }
+        let first_buffered = self.first_buffered();
+        let Some(spool) = self.spool.as_mut() else {
+            return Ok(());
+        };
+        spool.log.flush()?;
+        // Read a screenful either side, so scrolling does not go back to the file each line.
+        let margin = range.end - range.start;
+        let first = range.start.saturating_sub(margin);
+        let end = range.end.saturating_add(margin).min(first_buffered);
+        let lines = search::read_log_lines(&spool.path, first..end)?;
+        self.spooled = Some((first, lines));
+        Ok(())
+    }
+
+    /// The row under a scrolled view: the search being typed or its result, and the keys.
+    fn status_row(&self, bottom: u64) -> String {
+        let keys = "n/N: older/newer  Esc: back to live output";
+        match &self.search {
+            Some(search) if search.editing => format!("/{}_", search.query),
+            Some(search) if search.missed => {
+                format!("/{}: no more matches  {keys}", search.query)
+            }
+            Some(search) => format!(
+                "/{}: line {} of {}  {keys}",
+                search.query,
+                search.hit.map_or(bottom, |hit| hit) + 1,
+                self.lines_seen
+            ),
+            None => format!(
+                "Line {} of {}  Up/Down/PgUp/PgDn: scroll  /: search  Esc: back to live output",
+                bottom + 1,
+                self.lines_seen
+            ),
+        }
+    }
+
+    fn render(&mut self) -> io::Result<()> {
+        let rows = terminal_rows();
+        let cols = terminal_cols();
+        let mut header = self.header_lines.clone();
+        let elapsed = self.started.elapsed();
+        let status = match &self.settings.progress {
+            Some(progress) => Some(progress.status_line(elapsed)),
+            None => self
+                .settings
+                .spinner
+                .then(|| format!("Elapsed {}", format_clock(elapsed))),
+        };
+        if let Some(status) = status {
+            let status = if self.settings.spinner {
+                format!("{} {status}", spinner_frame(elapsed))
+            } else {
+                status
+            };
+            // Above the header's closing rule.
+            header.insert(header.len().saturating_sub(1), status);
+        }
+        let idle = self.last_output.elapsed();
+        if self.settings.heartbeat.is_some_and(|after| idle >= after) {
+            let heartbeat = heartbeat_line(idle, elapsed);
+            header.insert(header.len().saturating_sub(1), heartbeat);
+        }
+        let mut body_rows = rows.saturating_sub(header.len());
+        self.body_rows = body_rows;
+        // Typing a search keeps the view where it is until the search runs.
+        let status = match (self.scroll, &self.search) {
+            (Some(bottom), _) => Some(self.status_row(bottom)),
+            (None, Some(_)) => Some(self.status_row(self.last_line())),
+            (None, None) => None,
+        };
+        if status.is_some() {
+            body_rows = body_rows.saturating_sub(1);
+        }
+        if let Some(bottom) = self.scroll {
+            let start = (bottom + 1).saturating_sub(body_rows as u64);
+            self.load_spooled(start..(bottom + 1).min(self.first_buffered()))?;
+        }
+
+        let current_line = self.stamped(&self.current_line, self.current_line_at);
+        let bottom = self.scroll.unwrap_or(u64::MAX);
+        let first_buffered = self.first_buffered();
+        let mut visible_lines: Vec<(&str, OutputStream, Option<u64>)> = Vec::new();
+        if let Some((first, lines)) = self.scroll.and(self.spooled.as_ref()) {
+            visible_lines.extend(
+                (*first..)
+                    .zip(lines)
+                    .filter(|&(number, _)| number < first_buffered && number <= bottom)
+                    .map(|(number, line)| (line.as_str(), OutputStream::Stdout, Some(number))),
+            );
+        }
+        visible_lines.extend(
+            self.output_lines
+                .iter()
+                .filter(|line| line.number <= bottom)
+                .map(|line| (line.text.as_str(), line.stream, Some(line.number))),
+        );
+        if self.scroll.is_none()
+            && !self.current_line.is_empty()
+            && self.settings.filter.shows(&self.current_line)
+        {
+            visible_lines.push((current_line.as_str(), self.current_stream, None));
+        }
+        let hit = self.search.as_ref().and_then(|search| search.hit);
+        let stderr_gutter = theme().stderr.paint(STDERR_GUTTER);
+
+        // Walk back from the newest line until the body is full.
+        let mut body: Vec<String> = Vec::with_capacity(body_rows);
+        for &(line, stream, number) in visible_lines.iter().rev() {
+            if body.len() >= body_rows {
+                break;
+            }
+            // The current match is shown in reverse video.
+            let (on, off) = if number.is_some() && number == hit {
+                ("\x1b[7m", "\x1b[27m")
+            } else {
+                ("", "")
+            };
+            let (gutter, width) = match stream {
+                OutputStream::Stdout => ("", cols),
+                OutputStream::Stderr => (
+                    stderr_gutter.as_str(),
+                    cols.saturating_sub(STDERR_GUTTER_WIDTH).max(1),
+                ),
+            };
+            if self.settings.wrap_lines {
+                let wrapped = wrap_terminal_line(line, width);
+                let room = body_rows - body.len();
+                body.extend(
+                    wrapped
+                        .into_iter()
+                        .rev()
+                        .take(room)
+                        .map(|row| format!("{gutter}{on}{row}{off}")),
+                );
+            } else {
+                body.push(format!(
+                    "{gutter}{on}{}{off}",
+                    fit_terminal_line(line, width)
+                ));
+            }
+        }
+        body.reverse();
+        if let Some(status) = status {
+            body.resize(body_rows, String::new());
+            body.push(theme().status.paint(&fit_terminal_line(&status, cols)));
+        }
+
+        let mut frame: Vec<String> = header
+            .iter()
+            .map(|line| theme().header.paint(&fit_terminal_line(line, cols)))
+            .chain(body)
+            .collect();
+        frame.resize(header.len() + self.body_rows, String::new());
+
+        // A size change invalidates every row, so repaint from scratch.
+        let full_redraw = frame.len() != self.last_frame.len();
+        let mut out = io::BufWriter::new(io::stdout().lock());
+        for (row, line) in frame.iter().enumerate() {
+            if full_redraw || self.last_frame.get(row) != Some(line) {
+                write!(out, "\x1b[{};1H\x1b[2K{line}", row + 1)?;
+            }
+        }
+        if full_redraw {
+            write!(out, "\x1b[J")?;
+        }
+        out.flush()?;
+
+        self.last_frame = frame;
+        self.last_render = Some(Instant::now());
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+impl Drop for PinnedOutputRenderer {
+    fn drop(&mut self) {
+        // Also reached when the run errors or panics mid-render.
+        restore_screen();
+        let mut out = io::stdout();
+        let _ = write!(out, "\x1b[?25h");
+        let _ = out.flush();
+    }
+}
@@ -0,0 +1,208 @@
+//! Parsing and comparing agent CLI versions.
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+
+/// Oldest codex release agent-loops is tested against.
+pub const MIN_CODEX_VERSION: Version = Version::new(0, 30, 0);
+
+/// How long `<agent> --version` may take before it is treated as hung.
+const VERSION_QUERY_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// A `major.minor.patch` version. Missing components parse as 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl Version {
+    pub const fn new(major: u64, minor: u64, patch: u64) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Parse `1.2.3`, `1.2` or `v1.2.3`. Pre-release and build suffixes are ignored.
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        let text = text.strip_prefix(['v', 'V']).unwrap_or(text);
+        let core = text.split(['-', '+']).next().unwrap_or_default();
+        let mut parts = core.split('.');
+        let major = parts.next()?.parse().ok()?;
+        let minor = match parts.next() {
+            Some(part) => part.parse().ok()?,
+            None => 0,
+        };
+        let patch = match parts.next() {
+            Some(part) => part.parse().ok()?,
+            None => 0,
+        };
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Self::new(major, minor, patch))
+    }
+
+    /// First dotted version in `text`, e.g. `0.30.0` in `codex-cli 0.30.0`.
+    pub fn find_in(text: &str) -> Option<Self> {
+        text.split(|c: char| c.is_whitespace() || c == ',' || c == '(' || c == ')')
+            .filter(|token| token.contains('.'))
+            .find_map(Self::parse)
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Comparison operator in a [`VersionReq`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Greater,
+    GreaterEq,
+    Eq,
+    LessEq,
+    Less,
+}
+
+/// Comma-separated version constraints such as `>=0.30` or `>=0.30, <0.40`.
+/// A bare version means `>=`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionReq {
+    comparators: Vec<(Op, Version)>,
+}
+
+impl VersionReq {
+    /// Requirement satisfied by `version` and anything newer.
+    pub fn at_least(version: Version) -> Self {
+        Self {
+            comparators: vec![(Op::GreaterEq, version)],
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut comparators = Vec::new();
+        for part in text.split(',') {
+            let part = part.trim();
+            let (op, rest) = [
+                (">=", Op::GreaterEq),
+                ("<=", Op::LessEq),
+                (">", Op::Greater),
+                ("<", Op::Less),
+                ("=", Op::Eq),
+            ]
+            .into_iter()
+            .find_map(|(prefix, op)| part.strip_prefix(prefix).map(|rest| (op, rest)))
+            .unwrap_or((Op::GreaterEq, part));
+            let version = Version::parse(rest)
+                .ok_or_else(|| format!("invalid version requirement `{part}`"))?;
+            comparators.push((op, version));
+        }
+        Ok(Self { comparators })
+    }
+
+    pub fn matches(&self, version: Version) -> bool {
+        self.comparators.iter().all(|(op, bound)| match op {
+            Op::Greater => version > *bound,
+            Op::GreaterEq => version >= *bound,
+            Op::Eq => version == *bound,
+            Op::LessEq => version <= *bound,
+            Op::Less => version < *bound,
+        })
+    }
+}
+
+impl fmt::Display for VersionReq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (op, version)) in self.comparators.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            let op = match op {
+                Op::Greater => ">",
+                Op::GreaterEq => ">=",
+                Op::Eq => "=",
+                Op::LessEq => "<=",
+                Op::Less => "<",
+            };
+            write!(f, "{op}{version}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for VersionReq {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Self::parse(text)
+    }
+}
+
+/// Run `<program> --version` and check the reported version against `req`.
+/// Returns the detected version on success.
+pub async fn check_agent_version(program: &Path, req: &VersionReq) -> io::Result<Version> {
+    let output = query_version_output(program).await?;
+    let version = Version::find_in(&output).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("could not parse a version from `{output}`"),
+        )
+    })?;
+    if req.matches(version) {
+        Ok(version)
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("installed version {version} does not satisfy {req}"),
+        ))
+    }
+}
+
+/// Run `<program> --version` and return its first non-empty output line.
+pub async fn query_version_output(program: &Path) -> io::Result<String> {
+    let child = tokio::process::Command::new(program)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let output = tokio::time::timeout(VERSION_QUERY_TIMEOUT, child.wait_with_output())
+        .await
+        .map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!(
+                    "`--version` did not finish within {}s",
+                    VERSION_QUERY_TIMEOUT.as_secs()
+                ),
+            )
+        })??;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "`--version` exited with {}",
+            output.status
+        )));
+    }
+    let text = if output.stdout.iter().all(u8::is_ascii_whitespace) {
+        output.stderr
+    } else {
+        output.stdout
+    };
+    Ok(String::from_utf8_lossy(&text)
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default()
+        .to_string())
+}
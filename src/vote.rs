@@ -0,0 +1,78 @@
+//! Consensus execution of one prompt (`agent-loops vote`): the prompt runs several
+//! times in separate worktrees, a check command verifies each sample, and the work only
+//! reaches the real work dir when enough samples pass.
+
+use std::fmt::Write as _;
+
+use crate::git::DiffStat;
+
+/// One run of the prompt in its own worktree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sample {
+    /// The agent succeeded and the check passed.
+    pub passed: bool,
+    /// Snapshot of the worktree after the run, when it could be taken.
+    pub tree: Option<String>,
+    pub changes: Option<DiffStat>,
+}
+
+/// Index of the sample to apply: a passing one from the largest group of passing samples
+/// that ended with identical trees, the earliest on ties.
+pub fn pick_consensus(samples: &[Sample]) -> Option<usize> {
+    let agreeing = |tree: &str| {
+        samples
+            .iter()
+            .filter(|s| s.passed && s.tree.as_deref() == Some(tree))
+            .count()
+    };
+    samples
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| s.passed)
+        .filter_map(|(idx, s)| Some((idx, agreeing(s.tree.as_deref()?))))
+        .max_by(|(a_idx, a), (b_idx, b)| a.cmp(b).then(b_idx.cmp(a_idx)))
+        .map(|(idx, _)| idx)
+}
+
+/// How many passing samples ended with the same tree as `samples[idx]`.
+pub fn agreement(samples: &[Sample], idx: usize) -> usize {
+    samples
+        .iter()
+        .filter(|s| s.passed && s.tree.is_some() && s.tree == samples[idx].tree)
+        .count()
+}
+
+/// The vote summary: each sample's verdict and changes, then the decision.
+pub fn format_vote(samples: &[Sample], required: usize, chosen: Option<usize>) -> String {
+    let mut out = String::from("=== Vote ===\n");
+    for (idx, sample) in samples.iter().enumerate() {
+        let _ = write!(
+            out,
+            "  Sample {}: {}",
+            idx + 1,
+            if sample.passed { "PASSED" } else { "FAILED" }
+        );
+        if let Some(changes) = &sample.changes {
+            let _ = write!(out, "  {changes}");
+        }
+        out.push('\n');
+    }
+    let passed = samples.iter().filter(|s| s.passed).count();
+    let _ = write!(
+        out,
+        "{passed}/{} samples passed, {required} required.",
+        samples.len()
+    );
+    match chosen {
+        Some(idx) if passed >= required => {
+            let _ = writeln!(
+                out,
+                " Applying sample {}, identical to {} of the passing samples.",
+                idx + 1,
+                agreement(samples, idx)
+            );
+        }
+        _ => out.push_str(" Nothing applied.\n"),
+    }
+    out
+}
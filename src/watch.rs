@@ -0,0 +1,133 @@
+//! Polling file watcher behind `agent-loops watch`.
+//!
+//! Polling keeps the watcher dependency-free and identical on every platform; trees
+//! worth watching for agent passes are small enough that a scan per second is cheap.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::CancelToken;
+
+/// How often the watched paths are rescanned.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Directories never descended into: VCS metadata and build output change on their
+/// own and would retrigger passes endlessly.
+const IGNORED_DIRS: &[&str] = &[".git", ".hg", ".svn", "target", "node_modules"];
+
+/// Modification time and size of a file, enough to notice edits.
+type Stamp = (Option<SystemTime>, u64);
+
+/// Watches files and directory trees for added, removed or modified files.
+#[derive(Debug)]
+pub struct PathWatcher {
+    paths: Vec<PathBuf>,
+    state: BTreeMap<PathBuf, Stamp>,
+}
+
+impl PathWatcher {
+    /// Start watching `paths`; their current contents are the baseline.
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        let mut watcher = Self {
+            paths,
+            state: BTreeMap::new(),
+        };
+        watcher.rebaseline();
+        watcher
+    }
+
+    /// Accept the current contents as unchanged, e.g. after a pass edited them.
+    pub fn rebaseline(&mut self) {
+        self.state = self.scan();
+    }
+
+    /// Files added, removed or modified since the last call (or baseline).
+    pub fn changed_paths(&mut self) -> Vec<PathBuf> {
+        let current = self.scan();
+        let mut changed: Vec<PathBuf> = current
+            .iter()
+            .filter(|(path, stamp)| self.state.get(*path) != Some(*stamp))
+            .map(|(path, _)| path.clone())
+            .collect();
+        changed.extend(
+            self.state
+                .keys()
+                .filter(|path| !current.contains_key(*path))
+                .cloned(),
+        );
+        changed.sort();
+        self.state = current;
+        changed
+    }
+
+    /// Wait until something changes, then until nothing has changed for `debounce`.
+    /// Returns every changed path, or `None` once `cancel` fires.
+    pub async fn wait_for_change(
+        &mut self,
+        poll: Duration,
+        debounce: Duration,
+        cancel: &CancelToken,
+    ) -> Option<Vec<PathBuf>> {
+        let mut changed = Vec::new();
+        let mut quiet_since = None;
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(poll) => {}
+                _ = cancel.cancelled() => return None,
+            }
+            let new = self.changed_paths();
+            if !new.is_empty() {
+                changed.extend(new);
+                quiet_since = Some(tokio::time::Instant::now());
+            } else if quiet_since.is_some_and(|since| since.elapsed() >= debounce) {
+                changed.sort();
+                changed.dedup();
+                return Some(changed);
+            }
+        }
+    }
+
+    fn scan(&self) -> BTreeMap<PathBuf, Stamp> {
+        let mut state = BTreeMap::new();
+        for path in &self.paths {
+            scan_path(path, &mut state);
+        }
+        state
+    }
+}
+
+fn scan_path(path: &Path, state: &mut BTreeMap<PathBuf, Stamp>) {
+    match fs::metadata(path) {
+        Ok(meta) if meta.is_dir() => scan_dir(path, state),
+        Ok(meta) => {
+            state.insert(path.to_path_buf(), stamp(&meta));
+        }
+        Err(_) => {}
+    }
+}
+
+/// Record every file under `dir`. Symlinks are recorded, not followed, so link cycles
+/// cannot trap the scan.
+fn scan_dir(dir: &Path, state: &mut BTreeMap<PathBuf, Stamp>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            if !IGNORED_DIRS.iter().any(|name| entry.file_name() == *name) {
+                scan_dir(&entry.path(), state);
+            }
+        } else if let Ok(meta) = entry.metadata() {
+            state.insert(entry.path(), stamp(&meta));
+        }
+    }
+}
+
+fn stamp(meta: &fs::Metadata) -> Stamp {
+    (meta.modified().ok(), meta.len())
+}
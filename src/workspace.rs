@@ -0,0 +1,185 @@
+//! Running the same session over several repositories.
+//!
+//! A workspace file lists the repositories, each with its own tasks:
+//!
+//! ```toml
+//! [[repo]]
+//! name = "api"
+//! path = "../api"
+//! tasks = "maintenance.toml"
+//!
+//! [[repo]]
+//! path = "../web"
+//! task = [{ prompt = "Update the lockfile and fix any breakage" }]
+//! ```
+//!
+//! Relative paths are resolved against the workspace file's directory. `tasks` names a
+//! prompts or task file (see [`crate::tasks`]); inline `task` entries are appended to it.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::artifacts::ArtifactCollector;
+use crate::tee::println_tee;
+use crate::{
+    OrchestrateOptions, RunRecord, Runner, SessionState, TaskSpec, load_tasks,
+    orchestrate_runner_in,
+};
+
+/// One repository of a workspace.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RepoSpec {
+    /// Label used in output; defaults to the directory name.
+    #[serde(default)]
+    pub name: Option<String>,
+    pub path: PathBuf,
+    /// Prompts or task file with this repository's tasks.
+    #[serde(default)]
+    pub tasks: Option<PathBuf>,
+    #[serde(default)]
+    pub task: Vec<TaskSpec>,
+}
+
+impl RepoSpec {
+    pub fn display_name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| {
+            self.path.file_name().map_or_else(
+                || self.path.display().to_string(),
+                |name| name.to_string_lossy().into_owned(),
+            )
+        })
+    }
+}
+
+/// Repositories of a workspace file, with task files already loaded into `task`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Workspace {
+    #[serde(default)]
+    pub repo: Vec<RepoSpec>,
+}
+
+/// Load a workspace file, resolving relative paths and reading each repository's
+/// task file.
+pub fn load_workspace(path: &Path) -> io::Result<Workspace> {
+    let mut workspace: Workspace = toml::from_str(&fs::read_to_string(path)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    let base = path.parent().unwrap_or(Path::new("."));
+    for repo in &mut workspace.repo {
+        repo.path = base.join(&repo.path);
+        if let Some(file) = repo.tasks.take() {
+            let file = base.join(file);
+            let mut tasks = load_tasks(&file).map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("{}: {}: {e}", repo.display_name(), file.display()),
+                )
+            })?;
+            tasks.append(&mut repo.task);
+            repo.task = tasks;
+        }
+        if repo.task.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("repository `{}` has no tasks", repo.display_name()),
+            ));
+        }
+        if !repo.path.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "repository `{}`: {} is not a directory",
+                    repo.display_name(),
+                    repo.path.display()
+                ),
+            ));
+        }
+    }
+    Ok(workspace)
+}
+
+/// Results of one repository's part of a workspace session.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepoResults {
+    pub name: String,
+    pub path: PathBuf,
+    pub results: Vec<RunRecord>,
+}
+
+/// Run each repository's tasks `loops` times in turn, with a runner made for that
+/// repository by `make_runner`. The repositories make one session: the session cost
+/// limit, the run limit per hour and the failure streak span all of them. Stops before
+/// the next repository once cancelled or stopped by a limit.
+pub async fn orchestrate_workspace<R: Runner>(
+    workspace: &Workspace,
+    loops: usize,
+    options: &OrchestrateOptions,
+    make_runner: impl Fn(&RepoSpec) -> R,
+) -> Vec<RepoResults> {
+    let mut all = Vec::new();
+    let mut state = SessionState::default();
+    for repo in &workspace.repo {
+        if options.cancel.is_cancelled() || state.stopped {
+            break;
+        }
+        let name = repo.display_name();
+        println_tee(
+            options.tee.as_ref(),
+            &crate::theme::theme().header.paint(&format!(
+                "=== Repository {name} ({}) ===",
+                repo.path.display()
+            )),
+        );
+        let prompts: Vec<String> = repo.task.iter().map(|t| t.prompt.clone()).collect();
+        crate::print_plan(&prompts, loops, repo.path.to_str());
+        let repo_options = OrchestrateOptions {
+            git_work_dir: Some(repo.path.clone()),
+            artifacts: options
+                .artifacts
+                .as_ref()
+                .map(|collector| ArtifactCollector {
+                    dir: collector.dir.join(&name),
+                    ..collector.clone()
+                }),
+            ..options.clone()
+        };
+        let runner = make_runner(repo);
+        let results =
+            orchestrate_runner_in(&repo.task, loops, &repo_options, &runner, &mut state).await;
+        all.push(RepoResults {
+            name,
+            path: repo.path.clone(),
+            results,
+        });
+    }
+    all
+}
+
+/// Combined summary: one line per repository, then its failed runs.
+pub fn format_workspace_summary(repos: &[RepoResults]) -> String {
+    let mut out = String::from("=== Workspace summary ===\n");
+    for repo in repos {
+        let ok = repo.results.iter().filter(|r| r.outcome.success).count();
+        let _ = writeln!(
+            out,
+            "{}: {ok}/{} runs OK ({})",
+            repo.name,
+            repo.results.len(),
+            repo.path.display()
+        );
+        for record in repo.results.iter().filter(|r| !r.outcome.success) {
+            let _ = writeln!(
+                out,
+                "  FAILED loop {} task {}",
+                record.loop_idx + 1,
+                record.task_idx + 1
+            );
+        }
+    }
+    out
+}
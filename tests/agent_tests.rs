@@ -0,0 +1,188 @@
+use agent_loops::RunOptions;
+use agent_loops::agent::{Agent, RunnerTemplate};
+use std::path::PathBuf;
+
+#[test]
+fn test_agent_args() {
+    let options = RunOptions {
+        work_dir: Some(PathBuf::from("/srv/app")),
+        model: Some("sonnet".to_string()),
+        ..RunOptions::default()
+    };
+    assert_eq!(
+        Agent::Codex.args("fix it", &options),
+        [
+            "exec",
+            "--dangerously-bypass-approvals-and-sandbox",
+            "-C",
+            "/srv/app",
+            "--model",
+            "sonnet",
+            "fix it"
+        ]
+    );
+    // aider has no work dir flag; it is started in the work dir instead.
+    assert_eq!(
+        Agent::Aider.args("fix it", &options),
+        [
+            "--yes",
+            "--no-check-update",
+            "--no-pretty",
+            "--model",
+            "sonnet",
+            "--message",
+            "fix it"
+        ]
+    );
+    assert_eq!(
+        Agent::Gemini.args("fix it", &options),
+        ["--yolo", "--model", "sonnet", "--prompt", "fix it"]
+    );
+}
+
+#[test]
+fn test_agent_names() {
+    assert_eq!("Aider".parse::<Agent>(), Ok(Agent::Aider));
+    assert_eq!("gemini".parse::<Agent>(), Ok(Agent::Gemini));
+    assert_eq!(Agent::Codex.to_string(), "codex");
+    assert!("cursor".parse::<Agent>().is_err());
+}
+
+#[test]
+fn test_runner_template_fills_in_placeholders() {
+    let template: RunnerTemplate = "mytool run --task {prompt} --dir={work_dir} 'two words'"
+        .parse()
+        .unwrap();
+    assert_eq!(template.program(), "mytool");
+    let options = RunOptions {
+        work_dir: Some(PathBuf::from("/srv/app")),
+        ..RunOptions::default()
+    };
+    assert_eq!(
+        template.args("fix {model} and more", &options),
+        [
+            "run",
+            "--task",
+            "fix {model} and more",
+            "--dir=/srv/app",
+            "two words"
+        ]
+    );
+
+    assert!("mytool {task}".parse::<RunnerTemplate>().is_err());
+    assert!("mytool --dir {work_dir}".parse::<RunnerTemplate>().is_err());
+    assert!("mytool '{prompt}".parse::<RunnerTemplate>().is_err());
+}
+
+#[test]
+fn test_runner_template_maps_exit_codes_and_pattern() {
+    let mut template: RunnerTemplate = "mytool {prompt}".parse().unwrap();
+    assert!(template.succeeded(Some(0), false));
+    assert!(!template.succeeded(Some(3), false));
+    assert!(!template.succeeded(None, false));
+
+    template.success_codes = vec![0, 3];
+    template.success_pattern = Some(regex::Regex::new("^done").unwrap());
+    assert!(template.succeeded(Some(3), true));
+    assert!(!template.succeeded(Some(3), false));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_runs_a_runner_template() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("agent-loops-template-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let work = dir.join("work");
+    std::fs::create_dir_all(&work).unwrap();
+    let calls = dir.join("calls.txt");
+    let script = dir.join("mytool.sh");
+    std::fs::write(
+        &script,
+        format!(
+            "#!/bin/sh\nfor arg in \"$@\"; do echo \"$arg\" >> '{calls}'; done\necho 'all done'\nexit 3\n",
+            calls = calls.display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let run = |pattern: &str| {
+        let _ = std::fs::remove_file(&calls);
+        assert_cmd::cargo::cargo_bin_cmd!("agent-loops")
+            .args(["-p", "tidy up", "--runner-template"])
+            .arg(format!(
+                "{} --task {{prompt}} --dir {{work_dir}}",
+                script.display()
+            ))
+            .args([
+                "--runner-success-codes",
+                "0,3",
+                "--runner-success-pattern",
+                pattern,
+            ])
+            .arg("-C")
+            .arg(&work)
+            .arg("--data-dir")
+            .arg(dir.join("data"))
+            .arg("--spool-dir")
+            .arg(dir.join("spool"))
+            .assert()
+    };
+
+    run("^all done$").success();
+    let calls_text = std::fs::read_to_string(&calls).unwrap();
+    assert_eq!(
+        calls_text.lines().collect::<Vec<_>>(),
+        ["--task", "tidy up", "--dir", work.to_str().unwrap()]
+    );
+    run("^nothing to do$").failure();
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_runs_aider_in_the_work_dir() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("agent-loops-aider-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let work = dir.join("work");
+    std::fs::create_dir_all(&work).unwrap();
+    let calls = dir.join("calls.txt");
+    let script = dir.join("aider.sh");
+    std::fs::write(
+        &script,
+        format!(
+            "#!/bin/sh\npwd >> '{calls}'\necho \"$@\" >> '{calls}'\necho 'Tokens: 2.1k sent, 310 received. Cost: $0.01 message, $0.03 session.'\n",
+            calls = calls.display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    assert_cmd::cargo::cargo_bin_cmd!("agent-loops")
+        .args(["-p", "tidy up", "--agent", "aider", "--codex-bin"])
+        .arg(&script)
+        .arg("-C")
+        .arg(&work)
+        .arg("--data-dir")
+        .arg(dir.join("data"))
+        .arg("--spool-dir")
+        .arg(dir.join("spool"))
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("[Run 1/1] Cost: $0.0100"));
+
+    let calls = std::fs::read_to_string(&calls).unwrap();
+    let calls: Vec<&str> = calls.lines().collect();
+    assert_eq!(
+        std::fs::canonicalize(calls[0]).unwrap(),
+        std::fs::canonicalize(&work).unwrap()
+    );
+    assert_eq!(
+        calls[1],
+        "--yes --no-check-update --no-pretty --message tidy up"
+    );
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,129 @@
+#![cfg(feature = "api")]
+
+use agent_loops::api::{ApiClient, ApiModel, Provider, request_body, response_text};
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+#[test]
+fn test_api_model_parses_and_displays() {
+    let model: ApiModel = "anthropic:claude-3-5-haiku-latest".parse().unwrap();
+    assert_eq!(model.provider, Provider::Anthropic);
+    assert_eq!(model.model, "claude-3-5-haiku-latest");
+    assert_eq!(model.to_string(), "anthropic:claude-3-5-haiku-latest");
+
+    let model: ApiModel = "ollama:llama3.1:8b".parse().unwrap();
+    assert_eq!(model.provider, Provider::Ollama);
+    assert_eq!(model.model, "llama3.1:8b");
+
+    assert!("gpt-4o".parse::<ApiModel>().is_err());
+    assert!("mistral:large".parse::<ApiModel>().is_err());
+    assert!("openai:".parse::<ApiModel>().is_err());
+}
+
+#[test]
+fn test_requests_and_responses_per_provider() {
+    let model: ApiModel = "anthropic:haiku".parse().unwrap();
+    let body = request_body(&model, "judge this");
+    assert_eq!(body["messages"][0]["content"], "judge this");
+    assert!(body["max_tokens"].is_u64());
+    assert_eq!(
+        response_text(
+            Provider::Anthropic,
+            &json!({ "content": [{ "type": "text", "text": "VERDICT: " }, { "type": "text", "text": "APPROVE" }] })
+        )
+        .as_deref(),
+        Some("VERDICT: APPROVE")
+    );
+
+    assert_eq!(
+        response_text(
+            Provider::OpenAi,
+            &json!({ "choices": [{ "message": { "role": "assistant", "content": "ok" } }] })
+        )
+        .as_deref(),
+        Some("ok")
+    );
+    assert_eq!(
+        response_text(Provider::OpenAi, &json!({ "choices": [] })),
+        None
+    );
+}
+
+/// Answers one HTTP request with `status` and `body`, and returns the request it got.
+async fn serve_once(
+    status: &'static str,
+    body: &'static str,
+) -> (String, tokio::task::JoinHandle<String>) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0; 4096];
+        loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&request);
+            if let Some((head, rest)) = text.split_once("\r\n\r\n") {
+                let length = head
+                    .lines()
+                    .find_map(|line| {
+                        line.to_ascii_lowercase()
+                            .strip_prefix("content-length:")
+                            .map(|v| v.trim().parse::<usize>().unwrap())
+                    })
+                    .unwrap_or(0);
+                if rest.len() >= length {
+                    break;
+                }
+            }
+        }
+        let response = format!(
+            "HTTP/1.1 {status}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(response.as_bytes()).await.unwrap();
+        String::from_utf8_lossy(&request).into_owned()
+    });
+    (url, server)
+}
+
+#[tokio::test]
+async fn test_client_sends_the_prompt_and_reads_the_answer() {
+    let (url, server) = serve_once(
+        "200 OK",
+        r#"{"choices":[{"message":{"content":"VERDICT: APPROVE"}}]}"#,
+    )
+    .await;
+    let client = ApiClient::new("openai:gpt-4o-mini".parse().unwrap(), &url, "sk-test");
+    assert_eq!(client.complete("review").await.unwrap(), "VERDICT: APPROVE");
+    let request = server.await.unwrap();
+    assert!(request.starts_with("POST /chat/completions "));
+    assert!(
+        request
+            .to_ascii_lowercase()
+            .contains("authorization: bearer sk-test")
+    );
+    assert!(request.contains(r#""content":"review""#));
+
+    let (url, server) = serve_once("529 Overloaded", r#"{"error":"overloaded"}"#).await;
+    let client = ApiClient::new("anthropic:haiku".parse().unwrap(), &url, "key");
+    let err = client.complete("review").await.unwrap_err();
+    assert!(err.to_string().contains("overloaded"), "{err}");
+    assert!(server.await.unwrap().contains("x-api-key: key"));
+}
+
+#[tokio::test]
+async fn test_client_asks_a_local_ollama_server() {
+    let (url, server) = serve_once(
+        "200 OK",
+        r#"{"message":{"role":"assistant","content":"VERDICT: REJECT"},"done":true}"#,
+    )
+    .await;
+    let client = ApiClient::new("ollama:llama3.1".parse().unwrap(), &url, "");
+    assert_eq!(client.complete("review").await.unwrap(), "VERDICT: REJECT");
+    let request = server.await.unwrap();
+    assert!(request.starts_with("POST /api/chat "));
+    assert!(request.contains(r#""stream":false"#));
+    assert!(!request.to_ascii_lowercase().contains("authorization"));
+}
@@ -0,0 +1,108 @@
+mod common;
+
+use agent_loops::artifacts::{ArtifactCollector, Glob};
+use agent_loops::testing::{MockRunner, MockStep};
+use agent_loops::{OrchestrateOptions, TaskSpec, orchestrate_runner};
+use common::temp_dir;
+use std::path::Path;
+
+fn glob(pattern: &str) -> Glob {
+    pattern.parse().unwrap()
+}
+
+#[test]
+fn test_glob_wildcards() {
+    let json = glob("reports/*.json");
+    assert!(json.matches(Path::new("reports/bench.json")));
+    assert!(!json.matches(Path::new("reports/old/bench.json")));
+    assert!(!json.matches(Path::new("reports/bench.txt")));
+
+    let deep = glob("**/*.svg");
+    assert!(deep.matches(Path::new("plot.svg")));
+    assert!(deep.matches(Path::new("target/criterion/report/plot.svg")));
+
+    assert!(glob("run-?.log").matches(Path::new("run-1.log")));
+    assert!(!glob("run-?.log").matches(Path::new("run-12.log")));
+}
+
+#[test]
+fn test_glob_matches_everything_under_a_matching_dir() {
+    assert!(glob("target/criterion/**").matches(Path::new("target/criterion/a/estimates.json")));
+    assert!(glob("./reports/").matches(Path::new("reports/nested/summary.md")));
+    assert!(!glob("reports").matches(Path::new("reports-old/summary.md")));
+}
+
+#[test]
+fn test_glob_must_stay_in_the_work_dir() {
+    for pattern in ["", "/etc/*", "../other/*", "a/../../b"] {
+        assert!(pattern.parse::<Glob>().is_err(), "accepted {pattern:?}");
+    }
+}
+
+fn write(path: &Path, text: &str) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, text).unwrap();
+}
+
+#[test]
+fn test_collect_copies_matching_files_into_the_run_dir() {
+    let dir = temp_dir("artifacts-collect");
+    let work = dir.join("work");
+    write(
+        &work.join("target/criterion/parse/new/estimates.json"),
+        "{}",
+    );
+    write(&work.join("reports/summary.json"), "{\"ok\":true}");
+    write(&work.join("reports/notes.txt"), "not collected");
+    write(&work.join(".git/config"), "not collected");
+    let collector = ArtifactCollector {
+        patterns: vec![
+            glob("target/criterion/**"),
+            glob("reports/*.json"),
+            glob("**/config"),
+        ],
+        dir: dir.join("artifacts"),
+    };
+
+    assert_eq!(collector.collect(&work, 3).unwrap(), 2);
+
+    let run_dir = collector.run_dir(3);
+    assert_eq!(run_dir, dir.join("artifacts").join("run-0003"));
+    assert_eq!(
+        std::fs::read_to_string(run_dir.join("reports/summary.json")).unwrap(),
+        "{\"ok\":true}"
+    );
+    assert!(
+        run_dir
+            .join("target/criterion/parse/new/estimates.json")
+            .exists()
+    );
+    assert!(!run_dir.join("reports/notes.txt").exists());
+    assert!(!run_dir.join(".git").exists());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_each_run_keeps_its_own_artifacts() {
+    let dir = temp_dir("artifacts-session");
+    let work = dir.join("work");
+    write(&work.join("bench.json"), "{}");
+    let runner = MockRunner::new().fallback(MockStep::success("done"));
+    let options = OrchestrateOptions {
+        git_work_dir: Some(work.clone()),
+        artifacts: Some(ArtifactCollector {
+            patterns: vec![glob("*.json"), glob("missing/**")],
+            dir: dir.join("artifacts"),
+        }),
+        ..OrchestrateOptions::default()
+    };
+
+    let results = orchestrate_runner(&[TaskSpec::new("a")], 2, &options, &runner).await;
+
+    for (idx, record) in results.iter().enumerate() {
+        let run_dir = dir.join("artifacts").join(format!("run-{:04}", idx + 1));
+        assert_eq!(record.outcome.artifacts.as_deref(), Some(run_dir.as_path()));
+        assert!(run_dir.join("bench.json").exists());
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,129 @@
+mod common;
+
+use agent_loops::CancelToken;
+use agent_loops::attach::{AttachCommand, follow};
+use agent_loops::sessions::{SessionRecord, SessionStatus, SessionStore};
+use common::temp_dir;
+use std::path::PathBuf;
+use std::time::Duration;
+
+fn stored_session(store: &SessionStore) -> SessionRecord {
+    let mut record = SessionRecord::new(None, vec![PathBuf::from(".")], 1, vec!["x".into()]);
+    store.create(&mut record).unwrap();
+    record
+}
+
+#[tokio::test]
+async fn test_paused_session_waits_until_resumed() {
+    let store = SessionStore::new(&temp_dir("attach-pause"));
+    let record = stored_session(&store);
+    let control = store.control(&record.id);
+    let cancel = CancelToken::new();
+    assert!(control.wait_while_paused(&cancel).await);
+
+    control.pause().unwrap();
+    assert!(control.is_paused());
+    let waiting = tokio::spawn({
+        let control = control.clone();
+        let cancel = cancel.clone();
+        async move { control.wait_while_paused(&cancel).await }
+    });
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    assert!(!waiting.is_finished());
+    control.resume().unwrap();
+    assert!(waiting.await.unwrap());
+    control.resume().unwrap();
+}
+
+#[tokio::test]
+async fn test_stop_request_cancels_the_session() {
+    let store = SessionStore::new(&temp_dir("attach-stop"));
+    let record = stored_session(&store);
+    let control = store.control(&record.id);
+    let cancel = CancelToken::new();
+    let watching = tokio::spawn(control.clone().watch_for_stop(cancel.clone()));
+    control.stop().unwrap();
+    tokio::time::timeout(Duration::from_secs(5), watching)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(cancel.is_cancelled());
+}
+
+#[tokio::test]
+async fn test_follow_prints_the_log_of_an_ended_session() {
+    let store = SessionStore::new(&temp_dir("attach-follow"));
+    let mut record = stored_session(&store);
+    record.finish(&[], false);
+    store.save(&record).unwrap();
+    std::fs::write(
+        store.log_path(&record.id),
+        "=== Agent Loops ===\nall done\n",
+    )
+    .unwrap();
+
+    let mut out = Vec::new();
+    let status = follow(&store, &record.id, &mut out, &CancelToken::new())
+        .await
+        .unwrap();
+    assert_eq!(status, Some(SessionStatus::Succeeded));
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "=== Agent Loops ===\nall done\n"
+    );
+    assert_eq!(
+        AttachCommand::for_key('P'),
+        Some(AttachCommand::TogglePause)
+    );
+    assert_eq!(AttachCommand::for_key('x'), None);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_detached_session_stops_on_request() {
+    let dir = temp_dir("attach-cli");
+    let output = assert_cmd::cargo::cargo_bin_cmd!("agent-loops")
+        .args(["-p", "wait", "-p", "never", "--detach", "--yes"])
+        .args([
+            "--runner-template",
+            "sh -c 'sleep 30; echo {prompt}'",
+            "--data-dir",
+        ])
+        .arg(&dir)
+        .arg("--spool-dir")
+        .arg(dir.join("spool"))
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let output = String::from_utf8(output).unwrap();
+    let id = output.split_whitespace().nth(1).unwrap();
+
+    let store = SessionStore::new(&dir);
+    let deadline = std::time::Instant::now() + Duration::from_secs(20);
+    while store.find(id).is_err() {
+        assert!(std::time::Instant::now() < deadline, "{output}");
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    store.control(id).stop().unwrap();
+    let record = loop {
+        let record = store.find(id).unwrap();
+        if record.status != SessionStatus::Running {
+            break record;
+        }
+        assert!(std::time::Instant::now() < deadline, "{output}");
+        std::thread::sleep(Duration::from_millis(100));
+    };
+    assert_eq!(record.status, SessionStatus::Cancelled);
+
+    assert_cmd::cargo::cargo_bin_cmd!("agent-loops")
+        .args(["--data-dir"])
+        .arg(&dir)
+        .args(["attach", id])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(format!(
+            "Session {id} cancelled."
+        )));
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use agent_loops::benchmark::{ModelResult, benchmark_tasks, format_benchmark};
+use agent_loops::{RunOutcome, RunRecord, TaskSpec};
+use std::time::Duration;
+
+fn record(success: bool, secs: u64, cost_usd: Option<f64>) -> RunRecord {
+    let outcome = RunOutcome {
+        success,
+        cost_usd,
+        ..RunOutcome::default()
+    };
+    RunRecord::new(0, 0, outcome, Duration::from_secs(secs))
+}
+
+#[test]
+fn test_benchmark_tasks_use_the_benchmarked_model() {
+    let tasks = vec![TaskSpec {
+        name: Some("lint".to_string()),
+        model: Some("pinned-model".to_string()),
+        codex_bin: Some("/opt/codex".to_string()),
+        ..TaskSpec::new("Fix clippy warnings")
+    }];
+    assert_eq!(
+        benchmark_tasks(&tasks),
+        vec![TaskSpec {
+            name: Some("lint".to_string()),
+            ..TaskSpec::new("Fix clippy warnings")
+        }]
+    );
+}
+
+#[test]
+fn test_benchmark_table() {
+    let fast = ModelResult::new(
+        "gpt-5-mini",
+        &[
+            record(true, 60, Some(0.05)),
+            record(false, 120, None),
+            record(true, 90, Some(0.10)),
+        ],
+    );
+    assert_eq!((fast.successes, fast.runs), (2, 3));
+    assert_eq!(fast.mean_duration, Some(Duration::from_secs(90)));
+    let slow = ModelResult::new("gpt-5", &[record(true, 300, None)]);
+    assert_eq!(slow.cost_usd, None);
+
+    assert_eq!(
+        format_benchmark(&[fast, slow, ModelResult::new("unused", &[])]),
+        "=== Model benchmark ===\n\
+         \x20 model            ok  rate      mean      cost\n\
+         \x20 gpt-5-mini      2/3   67%     1m30s     $0.15\n\
+         \x20 gpt-5           1/1  100%     5m00s         -\n\
+         \x20 unused          0/0    0%         -         -\n"
+    );
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_cli_benchmark_runs_each_model_in_its_own_worktree() {
+    use agent_loops::git::git;
+    use common::{agent_loops, init_repo, temp_dir};
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = temp_dir("benchmark-cli");
+    let repo = dir.join("repo");
+    std::fs::create_dir_all(&repo).unwrap();
+    std::fs::write(repo.join("tracked.txt"), "one\n").unwrap();
+    init_repo(&repo).await;
+
+    let calls = dir.join("calls.txt");
+    let script = dir.join("codex.sh");
+    std::fs::write(
+        &script,
+        format!("#!/bin/sh\necho \"$@\" >> '{}'\n", calls.display()),
+    )
+    .unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    agent_loops(&repo, &dir)
+        .args(["-p", "tidy up", "--codex-bin"])
+        .arg(&script)
+        .args(["benchmark", "--model", "model-a", "--model", "model-b"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("model-a      1/1  100%"))
+        .stdout(predicates::str::contains("model-b      1/1  100%"));
+
+    let calls = std::fs::read_to_string(&calls).unwrap();
+    let calls: Vec<&str> = calls.lines().collect();
+    assert_eq!(calls.len(), 2, "{calls:?}");
+    assert!(calls[0].contains("agent-loops-benchmark-") && calls[0].contains("--model model-a"));
+    assert!(calls[1].contains("--model model-b"));
+    assert!(!calls[0].contains(&repo.display().to_string()), "{calls:?}");
+    let worktrees = git(&repo, &["worktree", "list"]).await.unwrap();
+    assert_eq!(worktrees.lines().count(), 1, "{worktrees}");
+    let _ = std::fs::remove_dir_all(&dir);
+}
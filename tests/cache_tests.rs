@@ -0,0 +1,77 @@
+mod common;
+
+use agent_loops::cache::{CachedRunner, RunCache, cache_key};
+use agent_loops::git::git;
+use agent_loops::sessions::{SessionRecord, SessionStore};
+use agent_loops::testing::{MockRunner, MockStep};
+use agent_loops::{OrchestrateOptions, RunOptions, TaskSpec, orchestrate_runner};
+use common::{init_repo, temp_dir};
+use std::path::Path;
+
+#[test]
+fn test_cache_key_covers_work_dir_head_prompt_and_occurrence() {
+    let key = cache_key(Path::new("/srv/app"), "abc123", "Fix lint", 1);
+    assert_eq!(key.len(), 32);
+    assert_eq!(
+        key,
+        cache_key(Path::new("/srv/app"), "abc123", "Fix lint", 1)
+    );
+    for other in [
+        cache_key(Path::new("/srv/other"), "abc123", "Fix lint", 1),
+        cache_key(Path::new("/srv/app"), "def456", "Fix lint", 1),
+        cache_key(Path::new("/srv/app"), "abc123", "Fix lint!", 1),
+        cache_key(Path::new("/srv/app"), "abc123", "Fix lint", 2),
+    ] {
+        assert_ne!(key, other);
+    }
+}
+
+#[tokio::test]
+async fn test_rerun_skips_the_runs_that_succeeded() {
+    let dir = temp_dir("cache-rerun");
+    let repo = dir.join("repo");
+    init_repo(&repo).await;
+    let options = RunOptions {
+        work_dir: Some(repo.clone()),
+        ..RunOptions::default()
+    };
+    let tasks = [TaskSpec::new("build"), TaskSpec::new("test")];
+    let store = SessionStore::new(&dir);
+    let session = async |inner: &MockRunner| {
+        let cache = RunCache::load(&store).unwrap();
+        let runner = CachedRunner::new(inner, Some(cache), options.clone());
+        let results = orchestrate_runner(&tasks, 2, &OrchestrateOptions::default(), &runner).await;
+        let mut record = SessionRecord::new(None, Vec::new(), 2, Vec::new());
+        record.finish(&results, false);
+        store.create(&mut record).unwrap();
+        results
+    };
+
+    // `test` fails in loop 2.
+    let first = MockRunner::new()
+        .fallback(MockStep::success("done"))
+        .fail_on([4]);
+    session(&first).await;
+    first.assert_results(&[true, true, true, false]);
+
+    let second = MockRunner::new().fallback(MockStep::success("done"));
+    let results = session(&second).await;
+    assert_eq!(results.len(), 4);
+    assert!(results.iter().all(|r| r.outcome.success));
+    let cached: Vec<bool> = results.iter().map(|r| r.outcome.cached).collect();
+    assert_eq!(cached, [true, true, true, false]);
+    second.assert_prompts(&["test"]);
+
+    git(&repo, &["commit", "-q", "--allow-empty", "-m", "next"])
+        .await
+        .unwrap();
+    let after_commit = MockRunner::new().fallback(MockStep::success("done"));
+    session(&after_commit).await;
+    after_commit.assert_prompts(&["build", "test", "build", "test"]);
+
+    let uncached = MockRunner::new().fallback(MockStep::success("done"));
+    let runner = CachedRunner::new(&uncached, None, options.clone());
+    orchestrate_runner(&tasks, 1, &OrchestrateOptions::default(), &runner).await;
+    uncached.assert_prompts(&["build", "test"]);
+    let _ = std::fs::remove_dir_all(&dir);
+}
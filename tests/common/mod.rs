@@ -0,0 +1,91 @@
+//! Helpers shared by the integration tests. Each test file pulls them in with
+//! `mod common;` and uses the ones it needs.
+
+#![allow(dead_code)]
+
+use agent_loops::fixture::{Fixture, FixtureEvent, FixtureStream};
+use agent_loops::git::git;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// An empty `agent-loops-<name>-<pid>` dir under the system temp dir.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("agent-loops-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Write a fixture to `dir/name` that replays `events` at once, then exits with `exit_code`.
+pub fn write_events(dir: &Path, name: &str, events: &[(FixtureStream, &[u8])], exit_code: i32) {
+    let fixture = Fixture {
+        command: Vec::new(),
+        events: events
+            .iter()
+            .map(|&(stream, bytes)| FixtureEvent {
+                at: Duration::ZERO,
+                stream,
+                bytes: bytes.to_vec(),
+            })
+            .collect(),
+        exit_code: Some(exit_code),
+    };
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(dir.join(name), fixture.render()).unwrap();
+}
+
+/// Write a fixture to `dir/name` that prints `output` and exits with `exit_code`.
+pub fn write_fixture(dir: &Path, name: &str, output: &str, exit_code: i32) {
+    write_events(
+        dir,
+        name,
+        &[(FixtureStream::Stdout, output.as_bytes())],
+        exit_code,
+    );
+}
+
+/// Replace the fixtures in `dir` with one run per exit code, each printing `output`.
+pub fn write_fixtures(dir: &Path, output: &str, exit_codes: &[i32]) {
+    let _ = std::fs::remove_dir_all(dir);
+    for (idx, &exit_code) in exit_codes.iter().enumerate() {
+        write_fixture(
+            dir,
+            &format!("run-{:04}.fixture", idx + 1),
+            output,
+            exit_code,
+        );
+    }
+}
+
+/// Turn `repo` into a git repo whose first commit holds the files already in it.
+pub async fn init_repo(repo: &Path) {
+    std::fs::create_dir_all(repo).unwrap();
+    git(repo, &["init", "-q"]).await.unwrap();
+    git(repo, &["config", "user.email", "test@example.com"])
+        .await
+        .unwrap();
+    git(repo, &["config", "user.name", "Test"]).await.unwrap();
+    git(repo, &["add", "."]).await.unwrap();
+    git(repo, &["commit", "-q", "--allow-empty", "-m", "initial"])
+        .await
+        .unwrap();
+}
+
+/// The `agent-loops` binary working in `work_dir`, with its data and spool dirs in `dir`.
+pub fn agent_loops(work_dir: &Path, dir: &Path) -> assert_cmd::Command {
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("agent-loops");
+    cmd.arg("-C")
+        .arg(work_dir)
+        .arg("--data-dir")
+        .arg(dir.join("data"))
+        .arg("--spool-dir")
+        .arg(dir.join("spool"));
+    cmd
+}
+
+/// The `agent-loops` binary working in `dir` and replaying the fixtures in `dir/fixtures`.
+pub fn replay(dir: &Path) -> assert_cmd::Command {
+    let mut cmd = agent_loops(dir, dir);
+    cmd.arg("--replay-fixtures").arg(dir.join("fixtures"));
+    cmd
+}
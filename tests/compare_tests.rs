@@ -0,0 +1,162 @@
+mod common;
+
+use agent_loops::compare::{
+    Variant, VariantResult, format_comparison, load_variant, trial_order, trial_tasks,
+    variant_results,
+};
+use agent_loops::stats::fisher_exact;
+use agent_loops::{RunOutcome, RunRecord};
+use common::temp_dir;
+use std::time::Duration;
+
+#[test]
+fn test_trial_order_rotates_or_shuffles_each_round() {
+    assert_eq!(trial_order(2, 3, None), vec![0, 1, 1, 0, 0, 1]);
+    assert_eq!(trial_order(3, 2, None), vec![0, 1, 2, 1, 2, 0]);
+
+    let shuffled = trial_order(3, 20, Some(42));
+    assert_eq!(shuffled, trial_order(3, 20, Some(42)));
+    for round in shuffled.chunks(3) {
+        let mut sorted = round.to_vec();
+        sorted.sort();
+        assert_eq!(sorted, vec![0, 1, 2]);
+    }
+    assert_ne!(shuffled[..6], trial_order(3, 20, Some(7))[..6]);
+}
+
+#[test]
+fn test_load_variant_and_trial_tasks() {
+    let dir = temp_dir("compare-load");
+    let path = dir.join("terse.txt");
+    std::fs::write(&path, "\n  Fix the bug.\nKeep it short.\n\n").unwrap();
+    let variant = load_variant(&path).unwrap();
+    assert_eq!(variant.label, "terse.txt");
+    assert_eq!(variant.prompt, "Fix the bug.\nKeep it short.");
+
+    std::fs::write(&path, " \n").unwrap();
+    assert!(load_variant(&path).is_err());
+
+    let variants = vec![
+        variant,
+        Variant {
+            label: "verbose.txt".to_string(),
+            prompt: "Please fix the bug.".to_string(),
+        },
+    ];
+    let tasks = trial_tasks(&variants, &[1, 0]);
+    assert_eq!(tasks[0].name.as_deref(), Some("B verbose.txt"));
+    assert_eq!(tasks[1].prompt, "Fix the bug.\nKeep it short.");
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_fisher_exact() {
+    assert!((fisher_exact(8, 10, 2, 10) - 0.023).abs() < 0.001);
+    assert!((fisher_exact(3, 4, 2, 4) - 1.0).abs() < 1e-9);
+    assert!((fisher_exact(10, 10, 0, 10) - 0.0000108).abs() < 1e-6);
+    assert!((fisher_exact(0, 0, 0, 0) - 1.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_variant_results_and_summary() {
+    let variants: Vec<Variant> = ["a.txt", "b.txt"]
+        .iter()
+        .map(|label| Variant {
+            label: label.to_string(),
+            prompt: "prompt".to_string(),
+        })
+        .collect();
+    let order = trial_order(2, 10, None);
+    let results: Vec<RunRecord> = order
+        .iter()
+        .enumerate()
+        .map(|(task_idx, &variant)| {
+            // a.txt fails twice, b.txt succeeds twice.
+            let success = if variant == 0 {
+                task_idx >= 4
+            } else {
+                task_idx < 4
+            };
+            RunRecord::new(
+                0,
+                task_idx,
+                RunOutcome::from(success),
+                Duration::from_secs(60 * (variant as u64 + 1)),
+            )
+        })
+        .collect();
+
+    let compared = variant_results(&variants, &order, &results);
+    assert_eq!(
+        compared[0],
+        VariantResult {
+            label: "a.txt".to_string(),
+            trials: 10,
+            successes: 8,
+            mean_duration: Some(Duration::from_secs(60)),
+        }
+    );
+    assert_eq!(compared[1].successes, 2);
+    assert_eq!(
+        format_comparison(&compared),
+        "=== Comparison ===\n\
+         \x20 A a.txt  8/10 (80%)  mean 1m00s\n\
+         \x20 B b.txt  2/10 (20%)  mean 2m00s\n\
+         Winner: A (a.txt), 80% vs 20% for B; Fisher's exact p = 0.023 (significant at 0.05)\n"
+    );
+
+    let tied = vec![compared[0].clone(), compared[0].clone()];
+    assert!(format_comparison(&tied).ends_with("No winner: the best variants tie at 80%.\n"));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_cli_compare_checks_each_run_and_resets_the_work_dir() {
+    use common::{agent_loops, init_repo, write_fixtures};
+
+    let dir = temp_dir("compare-cli");
+    let repo = dir.join("repo");
+    std::fs::create_dir_all(&repo).unwrap();
+    std::fs::write(repo.join("README"), "hello\n").unwrap();
+    init_repo(&repo).await;
+
+    let fixtures = dir.join("fixtures");
+    // Trial order is A B B A; A's second run fails, so A wins 1 of 2 and B 2 of 2.
+    write_fixtures(&fixtures, "done\n", &[0, 0, 0, 1]);
+    std::fs::write(dir.join("a.txt"), "Variant A\n").unwrap();
+    std::fs::write(dir.join("b.txt"), "Variant B\n").unwrap();
+
+    // The check fails when an earlier trial's marker file was left behind.
+    agent_loops(&repo, &dir)
+        .arg("--replay-fixtures")
+        .arg(&fixtures)
+        .arg("compare")
+        .arg("--variant")
+        .arg(dir.join("a.txt"))
+        .arg("--variant")
+        .arg(dir.join("b.txt"))
+        .args([
+            "--loops",
+            "2",
+            "--check",
+            "test ! -e marker && touch marker",
+        ])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("A a.txt  1/2 (50%)"))
+        .stdout(predicates::str::contains("B b.txt  2/2 (100%)"))
+        .stdout(predicates::str::contains(
+            "Winner: B (b.txt), 100% vs 50% for A",
+        ));
+    assert!(!repo.join("marker").exists());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_cli_compare_needs_two_variants() {
+    assert_cmd::cargo::cargo_bin_cmd!("agent-loops")
+        .args(["compare", "--variant", "a.txt"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("at least two --variant files"));
+}
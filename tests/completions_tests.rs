@@ -0,0 +1,25 @@
+#[test]
+fn test_completions_cover_subcommands_and_flags() {
+    assert_cmd::cargo::cargo_bin_cmd!("agent-loops")
+        .args(["completions", "bash"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("_agent__loops()"))
+        .stdout(predicates::str::contains("--runner-template"))
+        .stdout(predicates::str::contains("install-service"));
+    assert_cmd::cargo::cargo_bin_cmd!("agent-loops")
+        .args(["completions", "tcsh"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_manpage_documents_the_options() {
+    assert_cmd::cargo::cargo_bin_cmd!("agent-loops")
+        .arg("manpage")
+        .assert()
+        .success()
+        .stdout(predicates::str::starts_with(".ie"))
+        .stdout(predicates::str::contains(".TH agent-loops 1"))
+        .stdout(predicates::str::contains("\\-\\-prompts\\-file"));
+}
@@ -0,0 +1,186 @@
+use agent_loops::config::{Starter, config_args, parse_config};
+use clap::{Arg, ArgAction, Command};
+
+fn command() -> Command {
+    Command::new("agent-loops")
+        .arg(Arg::new("loops").long("loops").short('l'))
+        .arg(Arg::new("prompts").long("prompts").num_args(1..))
+        .arg(
+            Arg::new("require_clean")
+                .long("require-clean")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(Arg::new("verbose").long("verbose").action(ArgAction::Count))
+        .arg(Arg::new("env").long("env").value_name("KEY=VALUE"))
+        .arg(Arg::new("theme").long("theme").value_name("ROLE=STYLE,..."))
+}
+
+#[test]
+fn test_parse_config_splits_options_and_tasks() {
+    let config = parse_config(
+        "loops = 3\nrequire_clean = true\n\n[[task]]\nname = \"lint\"\nprompt = \"Fix lint\"\n",
+    )
+    .unwrap();
+    assert_eq!(config.options.len(), 2);
+    assert_eq!(config.tasks.len(), 1);
+    assert_eq!(config.tasks[0].name.as_deref(), Some("lint"));
+    assert!(parse_config("[[task]]\nprompt = \"\"\n").is_err());
+}
+
+#[test]
+fn test_config_args() {
+    let config =
+        parse_config("loops = 3\nprompts = [\"a\", \"b c\"]\nrequire_clean = true\nverbose = 2\n")
+            .unwrap();
+    let args = config_args(&command(), &config.options, |_| false).unwrap();
+    assert_eq!(
+        args,
+        [
+            "--loops=3",
+            "--prompts=a",
+            "--prompts=b c",
+            "--require-clean",
+            "--verbose",
+            "--verbose"
+        ]
+    );
+    // What the command line sets is left alone.
+    let args = config_args(&command(), &config.options, |id| id != "loops").unwrap();
+    assert_eq!(args, ["--loops=3"]);
+
+    let config = parse_config(
+        "[env]\nRUST_LOG = \"debug\"\nRETRIES = 2\n\n[theme]\nheader = \"bold blue\"\nfailed = \"red\"\n",
+    )
+    .unwrap();
+    let args = config_args(&command(), &config.options, |_| false).unwrap();
+    assert_eq!(
+        args,
+        [
+            "--env=RETRIES=2",
+            "--env=RUST_LOG=debug",
+            "--theme=failed=red,header=bold blue"
+        ]
+    );
+
+    let config = parse_config("lops = 3\n").unwrap();
+    let err = config_args(&command(), &config.options, |_| false).unwrap_err();
+    assert_eq!(err, "unknown option `lops`");
+    let config = parse_config("require-clean = \"yes\"\n").unwrap();
+    let err = config_args(&command(), &config.options, |_| false).unwrap_err();
+    assert_eq!(err, "`require-clean` takes true or false");
+}
+
+#[test]
+fn test_starter_config_parses_back() {
+    let starter = Starter {
+        prompts: vec!["Fix \"lint\"".to_string(), "Add tests".to_string()],
+        loops: 2,
+        check: Some("cargo test".to_string()),
+        require_clean: true,
+        git_branch_strategy: None,
+    };
+    let config = parse_config(&starter.to_toml()).unwrap();
+    assert_eq!(config.options["loops"].as_integer(), Some(2));
+    assert_eq!(config.options["check"].as_str(), Some("cargo test"));
+    assert_eq!(config.options["require-clean"].as_bool(), Some(true));
+    assert!(!config.options.contains_key("git-branch-strategy"));
+    let prompts: Vec<&str> = config.tasks.iter().map(|t| t.prompt.as_str()).collect();
+    assert_eq!(prompts, ["Fix \"lint\"", "Add tests"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_init_then_run_from_the_config() {
+    let dir = std::env::temp_dir().join(format!("agent-loops-config-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    assert_cmd::cargo::cargo_bin_cmd!("agent-loops")
+        .current_dir(&dir)
+        .arg("init")
+        .write_stdin("say hi\n\n2\nfalse\nn\n\n")
+        .assert()
+        .success();
+    assert_cmd::cargo::cargo_bin_cmd!("agent-loops")
+        .current_dir(&dir)
+        .arg("init")
+        .assert()
+        .failure();
+
+    let run = |extra: &[&str]| {
+        assert_cmd::cargo::cargo_bin_cmd!("agent-loops")
+            .current_dir(&dir)
+            .args(["--runner-template", "echo ran {prompt}"])
+            .args(["--data-dir", "data", "--spool-dir", "spool"])
+            .args(extra)
+            .assert()
+    };
+    // The check in the config fails every run.
+    run(&[])
+        .failure()
+        .stdout(predicates::str::contains("ran say hi"))
+        .stdout(predicates::str::contains("0/2"));
+    // Command-line options win over the config.
+    run(&["--check", "true", "-l", "1"])
+        .success()
+        .stdout(predicates::str::contains("1/1"));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_precedence_is_command_line_then_env_then_config() {
+    let dir = std::env::temp_dir().join(format!("agent-loops-env-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("agent-loops.toml"),
+        "loops = 2\ncheck = \"false\"\n\n[[task]]\nprompt = \"say hi\"\n",
+    )
+    .unwrap();
+
+    let run = |env: &[(&str, &str)], extra: &[&str]| {
+        assert_cmd::cargo::cargo_bin_cmd!("agent-loops")
+            .current_dir(&dir)
+            .envs(env.iter().copied())
+            .env("AGENT_LOOPS_RUNNER_TEMPLATE", "echo ran {prompt}")
+            .args(["--data-dir", "data", "--spool-dir", "spool"])
+            .args(extra)
+            .assert()
+    };
+    run(&[("AGENT_LOOPS_CHECK", "true")], &[])
+        .success()
+        .stdout(predicates::str::contains("ran say hi"))
+        .stdout(predicates::str::contains("2/2"));
+    run(
+        &[("AGENT_LOOPS_CHECK", "true"), ("AGENT_LOOPS_LOOPS", "3")],
+        &["-l", "1"],
+    )
+    .success()
+    .stdout(predicates::str::contains("1/1"));
+    run(&[("AGENT_LOOPS_LOOPS", "1")], &[])
+        .failure()
+        .stdout(predicates::str::contains("0/1"));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_theme_from_the_config() {
+    let dir = std::env::temp_dir().join(format!("agent-loops-theme-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("agent-loops.toml"),
+        "color = \"always\"\nrunner_template = \"echo {prompt}\"\n\n[theme]\nheader = \"underline\"\n\n[[task]]\nprompt = \"say hi\"\n",
+    )
+    .unwrap();
+
+    assert_cmd::cargo::cargo_bin_cmd!("agent-loops")
+        .current_dir(&dir)
+        .args(["--data-dir", "data", "--spool-dir", "spool"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("\x1b[4m==="));
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,90 @@
+#![cfg(unix)]
+
+use agent_loops::RunOptions;
+use agent_loops::container::Container;
+use std::path::PathBuf;
+
+#[test]
+fn test_container_run_args_mount_the_work_dir() {
+    let container = Container {
+        pass_env: vec!["OPENAI_API_KEY".to_string()],
+        ..Container::new("ghcr.io/acme/codex:latest")
+    };
+    let options = RunOptions {
+        work_dir: Some(PathBuf::from("/srv/app")),
+        env: vec![("MODE".to_string(), "ci".to_string())],
+        ..RunOptions::default()
+    };
+    let args = container.run_args(
+        "agent-loops-1-1",
+        "codex",
+        &["exec".to_string(), "fix it".to_string()],
+        &options,
+    );
+
+    assert_eq!(
+        args[..9],
+        [
+            "run",
+            "--rm",
+            "--init",
+            "--name",
+            "agent-loops-1-1",
+            "--volume",
+            "/srv/app:/srv/app",
+            "--workdir",
+            "/srv/app"
+        ]
+    );
+    let image = args
+        .iter()
+        .position(|arg| arg == "ghcr.io/acme/codex:latest")
+        .unwrap();
+    assert_eq!(args[image + 1..], ["codex", "exec", "fix it"]);
+    let env = args[..image].join(" ");
+    assert!(env.contains("--env OPENAI_API_KEY --env MODE=ci"), "{env}");
+    assert_ne!(Container::next_name(), Container::next_name());
+}
+
+#[test]
+fn test_cli_runs_the_agent_through_the_container_engine() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("agent-loops-container-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let work = dir.join("work");
+    std::fs::create_dir_all(&work).unwrap();
+    let calls = dir.join("calls.txt");
+    // Stands in for docker: logs each call and runs what follows the image.
+    let engine = dir.join("engine.sh");
+    std::fs::write(
+        &engine,
+        format!(
+            "#!/bin/sh\necho \"$1 $3\" >> '{calls}'\n[ \"$1\" = run ] || exit 1\nwhile [ \"$1\" != test-image ]; do shift; done\nshift\nexec \"$@\"\n",
+            calls = calls.display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&engine, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    assert_cmd::cargo::cargo_bin_cmd!("agent-loops")
+        .args(["-p", "tidy up", "--runner-template", "echo ran {prompt}"])
+        .args(["--in-container", "test-image", "--container-engine"])
+        .arg(&engine)
+        .arg("-C")
+        .arg(&work)
+        .arg("--data-dir")
+        .arg(dir.join("data"))
+        .arg("--spool-dir")
+        .arg(dir.join("spool"))
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("ran tidy up"));
+
+    let calls = std::fs::read_to_string(&calls).unwrap();
+    let calls: Vec<&str> = calls.lines().collect();
+    assert!(calls[0].starts_with("run --init"), "{calls:?}");
+    // The container is removed after the run, in case the engine left it behind.
+    assert!(calls[1].starts_with("rm "), "{calls:?}");
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,71 @@
+use agent_loops::disk::{DiskGuard, LowDiskAction, format_bytes, free_space};
+use agent_loops::testing::MockRunner;
+use agent_loops::{CancelToken, OrchestrateOptions, TaskSpec, orchestrate_runner};
+
+fn guard(min_free: u64, action: LowDiskAction) -> DiskGuard {
+    DiskGuard {
+        min_free,
+        dirs: vec![
+            std::env::temp_dir(),
+            std::env::temp_dir().join("agent-loops-not-created-yet"),
+        ],
+        action,
+    }
+}
+
+#[test]
+fn test_free_space_of_the_temp_dir() {
+    assert!(free_space(&std::env::temp_dir()).unwrap() > 0);
+    assert!(free_space(std::path::Path::new("/definitely/not/here")).is_err());
+}
+
+#[test]
+fn test_format_bytes() {
+    assert_eq!(format_bytes(512), "512 B");
+    assert_eq!(format_bytes(300 << 20), "300.0 MiB");
+    assert_eq!(format_bytes(3 << 29), "1.5 GiB");
+}
+
+#[test]
+fn test_low_disk_action_parses() {
+    assert_eq!("pause".parse(), Ok(LowDiskAction::Pause));
+    assert_eq!("abort".parse(), Ok(LowDiskAction::Abort));
+    assert!("ignore".parse::<LowDiskAction>().is_err());
+    assert_eq!(LowDiskAction::Pause.to_string(), "pause");
+}
+
+#[test]
+fn test_low_dir_compares_against_the_threshold() {
+    assert_eq!(guard(1, LowDiskAction::Abort).low_dir(), None);
+    let low = guard(u64::MAX, LowDiskAction::Abort);
+    assert_eq!(
+        low.low_dir().map(|(dir, _)| dir),
+        Some(std::env::temp_dir().as_path())
+    );
+}
+
+#[tokio::test]
+async fn test_paused_session_stops_when_cancelled() {
+    let cancel = CancelToken::new();
+    cancel.cancel();
+    assert!(
+        !guard(u64::MAX, LowDiskAction::Pause)
+            .wait_for_space(&cancel)
+            .await
+    );
+    assert!(guard(1, LowDiskAction::Pause).wait_for_space(&cancel).await);
+}
+
+#[tokio::test]
+async fn test_session_aborts_before_a_run_without_space() {
+    let runner = MockRunner::new();
+    let options = OrchestrateOptions {
+        disk_guard: Some(guard(u64::MAX, LowDiskAction::Abort)),
+        ..OrchestrateOptions::default()
+    };
+
+    let results = orchestrate_runner(&[TaskSpec::new("build")], 3, &options, &runner).await;
+
+    assert!(results.is_empty());
+    assert_eq!(runner.call_count(), 0);
+}
@@ -0,0 +1,97 @@
+use agent_loops::doctor::{CheckResult, CheckStatus, format_checklist};
+use agent_loops::{Version, VersionReq};
+
+// --- version parsing ---
+
+#[test]
+fn test_version_parse_forms() {
+    assert_eq!(Version::parse("0.30.1"), Some(Version::new(0, 30, 1)));
+    assert_eq!(Version::parse("v1.2"), Some(Version::new(1, 2, 0)));
+    assert_eq!(Version::parse("1.2.3-beta.1"), Some(Version::new(1, 2, 3)));
+    assert_eq!(Version::parse("1.2.3.4"), None);
+    assert_eq!(Version::parse("latest"), None);
+}
+
+#[test]
+fn test_version_find_in_cli_output() {
+    assert_eq!(
+        Version::find_in("codex-cli 0.30.0"),
+        Some(Version::new(0, 30, 0))
+    );
+    assert_eq!(Version::find_in("no version here"), None);
+    assert!(Version::new(0, 29, 9) < Version::new(0, 30, 0));
+}
+
+#[test]
+fn test_version_req_comparators() {
+    let req = VersionReq::parse(">=0.30, <0.40").unwrap();
+    assert!(req.matches(Version::new(0, 30, 0)));
+    assert!(req.matches(Version::new(0, 39, 9)));
+    assert!(!req.matches(Version::new(0, 29, 1)));
+    assert!(!req.matches(Version::new(0, 40, 0)));
+    assert_eq!(req.to_string(), ">=0.30.0, <0.40.0");
+
+    assert!(
+        VersionReq::parse("0.30")
+            .unwrap()
+            .matches(Version::new(1, 0, 0))
+    );
+    assert!(
+        VersionReq::parse("=1.2.3")
+            .unwrap()
+            .matches(Version::new(1, 2, 3))
+    );
+    assert!(VersionReq::parse(">=latest").is_err());
+}
+
+// --- checklist ---
+
+#[test]
+fn test_format_checklist_indents_continuation_lines() {
+    let checks = vec![
+        CheckResult {
+            name: "agent binary",
+            status: CheckStatus::Fail,
+            detail: "not found\nHint: use --codex-bin".to_string(),
+        },
+        CheckResult {
+            name: "terminal",
+            status: CheckStatus::Warn,
+            detail: "not a terminal".to_string(),
+        },
+    ];
+    assert_eq!(
+        format_checklist(&checks),
+        "[FAIL] agent binary: not found\n       Hint: use --codex-bin\n[WARN] terminal: not a terminal\n"
+    );
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_doctor_flags_outdated_agent_and_empty_prompts_file() {
+    use agent_loops::doctor::{DoctorOptions, run_doctor};
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("agent-loops-doctor-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let bin = dir.join("codex");
+    std::fs::write(&bin, "#!/bin/sh\necho 'codex-cli 0.1.0'\n").unwrap();
+    std::fs::set_permissions(&bin, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let prompts = dir.join("prompts.txt");
+    std::fs::write(&prompts, "\n  \n").unwrap();
+
+    let checks = run_doctor(&DoctorOptions {
+        codex_bin: bin.to_str().unwrap().to_string(),
+        work_dir: Some(dir.clone()),
+        prompts_file: Some(prompts),
+        ..DoctorOptions::default()
+    })
+    .await;
+    let status_of = |name: &str| checks.iter().find(|c| c.name == name).unwrap().status;
+
+    assert_eq!(status_of("agent binary"), CheckStatus::Pass);
+    assert_eq!(status_of("agent version"), CheckStatus::Fail);
+    assert_eq!(status_of("work dir"), CheckStatus::Pass);
+    assert_eq!(status_of("prompts file"), CheckStatus::Fail);
+    let _ = std::fs::remove_dir_all(&dir);
+}
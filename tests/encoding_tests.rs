@@ -0,0 +1,38 @@
+use agent_loops::encoding::OutputDecoder;
+
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| char::from(b)).collect()
+}
+
+#[test]
+fn test_decoder_passes_utf8_through() {
+    let mut decoder = OutputDecoder::with_fallback(latin1);
+    assert_eq!(
+        decoder.push("héllo 日本".as_bytes()),
+        "héllo 日本".as_bytes()
+    );
+    assert!(decoder.finish().is_empty());
+}
+
+#[test]
+fn test_decoder_joins_sequences_split_across_chunks() {
+    let mut decoder = OutputDecoder::with_fallback(latin1);
+    let bytes = "日本".as_bytes();
+    assert_eq!(decoder.push(&bytes[..2]), Vec::<u8>::new());
+    assert_eq!(decoder.push(&bytes[2..4]), "日".as_bytes());
+    assert_eq!(decoder.push(&bytes[4..]), "本".as_bytes());
+}
+
+#[test]
+fn test_decoder_uses_fallback_for_invalid_utf8() {
+    let mut decoder = OutputDecoder::with_fallback(latin1);
+    // "café" in a single-byte codepage.
+    assert_eq!(decoder.push(b"caf\xe9 ok"), "café ok".as_bytes());
+}
+
+#[test]
+fn test_decoder_finish_flushes_incomplete_tail() {
+    let mut decoder = OutputDecoder::with_fallback(latin1);
+    assert!(decoder.push(b"ab\xe6").starts_with(b"ab"));
+    assert_eq!(decoder.finish(), "æ".as_bytes());
+}
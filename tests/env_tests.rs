@@ -0,0 +1,54 @@
+use agent_loops::{RunOptions, TaskSpec};
+
+#[test]
+fn test_for_task_layers_task_env_over_session_env() {
+    let options = RunOptions {
+        env: vec![("MODE".to_string(), "session".to_string())],
+        ..RunOptions::default()
+    };
+    let mut task = TaskSpec::new("prompt");
+    task.env.insert("MODE".to_string(), "task".to_string());
+    task.env_allowlist = Some(vec!["PATH".to_string()]);
+
+    let merged = options.for_task(&task);
+    assert_eq!(
+        merged.env.last(),
+        Some(&("MODE".to_string(), "task".to_string()))
+    );
+    assert_eq!(merged.env_allowlist, Some(vec!["PATH".to_string()]));
+    assert_eq!(
+        options.for_task(&TaskSpec::new("other")).env_allowlist,
+        None
+    );
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_run_codex_applies_env_and_allowlist() {
+    use agent_loops::run_codex;
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("agent-loops-env-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let out = dir.join("env.txt");
+    let script = dir.join("fake-codex.sh");
+    std::fs::write(
+        &script,
+        format!(
+            "#!/bin/sh\nprintf '%s|%s' \"$AGENT_LOOPS_TEST_VAR\" \"${{HOME:-unset}}\" > '{}'\n",
+            out.display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let options = RunOptions {
+        codex_bin: script.to_string_lossy().into_owned(),
+        env: vec![("AGENT_LOOPS_TEST_VAR".to_string(), "hello".to_string())],
+        env_allowlist: Some(vec!["PATH".to_string()]),
+        ..RunOptions::default()
+    };
+    assert!(run_codex("prompt", &options).await.unwrap().success);
+    assert_eq!(std::fs::read_to_string(&out).unwrap(), "hello|unset");
+    let _ = std::fs::remove_dir_all(&dir);
+}
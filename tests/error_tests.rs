@@ -0,0 +1,41 @@
+use agent_loops::testing::{MockRunner, MockStep};
+use agent_loops::{AgentLoopsError, OrchestrateOptions, TaskSpec, orchestrate_runner};
+use std::io;
+use std::time::Duration;
+
+#[test]
+fn test_io_error_conversion_keeps_cancellation() {
+    let err: AgentLoopsError = io::Error::new(io::ErrorKind::Interrupted, "stop").into();
+    assert!(matches!(err, AgentLoopsError::Cancelled));
+
+    let err: AgentLoopsError = io::Error::new(io::ErrorKind::BrokenPipe, "gone").into();
+    assert!(matches!(err, AgentLoopsError::ChildIo(_)));
+}
+
+#[test]
+fn test_conversion_back_to_io_error_kind() {
+    let timeout: io::Error = AgentLoopsError::Timeout(Duration::from_secs(5)).into();
+    assert_eq!(timeout.kind(), io::ErrorKind::TimedOut);
+    assert!(timeout.to_string().contains("timeout of 5s"));
+
+    let missing: io::Error = AgentLoopsError::BinaryNotFound {
+        program: "codex".to_string(),
+        detail: "it was not found in PATH".to_string(),
+    }
+    .into();
+    assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+}
+
+#[tokio::test]
+async fn test_orchestrate_runner_stops_on_cancelled_error() {
+    let runner = MockRunner::new()
+        .step(MockStep::error(|| {
+            AgentLoopsError::Timeout(Duration::from_secs(1))
+        }))
+        .step(MockStep::error(|| AgentLoopsError::Cancelled));
+    let tasks = ["a", "b", "c"].map(TaskSpec::new);
+    let results = orchestrate_runner(&tasks, 1, &OrchestrateOptions::default(), &runner).await;
+
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|r| !r.outcome.success));
+}
@@ -0,0 +1,89 @@
+mod common;
+
+use agent_loops::LineFilter;
+use agent_loops::fixture::FixtureStream;
+use common::{replay, temp_dir, write_events};
+use predicates::prelude::*;
+
+#[test]
+fn test_empty_filter_shows_everything() {
+    let filter = LineFilter::new();
+    assert!(filter.is_empty());
+    assert!(filter.shows("anything"));
+    assert!(filter.shows(""));
+}
+
+#[test]
+fn test_include_patterns_show_only_matching_lines() {
+    let filter = LineFilter::new()
+        .with_include("^error")
+        .unwrap()
+        .with_include("warning")
+        .unwrap();
+    assert!(filter.shows("error: build failed"));
+    assert!(filter.shows("1 warning emitted"));
+    assert!(!filter.shows("Compiling agent-loops"));
+}
+
+#[test]
+fn test_exclude_patterns_win_over_includes() {
+    let filter = LineFilter::new()
+        .with_include("npm")
+        .unwrap()
+        .with_exclude(r"npm (http|timing)")
+        .unwrap();
+    assert!(filter.shows("npm ERR! missing script"));
+    assert!(!filter.shows("npm timing idealTree Completed in 120ms"));
+}
+
+#[test]
+fn test_invalid_pattern_is_rejected() {
+    assert!(LineFilter::new().with_exclude("(unclosed").is_err());
+}
+
+#[test]
+fn test_cli_hides_filtered_lines() {
+    let dir = temp_dir("filter-cli");
+    write_events(
+        &dir.join("fixtures"),
+        "run-0001.fixture",
+        &[
+            (
+                FixtureStream::Stdout,
+                b"Installing\n[##  ] 50%\r[####] 100%\nInst",
+            ),
+            (
+                FixtureStream::Stdout,
+                b"alled 42 packages\n\x1b[32mdone\x1b[0m",
+            ),
+        ],
+        0,
+    );
+
+    replay(&dir)
+        .args([
+            "-p",
+            "install",
+            "--grep-v",
+            r"^\[#+ *\]",
+            "--grep-v",
+            "^done$",
+        ])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "Installing\nInstalled 42 packages\n",
+        ))
+        .stdout(predicates::str::contains("100%").not())
+        .stdout(predicates::str::contains("done\u{1b}").not())
+        .stdout(predicates::str::contains("Full output: "));
+
+    let log = std::fs::read_dir(dir.join("spool"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.is_file())
+        .expect("the unfiltered output is spooled");
+    let log = String::from_utf8_lossy(&std::fs::read(log).unwrap()).into_owned();
+    assert!(log.contains("100%"), "{log}");
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,312 @@
+mod common;
+
+use agent_loops::git::{
+    BranchStrategy, Checkpoint, CleanPolicy, DiffStat, Worktree, diff_stat, ensure_clean, git,
+    is_dirty, snapshot,
+};
+use agent_loops::{OrchestrateOptions, orchestrate_with};
+use assert_cmd::cargo::cargo_bin_cmd;
+use common::{init_repo, temp_dir};
+use predicates::prelude::*;
+use std::path::{Path, PathBuf};
+
+async fn temp_repo(name: &str) -> PathBuf {
+    let dir = temp_dir(&format!("git-{name}"));
+    std::fs::write(dir.join("tracked.txt"), "one\n").unwrap();
+    init_repo(&dir).await;
+    dir
+}
+
+fn read(dir: &Path, file: &str) -> String {
+    std::fs::read_to_string(dir.join(file)).unwrap()
+}
+
+#[tokio::test]
+async fn test_dirty_detection_includes_untracked_files() {
+    let dir = temp_repo("dirty").await;
+    assert!(!is_dirty(&dir).await.unwrap());
+
+    std::fs::write(dir.join("new.txt"), "x").unwrap();
+    assert!(is_dirty(&dir).await.unwrap());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_auto_stash_round_trip() {
+    let dir = temp_repo("stash").await;
+    std::fs::write(dir.join("tracked.txt"), "two\n").unwrap();
+    std::fs::write(dir.join("new.txt"), "x").unwrap();
+
+    let stash = ensure_clean(&dir, CleanPolicy::AutoStash)
+        .await
+        .unwrap()
+        .expect("changes were stashed");
+    assert!(stash.restore_after);
+    assert!(!is_dirty(&dir).await.unwrap());
+    assert_eq!(read(&dir, "tracked.txt"), "one\n");
+
+    stash.restore().await.unwrap();
+    assert_eq!(read(&dir, "tracked.txt"), "two\n");
+    assert_eq!(read(&dir, "new.txt"), "x");
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_require_clean_refuses_dirty_tree_without_terminal() {
+    let dir = temp_repo("require").await;
+    assert!(
+        ensure_clean(&dir, CleanPolicy::Require)
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    std::fs::write(dir.join("tracked.txt"), "two\n").unwrap();
+    cargo_bin_cmd!("agent-loops")
+        .args([
+            "--require-clean",
+            "--codex-bin",
+            "agent-loops-not-run",
+            "-p",
+            "task",
+            "-C",
+        ])
+        .arg(&dir)
+        .write_stdin("")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("uncommitted changes"));
+    assert_eq!(read(&dir, "tracked.txt"), "two\n");
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_numstat_parsing() {
+    let stat = DiffStat::parse_numstat("3\t1\tsrc/lib.rs\n-\t-\tlogo.png\n10\t0\tREADME.md\n");
+    assert_eq!(
+        stat,
+        DiffStat {
+            files_changed: 3,
+            insertions: 13,
+            deletions: 1,
+        }
+    );
+    assert_eq!(stat.to_string(), "3 files changed, +13 -1");
+    assert!(DiffStat::parse_numstat("").is_empty());
+}
+
+#[tokio::test]
+async fn test_snapshot_diff_sees_uncommitted_and_untracked_changes() {
+    let dir = temp_repo("snapshot").await;
+    let before = snapshot(&dir).await.unwrap();
+    assert_eq!(snapshot(&dir).await.unwrap(), before);
+
+    std::fs::write(dir.join("tracked.txt"), "one\ntwo\n").unwrap();
+    std::fs::write(dir.join("new.txt"), "x\n").unwrap();
+    let after = snapshot(&dir).await.unwrap();
+
+    let stat = diff_stat(&dir, &before, &after).await.unwrap();
+    assert_eq!(stat.files_changed, 2);
+    assert_eq!(stat.insertions, 2);
+    // Snapshots leave the real index alone.
+    assert_eq!(
+        git(&dir, &["diff", "--cached", "--name-only"])
+            .await
+            .unwrap(),
+        ""
+    );
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_orchestrator_reports_run_changes() {
+    let dir = temp_repo("orchestrate").await;
+    let options = OrchestrateOptions {
+        git_work_dir: Some(dir.clone()),
+        ..OrchestrateOptions::default()
+    };
+    let prompts = ["edit".to_string(), "talk".to_string()];
+
+    let results = orchestrate_with(&prompts, 1, &options, |prompt| {
+        let dir = dir.clone();
+        async move {
+            if prompt == "edit" {
+                std::fs::write(dir.join("tracked.txt"), "changed\n")?;
+            }
+            Ok(true)
+        }
+    })
+    .await;
+
+    assert_eq!(results[0].outcome.changes.unwrap().files_changed, 1);
+    assert!(results[1].outcome.changes.unwrap().is_empty());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_require_changes_fails_and_nudges_idle_runs() {
+    let dir = temp_repo("require-changes").await;
+    let options = OrchestrateOptions {
+        git_work_dir: Some(dir.clone()),
+        require_changes: true,
+        no_change_retries: 1,
+        ..OrchestrateOptions::default()
+    };
+    let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+
+    let results = orchestrate_with(&["talk".to_string()], 2, &options, |prompt| {
+        let dir = dir.clone();
+        let seen = seen.clone();
+        async move {
+            let nudged = prompt.contains("made no changes");
+            seen.lock().unwrap().push(nudged);
+            // Only the first reminded attempt edits anything.
+            if nudged && seen.lock().unwrap().len() == 2 {
+                std::fs::write(dir.join("tracked.txt"), "changed\n")?;
+            }
+            Ok(true)
+        }
+    })
+    .await;
+
+    assert_eq!(*seen.lock().unwrap(), [false, true, false, true]);
+    assert!(results[0].outcome.success);
+    assert_eq!(results[0].outcome.changes.unwrap().files_changed, 1);
+    assert!(!results[1].outcome.success);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_checkpoint_restore_undoes_edits_new_files_and_commits() {
+    let dir = temp_repo("checkpoint").await;
+    std::fs::write(dir.join("staged.txt"), "staged\n").unwrap();
+    git(&dir, &["add", "staged.txt"]).await.unwrap();
+    let head = git(&dir, &["rev-parse", "HEAD"]).await.unwrap();
+    let checkpoint = Checkpoint::create(&dir).await.unwrap();
+
+    std::fs::write(dir.join("tracked.txt"), "agent edit\n").unwrap();
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(dir.join("src/new.rs"), "fn main() {}\n").unwrap();
+    git(&dir, &["add", "--all"]).await.unwrap();
+    git(&dir, &["commit", "-q", "-m", "agent commit"])
+        .await
+        .unwrap();
+    std::fs::remove_file(dir.join("staged.txt")).unwrap();
+
+    checkpoint.restore().await.unwrap();
+
+    assert_eq!(git(&dir, &["rev-parse", "HEAD"]).await.unwrap(), head);
+    assert_eq!(read(&dir, "tracked.txt"), "one\n");
+    assert_eq!(read(&dir, "staged.txt"), "staged\n");
+    assert!(!dir.join("src/new.rs").exists());
+    assert_eq!(
+        git(&dir, &["status", "--porcelain"]).await.unwrap(),
+        "A  staged.txt"
+    );
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_failed_runs_are_rolled_back() {
+    let dir = temp_repo("rollback").await;
+    let options = OrchestrateOptions {
+        git_work_dir: Some(dir.clone()),
+        rollback_on_failure: true,
+        ..OrchestrateOptions::default()
+    };
+    let prompts = ["good".to_string(), "bad".to_string()];
+
+    let results = orchestrate_with(&prompts, 1, &options, |prompt| {
+        let dir = dir.clone();
+        async move {
+            std::fs::write(dir.join(format!("{prompt}.txt")), "x\n")?;
+            Ok(prompt == "good")
+        }
+    })
+    .await;
+
+    assert_eq!(results[1].outcome.changes.unwrap().files_changed, 1);
+    assert!(dir.join("good.txt").exists());
+    assert!(!dir.join("bad.txt").exists());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_loop_branches_hold_each_loops_work() {
+    let dir = temp_repo("branches").await;
+    let options = OrchestrateOptions {
+        git_work_dir: Some(dir.clone()),
+        branch_strategy: Some(BranchStrategy::Loop),
+        ..OrchestrateOptions::default()
+    };
+    let loop_no = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let utc_date = || {
+        chrono::Utc::now()
+            .format("agent-loops/%Y-%m-%d/")
+            .to_string()
+    };
+    let dates = [utc_date()];
+
+    orchestrate_with(&["edit".to_string()], 2, &options, |_prompt| {
+        let dir = dir.clone();
+        let n = loop_no.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+        async move {
+            std::fs::write(dir.join("tracked.txt"), format!("loop {n}\n"))?;
+            Ok(true)
+        }
+    })
+    .await;
+
+    let branches = git(
+        &dir,
+        &[
+            "branch",
+            "--list",
+            "--format=%(refname:short)",
+            "agent-loops/*",
+        ],
+    )
+    .await
+    .unwrap();
+    let branches: Vec<&str> = branches.lines().collect();
+    assert_eq!(branches.len(), 2);
+    let dates = [dates[0].clone(), utc_date()];
+    assert!(
+        dates
+            .iter()
+            .any(|date| branches[0].starts_with(date.as_str()))
+    );
+    assert!(branches[0].ends_with("/loop-1"));
+    assert!(branches[1].ends_with("/loop-2"));
+    for (branch, content) in branches.iter().zip(["loop 1\n", "loop 2\n"]) {
+        let file = git(&dir, &["show", &format!("{branch}:tracked.txt")])
+            .await
+            .unwrap();
+        assert_eq!(format!("{file}\n"), content);
+    }
+    assert!(!is_dirty(&dir).await.unwrap());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_branch_strategy_parsing() {
+    assert_eq!("task".parse::<BranchStrategy>(), Ok(BranchStrategy::Task));
+    assert!("weekly".parse::<BranchStrategy>().is_err());
+}
+
+#[tokio::test]
+async fn test_worktree_starts_from_head_and_is_removed() {
+    let dir = temp_repo("worktree").await;
+    std::fs::write(dir.join("tracked.txt"), "uncommitted\n").unwrap();
+    let path = dir.with_extension("wt");
+    let _ = std::fs::remove_dir_all(&path);
+
+    let worktree = Worktree::add(&dir, &path).await.unwrap();
+    assert_eq!(read(&path, "tracked.txt"), "one\n");
+    std::fs::write(path.join("new.txt"), "work").unwrap();
+
+    worktree.remove().await.unwrap();
+    assert!(!path.exists());
+    assert_eq!(read(&dir, "tracked.txt"), "uncommitted\n");
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,147 @@
+use agent_loops::agent::Agent;
+use agent_loops::launch::{
+    cmd_exe_command_line, escape_cmd_metachars, find_executable_in, not_found_diagnostic,
+    quote_windows_arg, sandbox_bypass_notice,
+};
+use std::path::PathBuf;
+
+// --- executable lookup ---
+
+#[cfg(unix)]
+fn make_executable(path: &std::path::Path) {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::write(path, "#!/bin/sh\n").unwrap();
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_find_executable_in_path_with_extensions() {
+    let dir = std::env::temp_dir().join(format!("agent-loops-launch-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    make_executable(&dir.join("codex.cmd"));
+
+    let path_var = std::env::join_paths([std::path::Path::new("/nonexistent"), &dir]).unwrap();
+    assert_eq!(
+        find_executable_in("codex", &path_var, &[".exe", ".cmd"]),
+        Some(dir.join("codex.cmd"))
+    );
+    assert_eq!(find_executable_in("codex", &path_var, &[]), None);
+    assert_eq!(
+        find_executable_in("codex.cmd", &path_var, &[]),
+        Some(dir.join("codex.cmd"))
+    );
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_not_found_diagnostic_lists_search_dirs_and_hint() {
+    let text = not_found_diagnostic("agent-loops-definitely-missing-binary");
+    assert!(text.contains("Searched PATH:"));
+    assert!(text.contains("Searched common install locations:"));
+    assert!(text.contains("--codex-bin"));
+}
+
+#[test]
+fn test_not_found_diagnostic_for_explicit_path() {
+    let text = not_found_diagnostic("./missing/codex");
+    assert!(text.contains("does not exist"));
+    assert!(!text.contains("Searched PATH:"));
+}
+
+#[test]
+fn test_sandbox_bypass_notice_names_the_dirs_and_runs() {
+    let dirs = [PathBuf::from("/"), PathBuf::from("/srv/app")];
+    let text = sandbox_bypass_notice(Agent::Codex, &dirs, 60);
+    assert!(
+        text.starts_with(
+            "About to start 60 codex run(s) with --dangerously-bypass-approvals-and-sandbox in:\n  /\n  /srv/app\n"
+        ),
+        "{text}"
+    );
+    let text = sandbox_bypass_notice(Agent::Aider, &dirs[..1], 1);
+    assert!(
+        text.starts_with("About to start 1 aider run(s) with --yes in:\n  /\n"),
+        "{text}"
+    );
+}
+
+#[test]
+fn test_find_executable_in_rejects_empty_name() {
+    assert_eq!(
+        find_executable_in("", std::ffi::OsStr::new("/bin"), &[]),
+        None
+    );
+}
+
+// --- Windows quoting ---
+
+#[test]
+fn test_quote_windows_arg_plain() {
+    assert_eq!(quote_windows_arg("codex"), "codex");
+    assert_eq!(quote_windows_arg(""), "\"\"");
+}
+
+#[test]
+fn test_quote_windows_arg_spaces_and_quotes() {
+    assert_eq!(quote_windows_arg("say \"hi\""), r#""say \"hi\"""#);
+    assert_eq!(
+        quote_windows_arg(r"C:\dir with space\"),
+        r#""C:\dir with space\\""#
+    );
+}
+
+#[test]
+fn test_escape_cmd_metachars() {
+    assert_eq!(escape_cmd_metachars("100% & done!"), "100^% ^& done^!");
+    assert_eq!(escape_cmd_metachars("a^b"), "a^^b");
+}
+
+#[test]
+fn test_cmd_exe_command_line() {
+    let line = cmd_exe_command_line(
+        "codex",
+        &["exec".to_string(), "fix \"50%\" bug".to_string()],
+    );
+    assert_eq!(line, r#"/d /c codex exec ^"fix \^"50^%\^" bug^""#);
+}
+
+// --- shell fallback ---
+
+#[test]
+fn test_shell_fallback_flags() {
+    use agent_loops::ShellFallback;
+    use agent_loops::launch::shell_fallback_flags;
+
+    assert_eq!(
+        shell_fallback_flags("/bin/zsh", ShellFallback::Interactive),
+        vec!["-i", "-lc"]
+    );
+    assert_eq!(
+        shell_fallback_flags("/usr/bin/fish", ShellFallback::Interactive),
+        vec!["-lc"]
+    );
+    assert_eq!(
+        shell_fallback_flags("/bin/bash", ShellFallback::NonInteractive),
+        vec!["-lc"]
+    );
+    assert!(shell_fallback_flags("/bin/bash", ShellFallback::Disabled).is_empty());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_disabled_shell_fallback_reports_not_found() {
+    use agent_loops::{AgentLoopsError, RunOptions, ShellFallback, run_codex};
+
+    let options = RunOptions {
+        codex_bin: "agent-loops-definitely-missing-binary".to_string(),
+        shell_fallback: ShellFallback::Disabled,
+        ..RunOptions::default()
+    };
+    let err = run_codex("prompt", &options).await.unwrap_err();
+    assert!(
+        matches!(err, AgentLoopsError::BinaryNotFound { .. }),
+        "{err}"
+    );
+    assert!(err.to_string().contains("--codex-bin"));
+}
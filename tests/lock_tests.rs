@@ -0,0 +1,53 @@
+mod common;
+
+use agent_loops::lock::{LockHolder, SessionLock, lock_path, process_alive};
+use common::temp_dir;
+
+#[test]
+fn test_lock_holder_parsing() {
+    let holder = LockHolder::parse("pid=4242\nstarted=2024-06-01 02:00:00 +00:00\n").unwrap();
+    assert_eq!(holder.pid, 4242);
+    assert_eq!(holder.started, "2024-06-01 02:00:00 +00:00");
+    assert_eq!(LockHolder::parse("started=now\n"), None);
+}
+
+#[test]
+fn test_current_process_is_alive() {
+    assert!(process_alive(std::process::id()));
+}
+
+#[tokio::test]
+async fn test_second_session_is_refused_until_released() {
+    let dir = temp_dir("lock-refuse");
+    let lock = SessionLock::acquire(&dir, false).await.unwrap();
+    assert!(lock.path().exists());
+    let content = std::fs::read_to_string(lock.path()).unwrap();
+    assert_eq!(LockHolder::parse(&content).unwrap().pid, std::process::id());
+
+    let err = SessionLock::acquire(&dir, false).await.unwrap_err();
+    assert!(err.to_string().contains("--force"), "{err}");
+
+    let path = lock.path().to_path_buf();
+    drop(lock);
+    assert!(!path.exists());
+    drop(SessionLock::acquire(&dir, false).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_force_takes_over_a_live_lock() {
+    let dir = temp_dir("lock-force");
+    let first = SessionLock::acquire(&dir, false).await.unwrap();
+    let second = SessionLock::acquire(&dir, true).await.unwrap();
+    // The first session's release must not remove the second's lock.
+    drop(first);
+    assert!(second.path().exists());
+}
+
+#[tokio::test]
+async fn test_stale_lock_is_taken_over() {
+    let dir = temp_dir("lock-stale");
+    let path = lock_path(&dir).await;
+    std::fs::write(&path, "pid=4294967295\nstarted=long ago\n").unwrap();
+    let lock = SessionLock::acquire(&dir, false).await.unwrap();
+    assert_eq!(lock.path(), path);
+}
@@ -0,0 +1,28 @@
+use agent_loops::logging::{LogFormat, verbosity};
+use tracing_subscriber::filter::LevelFilter;
+
+#[test]
+fn test_verbosity_levels() {
+    assert_eq!(verbosity(0, 0), LevelFilter::INFO);
+    assert_eq!(verbosity(1, 0), LevelFilter::DEBUG);
+    assert_eq!(verbosity(3, 0), LevelFilter::TRACE);
+    assert_eq!(verbosity(0, 1), LevelFilter::WARN);
+    assert_eq!(verbosity(0, 2), LevelFilter::ERROR);
+    assert_eq!(verbosity(0, 5), LevelFilter::ERROR);
+}
+
+#[test]
+fn test_log_format_parsing() {
+    assert_eq!("text".parse::<LogFormat>(), Ok(LogFormat::Text));
+    assert_eq!("JSON".parse::<LogFormat>(), Ok(LogFormat::Json));
+    assert!("yaml".parse::<LogFormat>().is_err());
+}
+
+#[test]
+fn test_quiet_conflicts_with_verbose() {
+    assert_cmd::cargo::cargo_bin_cmd!("agent-loops")
+        .args(["--prompts", "x", "-v", "-q"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("cannot be used with"));
+}
@@ -0,0 +1,23 @@
+use agent_loops::notify::notification_command;
+
+#[test]
+fn test_notification_command_carries_title_and_body() {
+    let cmd = notification_command("agent-loops", "Run 2/4 failed: \"fix\" it");
+    let args: Vec<String> = cmd
+        .as_std()
+        .get_args()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    let joined = args.join(" ");
+    assert!(joined.contains("agent-loops"));
+    assert!(joined.contains("Run 2/4 failed"));
+    if cfg!(target_os = "macos") {
+        assert!(
+            joined.contains(r#"\"fix\""#),
+            "quotes must be escaped: {joined}"
+        );
+    } else if !cfg!(windows) {
+        assert_eq!(cmd.as_std().get_program(), "notify-send");
+        assert_eq!(args.last().unwrap(), "Run 2/4 failed: \"fix\" it");
+    }
+}
@@ -1,5 +1,11 @@
-use agent_loops::{orchestrate, print_plan, truncate_display};
+#[cfg(unix)]
+use agent_loops::edit_prompt_in_editor;
+use agent_loops::{
+    CancelToken, ExitPolicy, OrchestrateOptions, RunOutcome, RunRecord, StartAt, orchestrate,
+    orchestrate_with, print_plan, prune_spool, truncate_display,
+};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 /// The three prompts used across tests.
 fn test_prompts() -> Vec<String> {
@@ -144,3 +150,276 @@ fn test_print_plan_does_not_panic() {
     let prompts = test_prompts();
     print_plan(&prompts, 2, None);
 }
+
+#[test]
+fn test_cli_plan_truncation_is_configurable() {
+    let dir = std::env::temp_dir().join(format!("agent-loops-plan-len-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let prompt = format!("Refactor {}\nthen test", "the cache ".repeat(8));
+    let plan = |args: &[&str]| {
+        let output = assert_cmd::cargo::cargo_bin_cmd!("agent-loops")
+            .current_dir(&dir)
+            .args([
+                "-p",
+                &prompt,
+                "--runner-template",
+                "echo {prompt}",
+                "--data-dir",
+                "data",
+            ])
+            .args(["--spool-dir", "spool"])
+            .args(args)
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        String::from_utf8(output).unwrap()
+    };
+
+    let truncated: String = prompt.chars().take(57).collect();
+    assert!(plan(&[]).contains(&format!("  1. {truncated}...\n")));
+    assert!(plan(&["--display-len", "20"]).contains("  1. Refactor the cach...\n"));
+    assert!(plan(&["--plan-full"]).contains(&format!(
+        "  1. Refactor {}\n     then test\n",
+        "the cache ".repeat(8)
+    )));
+    std::fs::write(dir.join("agent-loops.toml"), "display-len = 20\n").unwrap();
+    assert!(plan(&[]).contains("  1. Refactor the cach...\n"));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[cfg(unix)]
+fn write_editor_script(name: &str, body: &str) -> std::path::PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = std::env::temp_dir().join(format!("agent-loops-{name}-{}.sh", std::process::id()));
+    std::fs::write(&path, format!("#!/bin/sh\n{body}\n")).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_edit_prompt_in_editor_returns_edited_text() {
+    let editor = write_editor_script("edit", "printf 'edited prompt\\n' > \"$1\"");
+    let edited = edit_prompt_in_editor("original", Some(editor.to_str().unwrap()))
+        .await
+        .unwrap();
+    assert_eq!(edited, "edited prompt");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_orchestrate_with_edit_prompts_carries_edits_to_later_loops() {
+    let editor = write_editor_script("append", "printf '%s!' \"$(cat \"$1\")\" > \"$1\"");
+    let log: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(Vec::new()));
+    let log_clone = Arc::clone(&log);
+    let options = OrchestrateOptions {
+        edit_prompts: true,
+        editor: Some(editor.to_str().unwrap().to_string()),
+        ..OrchestrateOptions::default()
+    };
+
+    orchestrate_with(&["task".to_string()], 2, &options, |prompt| {
+        let log = Arc::clone(&log_clone);
+        async move {
+            log.lock().unwrap().push(prompt);
+            Ok(true)
+        }
+    })
+    .await;
+
+    assert_eq!(*log.lock().unwrap(), vec!["task!", "task!!"]);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_edit_prompts_edits_the_loop_prompt_that_is_sent() {
+    use agent_loops::testing::MockRunner;
+    use agent_loops::{TaskSpec, orchestrate_runner};
+
+    let editor = write_editor_script("append-staged", "printf '%s!' \"$(cat \"$1\")\" > \"$1\"");
+    let options = OrchestrateOptions {
+        edit_prompts: true,
+        editor: Some(editor.to_str().unwrap().to_string()),
+        ..OrchestrateOptions::default()
+    };
+    let task = TaskSpec {
+        loop_prompts: vec!["Implement it".to_string(), "Review it".to_string()],
+        ..TaskSpec::new("unused")
+    };
+    let runner = MockRunner::new();
+
+    orchestrate_runner(&[task], 3, &options, &runner).await;
+
+    runner.assert_prompts(&["Implement it!", "Review it!", "Review it!!"]);
+}
+
+#[tokio::test]
+async fn test_orchestrate_with_records_run_outcomes() {
+    let prompts = test_prompts();
+    let results = orchestrate_with(
+        &prompts,
+        1,
+        &OrchestrateOptions::default(),
+        |prompt| async move {
+            Ok(RunOutcome {
+                success: prompt != "What functions do you have?",
+                output_log: Some(std::path::PathBuf::from(format!("{prompt}.log"))),
+                ..RunOutcome::default()
+            })
+        },
+    )
+    .await;
+
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[1].task_idx, 1);
+    assert!(!results[1].outcome.success);
+    assert_eq!(
+        results[2].outcome.output_log.as_deref(),
+        Some(std::path::Path::new("What type of project is this?.log"))
+    );
+}
+
+#[tokio::test]
+async fn test_orchestrate_with_stops_after_cancel() {
+    let cancel = CancelToken::new();
+    let options = OrchestrateOptions {
+        cancel: cancel.clone(),
+        ..OrchestrateOptions::default()
+    };
+    let prompts = test_prompts();
+
+    let results = orchestrate_with(&prompts, 2, &options, |_prompt| {
+        let cancel = cancel.clone();
+        async move {
+            cancel.cancel();
+            Err::<bool, _>(std::io::Error::new(
+                std::io::ErrorKind::Interrupted,
+                "run cancelled",
+            ))
+        }
+    })
+    .await;
+
+    assert_eq!(results.len(), 1);
+    assert!(!results[0].outcome.success);
+}
+
+fn records(outcomes: &[bool]) -> Vec<RunRecord> {
+    outcomes
+        .iter()
+        .enumerate()
+        .map(|(task_idx, &success)| {
+            RunRecord::new(
+                1,
+                task_idx + 1,
+                RunOutcome::from(success),
+                Duration::from_secs(60),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_exit_policy_parses_and_displays() {
+    assert_eq!("any-failure".parse(), Ok(ExitPolicy::AnyFailure));
+    assert_eq!("always-zero".parse(), Ok(ExitPolicy::AlwaysZero));
+    assert_eq!("threshold:5".parse(), Ok(ExitPolicy::Threshold(5.0)));
+    assert_eq!("threshold:2.5%".parse(), Ok(ExitPolicy::Threshold(2.5)));
+    assert!("threshold:101".parse::<ExitPolicy>().is_err());
+    assert!("threshold:".parse::<ExitPolicy>().is_err());
+    assert!("sometimes".parse::<ExitPolicy>().is_err());
+    assert_eq!(ExitPolicy::Threshold(5.0).to_string(), "threshold:5");
+}
+
+#[test]
+fn test_exit_policy_threshold_tolerates_a_share_of_failures() {
+    let mut outcomes = vec![true; 60];
+    outcomes[7] = false;
+    let one_of_sixty = records(&outcomes);
+    outcomes[8] = false;
+    outcomes[9] = false;
+    outcomes[10] = false;
+    let four_of_sixty = records(&outcomes);
+
+    assert!(!ExitPolicy::AnyFailure.passes(&one_of_sixty));
+    assert!(ExitPolicy::Threshold(5.0).passes(&one_of_sixty));
+    assert!(!ExitPolicy::Threshold(5.0).passes(&four_of_sixty));
+    assert!(ExitPolicy::AlwaysZero.passes(&four_of_sixty));
+    assert!(ExitPolicy::Threshold(0.0).passes(&records(&[true, true])));
+    assert!(ExitPolicy::AnyFailure.passes(&[]));
+}
+
+#[test]
+fn test_start_at_parses_and_skips_earlier_runs() {
+    assert_eq!("37".parse(), Ok(StartAt::Run(37)));
+    assert_eq!("run:37".parse(), Ok(StartAt::Run(37)));
+    assert_eq!("task:3".parse(), Ok(StartAt::Task(3)));
+    assert_eq!("loop:2".parse(), Ok(StartAt::Loop(2)));
+    assert!("0".parse::<StartAt>().is_err());
+    assert!("step:2".parse::<StartAt>().is_err());
+    assert_eq!(StartAt::Loop(2).to_string(), "loop:2");
+
+    assert!(StartAt::Run(37).skips(36, 7, 0));
+    assert!(!StartAt::Run(37).skips(37, 7, 1));
+    assert!(StartAt::Task(3).skips(5, 0, 1));
+    assert!(!StartAt::Task(3).skips(1, 0, 2));
+    assert!(!StartAt::Task(3).skips(6, 1, 0));
+    assert!(StartAt::Loop(3).skips(10, 1, 4));
+    assert!(!StartAt::Loop(3).skips(11, 2, 0));
+}
+
+#[test]
+fn test_cli_rejects_a_start_past_the_end_of_the_session() {
+    for (start, count) in [
+        ("task:3", "2 task(s)"),
+        ("loop:4", "3 loop(s)"),
+        ("7", "6 run(s)"),
+    ] {
+        assert_cmd::cargo::cargo_bin_cmd!("agent-loops")
+            .args(["--loops", "3", "--start-at", start])
+            .args(["--codex-bin", "agent-loops-not-run", "-p", "one", "two"])
+            .assert()
+            .failure()
+            .stderr(predicates::str::contains(format!(
+                "past the end of the session, which has {count}"
+            )));
+    }
+}
+
+#[test]
+fn test_prune_spool_deletes_only_old_run_logs() {
+    let dir = std::env::temp_dir().join(format!("agent-loops-prune-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let session = dir.join("session-20260101-0300");
+    std::fs::create_dir_all(&session).unwrap();
+    let old = std::time::SystemTime::now() - Duration::from_secs(10 * 24 * 60 * 60);
+    let write = |path: std::path::PathBuf, modified: Option<std::time::SystemTime>| {
+        std::fs::write(&path, "log").unwrap();
+        if let Some(modified) = modified {
+            std::fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        }
+        path
+    };
+    let stale = write(dir.join("run-1-0.log"), Some(old));
+    let fresh = write(dir.join("run-1-1.log"), None);
+    let other = write(dir.join("notes.txt"), Some(old));
+    write(session.join("run-2-0.log"), Some(old));
+
+    let week = Duration::from_secs(7 * 24 * 60 * 60);
+    assert_eq!(prune_spool(&dir, week).unwrap(), 2);
+    assert!(!stale.exists());
+    assert!(fresh.exists());
+    assert!(other.exists());
+    assert!(!session.exists());
+    assert_eq!(prune_spool(&dir.join("missing"), week).unwrap(), 0);
+    let _ = std::fs::remove_dir_all(&dir);
+}
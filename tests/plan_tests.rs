@@ -0,0 +1,140 @@
+mod common;
+
+use agent_loops::TaskSpec;
+use agent_loops::plan::{
+    format_plan, judge_prompt, parse_plan, parse_verdict, plan_prompt, step_tasks,
+};
+use common::{replay, temp_dir, write_fixture};
+use std::path::Path;
+
+const PLAN_OUTPUT: &str = "\
+user
+Write a plan.
+codex
+Here is the plan:
+STEP 1: Add the parser
+**STEP 2:** Cover it with tests
+STEP 3: Update the docs
+tokens used: 1200
+";
+
+#[test]
+fn test_parse_plan_reads_the_numbered_steps() {
+    assert_eq!(
+        parse_plan(PLAN_OUTPUT),
+        vec!["Add the parser", "Cover it with tests", "Update the docs"]
+    );
+    assert!(parse_plan("no plan here\n").is_empty());
+}
+
+#[test]
+fn test_parse_plan_keeps_the_last_plan() {
+    // The echoed prompt and an earlier draft come before the final plan.
+    let output = "STEP 1: draft\nSTEP 2: draft two\nSTEP 1: final\nSTEP 3: skipped\nSTEP 2: done\n";
+    assert_eq!(parse_plan(output), vec!["final", "done"]);
+}
+
+#[test]
+fn test_plan_prompt_lines_are_not_steps() {
+    assert!(parse_plan(&plan_prompt("Refactor the cache")).is_empty());
+    let steps = vec!["a".to_string()];
+    assert_eq!(
+        parse_verdict(&judge_prompt("Be strict.", "goal", &steps)),
+        None
+    );
+}
+
+#[test]
+fn test_parse_verdict_takes_the_last_verdict() {
+    assert_eq!(parse_verdict("VERDICT: APPROVE\n"), Some(true));
+    assert_eq!(
+        parse_verdict("VERDICT: APPROVE\nOn second thought:\n**VERDICT: REJECT**\n"),
+        Some(false)
+    );
+    assert_eq!(parse_verdict("looks fine\n"), None);
+}
+
+#[test]
+fn test_step_tasks_carry_the_goal_and_the_plan() {
+    let goal = TaskSpec {
+        name: Some("cache".to_string()),
+        model: Some("o3".to_string()),
+        ..TaskSpec::new("Refactor the cache")
+    };
+    let steps = vec!["Add the parser".to_string(), "Cover it".to_string()];
+    let tasks = step_tasks(&goal, &steps);
+
+    assert_eq!(tasks.len(), 2);
+    assert_eq!(tasks[1].name.as_deref(), Some("cache step 2/2"));
+    assert_eq!(tasks[1].model.as_deref(), Some("o3"));
+    assert_eq!(
+        tasks[1].prompt,
+        "Goal: Refactor the cache\n\nPlan:\n1. Add the parser\n2. Cover it\n\nDo step 2 now: Cover it"
+    );
+    assert_eq!(
+        format_plan(&steps),
+        "=== Plan ===\n  1. Add the parser\n  2. Cover it\n"
+    );
+}
+
+fn plan_first(dir: &Path, approval: &[&str]) -> assert_cmd::assert::Assert {
+    replay(dir)
+        .args(["-p", "Refactor the cache", "--plan-first"])
+        .args(approval)
+        .assert()
+}
+
+#[test]
+fn test_cli_plan_first_runs_the_approved_steps() {
+    let dir = temp_dir("plan-auto");
+    let fixtures = dir.join("fixtures");
+    write_fixture(&fixtures, "run-0001.fixture", PLAN_OUTPUT, 0);
+    for n in 2..=4 {
+        write_fixture(&fixtures, &format!("run-{n:04}.fixture"), "step done\n", 0);
+    }
+
+    let output = plan_first(&dir, &["--plan-auto-approve"]).success();
+    let stdout = String::from_utf8_lossy(&output.get_output().stdout);
+
+    assert!(
+        stdout.contains("=== Plan ===\n  1. Add the parser\n"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("Tasks: 3 | Total runs: 3"), "{stdout}");
+    assert!(
+        stdout.contains("All tasks completed successfully."),
+        "{stdout}"
+    );
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_cli_plan_first_stops_when_the_judge_rejects() {
+    let dir = temp_dir("plan-judge");
+    let fixtures = dir.join("fixtures");
+    write_fixture(&fixtures, "run-0001.fixture", PLAN_OUTPUT, 0);
+    write_fixture(
+        &fixtures,
+        "run-0002.fixture",
+        "Step 3 is unnecessary.\nVERDICT: REJECT\n",
+        0,
+    );
+    write_fixture(&fixtures, "run-0003.fixture", "step done\n", 0);
+
+    plan_first(&dir, &["--plan-judge", "Reject plans that touch docs."])
+        .failure()
+        .stderr(predicates::str::contains("The plan was not approved"));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_cli_plan_first_needs_approval_without_a_terminal() {
+    let dir = temp_dir("plan-no-tty");
+    let fixtures = dir.join("fixtures");
+    write_fixture(&fixtures, "run-0001.fixture", PLAN_OUTPUT, 0);
+
+    plan_first(&dir, &[])
+        .failure()
+        .stderr(predicates::str::contains("--plan-auto-approve"));
+    let _ = std::fs::remove_dir_all(&dir);
+}
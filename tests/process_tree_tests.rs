@@ -0,0 +1,150 @@
+#![cfg(unix)]
+
+use agent_loops::{AgentLoopsError, ResourceLimits, RunOptions, run_codex};
+use std::os::unix::fs::PermissionsExt;
+use std::time::Duration;
+
+fn process_alive(pid: &str) -> bool {
+    std::process::Command::new("kill")
+        .args(["-0", pid])
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
+#[tokio::test]
+async fn test_timeout_kills_grandchildren() {
+    let dir = std::env::temp_dir().join(format!("agent-loops-tree-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let pid_file = dir.join("grandchild.pid");
+    let script = dir.join("fake-codex.sh");
+    std::fs::write(
+        &script,
+        format!(
+            "#!/bin/sh\nsleep 30 &\necho $! > '{}'\nwait\n",
+            pid_file.display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let options = RunOptions {
+        codex_bin: script.to_string_lossy().into_owned(),
+        timeout: Some(Duration::from_millis(500)),
+        ..RunOptions::default()
+    };
+    let err = run_codex("prompt", &options).await.unwrap_err();
+    assert!(matches!(err, AgentLoopsError::Timeout(_)), "{err}");
+
+    let grandchild = std::fs::read_to_string(&pid_file).unwrap();
+    let grandchild = grandchild.trim();
+    for _ in 0..50 {
+        if !process_alive(grandchild) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(
+        !process_alive(grandchild),
+        "grandchild {grandchild} survived"
+    );
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_resource_limits_apply_to_the_agent_and_its_children() {
+    let dir = std::env::temp_dir().join(format!("agent-loops-limits-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let out = dir.join("limits.txt");
+    let script = dir.join("fake-codex.sh");
+    std::fs::write(
+        &script,
+        format!(
+            "#!/bin/sh\nsh -c 'echo \"$(ulimit -t) $(ulimit -d)\"' > '{}'\n",
+            out.display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let options = RunOptions {
+        codex_bin: script.to_string_lossy().into_owned(),
+        limits: ResourceLimits {
+            memory_bytes: Some(512 << 20),
+            cpu_time: Some(Duration::from_secs(120)),
+        },
+        ..RunOptions::default()
+    };
+    assert!(run_codex("prompt", &options).await.unwrap().success);
+
+    // `ulimit -d` reports kilobytes.
+    assert_eq!(std::fs::read_to_string(&out).unwrap().trim(), "120 524288");
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_sigterm_stops_the_session_and_its_agent() {
+    let dir = std::env::temp_dir().join(format!("agent-loops-sigterm-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let pid_file = dir.join("grandchild.pid");
+    let script = dir.join("fake-codex.sh");
+    std::fs::write(
+        &script,
+        format!(
+            "#!/bin/sh\nsleep 30 &\necho $! > '{}'\nwait\n",
+            pid_file.display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let mut session = std::process::Command::new(env!("CARGO_BIN_EXE_agent-loops"))
+        .args(["-p", "wait", "--no-pty", "--codex-bin"])
+        .arg(&script)
+        .arg("-C")
+        .arg(&dir)
+        .arg("--data-dir")
+        .arg(dir.join("data"))
+        .arg("--spool-dir")
+        .arg(dir.join("spool"))
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    let started = std::time::Instant::now();
+    while !pid_file.exists() || std::fs::read_to_string(&pid_file).unwrap().is_empty() {
+        assert!(
+            started.elapsed() < Duration::from_secs(10),
+            "agent never started"
+        );
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    std::process::Command::new("kill")
+        .args(["-TERM", &session.id().to_string()])
+        .status()
+        .unwrap();
+
+    let status = session.wait().unwrap();
+    assert!(!status.success());
+    let grandchild = std::fs::read_to_string(&pid_file).unwrap();
+    let grandchild = grandchild.trim();
+    for _ in 0..50 {
+        if !process_alive(grandchild) {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    assert!(
+        !process_alive(grandchild),
+        "grandchild {grandchild} survived"
+    );
+    let record = std::fs::read_dir(dir.join("data").join("sessions"))
+        .unwrap()
+        .map(|entry| std::fs::read_to_string(entry.unwrap().path().join("session.toml")).unwrap())
+        .next()
+        .unwrap();
+    assert!(record.contains("status = \"cancelled\""), "{record}");
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,167 @@
+mod common;
+
+use agent_loops::progress::{
+    ProgressEstimator, RunProgress, SPINNER_FRAME_TIME, format_clock, format_run_time,
+    heartbeat_line, spinner_frame,
+};
+use common::{replay, temp_dir, write_fixture};
+use std::time::Duration;
+
+const MIN: Duration = Duration::from_secs(60);
+
+#[test]
+fn test_format_clock_adds_hours_when_needed() {
+    assert_eq!(format_clock(Duration::from_secs(0)), "00:00");
+    assert_eq!(format_clock(Duration::from_secs(133)), "02:13");
+    assert_eq!(format_clock(Duration::from_secs(3600 + 12 * 60)), "1:12:00");
+}
+
+#[test]
+fn test_estimator_averages_finished_runs() {
+    let mut estimator = ProgressEstimator::new();
+    assert_eq!(estimator.average(), None);
+    estimator.record(4 * MIN);
+    estimator.record(6 * MIN);
+    assert_eq!(estimator.average(), Some(5 * MIN));
+}
+
+#[test]
+fn test_eta_counts_rest_of_current_run_and_later_runs() {
+    let progress = RunProgress {
+        average: Some(5 * MIN),
+        runs_after: 2,
+    };
+    assert_eq!(progress.eta(2 * MIN), Some(13 * MIN));
+    // A run past the average only contributes the later runs.
+    assert_eq!(progress.eta(9 * MIN), Some(10 * MIN));
+}
+
+#[test]
+fn test_status_line() {
+    let progress = RunProgress {
+        average: Some(5 * MIN + Duration::from_secs(40)),
+        runs_after: 12,
+    };
+    assert_eq!(
+        progress.status_line(Duration::from_secs(133)),
+        "Elapsed 02:13 | Avg run 05:40 | ETA 1:11:27 (13 runs left)"
+    );
+    let first = RunProgress {
+        average: None,
+        runs_after: 0,
+    };
+    assert_eq!(first.eta(MIN), None);
+    assert_eq!(
+        first.status_line(MIN),
+        "Elapsed 01:00 | ETA -- (1 run left)"
+    );
+}
+
+#[test]
+fn test_format_run_time_is_compact() {
+    assert_eq!(format_run_time(Duration::from_secs(45)), "45s");
+    assert_eq!(format_run_time(Duration::from_secs(4 * 60 + 2)), "4m02s");
+    assert_eq!(format_run_time(Duration::from_secs(3723)), "1h02m03s");
+}
+
+#[test]
+fn test_spinner_turns_with_time() {
+    let first = spinner_frame(Duration::ZERO);
+    assert_eq!(spinner_frame(SPINNER_FRAME_TIME / 2), first);
+    assert_ne!(spinner_frame(SPINNER_FRAME_TIME), first);
+    // It comes back around.
+    assert_eq!(spinner_frame(SPINNER_FRAME_TIME * 10), first);
+}
+
+#[test]
+fn test_heartbeat_line() {
+    assert_eq!(
+        heartbeat_line(3 * MIN, 22 * MIN + Duration::from_secs(10)),
+        "Still running, idle 3m00s, elapsed 22m10s"
+    );
+}
+
+#[test]
+fn test_cli_ci_output_prints_one_line_per_run() {
+    let dir = temp_dir("progress-ci");
+    let fixtures = dir.join("fixtures");
+    write_fixture(&fixtures, "run-0001.fixture", "first run output\n", 0);
+    write_fixture(&fixtures, "run-0002.fixture", "second run output\n", 1);
+    let spool = dir.join("spool");
+
+    let output = replay(&dir)
+        .args(["-p", "one", "-p", "two", "--ci-output"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(stdout.contains("[1/2] task 1 loop 1 OK 0s\n"), "{stdout}");
+    assert!(
+        stdout.contains("[2/2] task 2 loop 1 FAILED 0s log "),
+        "{stdout}"
+    );
+    assert!(!stdout.contains("run output"), "{stdout}");
+    assert!(!stdout.contains("Result:"), "{stdout}");
+    let logs: Vec<String> = std::fs::read_dir(&spool)
+        .unwrap()
+        .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+        .collect();
+    assert!(
+        logs.iter().any(|log| log == "first run output\n"),
+        "{logs:?}"
+    );
+    assert!(
+        logs.iter().any(|log| log == "second run output\n"),
+        "{logs:?}"
+    );
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_cli_exit_policy_decides_the_exit_code() {
+    let dir = temp_dir("progress-exit-policy");
+    let fixtures = dir.join("fixtures");
+    let run = |policy: &str| {
+        let _ = std::fs::remove_dir_all(&fixtures);
+        write_fixture(&fixtures, "run-0001.fixture", "ok\n", 0);
+        write_fixture(&fixtures, "run-0002.fixture", "broken\n", 1);
+        replay(&dir)
+            .args(["-p", "one", "-p", "two", "--exit-policy", policy])
+            .assert()
+    };
+
+    run("any-failure").failure();
+    run("threshold:40").failure();
+    run("threshold:50")
+        .success()
+        .stderr(predicates::str::contains(
+            "1 run(s) failed; exiting successfully under --exit-policy threshold:50.",
+        ));
+    run("always-zero").success();
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_heartbeat_reports_a_quiet_run() {
+    use common::agent_loops;
+
+    let dir = temp_dir("progress-heartbeat");
+    let run = |heartbeat: &str| {
+        let output = agent_loops(&dir, &dir)
+            .args(["-p", "think", "--heartbeat", heartbeat])
+            .args(["--runner-template", "sh -c 'sleep 3; echo {prompt}'"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    };
+
+    let stderr = run("1");
+    assert!(
+        stderr.contains("Still running, idle 1s, elapsed 1s\n"),
+        "{stderr}"
+    );
+    assert!(!run("0").contains("Still running"));
+    let _ = std::fs::remove_dir_all(&dir);
+}
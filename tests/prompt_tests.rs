@@ -0,0 +1,311 @@
+use agent_loops::TaskSpec;
+use agent_loops::prompt::{
+    PromptVars, PromptWrap, format_task, format_task_list, lint_prompts, load_vars_file,
+    parse_refined, refine_prompt, render_prompt,
+};
+use predicates::prelude::*;
+
+#[test]
+fn test_prompt_wrap() {
+    assert_eq!(PromptWrap::default().apply("Fix lint"), "Fix lint");
+    let wrap = PromptWrap {
+        preamble: Some("This is a Rust CLI.\n".to_string()),
+        prefix: Some("Follow the house style.".to_string()),
+        suffix: Some("  ".to_string()),
+    };
+    assert_eq!(
+        wrap.apply("Fix lint"),
+        "This is a Rust CLI.\n\nFollow the house style.\n\nFix lint"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_wraps_every_prompt() {
+    let dir = std::env::temp_dir().join(format!("agent-loops-prompt-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let preamble = dir.join("preamble.md");
+    std::fs::write(&preamble, "Context.\n").unwrap();
+
+    assert_cmd::cargo::cargo_bin_cmd!("agent-loops")
+        .args(["-p", "one", "-p", "two {{ n }}", "--var", "n=2", "--no-pty"])
+        .args([
+            "--runner-template",
+            "printf [%s] {prompt}",
+            "--preamble-file",
+        ])
+        .arg(&preamble)
+        .args(["--prompt-prefix", "Be brief.", "--prompt-suffix", "Done?"])
+        .arg("--data-dir")
+        .arg(dir.join("data"))
+        .arg("--spool-dir")
+        .arg(dir.join("spool"))
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "[Context.\n\nBe brief.\n\none\n\nDone?]",
+        ))
+        .stdout(predicates::str::contains(
+            "[Context.\n\nBe brief.\n\ntwo 2\n\nDone?]",
+        ));
+
+    assert_cmd::cargo::cargo_bin_cmd!("agent-loops")
+        .args(["-p", "one", "--preamble-file"])
+        .arg(dir.join("missing.md"))
+        .assert()
+        .failure();
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_render_prompt_reads_the_work_dir() {
+    let dir = std::env::temp_dir().join(format!("agent-loops-expand-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("src/bin")).unwrap();
+    std::fs::write(dir.join("spec.md"), "Be fast.\n").unwrap();
+    std::fs::write(dir.join("src/lib.rs"), "pub fn a() {}\n").unwrap();
+    std::fs::write(dir.join("src/bin/main.rs"), "fn main() {}\n").unwrap();
+    std::fs::write(dir.join("src/notes.txt"), "x").unwrap();
+
+    let render = |prompt: &str| render_prompt(prompt, &PromptVars::new(), &dir);
+    assert_eq!(render("Spec: {{file:spec.md}}").unwrap(), "Spec: Be fast.");
+    assert_eq!(
+        render("Spec: {{ file('spec.md') }}").unwrap(),
+        "Spec: Be fast."
+    );
+    assert_eq!(
+        render("Files:\n{{glob:src/**/*.rs:paths}}").unwrap(),
+        "Files:\nsrc/bin/main.rs\nsrc/lib.rs"
+    );
+    assert_eq!(
+        render("{{ glob:src/*.rs:contents }}").unwrap(),
+        "--- src/lib.rs ---\npub fn a() {}"
+    );
+    assert_eq!(render("{% include 'spec.md' %}").unwrap(), "Be fast.\n");
+    let err = render("{{file:missing.md}}").unwrap_err();
+    assert!(err.contains("cannot read `missing.md`"), "{err}");
+    assert!(render("{{glob:../*.rs}}").is_err());
+    assert!(render("{{ glob('src', 'sizes') }}").is_err());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_render_prompt_with_variables() {
+    let dir = std::env::temp_dir().join(format!("agent-loops-vars-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("vars.toml"),
+        "crates = [\"parser\", \"lexer\"]\nstrict = true\n",
+    )
+    .unwrap();
+    let mut vars = load_vars_file(&dir.join("vars.toml")).unwrap();
+    vars.insert("goal".to_string(), "speed".into());
+
+    assert_eq!(
+        render_prompt(
+            "Improve {{ goal }} in {% for c in crates %}{{ c }}{% if not loop.last %}, {% endif %}{% endfor %}.{% if strict %} No new deps.{% endif %}",
+            &vars,
+            &dir,
+        )
+        .unwrap(),
+        "Improve speed in parser, lexer. No new deps."
+    );
+    // An undefined variable is an error rather than a blank.
+    let err = render_prompt("Fix {{ gaol }}", &vars, &dir).unwrap_err();
+    assert!(err.contains("undefined"), "{err}");
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_lint_prompts() {
+    let dir = std::env::temp_dir();
+    let mut vars = PromptVars::new();
+    vars.insert("blank".to_string(), "".into());
+    let issues = lint_prompts(
+        &[
+            "Fix lint",
+            "{{ blank }}",
+            "Fix {{ typo }}",
+            "fix lint ",
+            "Fix lint",
+            "x".repeat(50).as_str(),
+        ],
+        &PromptWrap::default(),
+        &vars,
+        &dir,
+        40,
+    );
+    let issues: Vec<(usize, bool, String)> = issues
+        .into_iter()
+        .map(|issue| (issue.task, issue.fatal, issue.to_string()))
+        .collect();
+    assert_eq!(issues.len(), 4, "{issues:?}");
+    assert_eq!(
+        issues[0],
+        (
+            2,
+            true,
+            "task 2: the prompt is empty once rendered".to_string()
+        )
+    );
+    assert!(
+        issues[1].1 && issues[1].2.contains("undefined"),
+        "{issues:?}"
+    );
+    assert_eq!(
+        issues[2],
+        (5, false, "task 5: same prompt as task 1".to_string())
+    );
+    assert_eq!(
+        issues[3],
+        (
+            6,
+            true,
+            "task 6: the rendered prompt has 50 characters, more than the limit of 40".to_string()
+        )
+    );
+}
+
+#[test]
+fn test_cli_fails_before_running_a_broken_prompt() {
+    assert_cmd::cargo::cargo_bin_cmd!("agent-loops")
+        .args(["-p", "fine", "-p", "Fix {{ module }}"])
+        .args(["--runner-template", "echo ran {prompt}"])
+        .assert()
+        .failure()
+        .stdout(predicates::str::contains("ran").not())
+        .stderr(predicates::str::contains("Prompt check failed for task 2"));
+}
+
+#[test]
+fn test_parse_refined_takes_the_last_block() {
+    let echoed = refine_prompt("fix it");
+    assert_eq!(parse_refined(&echoed), None);
+    let output = format!(
+        "{echoed}\ncodex\nBEGIN PROMPT\ndraft\nEND PROMPT\n**BEGIN PROMPT**\n\
+         Fix the failing `cache` tests.\n\nDone when `cargo test` passes.\n`END PROMPT`\n"
+    );
+    assert_eq!(
+        parse_refined(&output).as_deref(),
+        Some("Fix the failing `cache` tests.\n\nDone when `cargo test` passes.")
+    );
+    assert_eq!(parse_refined("BEGIN PROMPT\n\nEND PROMPT\n"), None);
+    assert_eq!(parse_refined("BEGIN PROMPT\nunfinished\n"), None);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_runs_the_refined_prompts() {
+    let dir = std::env::temp_dir().join(format!("agent-loops-refine-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    // Stands in for the agent: rewrites prompts when asked to, otherwise echoes them.
+    let agent = dir.join("agent.sh");
+    std::fs::write(
+        &agent,
+        "case \"$1\" in\n\
+         *'Rewrite the task prompt'*) printf 'BEGIN PROMPT\\nExplicit: %s\\nEND PROMPT\\n' \"${1##*Prompt: }\" ;;\n\
+         *) printf '[%s]\\n' \"$1\" ;;\n\
+         esac\n",
+    )
+    .unwrap();
+    let cli = |approval: &[&str]| {
+        let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("agent-loops");
+        cmd.args(["-p", "tidy up", "--refine-prompts", "--no-pty"])
+            .args(approval)
+            .arg("--runner-template")
+            .arg(format!("sh {} {{prompt}}", agent.display()))
+            .arg("--data-dir")
+            .arg(dir.join("data"))
+            .arg("--spool-dir")
+            .arg(dir.join("spool"));
+        cmd.assert()
+    };
+
+    cli(&["--refine-auto-approve"])
+        .success()
+        .stdout(predicates::str::contains(
+            "Before: tidy up\nAfter:\nExplicit: tidy up\n",
+        ))
+        .stdout(predicates::str::contains("[Explicit: tidy up]"));
+    cli(&[])
+        .failure()
+        .stderr(predicates::str::contains("--refine-auto-approve"));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_format_tasks_renders_full_prompts() {
+    let long = "word ".repeat(30);
+    let staged = TaskSpec {
+        name: Some("parser".to_string()),
+        model: Some("cheap-model".to_string()),
+        loop_prompts: vec![
+            "Implement {{ what }}".to_string(),
+            "Review {{ what }}".to_string(),
+        ],
+        tags: vec!["core".to_string()],
+        ..TaskSpec::new("Implement {{ what }}")
+    };
+    let tasks = [
+        staged.clone(),
+        TaskSpec::new(long.trim()),
+        TaskSpec::new("{{ nope }}"),
+    ];
+    let wrap = PromptWrap {
+        suffix: Some("Be brief.".to_string()),
+        ..PromptWrap::default()
+    };
+    let vars: PromptVars = [("what".to_string(), "the parser".into())].into();
+    let dir = std::env::temp_dir();
+
+    let list = format_task_list(&tasks, &wrap, &vars, &dir);
+    assert!(
+        list.starts_with(
+            "=== Task 1: parser ===\nImplement the parser\n\nBe brief.\n\
+             (loop 1 of 2 loop prompts; `tasks show 1` shows them all)\n\n=== Task 2 ===\n"
+        ),
+        "{list}"
+    );
+    assert!(
+        list.contains(&format!("{}\n\nBe brief.\n", long.trim())),
+        "{list}"
+    );
+    assert!(
+        list.contains("=== Task 3 ===\n(cannot render the prompt: "),
+        "{list}"
+    );
+    assert_eq!(format_task_list(&[], &wrap, &vars, &dir), "No tasks.\n");
+
+    assert_eq!(
+        format_task(1, &staged, &PromptWrap::default(), &vars, &dir),
+        "Task 1: parser\nModel: cheap-model\nTags: core\n\n=== Loop 1 ===\nImplement the parser\n\n\
+         === Loop 2 and later ===\nReview the parser\n"
+    );
+}
+
+#[test]
+fn test_cli_tasks_show() {
+    assert_cmd::cargo::cargo_bin_cmd!("agent-loops")
+        .args([
+            "-p",
+            "one",
+            "-p",
+            "Fix {{ what }}",
+            "--var",
+            "what=the build",
+        ])
+        .args(["tasks", "show", "2"])
+        .assert()
+        .success()
+        .stdout("Task 2\n\n=== Prompt ===\nFix the build\n");
+    assert_cmd::cargo::cargo_bin_cmd!("agent-loops")
+        .args(["tasks", "show", "2", "-p", "one"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "There is no task 2; the list has 1.",
+        ));
+}
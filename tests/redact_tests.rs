@@ -0,0 +1,82 @@
+use agent_loops::fixture::{Fixture, FixtureEvent, FixtureStream};
+use agent_loops::testing::FixtureRunner;
+use agent_loops::{Redactor, RunOptions, Runner, TaskSpec, is_secret_env_name};
+use std::time::Duration;
+
+#[test]
+fn test_secret_env_names() {
+    assert!(is_secret_env_name("OPENAI_API_KEY"));
+    assert!(is_secret_env_name("github_token"));
+    assert!(is_secret_env_name("DB_PASSWORD"));
+    assert!(!is_secret_env_name("PATH"));
+    assert!(!is_secret_env_name("HOME"));
+}
+
+#[test]
+fn test_redact_literal() {
+    let redactor = Redactor::new().with_literal("sk-abc123456", "[REDACTED:OPENAI_API_KEY]");
+    assert_eq!(
+        redactor.redact(b"key is sk-abc123456, again sk-abc123456\n"),
+        b"key is [REDACTED:OPENAI_API_KEY], again [REDACTED:OPENAI_API_KEY]\n".to_vec()
+    );
+}
+
+#[test]
+fn test_redact_user_pattern() {
+    let redactor = Redactor::new().with_pattern(r"ghp_[A-Za-z0-9]+").unwrap();
+    assert_eq!(
+        redactor.redact(b"token=ghp_XYZ789 done"),
+        b"token=[REDACTED] done".to_vec()
+    );
+}
+
+#[test]
+fn test_redact_invalid_pattern_is_error() {
+    assert!(Redactor::new().with_pattern("(unclosed").is_err());
+}
+
+#[test]
+fn test_empty_redactor_passes_through() {
+    let redactor = Redactor::new();
+    assert!(redactor.is_empty());
+    assert_eq!(redactor.redact(b"plain output"), b"plain output".to_vec());
+}
+
+#[tokio::test]
+async fn test_secret_split_at_a_forced_flush_is_masked() {
+    let spool =
+        std::env::temp_dir().join(format!("agent-loops-redact-flush-{}", std::process::id()));
+    let chunk = |bytes: Vec<u8>| FixtureEvent {
+        at: Duration::ZERO,
+        stream: FixtureStream::Stdout,
+        bytes,
+    };
+    // A line longer than 16 KiB, with the secret arriving across the point it is flushed.
+    let mut filler = vec![b'x'; 16 * 1024 - 4];
+    filler.extend_from_slice(b"sk-abc");
+    let fixture = Fixture {
+        command: Vec::new(),
+        events: vec![chunk(filler), chunk(b"123456 done\n".to_vec())],
+        exit_code: Some(0),
+    };
+    let runner = FixtureRunner::new(
+        vec![fixture],
+        RunOptions {
+            redactor: Redactor::new().with_literal("sk-abc123456", "[REDACTED:OPENAI_API_KEY]"),
+            capture_output: true,
+            spool_dir: Some(spool.clone()),
+            ..RunOptions::default()
+        },
+    );
+
+    let outcome = runner.run(&TaskSpec::new("prompt")).await.unwrap();
+
+    let output = std::fs::read_to_string(outcome.output_log.unwrap()).unwrap();
+    assert!(
+        !output.contains("sk-abc"),
+        "{}",
+        &output[output.len() - 40..]
+    );
+    assert!(output.ends_with("x[REDACTED:OPENAI_API_KEY] done\n"));
+    let _ = std::fs::remove_dir_all(&spool);
+}
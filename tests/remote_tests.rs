@@ -0,0 +1,85 @@
+use agent_loops::RunOptions;
+use agent_loops::remote::{Remote, shell_quote};
+use std::path::PathBuf;
+
+#[test]
+fn test_shell_quote() {
+    assert_eq!(shell_quote("--model"), "--model");
+    assert_eq!(shell_quote("/srv/app"), "/srv/app");
+    assert_eq!(shell_quote("fix it"), "'fix it'");
+    assert_eq!(shell_quote("it's $HOME"), r"'it'\''s $HOME'");
+    assert_eq!(shell_quote(""), "''");
+}
+
+#[test]
+fn test_remote_run_args() {
+    let options = RunOptions {
+        work_dir: Some(PathBuf::from("/srv/my app")),
+        env: vec![("MODE".to_string(), "ci run".to_string())],
+        ..RunOptions::default()
+    };
+    let args = Remote::new("me@build").run_args(
+        "codex",
+        &["exec".to_string(), "fix the bug".to_string()],
+        &options,
+    );
+    assert_eq!(
+        args,
+        [
+            "-tt",
+            "-o",
+            "BatchMode=yes",
+            "me@build",
+            "--",
+            "cd '/srv/my app' && exec env 'MODE=ci run' codex exec 'fix the bug'"
+        ]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_runs_the_agent_over_ssh() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("agent-loops-ssh-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let bin = dir.join("bin");
+    let work = dir.join("work");
+    std::fs::create_dir_all(&bin).unwrap();
+    std::fs::create_dir_all(&work).unwrap();
+    let calls = dir.join("calls.txt");
+    // Stands in for ssh: logs the destination and runs the remote command locally.
+    let ssh = bin.join("ssh");
+    std::fs::write(
+        &ssh,
+        format!(
+            "#!/bin/sh\necho \"$4\" >> '{calls}'\nshift 5\nexec sh -c \"$1\"\n",
+            calls = calls.display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&ssh, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap());
+
+    assert_cmd::cargo::cargo_bin_cmd!("agent-loops")
+        .env("PATH", path)
+        .args([
+            "-p",
+            "tidy up",
+            "--runner-template",
+            "sh -c 'pwd; echo {prompt}'",
+        ])
+        .args(["--ssh", "me@build", "--no-pty", "-C"])
+        .arg(&work)
+        .arg("--data-dir")
+        .arg(dir.join("data"))
+        .arg("--spool-dir")
+        .arg(dir.join("spool"))
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(format!("{}\n", work.display())))
+        .stdout(predicates::str::contains("tidy up"));
+
+    assert_eq!(std::fs::read_to_string(&calls).unwrap(), "me@build\n");
+    let _ = std::fs::remove_dir_all(&dir);
+}
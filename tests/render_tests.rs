@@ -0,0 +1,78 @@
+mod common;
+
+use agent_loops::{display_width, fit_terminal_line, truncate_display, wrap_terminal_line};
+
+// --- width-aware truncation ---
+
+#[test]
+fn test_display_width_counts_wide_chars() {
+    assert_eq!(display_width("abc"), 3);
+    assert_eq!(display_width("日本語"), 6);
+}
+
+#[test]
+fn test_truncate_display_does_not_split_multibyte() {
+    let s = "日本語のテキストです";
+    let result = truncate_display(s, 9);
+    assert_eq!(result, "日本語...");
+    assert!(display_width(&result) <= 9);
+}
+
+#[test]
+fn test_truncate_display_keeps_graphemes_whole() {
+    // "e" followed by a combining acute accent is one grapheme.
+    let s = "cafe\u{301} au lait";
+    assert_eq!(truncate_display(s, 7), "cafe\u{301}...");
+}
+
+#[test]
+fn test_fit_terminal_line_uses_columns() {
+    assert_eq!(fit_terminal_line("日本語のテキスト", 8), "日本...");
+    assert_eq!(fit_terminal_line("short", 8), "short");
+    assert_eq!(fit_terminal_line("anything", 0), "");
+    assert_eq!(fit_terminal_line("anything", 2), "..");
+}
+
+// --- wrapping ---
+
+#[test]
+fn test_wrap_terminal_line_splits_by_width() {
+    assert_eq!(wrap_terminal_line("abcdefg", 3), vec!["abc", "def", "g"]);
+    assert_eq!(wrap_terminal_line("日本語", 4), vec!["日本", "語"]);
+}
+
+#[test]
+fn test_wrap_terminal_line_empty_line_is_one_row() {
+    assert_eq!(wrap_terminal_line("", 10), vec![""]);
+    assert!(wrap_terminal_line("abc", 0).is_empty());
+}
+
+// --- timestamps ---
+
+#[test]
+fn test_timestamps_prefix_every_output_line() {
+    use agent_loops::fixture::FixtureStream;
+    use common::{replay, temp_dir, write_events};
+
+    let dir = temp_dir("stamps");
+    write_events(
+        &dir.join("fixtures"),
+        "run-0001.fixture",
+        &[
+            (FixtureStream::Stdout, b"reading files\nedit"),
+            (FixtureStream::Stdout, b"ing src/lib.rs\n"),
+            (FixtureStream::Stderr, b"warning: slow\n"),
+        ],
+        0,
+    );
+
+    replay(&dir)
+        .args(["-p", "edit", "--timestamps"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "[00:00:00] reading files\n[00:00:00] editing src/lib.rs\n",
+        ))
+        .stderr(predicates::str::contains("[00:00:00] warning: slow\n"));
+    let _ = std::fs::remove_dir_all(&dir);
+}
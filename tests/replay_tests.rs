@@ -0,0 +1,60 @@
+use agent_loops::replay::{ReplayLine, load_replay, replay_delays, timing_path};
+use std::path::PathBuf;
+use std::time::Duration;
+
+fn temp_log(name: &str, content: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("agent-loops-replay-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let log = dir.join(format!("{name}.log"));
+    std::fs::write(&log, content).unwrap();
+    let _ = std::fs::remove_file(timing_path(&log));
+    log
+}
+
+fn line(ms: u64, text: &str) -> ReplayLine {
+    ReplayLine {
+        at: Duration::from_millis(ms),
+        text: text.to_string(),
+    }
+}
+
+#[test]
+fn test_load_replay_uses_timing_sidecar() {
+    let log = temp_log("timed", "one\ntwo\nthree\n");
+    assert_eq!(timing_path(&log).extension().unwrap(), "timing");
+    std::fs::write(timing_path(&log), "5\n1200\n").unwrap();
+
+    let lines = load_replay(&log).unwrap();
+    assert_eq!(
+        lines,
+        [line(5, "one"), line(1200, "two"), line(1230, "three")]
+    );
+}
+
+#[test]
+fn test_load_replay_without_timing_uses_steady_pace() {
+    let log = temp_log("untimed", "a\nb\n");
+    let lines = load_replay(&log).unwrap();
+    assert_eq!(lines, [line(30, "a"), line(60, "b")]);
+}
+
+#[test]
+fn test_replay_delays_scale_and_cap_idle_gaps() {
+    let lines = [line(100, "a"), line(300, "b"), line(60_300, "c")];
+    assert_eq!(
+        replay_delays(&lines, 1.0),
+        [
+            Duration::from_millis(100),
+            Duration::from_millis(200),
+            Duration::from_secs(2)
+        ]
+    );
+    assert_eq!(
+        replay_delays(&lines, 4.0),
+        [
+            Duration::from_millis(25),
+            Duration::from_millis(50),
+            Duration::from_millis(500)
+        ]
+    );
+}
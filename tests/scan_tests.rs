@@ -0,0 +1,194 @@
+use agent_loops::scan::{Usage, is_rate_limit_message};
+use agent_loops::testing::{MockRunner, MockStep};
+use agent_loops::{OrchestrateOptions, TaskSpec, orchestrate_runner, rate_limit_backoff};
+use std::time::Duration;
+
+#[test]
+fn test_rate_limit_messages_are_detected() {
+    for line in [
+        "ERROR: stream error: 429 Too Many Requests",
+        "unexpected status 429: {\"error\":\"rate_limit_exceeded\"}",
+        "You exceeded your current quota, please check your plan and billing details.",
+        "Rate limit reached for gpt-5 in organization org-123",
+        "error: insufficient_quota",
+        "Quota exceeded for this project",
+        "HTTP 429",
+        "429 Too Many Requests",
+    ] {
+        assert!(is_rate_limit_message(line), "not detected: {line}");
+    }
+}
+
+#[test]
+fn test_ordinary_output_is_not_a_rate_limit() {
+    for line in [
+        "Edited src/lib.rs (+429 -12)",
+        "Added a limit to the retry loop",
+        "tests passed: 429",
+        "Added rate limiting to the login endpoint",
+        "The API now returns HTTP 429 Too Many Requests once the rate limit is exceeded",
+        "- Rate limit exceeded errors are retried with backoff",
+        "Handle quota exhausted responses from the billing service",
+        "",
+    ] {
+        assert!(!is_rate_limit_message(line), "false positive: {line}");
+    }
+}
+
+#[test]
+fn test_rate_limit_backoff_doubles_and_caps() {
+    let base = Duration::from_secs(60);
+    assert_eq!(rate_limit_backoff(base, 1), Duration::from_secs(60));
+    assert_eq!(rate_limit_backoff(base, 2), Duration::from_secs(120));
+    assert_eq!(rate_limit_backoff(base, 3), Duration::from_secs(240));
+    assert_eq!(rate_limit_backoff(base, 40), Duration::from_secs(30 * 60));
+}
+
+#[tokio::test]
+async fn test_rate_limited_run_is_retried_in_place() {
+    let runner = MockRunner::new()
+        .step(MockStep::failure("ERROR: 429 Too Many Requests"))
+        .step(MockStep::failure("ERROR: 429 Too Many Requests"))
+        .step(MockStep::success("done"));
+    let options = OrchestrateOptions {
+        rate_limit_retries: 3,
+        rate_limit_cooldown: Duration::from_millis(1),
+        ..OrchestrateOptions::default()
+    };
+    let tasks = ["a", "b"].map(TaskSpec::new);
+
+    let results = orchestrate_runner(&tasks, 1, &options, &runner).await;
+
+    runner.assert_prompts(&["a", "a", "a", "b"]);
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|r| r.outcome.success));
+}
+
+#[tokio::test]
+async fn test_rate_limit_retries_give_up_after_limit() {
+    let runner = MockRunner::new().fallback(MockStep::failure("Rate limit reached"));
+    let options = OrchestrateOptions {
+        rate_limit_retries: 1,
+        rate_limit_cooldown: Duration::from_millis(1),
+        ..OrchestrateOptions::default()
+    };
+
+    let results = orchestrate_runner(&[TaskSpec::new("a")], 1, &options, &runner).await;
+
+    assert_eq!(runner.call_count(), 2);
+    assert_eq!(results.len(), 1);
+    assert!(results[0].outcome.rate_limited);
+    assert!(!results[0].outcome.success);
+}
+
+#[test]
+fn test_usage_is_parsed_from_agent_output() {
+    let usage = Usage::parse_line("[2025-09-01T10:00:00] tokens used: 12,345");
+    assert_eq!(usage.tokens, Some(12_345));
+    assert_eq!(usage.cost(Some(0.01)), Some(0.12345));
+
+    let usage =
+        Usage::parse_line("Tokens: 2.1k sent, 300 received. Cost: $0.04 message, $0.10 session.");
+    assert_eq!(usage.cost_usd, Some(0.04));
+
+    let usage = Usage::parse_line("\u{2502}  Total Tokens   12,345  \u{2502}");
+    assert_eq!(usage.tokens, Some(12_345));
+    assert_eq!(Usage::parse_line("Edited src/cost.rs"), Usage::default());
+}
+
+#[test]
+fn test_later_usage_figures_replace_earlier_ones() {
+    let usage = Usage::parse_line("tokens used: 100").update(Usage::parse_line("tokens used: 250"));
+    assert_eq!(usage.tokens, Some(250));
+    assert_eq!(usage.cost(None), None);
+}
+
+#[tokio::test]
+async fn test_session_stops_at_cost_cap() {
+    let runner = MockRunner::new().fallback(MockStep::success("Cost: $0.40"));
+    let options = OrchestrateOptions {
+        max_session_cost: Some(1.0),
+        ..OrchestrateOptions::default()
+    };
+
+    let results = orchestrate_runner(&[TaskSpec::new("a")], 5, &options, &runner).await;
+
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].outcome.cost_usd, Some(0.40));
+}
+
+#[tokio::test]
+async fn test_retried_attempts_count_towards_cost() {
+    let runner = MockRunner::new()
+        .step(MockStep::failure("429 Too Many Requests\nCost: $0.10"))
+        .step(MockStep::success("Cost: $0.25"));
+    let options = OrchestrateOptions {
+        rate_limit_retries: 1,
+        rate_limit_cooldown: Duration::from_millis(1),
+        ..OrchestrateOptions::default()
+    };
+
+    let results = orchestrate_runner(&[TaskSpec::new("a")], 1, &options, &runner).await;
+
+    assert_eq!(results[0].outcome.cost_usd, Some(0.35));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_run_over_cost_limit_is_killed() {
+    use agent_loops::{RunOptions, run_codex};
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::Instant;
+
+    let dir = std::env::temp_dir().join(format!("agent-loops-cost-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("pricey-codex.sh");
+    std::fs::write(&script, "#!/bin/sh\necho 'tokens used: 5,000'\nsleep 30\n").unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let options = RunOptions {
+        codex_bin: script.to_string_lossy().into_owned(),
+        usd_per_1k_tokens: Some(0.5),
+        max_cost_per_run: Some(1.0),
+        ..RunOptions::default()
+    };
+    let started = Instant::now();
+    let outcome = run_codex("prompt", &options).await.unwrap();
+
+    assert!(started.elapsed() < Duration::from_secs(10));
+    assert!(outcome.over_budget);
+    assert!(!outcome.success);
+    assert_eq!(outcome.cost_usd, Some(2.5));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_run_flooding_its_output_is_killed() {
+    use agent_loops::{RunOptions, run_codex};
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::Instant;
+
+    let dir = std::env::temp_dir().join(format!("agent-loops-flood-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("chatty-codex.sh");
+    std::fs::write(&script, "#!/bin/sh\nwhile :; do echo flood; done\n").unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let options = RunOptions {
+        codex_bin: script.to_string_lossy().into_owned(),
+        spool_dir: Some(dir.join("spool")),
+        ci_output: true,
+        max_output_bytes: Some(64 * 1024),
+        ..RunOptions::default()
+    };
+    let started = Instant::now();
+    let outcome = run_codex("prompt", &options).await.unwrap();
+
+    assert!(started.elapsed() < Duration::from_secs(10));
+    assert!(outcome.output_flood);
+    assert!(!outcome.success);
+    let log = std::fs::metadata(outcome.output_log.unwrap()).unwrap();
+    assert!(log.len() < 1024 * 1024, "{} bytes logged", log.len());
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,143 @@
+use agent_loops::schedule::{Blackout, CronSchedule, blackout_end};
+use chrono::{NaiveDate, NaiveDateTime};
+
+fn at(y: i32, m: u32, d: u32, h: u32, min: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(y, m, d)
+        .unwrap()
+        .and_hms_opt(h, min, 0)
+        .unwrap()
+}
+
+#[test]
+fn test_daily_schedule() {
+    let cron = CronSchedule::parse("0 2 * * *").unwrap();
+    assert_eq!(
+        cron.next_after(at(2024, 6, 1, 1, 30)),
+        Some(at(2024, 6, 1, 2, 0))
+    );
+    // Exactly at a firing time, the next one is a day later.
+    assert_eq!(
+        cron.next_after(at(2024, 6, 1, 2, 0)),
+        Some(at(2024, 6, 2, 2, 0))
+    );
+    assert_eq!(
+        cron.next_after(at(2024, 12, 31, 23, 59)),
+        Some(at(2025, 1, 1, 2, 0))
+    );
+}
+
+#[test]
+fn test_steps_ranges_and_names() {
+    let cron = CronSchedule::parse("*/15 9-17 * * mon-fri").unwrap();
+    // 2024-06-01 is a Saturday.
+    assert_eq!(
+        cron.next_after(at(2024, 6, 1, 10, 0)),
+        Some(at(2024, 6, 3, 9, 0))
+    );
+    assert_eq!(
+        cron.next_after(at(2024, 6, 3, 9, 7)),
+        Some(at(2024, 6, 3, 9, 15))
+    );
+    assert_eq!(
+        cron.next_after(at(2024, 6, 3, 17, 45)),
+        Some(at(2024, 6, 4, 9, 0))
+    );
+    assert!(cron.matches(at(2024, 6, 3, 12, 30)));
+    assert!(!cron.matches(at(2024, 6, 3, 12, 31)));
+}
+
+#[test]
+fn test_day_fields_match_either_when_both_restricted() {
+    // The 1st of the month or any Sunday (7 is Sunday too).
+    let cron = CronSchedule::parse("0 0 1 * 7").unwrap();
+    assert_eq!(
+        cron.next_after(at(2024, 6, 1, 12, 0)),
+        Some(at(2024, 6, 2, 0, 0))
+    );
+    assert_eq!(
+        cron.next_after(at(2024, 6, 30, 12, 0)),
+        Some(at(2024, 7, 1, 0, 0))
+    );
+}
+
+#[test]
+fn test_impossible_schedule_never_fires() {
+    let cron = CronSchedule::parse("0 0 30 feb *").unwrap();
+    assert_eq!(cron.next_after(at(2024, 1, 1, 0, 0)), None);
+}
+
+#[test]
+fn test_invalid_expressions_are_rejected() {
+    for bad in [
+        "0 2 * *",
+        "60 * * * *",
+        "* 5-2 * * *",
+        "*/0 * * * *",
+        "* * * foo *",
+    ] {
+        assert!(CronSchedule::parse(bad).is_err(), "accepted `{bad}`");
+    }
+}
+
+#[test]
+fn test_blackout_window_parsing() {
+    let window: Blackout = "09:00-18:00".parse().unwrap();
+    assert_eq!(window.to_string(), "09:00-18:00");
+    assert!(Blackout::parse("9-18").is_err());
+    assert!(Blackout::parse("09:00").is_err());
+    assert!(Blackout::parse("25:00-26:00").is_err());
+    assert!(Blackout::parse("10:00-10:00").is_err());
+}
+
+#[test]
+fn test_blackout_end_waits_until_window_closes() {
+    let work_hours = Blackout::parse("09:00-18:00").unwrap();
+    assert_eq!(
+        work_hours.end_after(at(2024, 6, 3, 9, 0)),
+        Some(at(2024, 6, 3, 18, 0))
+    );
+    assert_eq!(work_hours.end_after(at(2024, 6, 3, 18, 0)), None);
+    assert_eq!(work_hours.end_after(at(2024, 6, 3, 8, 59)), None);
+
+    // A window past midnight ends the next morning.
+    let night = Blackout::parse("22:00-06:00").unwrap();
+    assert_eq!(
+        night.end_after(at(2024, 6, 3, 23, 0)),
+        Some(at(2024, 6, 4, 6, 0))
+    );
+    assert_eq!(
+        night.end_after(at(2024, 6, 4, 1, 0)),
+        Some(at(2024, 6, 4, 6, 0))
+    );
+    assert_eq!(night.end_after(at(2024, 6, 4, 12, 0)), None);
+}
+
+#[test]
+fn test_blackout_end_chains_adjacent_windows() {
+    let windows = [
+        Blackout::parse("09:00-12:00").unwrap(),
+        Blackout::parse("12:00-18:00").unwrap(),
+    ];
+    assert_eq!(
+        blackout_end(&windows, at(2024, 6, 3, 10, 0)),
+        Some(at(2024, 6, 3, 18, 0))
+    );
+    assert_eq!(blackout_end(&windows, at(2024, 6, 3, 19, 0)), None);
+    assert_eq!(blackout_end(&[], at(2024, 6, 3, 10, 0)), None);
+}
+
+#[test]
+fn test_on_calendar() {
+    let calendar = |text: &str| CronSchedule::parse(text).unwrap().on_calendar();
+    assert_eq!(calendar("0 2 * * *"), ["*-*-* 02:00:00"]);
+    assert_eq!(
+        calendar("*/15 9-11 * * mon-fri"),
+        ["Mon,Tue,Wed,Thu,Fri *-*-* 09,10,11:00,15,30,45:00"]
+    );
+    assert_eq!(calendar("30 * 1,15 jan *"), ["*-01-01,15 *:30:00"]);
+    // Cron runs when either day field matches; systemd needs both, so each gets a line.
+    assert_eq!(
+        calendar("0 0 1 * sun"),
+        ["*-*-01 00:00:00", "Sun *-*-* 00:00:00"]
+    );
+}
@@ -0,0 +1,88 @@
+use agent_loops::keys::{Key, parse_keys};
+use agent_loops::search::{Direction, line_matches, read_log_lines, search_log};
+use std::path::PathBuf;
+
+#[test]
+fn test_line_matches_is_smart_case() {
+    assert!(line_matches("error[E0308]: mismatched types", "error"));
+    assert!(line_matches("Error: boom", "error"));
+    assert!(!line_matches("error: boom", "Error"));
+    assert!(line_matches("Error: boom", "Error"));
+}
+
+fn write_log(name: &str, text: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "agent-loops-search-{name}-{}.log",
+        std::process::id()
+    ));
+    std::fs::write(&path, text).unwrap();
+    path
+}
+
+#[test]
+fn test_search_log_finds_the_closest_match() {
+    let log = write_log(
+        "closest",
+        "compiling\nerror: first\nwarning\nerror: second\ndone\n",
+    );
+
+    assert_eq!(
+        search_log(&log, "error", 0..5, Direction::Older).unwrap(),
+        Some(3)
+    );
+    assert_eq!(
+        search_log(&log, "error", 0..3, Direction::Older).unwrap(),
+        Some(1)
+    );
+    assert_eq!(
+        search_log(&log, "error", 2..5, Direction::Newer).unwrap(),
+        Some(3)
+    );
+    assert_eq!(
+        search_log(&log, "error", 4..5, Direction::Newer).unwrap(),
+        None
+    );
+    let _ = std::fs::remove_file(&log);
+}
+
+#[test]
+fn test_read_log_lines_reads_a_range() {
+    let log = write_log("range", "a\nb\nc\n");
+
+    assert_eq!(read_log_lines(&log, 1..3).unwrap(), vec!["b", "c"]);
+    assert_eq!(read_log_lines(&log, 2..10).unwrap(), vec!["c"]);
+    let _ = std::fs::remove_file(&log);
+}
+
+#[test]
+fn test_parse_keys_reads_characters_and_escape_sequences() {
+    assert_eq!(
+        parse_keys(b"/err\x7f\r"),
+        vec![
+            Key::Char('/'),
+            Key::Char('e'),
+            Key::Char('r'),
+            Key::Char('r'),
+            Key::Backspace,
+            Key::Enter
+        ]
+    );
+    assert_eq!(
+        parse_keys(b"\x1b[A\x1b[B\x1b[5~\x1b[6~\x1bOH\x1b[4~"),
+        vec![
+            Key::Up,
+            Key::Down,
+            Key::PageUp,
+            Key::PageDown,
+            Key::Home,
+            Key::End
+        ]
+    );
+    // Wheel turns as SGR mouse reports; clicks are dropped.
+    assert_eq!(
+        parse_keys(b"\x1b[<64;10;5M\x1b[<65;10;5M\x1b[<0;3;4M\x1b[<0;3;4m"),
+        vec![Key::WheelUp, Key::WheelDown]
+    );
+    // A lone ESC is the key; unknown sequences are dropped.
+    assert_eq!(parse_keys(b"\x1b[2~n\x1b"), vec![Key::Char('n'), Key::Esc]);
+}
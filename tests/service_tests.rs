@@ -0,0 +1,92 @@
+use agent_loops::schedule::CronSchedule;
+use agent_loops::service::{Service, service_args, systemd_quote};
+use std::path::PathBuf;
+
+fn service() -> Service {
+    Service {
+        name: "nightly".to_string(),
+        schedule: CronSchedule::parse("0 3 * * 1-5").unwrap(),
+        command: vec![
+            "/usr/bin/agent-loops".to_string(),
+            "-p".to_string(),
+            "fix lint at 100%".to_string(),
+        ],
+        work_dir: PathBuf::from("/srv/app"),
+        path: Some("/usr/local/bin:/usr/bin".to_string()),
+    }
+}
+
+#[test]
+fn test_service_args_drop_the_subcommand() {
+    let args: Vec<String> = [
+        "-p",
+        "tidy",
+        "-l",
+        "2",
+        "install-service",
+        "--schedule",
+        "0 3 * * *",
+        "--name=nightly",
+        "--print",
+        "-v",
+    ]
+    .map(String::from)
+    .to_vec();
+    assert_eq!(service_args(&args), ["-p", "tidy", "-l", "2", "-v"]);
+}
+
+#[test]
+fn test_systemd_quote() {
+    assert_eq!(
+        systemd_quote("/usr/bin/agent-loops"),
+        "/usr/bin/agent-loops"
+    );
+    assert_eq!(systemd_quote("fix it"), "\"fix it\"");
+    assert_eq!(systemd_quote("say \"hi\""), r#""say \"hi\"""#);
+    assert_eq!(systemd_quote("100%"), "100%%");
+    assert_eq!(systemd_quote("$HOME"), "$$HOME");
+}
+
+#[test]
+fn test_systemd_units() {
+    let service = service();
+    assert_eq!(
+        service.systemd_service(),
+        "[Unit]\nDescription=agent-loops session (nightly)\n\n[Service]\nType=oneshot\nWorkingDirectory=/srv/app\nEnvironment=PATH=/usr/local/bin:/usr/bin\nExecStart=/usr/bin/agent-loops -p \"fix lint at 100%%\"\n"
+    );
+    let timer = service.systemd_timer();
+    assert!(
+        timer.contains("OnCalendar=Mon,Tue,Wed,Thu,Fri *-*-* 03:00:00\nPersistent=true\n"),
+        "{timer}"
+    );
+    assert!(
+        timer.ends_with("[Install]\nWantedBy=timers.target\n"),
+        "{timer}"
+    );
+    assert_eq!(Service::validate_name("agent-loops.nightly"), Ok(()));
+    assert!(Service::validate_name("../evil").is_err());
+}
+
+#[test]
+fn test_windows_script_stays_resident_on_the_schedule() {
+    let script = service().windows_script();
+    assert!(
+        script.ends_with(
+            "/usr/bin/agent-loops --schedule \"0 3 * * 1-5\" -p \"fix lint at 100%%\"\r\n"
+        ),
+        "{script}"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_prints_the_units() {
+    assert_cmd::cargo::cargo_bin_cmd!("agent-loops")
+        .args(["-p", "tidy up", "-l", "3", "install-service"])
+        .args(["--schedule", "0 2 * * *", "--print"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("# agent-loops.service\n"))
+        .stdout(predicates::str::contains(" -p \"tidy up\" -l 3\n"))
+        .stdout(predicates::str::contains("OnCalendar=*-*-* 02:00:00\n"));
+}
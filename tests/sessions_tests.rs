@@ -0,0 +1,214 @@
+mod common;
+
+use agent_loops::sessions::{
+    RunEstimate, SessionRecord, SessionStatus, SessionStore, format_session, format_session_list,
+};
+use agent_loops::{RunOutcome, RunRecord};
+use common::temp_dir;
+use std::path::PathBuf;
+use std::time::Duration;
+
+fn record(name: Option<&str>) -> SessionRecord {
+    SessionRecord::new(
+        name.map(Into::into),
+        vec![PathBuf::from("/repo")],
+        2,
+        vec!["fix lints".to_string()],
+    )
+}
+
+fn run(loop_idx: usize, success: bool) -> RunRecord {
+    let outcome = RunOutcome {
+        success,
+        output_log: Some(PathBuf::from(format!("/tmp/run-{loop_idx}.log"))),
+        final_message: success.then(|| "Fixed the lints.\nNothing else changed.".to_string()),
+        ..RunOutcome::default()
+    };
+    RunRecord::new(loop_idx, 0, outcome, Duration::from_secs(30))
+}
+
+#[test]
+fn test_sessions_are_stored_and_found_by_id_or_name() {
+    let store = SessionStore::new(&temp_dir("sessions-store"));
+    assert!(store.list().unwrap().is_empty());
+
+    let mut first = record(Some("nightly"));
+    store.create(&mut first).unwrap();
+    let mut second = record(None);
+    store.create(&mut second).unwrap();
+    assert_ne!(first.id, second.id);
+
+    let listed = store.list().unwrap();
+    assert_eq!(listed.len(), 2);
+    assert_eq!(store.find(&second.id).unwrap(), second);
+    assert_eq!(store.find("nightly").unwrap().id, first.id);
+    assert!(store.find("missing").is_err());
+}
+
+#[test]
+fn test_finished_session_records_runs_and_status() {
+    let store = SessionStore::new(&temp_dir("sessions-finish"));
+    let mut session = record(None);
+    store.create(&mut session).unwrap();
+    assert_eq!(session.current_status(), SessionStatus::Running);
+
+    session.finish(&[run(0, true), run(1, false)], false);
+    store.save(&session).unwrap();
+    let stored = store.find(&session.id).unwrap();
+    assert_eq!(stored.status, SessionStatus::Failed);
+    assert_eq!(stored.runs.len(), 2);
+    assert!(stored.finished.is_some());
+
+    let shown = format_session(&stored);
+    assert!(shown.contains("Status: failed"));
+    assert!(shown.contains("loop 2 task 1: FAILED /tmp/run-1.log"));
+    assert!(shown.contains("loop 1 task 1: OK /tmp/run-0.log\n    > Fixed the lints.\n"));
+    assert!(format_session_list(&[stored]).contains("1/2"));
+}
+
+#[test]
+fn test_running_session_without_process_is_interrupted() {
+    let mut session = record(None);
+    session.pid = u32::MAX;
+    assert_eq!(session.current_status(), SessionStatus::Interrupted);
+}
+
+#[test]
+fn test_sessions_list_command() {
+    let dir = temp_dir("sessions-cli");
+    let mut session = record(Some("cli-test"));
+    SessionStore::new(&dir).create(&mut session).unwrap();
+    assert_cmd::cargo::cargo_bin_cmd!("agent-loops")
+        .args(["sessions", "list", "--data-dir"])
+        .arg(&dir)
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(session.id.as_str()))
+        .stdout(predicates::str::contains("cli-test"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_detached_session_logs_its_output() {
+    let dir = temp_dir("sessions-detach");
+    let work = dir.join("work");
+    std::fs::create_dir_all(&work).unwrap();
+    // `--detach` on the command line, then from the environment, which the detached
+    // session must not act on again.
+    for (flag, env) in [(Some("--detach"), None), (None, Some("true"))] {
+        let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("agent-loops");
+        if let Some(env) = env {
+            cmd.env("AGENT_LOOPS_DETACH", env);
+        }
+        let output = cmd
+            .args(["-p", "tidy up", "--yes"])
+            .args(flag)
+            .args(["--runner-template", "echo detached {prompt}", "-C"])
+            .arg(&work)
+            .arg("--data-dir")
+            .arg(&dir)
+            .arg("--spool-dir")
+            .arg(dir.join("spool"))
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        let output = String::from_utf8(output).unwrap();
+        let id = output
+            .strip_prefix("Session ")
+            .and_then(|rest| rest.split_whitespace().next())
+            .unwrap();
+
+        let store = SessionStore::new(&dir);
+        let deadline = std::time::Instant::now() + Duration::from_secs(20);
+        let record = loop {
+            if let Ok(record) = store.find(id)
+                && record.status != SessionStatus::Running
+            {
+                break record;
+            }
+            assert!(std::time::Instant::now() < deadline, "{output}");
+            std::thread::sleep(Duration::from_millis(100));
+        };
+        assert_eq!(record.status, SessionStatus::Succeeded);
+        assert_ne!(record.pid, std::process::id());
+        let log = std::fs::read_to_string(store.log_path(id)).unwrap();
+        assert!(log.contains("detached tidy up"), "{log}");
+    }
+}
+
+#[test]
+fn test_run_estimate_from_history() {
+    let mut priced = record(None);
+    priced.started = "2024-06-01 02:00:00".to_string();
+    priced.finished = Some("2024-06-01 02:10:00".to_string());
+    priced.runs = (0..2)
+        .map(|idx| {
+            let mut run = run(idx, true);
+            run.outcome.cost_usd = Some(0.5 + idx as f64);
+            (&run).into()
+        })
+        .collect();
+    let mut unpriced = record(None);
+    unpriced.started = "2024-06-02 02:00:00".to_string();
+    unpriced.finished = Some("2024-06-02 02:05:00".to_string());
+    unpriced.runs = vec![(&run(0, false)).into()];
+    let running = record(None);
+
+    assert_eq!(
+        RunEstimate::from_history(std::slice::from_ref(&running)),
+        None
+    );
+    let estimate = RunEstimate::from_history(&[priced, unpriced, running]).unwrap();
+    assert_eq!(
+        estimate,
+        RunEstimate {
+            duration: Duration::from_secs(300),
+            cost_usd: Some(1.0),
+        }
+    );
+    assert_eq!(estimate.describe(30), "about 2h30m00s and $30.00");
+}
+
+#[test]
+fn test_large_session_needs_confirmation() {
+    let dir = temp_dir("sessions-confirm");
+    assert_cmd::cargo::cargo_bin_cmd!("agent-loops")
+        .args([
+            "-p",
+            "a",
+            "-p",
+            "b",
+            "-l",
+            "150",
+            "--runner-template",
+            "echo {prompt}",
+        ])
+        .arg("--data-dir")
+        .arg(&dir)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "This session is set to start 300 runs, more than --confirm-above 200. \
+             No past session to estimate its time and spend from. Pass --yes",
+        ));
+    assert_cmd::cargo::cargo_bin_cmd!("agent-loops")
+        .args([
+            "-p",
+            "a",
+            "-p",
+            "b",
+            "-l",
+            "3",
+            "--runner-template",
+            "echo {prompt}",
+        ])
+        .args(["--confirm-above", "5", "--yes", "--data-dir"])
+        .arg(&dir)
+        .arg("--spool-dir")
+        .arg(dir.join("spool"))
+        .assert()
+        .success();
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,240 @@
+mod common;
+
+use agent_loops::stats::{
+    DurationStats, format_session_summary, format_task_stats, format_task_successes,
+    session_summary, stats_csv, stats_json, task_stats, task_successes,
+};
+use agent_loops::{RunOutcome, RunRecord, TaskSpec};
+use common::{replay, temp_dir, write_fixtures};
+use std::time::Duration;
+
+fn record(loop_idx: usize, task_idx: usize, success: bool) -> RunRecord {
+    RunRecord::new(
+        loop_idx,
+        task_idx,
+        RunOutcome::from(success),
+        Duration::from_secs(60),
+    )
+}
+
+fn timed(task_idx: usize, success: bool, secs: u64) -> RunRecord {
+    RunRecord::new(
+        0,
+        task_idx,
+        RunOutcome::from(success),
+        Duration::from_secs(secs),
+    )
+}
+
+#[test]
+fn test_task_successes_counts_runs_per_task() {
+    let tasks = vec![
+        TaskSpec {
+            name: Some("lint".to_string()),
+            min_successes: Some(2),
+            ..TaskSpec::new("Fix clippy warnings")
+        },
+        TaskSpec::new("Update the changelog"),
+    ];
+    let results = vec![
+        record(0, 0, true),
+        record(0, 1, false),
+        record(1, 0, false),
+        record(1, 1, true),
+        record(2, 0, true),
+        record(2, 1, true),
+    ];
+
+    let successes = task_successes(&tasks, &results);
+    assert_eq!(successes[0].label, "lint");
+    assert_eq!((successes[0].successes, successes[0].runs), (2, 3));
+    assert!(successes[0].met());
+    assert_eq!((successes[1].successes, successes[1].runs), (2, 3));
+    assert!(successes[1].met());
+
+    let stricter = vec![TaskSpec {
+        min_successes: Some(3),
+        ..tasks[0].clone()
+    }];
+    assert!(!task_successes(&stricter, &results)[0].met());
+}
+
+#[test]
+fn test_format_task_successes() {
+    let tasks = vec![
+        TaskSpec {
+            name: Some("lint".to_string()),
+            min_successes: Some(2),
+            ..TaskSpec::new("Fix clippy warnings")
+        },
+        TaskSpec::new("Update the changelog"),
+    ];
+    let results = vec![record(0, 0, false), record(0, 1, true), record(1, 1, false)];
+    assert_eq!(
+        format_task_successes(&task_successes(&tasks, &results)),
+        "=== Task success rates ===\n\
+         \x20 1. lint                  0/1 (0%)  needs 2: NOT MET\n\
+         \x20 2. Update the changelog  1/2 (50%)\n"
+    );
+}
+
+#[test]
+fn test_session_summary_table() {
+    let tasks = vec![
+        TaskSpec {
+            name: Some("lint".to_string()),
+            ..TaskSpec::new("Fix clippy warnings")
+        },
+        TaskSpec::new("Update the changelog"),
+        TaskSpec::new("never reached"),
+    ];
+    let results = vec![
+        record(0, 0, true),
+        record(0, 1, false),
+        record(1, 0, false),
+        record(1, 1, true),
+    ];
+
+    let summary = session_summary(&tasks, &results);
+    assert_eq!(summary.tasks[0].last_success, Some(false));
+    assert_eq!(summary.tasks[2].last_success, None);
+    assert_eq!((summary.successes(), summary.runs()), (2, 4));
+    assert_eq!(
+        summary.headline(),
+        "2/4 runs OK in 4m00s; failed: 1. lint (1/2), 2. Update the changelog (1/2)"
+    );
+    assert_eq!(
+        format_session_summary(&summary),
+        "=== Session summary ===\n\
+         \x20  # task                       ok      time  last\n\
+         \x20 1. lint                      1/2     2m00s  FAILED\n\
+         \x20 2. Update the changelog      1/2     2m00s  OK\n\
+         \x20 3. never reached             0/0         -  -\n\
+         \x20    loop 1                    1/2     2m00s\n\
+         \x20    loop 2                    1/2     2m00s\n\
+         \x20    all                       2/4     4m00s\n"
+    );
+}
+
+#[test]
+fn test_session_summary_marks_priorities() {
+    let tasks = vec![
+        TaskSpec {
+            priority: 5,
+            ..TaskSpec::new("hotfix")
+        },
+        TaskSpec::new("refactor"),
+    ];
+    let summary = session_summary(&tasks, &[record(0, 0, true), record(0, 1, true)]);
+    assert_eq!(
+        format_session_summary(&summary),
+        "=== Session summary ===\n\
+         \x20  # task                      ok      time  last\n\
+         \x20 1. hotfix [priority 5]      1/1     1m00s  OK\n\
+         \x20 2. refactor                 1/1     1m00s  OK\n"
+    );
+}
+
+#[test]
+fn test_cached_runs_are_counted_but_not_timed() {
+    let tasks = vec![TaskSpec::new("build")];
+    let cached = RunOutcome {
+        cached: true,
+        ..RunOutcome::from(true)
+    };
+    let results = vec![
+        RunRecord::new(0, 0, cached, Duration::ZERO),
+        timed(0, true, 60),
+    ];
+
+    let summary = session_summary(&tasks, &results);
+    assert_eq!(summary.cached(), 1);
+    assert_eq!(summary.headline(), "2/2 runs OK (1 cached) in 1m00s");
+    assert!(
+        format_session_summary(&summary).ends_with("1 run skipped as already done (--cache)\n")
+    );
+
+    let stats = task_stats(&tasks, &results);
+    assert_eq!(stats[0].success.successes, 2);
+    assert_eq!(stats[0].durations.unwrap().mean, Duration::from_secs(60));
+}
+
+#[test]
+fn test_duration_stats() {
+    let secs = |s: u64| Duration::from_secs(s);
+    let stats = DurationStats::of(&[secs(40), secs(10), secs(20), secs(30)]).unwrap();
+    assert_eq!(stats.mean, secs(25));
+    assert_eq!(stats.p50, secs(20));
+    assert_eq!(stats.p90, secs(40));
+    assert_eq!(stats.variance, 125.0);
+    assert_eq!(DurationStats::of(&[]), None);
+}
+
+#[test]
+fn test_task_stats_table_and_exports() {
+    let tasks = vec![
+        TaskSpec {
+            name: Some("lint, then test".to_string()),
+            ..TaskSpec::new("Fix clippy warnings")
+        },
+        TaskSpec::new("never ran"),
+    ];
+    let results = vec![
+        timed(0, true, 60),
+        timed(0, false, 180),
+        timed(0, true, 120),
+    ];
+    let stats = task_stats(&tasks, &results);
+
+    assert_eq!(
+        format_task_stats(&stats),
+        "=== Task statistics ===\n\
+         \x20  # task                  ok  rate      mean       p50       p90    stddev\n\
+         \x20 1. lint, then test      2/3   67%     2m00s     2m00s     3m00s       48s\n\
+         \x20 2. never ran            0/0    0%         -         -         -         -\n"
+    );
+    assert_eq!(
+        stats_csv(&stats),
+        "task,label,runs,successes,success_rate,min_successes,mean_secs,p50_secs,p90_secs,variance_secs2\n\
+         1,\"lint, then test\",3,2,66.7,,120.000,120.000,180.000,2400.000\n\
+         2,never ran,0,0,0.0,,,,,\n"
+    );
+    let json: Vec<serde_json::Value> = serde_json::from_str(&stats_json(&stats)).unwrap();
+    assert_eq!(json[0]["label"], "lint, then test");
+    assert_eq!(json[0]["successes"], 2);
+    assert_eq!(json[0]["p90_secs"], 180.0);
+    assert!(json[1]["mean_secs"].is_null());
+}
+
+#[test]
+fn test_cli_judges_tasks_by_min_successes() {
+    let dir = temp_dir("stats-cli");
+    let run = |min_successes: usize| {
+        std::fs::write(
+            dir.join("tasks.toml"),
+            format!("[[task]]\nprompt = \"flaky\"\nmin_successes = {min_successes}\n"),
+        )
+        .unwrap();
+        write_fixtures(&dir.join("fixtures"), "working\n", &[0, 1, 0]);
+        replay(&dir)
+            .args(["--loops", "3", "--prompts-file"])
+            .arg(dir.join("tasks.toml"))
+            .assert()
+    };
+
+    run(2)
+        .success()
+        .stdout(predicates::str::contains(
+            "  1. flaky       2/3        0s  OK      needs 2: met\n",
+        ))
+        .stdout(predicates::str::contains(
+            "All tasks completed successfully.",
+        ));
+    run(3)
+        .failure()
+        .stdout(predicates::str::contains("needs 3: NOT MET"))
+        .stderr(predicates::str::contains(
+            "1 task(s) below their min_successes: 1.",
+        ));
+    let _ = std::fs::remove_dir_all(&dir);
+}
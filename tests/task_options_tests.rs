@@ -0,0 +1,123 @@
+use agent_loops::agent::Agent;
+use agent_loops::{RunOptions, TaskSpec};
+
+#[test]
+fn test_for_task_overrides_binary_and_model() {
+    let options = RunOptions {
+        model: Some("session-model".to_string()),
+        ..RunOptions::default()
+    };
+    let task = TaskSpec {
+        codex_bin: Some("/opt/codex-nightly".to_string()),
+        model: Some("cheap-model".to_string()),
+        ..TaskSpec::new("prompt")
+    };
+
+    let merged = options.for_task(&task);
+    assert_eq!(merged.codex_bin, "/opt/codex-nightly");
+    assert_eq!(merged.model.as_deref(), Some("cheap-model"));
+
+    let unchanged = options.for_task(&TaskSpec::new("other"));
+    assert_eq!(unchanged.codex_bin, "codex");
+    assert_eq!(unchanged.model.as_deref(), Some("session-model"));
+}
+
+#[test]
+fn test_for_task_switches_agent() {
+    let options = RunOptions::default();
+    let task = TaskSpec {
+        agent: Some(Agent::Gemini),
+        ..TaskSpec::new("prompt")
+    };
+    let merged = options.for_task(&task);
+    assert_eq!(merged.agent, Agent::Gemini);
+    assert_eq!(merged.codex_bin, "gemini");
+
+    let pinned = TaskSpec {
+        codex_bin: Some("/opt/gemini".to_string()),
+        ..task
+    };
+    assert_eq!(options.for_task(&pinned).codex_bin, "/opt/gemini");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_codex_runner_uses_task_binary_and_model() {
+    use agent_loops::{CodexRunner, Runner};
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("agent-loops-model-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let out = dir.join("args.txt");
+    let script = dir.join("task-codex.sh");
+    std::fs::write(
+        &script,
+        format!(
+            "#!/bin/sh
+echo \"$@\" > '{}'\n",
+            out.display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let runner = CodexRunner::new(RunOptions {
+        codex_bin: "agent-loops-definitely-missing-binary".to_string(),
+        ..RunOptions::default()
+    });
+    let task = TaskSpec {
+        codex_bin: Some(script.to_string_lossy().into_owned()),
+        model: Some("cheap-model".to_string()),
+        ..TaskSpec::new("tidy up")
+    };
+    assert!(runner.run(&task).await.unwrap().success);
+    assert_eq!(
+        std::fs::read_to_string(&out).unwrap().trim(),
+        "exec --dangerously-bypass-approvals-and-sandbox --model cheap-model tidy up"
+    );
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_clean_env_passes_only_the_listed_variables() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("agent-loops-clean-env-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let out = dir.join("env.txt");
+    let script = dir.join("fake-codex.sh");
+    std::fs::write(
+        &script,
+        format!(
+            "#!/bin/sh\nprintf '%s|%s' \"${{AGENT_LOOPS_KEEP:-unset}}\" \"${{AGENT_LOOPS_DROP:-unset}}\" > '{}'\n",
+            out.display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    assert_cmd::cargo::cargo_bin_cmd!("agent-loops")
+        .args([
+            "-p",
+            "prompt",
+            "--clean-env",
+            "--pass-env",
+            "PATH,AGENT_LOOPS_KEEP",
+        ])
+        .arg("--codex-bin")
+        .arg(&script)
+        .arg("-C")
+        .arg(&dir)
+        .arg("--data-dir")
+        .arg(dir.join("data"))
+        .arg("--spool-dir")
+        .arg(dir.join("spool"))
+        .env("AGENT_LOOPS_KEEP", "kept")
+        .env("AGENT_LOOPS_DROP", "leaked")
+        .assert()
+        .success();
+    assert_eq!(std::fs::read_to_string(&out).unwrap(), "kept|unset");
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,297 @@
+use agent_loops::tasks::{
+    DedupeMode, TaskFilter, dedupe_tasks, filter_tasks, parse_added_tasks, parse_prompts,
+    parse_task_file,
+};
+use agent_loops::testing::{MockRunner, MockStep};
+use agent_loops::{
+    OrchestrateOptions, TaskSpec, load_prompts_file, load_tasks, orchestrate_runner,
+};
+
+fn temp_file(name: &str, content: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("agent-loops-{}-{name}", std::process::id()));
+    std::fs::write(&path, content).unwrap();
+    path
+}
+
+#[test]
+fn test_parse_prompts_trims_and_skips_blank_lines() {
+    assert_eq!(
+        parse_prompts("  first  \n\n\t\nsecond\r\n"),
+        vec!["first", "second"]
+    );
+}
+
+#[test]
+fn test_load_prompts_file_reads_lines() {
+    let path = temp_file("prompts.txt", "one\n\ntwo\n");
+    assert_eq!(load_prompts_file(&path).unwrap(), vec!["one", "two"]);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_parse_task_file() {
+    let tasks = parse_task_file(
+        r#"
+[[task]]
+name = "lint"
+prompt = "Fix clippy warnings"
+model = "cheap-model"
+tags = ["fast"]
+priority = 2
+
+[[task]]
+prompt = "Update the changelog"
+env = { OPENAI_BASE_URL = "http://localhost:8080/v1" }
+env_allowlist = ["PATH"]
+min_successes = 2
+"#,
+    )
+    .unwrap();
+    assert_eq!(
+        tasks,
+        vec![
+            TaskSpec {
+                name: Some("lint".to_string()),
+                model: Some("cheap-model".to_string()),
+                tags: vec!["fast".to_string()],
+                priority: 2,
+                ..TaskSpec::new("Fix clippy warnings")
+            },
+            TaskSpec {
+                env: [(
+                    "OPENAI_BASE_URL".to_string(),
+                    "http://localhost:8080/v1".to_string()
+                )]
+                .into(),
+                env_allowlist: Some(vec!["PATH".to_string()]),
+                min_successes: Some(2),
+                ..TaskSpec::new("Update the changelog")
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_parse_task_file_rejects_unknown_fields_and_empty_prompts() {
+    let unknown = parse_task_file("[[task]]\nprompt = \"x\"\nurgency = 1\n").unwrap_err();
+    assert_eq!(unknown.kind(), std::io::ErrorKind::InvalidData);
+
+    let empty = parse_task_file("[[task]]\nprompt = \"  \"\n").unwrap_err();
+    assert!(empty.to_string().contains("task 1"));
+
+    let missing = parse_task_file("[[task]]\nname = \"x\"\n").unwrap_err();
+    assert!(missing.to_string().contains("task 1"));
+    let empty_stage =
+        parse_task_file("[[task]]\nprompt = \"x\"\n\n[[task]]\nloop_prompts = [\"a\", \"\"]\n")
+            .unwrap_err();
+    assert!(empty_stage.to_string().contains("task 2"));
+}
+
+#[tokio::test]
+async fn test_loop_prompts_stage_the_loops() {
+    let tasks = parse_task_file(
+        "[[task]]\nloop_prompts = [\"Implement it\", \"Review it\"]\n\n[[task]]\nprompt = \"Lint\"\n",
+    )
+    .unwrap();
+    assert_eq!(tasks[0].prompt, "Implement it");
+    assert_eq!(tasks[0].for_loop(1).prompt, "Review it");
+    assert_eq!(tasks[0].for_loop(5).prompt, "Review it");
+    assert_eq!(tasks[1].for_loop(1).prompt, "Lint");
+
+    let runner = MockRunner::new().fallback(MockStep::success("done"));
+    orchestrate_runner(&tasks, 3, &OrchestrateOptions::default(), &runner).await;
+    runner.assert_prompts(&[
+        "Implement it",
+        "Lint",
+        "Review it",
+        "Lint",
+        "Review it",
+        "Lint",
+    ]);
+}
+
+#[test]
+fn test_load_tasks_picks_format_by_extension() {
+    let toml = temp_file("tasks.toml", "[[task]]\nprompt = \"from toml\"\n");
+    let text = temp_file("tasks.txt", "from text\n");
+    assert_eq!(load_tasks(&toml).unwrap(), vec![TaskSpec::new("from toml")]);
+    assert_eq!(load_tasks(&text).unwrap(), vec![TaskSpec::new("from text")]);
+    let _ = std::fs::remove_file(&toml);
+    let _ = std::fs::remove_file(&text);
+}
+
+#[test]
+fn test_load_tasks_expands_includes() {
+    let dir = std::env::temp_dir().join(format!("agent-loops-include-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("more")).unwrap();
+    std::fs::write(
+        dir.join("main.txt"),
+        "first\n#include more/lint.txt\nlast\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("more/lint.txt"),
+        "  #include\tlint.toml  \n#included is a prompt\n",
+    )
+    .unwrap();
+    std::fs::write(dir.join("more/lint.toml"), "[[task]]\nprompt = \"lint\"\n").unwrap();
+    assert_eq!(
+        load_tasks(&dir.join("main.txt")).unwrap(),
+        vec![
+            TaskSpec::new("first"),
+            TaskSpec::new("lint"),
+            TaskSpec::new("#included is a prompt"),
+            TaskSpec::new("last"),
+        ]
+    );
+
+    std::fs::write(dir.join("a.txt"), "a\n#include b.txt\n").unwrap();
+    std::fs::write(dir.join("b.txt"), "#include a.txt\n").unwrap();
+    let err = load_tasks(&dir.join("a.txt")).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(err.to_string().contains("include cycle"), "{err}");
+    assert!(err.to_string().contains("b.txt ->"), "{err}");
+
+    std::fs::write(dir.join("missing.txt"), "#include nowhere.txt\n").unwrap();
+    let err = load_tasks(&dir.join("missing.txt")).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+    assert!(err.to_string().contains("nowhere.txt"), "{err}");
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_parse_added_tasks_reads_markers_and_fenced_blocks() {
+    let output = "\
+Split the work:
+AGENT_LOOPS_ADD_TASK: Add the parser
+```agent-loops-tasks
+[\"Cover it with tests\",
+ {\"prompt\": \"Bump the version\", \"name\": \"release\"}]
+```
+```agent-loops-tasks
+not json
+```
+```agent-loops-tasks
+[{\"prompt\": \"Deploy\", \"codex_bin\": \"/tmp/evil\"}]
+```
+AGENT_LOOPS_ADD_TASK:   
+";
+    let tasks = parse_added_tasks(output);
+
+    assert_eq!(
+        tasks.iter().map(|t| t.prompt.as_str()).collect::<Vec<_>>(),
+        ["Add the parser", "Cover it with tests", "Bump the version"]
+    );
+    assert_eq!(tasks[2].name.as_deref(), Some("release"));
+    assert!(tasks.iter().all(|t| t.codex_bin.is_none()));
+    assert!(parse_added_tasks("nothing to add\n").is_empty());
+}
+
+#[test]
+fn test_task_filter_parses_and_displays() {
+    assert_eq!(
+        "tag=infra".parse::<TaskFilter>(),
+        Ok(TaskFilter::Tag("infra".to_string()))
+    );
+    assert_eq!(
+        "name=lint".parse::<TaskFilter>().unwrap().to_string(),
+        "name=lint"
+    );
+    assert!("tag=".parse::<TaskFilter>().is_err());
+    assert!("infra".parse::<TaskFilter>().is_err());
+    assert_eq!("3".parse::<TaskFilter>(), Ok(TaskFilter::Number(3)));
+    assert!("0".parse::<TaskFilter>().is_err());
+    assert!("model=o3".parse::<TaskFilter>().is_err());
+}
+
+#[test]
+fn test_filter_tasks_applies_only_then_skip() {
+    let tagged = |prompt: &str, tags: &[&str]| TaskSpec {
+        tags: tags.iter().map(ToString::to_string).collect(),
+        ..TaskSpec::new(prompt)
+    };
+    let tasks = vec![
+        tagged("deploy", &["infra"]),
+        tagged("migrate", &["infra", "slow"]),
+        tagged("lint", &[]),
+    ];
+    let prompts = |tasks: Vec<TaskSpec>| tasks.into_iter().map(|t| t.prompt).collect::<Vec<_>>();
+    let infra = TaskFilter::Tag("infra".to_string());
+    let slow = TaskFilter::Tag("slow".to_string());
+
+    assert_eq!(prompts(filter_tasks(tasks.clone(), &[], &[])).len(), 3);
+    assert_eq!(
+        prompts(filter_tasks(
+            tasks.clone(),
+            &[infra],
+            std::slice::from_ref(&slow)
+        )),
+        ["deploy"]
+    );
+    assert_eq!(
+        prompts(filter_tasks(tasks.clone(), &[], &[slow])),
+        ["deploy", "lint"]
+    );
+    assert_eq!(
+        prompts(filter_tasks(tasks, &[], &[TaskFilter::Number(1)])),
+        ["migrate", "lint"]
+    );
+}
+
+#[test]
+fn test_dedupe_tasks() {
+    assert_eq!("exact".parse(), Ok(DedupeMode::Exact));
+    assert_eq!("whitespace".parse(), Ok(DedupeMode::Whitespace));
+    assert!("fuzzy".parse::<DedupeMode>().is_err());
+
+    let on_model = TaskSpec {
+        model: Some("cheap-model".to_string()),
+        ..TaskSpec::new("Fix lint")
+    };
+    let tasks = vec![
+        TaskSpec::new("Fix lint"),
+        TaskSpec::new("Fix  lint\n"),
+        on_model.clone(),
+        TaskSpec::new("Fix lint"),
+    ];
+    assert_eq!(
+        dedupe_tasks(tasks.clone(), DedupeMode::Exact),
+        (
+            vec![
+                TaskSpec::new("Fix lint"),
+                TaskSpec::new("Fix  lint\n"),
+                on_model.clone()
+            ],
+            vec![(4, 1)]
+        )
+    );
+    assert_eq!(
+        dedupe_tasks(tasks, DedupeMode::Whitespace),
+        (
+            vec![TaskSpec::new("Fix lint"), on_model],
+            vec![(2, 1), (4, 1)]
+        )
+    );
+}
+
+#[test]
+fn test_cli_dedupes_prompts_across_sources() {
+    let path = temp_file("dedupe.txt", "Fix lint\nUpdate docs\n");
+    let dir = std::env::temp_dir().join(format!("agent-loops-dedupe-{}", std::process::id()));
+    assert_cmd::cargo::cargo_bin_cmd!("agent-loops")
+        .args(["-p", "Fix lint", "--dedupe-prompts", "--prompts-file"])
+        .arg(&path)
+        .args(["--runner-template", "echo {prompt}", "--data-dir"])
+        .arg(dir.join("data"))
+        .arg("--spool-dir")
+        .arg(dir.join("spool"))
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Tasks: 2 | Total runs: 2"))
+        .stderr(predicates::str::contains(
+            "Dropped task 2: it repeats task 1.",
+        ));
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_dir_all(&dir);
+}
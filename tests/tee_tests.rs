@@ -0,0 +1,91 @@
+mod common;
+
+use agent_loops::TeeFile;
+use agent_loops::fixture::FixtureStream;
+use common::{replay, temp_dir, write_events};
+
+#[test]
+fn test_tee_file_clones_append_to_one_file() {
+    let dir = temp_dir("tee-clones");
+    let path = dir.join("tee.log");
+    std::fs::write(&path, "stale\n").unwrap();
+
+    let tee = TeeFile::create(&path).unwrap();
+    let copy = tee.clone();
+    tee.write_line("first");
+    copy.write_line("second");
+
+    assert_eq!(tee.path(), path);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "first\nsecond\n");
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_cli_tees_headers_output_and_results() {
+    let dir = temp_dir("tee-cli");
+    write_events(
+        &dir.join("fixtures"),
+        "run-0001.fixture",
+        &[
+            (
+                FixtureStream::Stdout,
+                b"\x1b[1mthinking\x1b[0m\n[#   ]\r[####]\ndone",
+            ),
+            (FixtureStream::Stderr, b"warning: slow\n"),
+        ],
+        0,
+    );
+    let tee = dir.join("session.log");
+
+    replay(&dir)
+        .args(["-p", "think hard", "--tee"])
+        .arg(&tee)
+        .assert()
+        .success();
+
+    let content = std::fs::read_to_string(&tee).unwrap();
+    assert!(content.contains("think hard"), "{content}");
+    assert!(content.contains("thinking\n[####]\n"), "{content}");
+    assert!(content.contains("\ndone\n"), "{content}");
+    assert!(content.contains("warning: slow\n"), "{content}");
+    assert!(content.contains("[Run 1/1] Result: OK\n"), "{content}");
+    assert!(
+        content.contains("=== All loops completed ===\n"),
+        "{content}"
+    );
+    assert!(
+        content.ends_with("All tasks completed successfully.\n"),
+        "{content}"
+    );
+    assert!(!content.contains('\x1b'), "{content}");
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_run_footer_reports_time_and_check() {
+    use common::agent_loops;
+
+    let dir = temp_dir("tee-footer");
+    let tee = dir.join("session.log");
+    let run = |check: &str| {
+        agent_loops(&dir, &dir)
+            .args(["-p", "fix it", "--runner-template", "echo {prompt}"])
+            .args(["--check", check, "--tee"])
+            .arg(&tee)
+            .assert()
+    };
+
+    run("true").success();
+    let content = std::fs::read_to_string(&tee).unwrap();
+    let footer = regex::Regex::new(
+        r"\[Run 1/1\] Result: OK\n\[Run 1/1\] Took: [0-9hm]+s\n\[Run 1/1\] Check: passed\n",
+    )
+    .unwrap();
+    assert!(footer.is_match(&content), "{content}");
+
+    run("false").failure();
+    let content = std::fs::read_to_string(&tee).unwrap();
+    assert!(content.contains("[Run 1/1] Check: failed\n"), "{content}");
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,19 @@
+#![cfg(feature = "otlp")]
+
+use agent_loops::telemetry::traces_endpoint;
+
+#[test]
+fn test_traces_endpoint_from_collector_url() {
+    assert_eq!(
+        traces_endpoint("http://localhost:4318"),
+        "http://localhost:4318/v1/traces"
+    );
+    assert_eq!(
+        traces_endpoint("http://localhost:4318/"),
+        "http://localhost:4318/v1/traces"
+    );
+    assert_eq!(
+        traces_endpoint("https://otel.example.com/v1/traces"),
+        "https://otel.example.com/v1/traces"
+    );
+}
@@ -0,0 +1,111 @@
+use agent_loops::testing::{MockRunner, MockStep};
+use agent_loops::{
+    AgentLoopsError, CancelToken, OrchestrateOptions, StartAt, TaskSpec, orchestrate_runner,
+};
+use std::time::Duration;
+
+fn tasks() -> Vec<TaskSpec> {
+    vec![TaskSpec::new("first"), TaskSpec::new("second")]
+}
+
+#[tokio::test]
+async fn test_mock_runner_plays_script_then_fallback() {
+    let runner = MockRunner::new()
+        .step(MockStep::success("ok"))
+        .step(MockStep::failure("boom"))
+        .fallback(MockStep::success(""));
+
+    let results = orchestrate_runner(&tasks(), 2, &OrchestrateOptions::default(), &runner).await;
+
+    runner.assert_prompts(&["first", "second", "first", "second"]);
+    runner.assert_results(&[true, false, true, true]);
+    assert_eq!(results.len(), 4);
+    assert!(!results[1].outcome.success);
+}
+
+#[tokio::test]
+async fn test_mock_runner_fail_on_schedule() {
+    let runner = MockRunner::new().fail_on([2, 3]);
+    orchestrate_runner(&tasks(), 2, &OrchestrateOptions::default(), &runner).await;
+    runner.assert_results(&[true, false, false, true]);
+}
+
+#[tokio::test]
+async fn test_mock_runner_errors_are_recorded() {
+    let runner = MockRunner::new().step(MockStep::error(|| AgentLoopsError::Cancelled));
+    let options = OrchestrateOptions {
+        cancel: CancelToken::new(),
+        ..OrchestrateOptions::default()
+    };
+
+    let results = orchestrate_runner(&tasks(), 1, &options, &runner).await;
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(runner.calls()[0].result, Err("run cancelled".to_string()));
+    assert!(options.cancel.is_cancelled());
+}
+
+#[tokio::test]
+async fn test_session_stops_after_consecutive_failures() {
+    let runner = MockRunner::new().fail_on([2, 4, 5]);
+    let options = OrchestrateOptions {
+        max_consecutive_failures: Some(2),
+        ..OrchestrateOptions::default()
+    };
+
+    let results = orchestrate_runner(&tasks(), 5, &options, &runner).await;
+
+    // Run 3 succeeds and resets the streak; runs 4 and 5 trip the breaker.
+    assert_eq!(results.len(), 5);
+    runner.assert_results(&[true, false, true, false, false]);
+}
+
+#[tokio::test]
+async fn test_mock_runner_delays_each_run() {
+    let runner =
+        MockRunner::new().fallback(MockStep::success("").with_delay(Duration::from_millis(50)));
+    let started = std::time::Instant::now();
+    orchestrate_runner(&tasks(), 1, &OrchestrateOptions::default(), &runner).await;
+    assert!(started.elapsed() >= Duration::from_millis(100));
+}
+
+#[tokio::test]
+async fn test_session_starts_at_a_later_run() {
+    let runner = MockRunner::new();
+    let options = OrchestrateOptions {
+        start_at: Some(StartAt::Run(3)),
+        ..OrchestrateOptions::default()
+    };
+
+    let results = orchestrate_runner(&tasks(), 2, &options, &runner).await;
+
+    runner.assert_prompts(&["first", "second"]);
+    assert_eq!(
+        results
+            .iter()
+            .map(|r| (r.loop_idx, r.task_idx))
+            .collect::<Vec<_>>(),
+        [(1, 0), (1, 1)]
+    );
+}
+
+#[tokio::test]
+async fn test_session_starts_at_a_task_whatever_its_priority() {
+    let runner = MockRunner::new();
+    let tasks = [
+        TaskSpec::new("low"),
+        TaskSpec {
+            priority: 5,
+            ..TaskSpec::new("urgent")
+        },
+        TaskSpec::new("later"),
+    ];
+    let options = OrchestrateOptions {
+        start_at: Some(StartAt::Task(2)),
+        ..OrchestrateOptions::default()
+    };
+
+    orchestrate_runner(&tasks, 2, &options, &runner).await;
+
+    runner.assert_prompts(&["urgent", "later", "urgent", "low", "later"]);
+}
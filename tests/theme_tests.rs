@@ -0,0 +1,44 @@
+use agent_loops::theme::{ColorChoice, Style, Theme};
+
+#[test]
+fn test_style_paints_with_sgr_codes() {
+    let style: Style = "bold cyan".parse().unwrap();
+    assert_eq!(style.paint("=== Plan ==="), "\x1b[1;36m=== Plan ===\x1b[0m");
+    assert_eq!(style.to_string(), "bold cyan");
+    assert_eq!(style.paint(""), "");
+
+    let none: Style = "none".parse().unwrap();
+    assert_eq!(none.paint("OK"), "OK");
+    assert!("blinking".parse::<Style>().is_err());
+}
+
+#[test]
+fn test_theme_overrides_defaults() {
+    let theme: Theme = "header=bold+blue, failed=bright-red".parse().unwrap();
+    assert_eq!(theme.header.to_string(), "bold blue");
+    assert_eq!(theme.failed.to_string(), "bright-red");
+    assert_eq!(theme.ok, Theme::default().ok);
+    assert_eq!(theme.outcome(false), "\x1b[91mFAILED\x1b[0m");
+
+    assert!("header".parse::<Theme>().is_err());
+    assert!("footer=red".parse::<Theme>().is_err());
+}
+
+#[test]
+fn test_no_color_keeps_attributes() {
+    let theme = Theme::default().without_colors();
+    assert_eq!(theme.header.to_string(), "bold");
+    assert_eq!(theme.status.to_string(), "reverse");
+    assert_eq!(theme.stderr.to_string(), "dim");
+    assert_eq!(Theme::plain().outcome(true), "OK");
+}
+
+#[test]
+fn test_color_choice() {
+    assert!(ColorChoice::Auto.use_colors(false));
+    assert!(!ColorChoice::Auto.use_colors(true));
+    assert!(ColorChoice::Always.use_colors(true));
+    assert!(!ColorChoice::Never.use_colors(false));
+    assert_eq!("NEVER".parse::<ColorChoice>(), Ok(ColorChoice::Never));
+    assert!("sometimes".parse::<ColorChoice>().is_err());
+}
@@ -0,0 +1,31 @@
+use agent_loops::RunThrottle;
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_throttle_allows_runs_up_to_the_limit() {
+    let mut throttle = RunThrottle::per_hour(2);
+    let start = Instant::now();
+
+    assert_eq!(throttle.delay_at(start), None);
+    throttle.record_start(start);
+    assert_eq!(throttle.delay_at(start), None);
+    throttle.record_start(start + Duration::from_secs(600));
+
+    assert_eq!(
+        throttle.delay_at(start + Duration::from_secs(900)),
+        Some(Duration::from_secs(2700))
+    );
+}
+
+#[test]
+fn test_throttle_window_slides() {
+    let mut throttle = RunThrottle::new(1, Duration::from_secs(60));
+    let start = Instant::now();
+    throttle.record_start(start);
+
+    assert_eq!(
+        throttle.delay_at(start + Duration::from_secs(20)),
+        Some(Duration::from_secs(40))
+    );
+    assert_eq!(throttle.delay_at(start + Duration::from_secs(60)), None);
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use agent_loops::git::DiffStat;
+use agent_loops::vote::{Sample, agreement, format_vote, pick_consensus};
+
+fn sample(passed: bool, tree: &str) -> Sample {
+    Sample {
+        passed,
+        tree: Some(tree.to_string()),
+        changes: Some(DiffStat {
+            files_changed: 1,
+            insertions: 2,
+            deletions: 0,
+        }),
+    }
+}
+
+#[test]
+fn test_pick_consensus_prefers_the_largest_agreeing_group() {
+    let samples = vec![
+        sample(true, "t1"),
+        sample(true, "t2"),
+        sample(false, "t1"),
+        sample(true, "t2"),
+    ];
+    assert_eq!(pick_consensus(&samples), Some(1));
+    assert_eq!(agreement(&samples, 1), 2);
+    // Failed samples do not count towards agreement.
+    assert_eq!(agreement(&samples, 0), 1);
+
+    let tie = vec![sample(false, "t0"), sample(true, "t1"), sample(true, "t2")];
+    assert_eq!(pick_consensus(&tie), Some(1));
+
+    let none = vec![sample(false, "t1"), sample(false, "t1")];
+    assert_eq!(pick_consensus(&none), None);
+}
+
+#[test]
+fn test_format_vote() {
+    let samples = vec![sample(true, "t1"), sample(false, "t2"), sample(true, "t1")];
+    assert_eq!(
+        format_vote(&samples, 2, pick_consensus(&samples)),
+        "=== Vote ===\n\
+         \x20 Sample 1: PASSED  1 file changed, +2 -0\n\
+         \x20 Sample 2: FAILED  1 file changed, +2 -0\n\
+         \x20 Sample 3: PASSED  1 file changed, +2 -0\n\
+         2/3 samples passed, 2 required. Applying sample 1, identical to 2 of the passing samples.\n"
+    );
+    assert!(
+        format_vote(&samples, 3, pick_consensus(&samples))
+            .ends_with("2/3 samples passed, 3 required. Nothing applied.\n")
+    );
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_cli_vote_applies_the_verified_consensus() {
+    use agent_loops::git::git;
+    use common::{agent_loops, init_repo, temp_dir};
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = temp_dir("vote-cli");
+    let repo = dir.join("repo");
+    std::fs::create_dir_all(&repo).unwrap();
+    std::fs::write(repo.join("answer.txt"), "unknown\n").unwrap();
+    init_repo(&repo).await;
+
+    // Samples answer 41, 42, 42 in turn; codex runs in its worktree via `-C`.
+    let counter = dir.join("counter");
+    let script = dir.join("codex.sh");
+    std::fs::write(
+        &script,
+        format!(
+            "#!/bin/sh\n\
+             n=$(cat '{counter}' 2>/dev/null || echo 0); n=$((n + 1)); echo $n > '{counter}'\n\
+             if [ $n -eq 1 ]; then answer=41; else answer=42; fi\n\
+             echo $answer > \"$4/answer.txt\"\n",
+            counter = counter.display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let vote = |required: &str| {
+        let _ = std::fs::remove_file(&counter);
+        agent_loops(&repo, &dir)
+            .args(["-p", "What is the answer?", "--codex-bin"])
+            .arg(&script)
+            .args(["vote", "--samples", "3", "--required", required])
+            .args(["--check", "grep -qx 42 answer.txt"])
+            .assert()
+    };
+
+    vote("3").failure().stdout(predicates::str::contains(
+        "2/3 samples passed, 3 required. Nothing applied.",
+    ));
+    assert_eq!(
+        std::fs::read_to_string(repo.join("answer.txt")).unwrap(),
+        "unknown\n"
+    );
+
+    vote("2")
+        .success()
+        .stdout(predicates::str::contains("Sample 1: FAILED"))
+        .stdout(predicates::str::contains(
+            "Applying sample 2, identical to 2",
+        ));
+    assert_eq!(
+        std::fs::read_to_string(repo.join("answer.txt")).unwrap(),
+        "42\n"
+    );
+    let worktrees = git(&repo, &["worktree", "list"]).await.unwrap();
+    assert_eq!(worktrees.lines().count(), 1, "{worktrees}");
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,93 @@
+use agent_loops::CancelToken;
+use agent_loops::watch::PathWatcher;
+use std::time::Duration;
+
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("agent-loops-watch-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(dir.join("src/lib.rs"), "fn a() {}\n").unwrap();
+    dir
+}
+
+#[test]
+fn test_watcher_reports_added_modified_and_removed_files() {
+    let dir = temp_dir("changes");
+    let mut watcher = PathWatcher::new(vec![dir.clone()]);
+    assert!(watcher.changed_paths().is_empty());
+
+    std::fs::write(dir.join("src/lib.rs"), "fn a() { todo!() }\n").unwrap();
+    std::fs::write(dir.join("src/new.rs"), "").unwrap();
+    assert_eq!(
+        watcher.changed_paths(),
+        [dir.join("src/lib.rs"), dir.join("src/new.rs")]
+    );
+
+    std::fs::remove_file(dir.join("src/new.rs")).unwrap();
+    assert_eq!(watcher.changed_paths(), [dir.join("src/new.rs")]);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_watcher_ignores_vcs_and_build_dirs_and_rebaselines() {
+    let dir = temp_dir("ignored");
+    let mut watcher = PathWatcher::new(vec![dir.clone()]);
+
+    for ignored in [".git", "target"] {
+        std::fs::create_dir_all(dir.join(ignored)).unwrap();
+        std::fs::write(dir.join(ignored).join("file"), "x").unwrap();
+    }
+    assert!(watcher.changed_paths().is_empty());
+
+    std::fs::write(dir.join("src/lib.rs"), "edited by the pass\n").unwrap();
+    watcher.rebaseline();
+    assert!(watcher.changed_paths().is_empty());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_wait_for_change_debounces_and_stops_on_cancel() {
+    let dir = temp_dir("wait");
+    let mut watcher = PathWatcher::new(vec![dir.join("src")]);
+    let cancel = CancelToken::new();
+
+    let writer = {
+        let dir = dir.clone();
+        tokio::spawn(async move {
+            for i in 0..3 {
+                tokio::time::sleep(Duration::from_millis(30)).await;
+                std::fs::write(dir.join(format!("src/{i}.rs")), "").unwrap();
+            }
+        })
+    };
+    let changed = watcher
+        .wait_for_change(
+            Duration::from_millis(10),
+            Duration::from_millis(100),
+            &cancel,
+        )
+        .await
+        .unwrap();
+    writer.await.unwrap();
+    assert_eq!(changed.len(), 3);
+
+    cancel.cancel();
+    let stopped = watcher
+        .wait_for_change(
+            Duration::from_millis(10),
+            Duration::from_millis(100),
+            &cancel,
+        )
+        .await;
+    assert!(stopped.is_none());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_watch_command_accepts_prompts_after_subcommand() {
+    assert_cmd::cargo::cargo_bin_cmd!("agent-loops")
+        .args(["watch", "--help"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("--path"));
+}
@@ -0,0 +1,94 @@
+use agent_loops::OrchestrateOptions;
+use agent_loops::testing::{MockRunner, MockStep};
+use agent_loops::workspace::{format_workspace_summary, load_workspace, orchestrate_workspace};
+
+fn temp_workspace(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("agent-loops-ws-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    for repo in ["api", "web"] {
+        std::fs::create_dir_all(dir.join(repo)).unwrap();
+    }
+    std::fs::write(dir.join("api-tasks.txt"), "Bump dependencies\nFix clippy\n").unwrap();
+    std::fs::write(
+        dir.join("workspace.toml"),
+        r#"
+[[repo]]
+name = "backend"
+path = "api"
+tasks = "api-tasks.txt"
+
+[[repo]]
+path = "web"
+task = [{ prompt = "Update the lockfile" }]
+"#,
+    )
+    .unwrap();
+    dir
+}
+
+#[test]
+fn test_load_workspace_resolves_paths_and_task_files() {
+    let dir = temp_workspace("load");
+    let workspace = load_workspace(&dir.join("workspace.toml")).unwrap();
+
+    assert_eq!(workspace.repo.len(), 2);
+    assert_eq!(workspace.repo[0].display_name(), "backend");
+    assert_eq!(workspace.repo[0].path, dir.join("api"));
+    assert_eq!(workspace.repo[0].task.len(), 2);
+    assert_eq!(workspace.repo[1].display_name(), "web");
+    assert_eq!(workspace.repo[1].task[0].prompt, "Update the lockfile");
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_load_workspace_rejects_repo_without_tasks() {
+    let dir = temp_workspace("empty");
+    std::fs::write(dir.join("workspace.toml"), "[[repo]]\npath = \"api\"\n").unwrap();
+
+    let err = load_workspace(&dir.join("workspace.toml")).unwrap_err();
+    assert!(err.to_string().contains("has no tasks"));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_workspace_results_are_grouped_by_repo() {
+    let dir = temp_workspace("run");
+    let workspace = load_workspace(&dir.join("workspace.toml")).unwrap();
+
+    let repos = orchestrate_workspace(&workspace, 1, &OrchestrateOptions::default(), |repo| {
+        if repo.display_name() == "web" {
+            MockRunner::new().fallback(MockStep::failure(""))
+        } else {
+            MockRunner::new()
+        }
+    })
+    .await;
+
+    assert_eq!(repos.len(), 2);
+    assert_eq!(repos[0].results.len(), 2);
+    let summary = format_workspace_summary(&repos);
+    assert!(summary.contains("backend: 2/2 runs OK"));
+    assert!(summary.contains("web: 0/1 runs OK"));
+    assert!(summary.contains("  FAILED loop 1 task 1"));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_session_cost_limit_spans_all_repos() {
+    let dir = temp_workspace("cost");
+    let workspace = load_workspace(&dir.join("workspace.toml")).unwrap();
+    let options = OrchestrateOptions {
+        max_session_cost: Some(0.3),
+        ..OrchestrateOptions::default()
+    };
+
+    let repos = orchestrate_workspace(&workspace, 1, &options, |_| {
+        MockRunner::new().fallback(MockStep::success("Cost: $0.20"))
+    })
+    .await;
+
+    // The second run of the first repository reaches the limit; `web` never starts.
+    assert_eq!(repos.len(), 1);
+    assert_eq!(repos[0].results.len(), 2);
+    let _ = std::fs::remove_dir_all(&dir);
+}